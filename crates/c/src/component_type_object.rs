@@ -1,8 +1,8 @@
 use anyhow::Result;
 use heck::ToSnakeCase;
 use wasm_encoder::{
-    CodeSection, CustomSection, Function, FunctionSection, LinkingSection, Module, SymbolTable,
-    TypeSection,
+    CodeSection, CustomSection, EntityType, Function, FunctionSection, ImportSection,
+    LinkingSection, MemoryType, Module, SymbolTable, TypeSection,
 };
 use wit_bindgen_core::wit_parser::{Resolve, WorldId};
 use wit_component::StringEncoding;
@@ -18,6 +18,7 @@ pub fn object(
     world_name: &str,
     encoding: StringEncoding,
     suffix: Option<&str>,
+    memory64: bool,
 ) -> Result<Vec<u8>> {
     let mut module = Module::new();
 
@@ -25,6 +26,24 @@ pub fn object(
     let mut types = TypeSection::new();
     types.function([], []);
     module.section(&types);
+
+    // LLD considers objects without a 64-bit `__linear_memory` import to be
+    // wasm32 objects and refuses to link them into a wasm64 module.
+    if memory64 {
+        let mut imports = ImportSection::new();
+        imports.import(
+            "env",
+            "__linear_memory",
+            EntityType::Memory(MemoryType {
+                minimum: 0,
+                maximum: None,
+                memory64: true,
+                shared: false,
+                page_size_log2: None,
+            }),
+        );
+        module.section(&imports);
+    }
     let mut funcs = FunctionSection::new();
     funcs.function(0);
     module.section(&funcs);
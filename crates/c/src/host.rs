@@ -0,0 +1,852 @@
+//! Host-side bindings, for embedding a guest with wasmtime's C API.
+//!
+//! The roles of the generated functions are swapped relative to guest
+//! bindings: exports of the guest are wrapped in functions which the host
+//! calls, and imports of the guest are implemented by the host and registered
+//! with a `wasmtime_linker_t` through trampolines. Lifting and lowering is
+//! shared with guest bindings, except that the guest's linear memory is only
+//! accessible through its instance, so values are copied in and out of it.
+
+use crate::{FunctionBindgen, InterfaceGenerator, Scalar, SourceType, C};
+use heck::*;
+use std::fmt::Write;
+use wit_bindgen_core::abi::{self, AbiVariant, Instruction, LiftLower, WasmType};
+use wit_bindgen_core::{uwrite, uwriteln, wit_parser::*};
+use wit_component::StringEncoding;
+
+/// The kind of function that a `FunctionBindgen` is generating in host mode.
+pub(crate) enum HostFunc {
+    /// A trampoline which the guest calls to invoke the host's implementation
+    /// of an import.
+    ///
+    /// Trampolines own the arguments they lift for the host and the results
+    /// the host returns, so `free_results` holds the statements which free
+    /// the results once they've been lowered into the guest.
+    Trampoline { free_results: String },
+    /// A wrapper around a guest's export with the core name `name`.
+    Export { name: String, post_return: bool },
+}
+
+/// An import implemented by the host, defined in `*_linker_define`.
+pub(crate) struct HostImport {
+    module: String,
+    name: String,
+    trampoline: String,
+    params: Vec<WasmType>,
+    results: Vec<WasmType>,
+}
+
+/// Returns whether lists of `ty` can be copied in and out of linear memory in
+/// bulk, which is only the case for primitives whose representation in C
+/// matches the canonical ABI.
+pub(crate) fn is_list_canonical(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::U8
+            | Type::S8
+            | Type::U16
+            | Type::S16
+            | Type::U32
+            | Type::S32
+            | Type::U64
+            | Type::S64
+            | Type::F32
+            | Type::F64
+    )
+}
+
+impl C {
+    /// The C type of a pointer into the guest's linear memory.
+    pub(crate) fn host_ptr_type(&self) -> &'static str {
+        if self.opts.wasm64 {
+            "uint64_t"
+        } else {
+            "uint32_t"
+        }
+    }
+
+    pub(crate) fn wasm_type(&self, ty: WasmType) -> &'static str {
+        match ty {
+            WasmType::Pointer | WasmType::Length if self.opts.host => self.host_ptr_type(),
+            ty => crate::wasm_type(ty),
+        }
+    }
+
+    /// Returns the `wasmtime_valkind_t`, the field of `wasmtime_valunion_t`,
+    /// and the `wasm_valkind_t` which represent `ty`.
+    fn host_val(&self, ty: WasmType) -> (&'static str, &'static str, &'static str) {
+        match ty {
+            WasmType::I32 => ("WASMTIME_I32", "i32", "WASM_I32"),
+            WasmType::I64 | WasmType::PointerOrI64 => ("WASMTIME_I64", "i64", "WASM_I64"),
+            WasmType::F32 => ("WASMTIME_F32", "f32", "WASM_F32"),
+            WasmType::F64 => ("WASMTIME_F64", "f64", "WASM_F64"),
+            WasmType::Pointer | WasmType::Length => {
+                if self.opts.wasm64 {
+                    ("WASMTIME_I64", "i64", "WASM_I64")
+                } else {
+                    ("WASMTIME_I32", "i32", "WASM_I32")
+                }
+            }
+        }
+    }
+
+    /// Converts the `wasmtime_val_t` `val` of type `ty` into a C value.
+    fn host_from_val(&self, ty: WasmType, val: &str) -> String {
+        let (_, field, _) = self.host_val(ty);
+        match ty {
+            WasmType::Pointer | WasmType::Length => {
+                format!("({}) {val}.of.{field}", self.host_ptr_type())
+            }
+            _ => format!("{val}.of.{field}"),
+        }
+    }
+
+    /// Stores the C value `op` of type `ty` into the `wasmtime_val_t` `val`.
+    fn host_to_val(&self, ty: WasmType, op: &str, val: &str) -> String {
+        let (kind, field, _) = self.host_val(ty);
+        let cast = match (ty, field) {
+            (WasmType::Pointer | WasmType::Length, "i32") => "(int32_t) ",
+            (WasmType::Pointer | WasmType::Length, _) => "(int64_t) ",
+            _ => "",
+        };
+        format!("{val}.kind = {kind};\n{val}.of.{field} = {cast}{op};\n")
+    }
+
+    /// Emits the instance type, the runtime support that host bindings use to
+    /// access the guest, and the function which defines the host's imports.
+    pub(crate) fn finish_host(&mut self) {
+        let snake = self.world.to_snake_case();
+        let ptr_ty = self.host_ptr_type();
+        let (kind, field, _) = self.host_val(WasmType::Pointer);
+        self.h_include("<wasmtime.h>");
+        self.c_include("<string.h>");
+
+        self.src.h_defs.push_str(&format!(
+            "
+                // An instance of the guest that bindings are called on.
+                //
+                // Exports are looked up through `caller` when it's set, as it is
+                // within the implementation of an import, and `instance`
+                // otherwise. If the guest traps or provides an invalid value then
+                // `trap` is set, and any further calls return zeroed results
+                // until it's taken.
+                typedef struct {snake}_instance_t {{
+                    wasmtime_context_t *context;
+                    wasmtime_instance_t instance;
+                    wasmtime_caller_t *caller;
+                    wasm_trap_t *trap;
+                }} {snake}_instance_t;
+            "
+        ));
+        self.src.h_fns.push_str(&format!(
+            "
+                // Defines the host's implementation of each import in `linker`.
+                //
+                // Arguments passed to an implementation are freed once it returns,
+                // and its results are freed once they're copied into the guest, so
+                // an implementation must copy anything it keeps.
+                wasmtime_error_t *{snake}_linker_define(wasmtime_linker_t *linker);
+            "
+        ));
+
+        self.src.c_defs.push_str(&format!(
+            "
+                // Guest runtime support
+
+                static inline bool {snake}_export({snake}_instance_t *instance, const char *name, wasmtime_extern_t *item) {{
+                    if (instance->caller != NULL) {{
+                        return wasmtime_caller_export_get(instance->caller, name, strlen(name), item);
+                    }}
+                    return wasmtime_instance_export_get(instance->context, &instance->instance, name, strlen(name), item);
+                }}
+
+                static inline void {snake}_trap({snake}_instance_t *instance, const char *message) {{
+                    if (instance->trap == NULL) {{
+                        instance->trap = wasmtime_trap_new(message, strlen(message));
+                    }}
+                }}
+
+                static inline uint8_t *{snake}_memory({snake}_instance_t *instance, size_t *size) {{
+                    wasmtime_extern_t item;
+                    if (!{snake}_export(instance, \"memory\", &item) || item.kind != WASMTIME_EXTERN_MEMORY) {{
+                        {snake}_trap(instance, \"guest does not export a memory\");
+                        *size = 0;
+                        return NULL;
+                    }}
+                    *size = wasmtime_memory_data_size(instance->context, &item.of.memory);
+                    return wasmtime_memory_data(instance->context, &item.of.memory);
+                }}
+
+                static inline bool {snake}_check({snake}_instance_t *instance, uint64_t offset, uint64_t len, uint64_t size) {{
+                    size_t memory_size;
+                    {snake}_memory(instance, &memory_size);
+                    if ((size != 0 && len > UINT64_MAX / size) || offset > memory_size || len * size > memory_size - offset) {{
+                        {snake}_trap(instance, \"out of bounds memory access\");
+                        return false;
+                    }}
+                    return true;
+                }}
+
+                static inline void {snake}_read({snake}_instance_t *instance, void *dst, uint64_t offset, size_t len) {{
+                    if (len == 0) {{
+                        return;
+                    }}
+                    if (!{snake}_check(instance, offset, len, 1)) {{
+                        memset(dst, 0, len);
+                        return;
+                    }}
+                    size_t memory_size;
+                    memcpy(dst, {snake}_memory(instance, &memory_size) + offset, len);
+                }}
+
+                static inline void {snake}_write({snake}_instance_t *instance, uint64_t offset, const void *src, size_t len) {{
+                    if (len == 0 || !{snake}_check(instance, offset, len, 1)) {{
+                        return;
+                    }}
+                    size_t memory_size;
+                    memcpy({snake}_memory(instance, &memory_size) + offset, src, len);
+                }}
+
+                static inline bool {snake}_call({snake}_instance_t *instance, const char *name, const wasmtime_val_t *args, size_t nargs, wasmtime_val_t *results, size_t nresults) {{
+                    if (instance->trap != NULL) {{
+                        return false;
+                    }}
+                    wasmtime_extern_t item;
+                    if (!{snake}_export(instance, name, &item) || item.kind != WASMTIME_EXTERN_FUNC) {{
+                        {snake}_trap(instance, \"guest is missing a function export\");
+                        return false;
+                    }}
+                    wasm_trap_t *trap = NULL;
+                    wasmtime_error_t *error = wasmtime_func_call(instance->context, &item.of.func, args, nargs, results, nresults, &trap);
+                    if (error != NULL) {{
+                        wasm_name_t message;
+                        wasmtime_error_message(error, &message);
+                        instance->trap = wasmtime_trap_new(message.data, message.size);
+                        wasm_name_delete(&message);
+                        wasmtime_error_delete(error);
+                        return false;
+                    }}
+                    if (trap != NULL) {{
+                        instance->trap = trap;
+                        return false;
+                    }}
+                    return true;
+                }}
+
+                static inline {ptr_ty} {snake}_alloc({snake}_instance_t *instance, {ptr_ty} align, {ptr_ty} size) {{
+                    if (size == 0) {{
+                        return align;
+                    }}
+                    wasmtime_val_t args[4];
+                    for (size_t i = 0; i < 4; i++) {{
+                        args[i].kind = {kind};
+                    }}
+                    args[0].of.{field} = 0;
+                    args[1].of.{field} = 0;
+                    args[2].of.{field} = align;
+                    args[3].of.{field} = size;
+                    wasmtime_val_t ret;
+                    if (!{snake}_call(instance, \"cabi_realloc\", args, 4, &ret, 1)) {{
+                        return 0;
+                    }}
+                    return ({ptr_ty}) ret.of.{field};
+                }}
+            "
+        ));
+
+        uwrite!(
+            self.src.c_adapters,
+            "\nwasmtime_error_t *{snake}_linker_define(wasmtime_linker_t *linker) {{\n"
+        );
+        if self.host_imports.is_empty() {
+            uwriteln!(self.src.c_adapters, "(void) linker;");
+        } else {
+            self.src.c_defs.push_str(&format!(
+                "
+                    static inline wasm_functype_t *{snake}_functype(const wasm_valkind_t *params, size_t nparams, const wasm_valkind_t *results, size_t nresults) {{
+                        wasm_valtype_vec_t param_types, result_types;
+                        wasm_valtype_vec_new_uninitialized(&param_types, nparams);
+                        for (size_t i = 0; i < nparams; i++) {{
+                            param_types.data[i] = wasm_valtype_new(params[i]);
+                        }}
+                        wasm_valtype_vec_new_uninitialized(&result_types, nresults);
+                        for (size_t i = 0; i < nresults; i++) {{
+                            result_types.data[i] = wasm_valtype_new(results[i]);
+                        }}
+                        return wasm_functype_new(&param_types, &result_types);
+                    }}
+                "
+            ));
+        }
+        for import in std::mem::take(&mut self.host_imports) {
+            let HostImport {
+                module,
+                name,
+                trampoline,
+                params,
+                results,
+            } = import;
+            uwriteln!(self.src.c_adapters, "{{");
+            let mut kinds = Vec::new();
+            for (name, tys) in [("params", &params), ("results", &results)] {
+                if tys.is_empty() {
+                    kinds.push("NULL".to_string());
+                    continue;
+                }
+                let valkinds = tys
+                    .iter()
+                    .map(|ty| self.host_val(*ty).2)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                uwriteln!(
+                    self.src.c_adapters,
+                    "wasm_valkind_t {name}[] = {{ {valkinds} }};"
+                );
+                kinds.push(name.to_string());
+            }
+            let (param_kinds, result_kinds) = (&kinds[0], &kinds[1]);
+            self.src.c_adapters.push_str(&format!(
+                "wasm_functype_t *ty = {snake}_functype({param_kinds}, {}, {result_kinds}, {});
+                    wasmtime_error_t *error = wasmtime_linker_define_func(linker, \"{module}\", {}, \"{name}\", {}, ty, {trampoline}, NULL, NULL);
+                    wasm_functype_delete(ty);
+                    if (error != NULL) {{
+                        return error;
+                    }}
+                }}
+                ",
+                params.len(),
+                results.len(),
+                module.len(),
+                name.len(),
+            ));
+        }
+        uwriteln!(self.src.c_adapters, "return NULL;\n}}");
+    }
+}
+
+impl InterfaceGenerator<'_> {
+    /// Generates the prototype of a function which the host implements for
+    /// the guest's import, and the trampoline which calls it.
+    pub(crate) fn host_import(&mut self, interface_name: Option<&WorldKey>, func: &Function) {
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+        let snake = self.gen.world.to_snake_case();
+
+        self.docs(&func.docs, SourceType::HFns);
+        let c_sig = self.print_sig(interface_name, func, !self.gen.opts.no_sig_flattening);
+        let trampoline = self.gen.names.tmp(&format!("__wasm_host_{}", c_sig.name));
+
+        self.src.c_adapters.push_str(&format!(
+            "
+                static wasm_trap_t *{trampoline}(void *env, wasmtime_caller_t *caller, const wasmtime_val_t *args, size_t nargs, wasmtime_val_t *results, size_t nresults) {{
+                    (void) env;
+                    (void) args;
+                    (void) nargs;
+                    (void) results;
+                    (void) nresults;
+                    {snake}_instance_t instance_;
+                    memset(&instance_, 0, sizeof(instance_));
+                    instance_.context = wasmtime_caller_context(caller);
+                    instance_.caller = caller;
+                    {snake}_instance_t *instance = &instance_;
+            "
+        ));
+
+        let mut f = FunctionBindgen::new(self, c_sig, &trampoline);
+        f.host = Some(HostFunc::Trampoline {
+            free_results: String::new(),
+        });
+        for name in [
+            "env",
+            "caller",
+            "args",
+            "nargs",
+            "results",
+            "nresults",
+            "instance_",
+            "instance",
+        ] {
+            f.locals.insert(name).unwrap();
+        }
+        for (i, ty) in sig.params.iter().enumerate() {
+            let param = f.gen.gen.host_from_val(*ty, &format!("args[{i}]"));
+            f.params.push(param);
+        }
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+        );
+        let FunctionBindgen { src, .. } = f;
+        self.src.c_adapters(&src);
+        self.src.c_adapters("}\n");
+
+        self.gen.host_imports.push(HostImport {
+            module: match interface_name {
+                Some(name) => self.resolve.name_world_key(name),
+                None => "$root".to_string(),
+            },
+            name: func.name.clone(),
+            trampoline,
+            params: sig.params,
+            results: sig.results,
+        });
+    }
+
+    /// Generates a function which the host calls to invoke the guest's
+    /// export.
+    pub(crate) fn host_export(&mut self, func: &Function, interface_name: Option<&WorldKey>) {
+        let core_module_name = interface_name.map(|s| self.resolve.name_world_key(s));
+        let export_name = func.core_export_name(core_module_name.as_deref());
+
+        self.docs(&func.docs, SourceType::HFns);
+        let c_sig = self.print_sig(interface_name, func, !self.gen.opts.no_sig_flattening);
+        self.src.c_adapters("\n");
+        self.src.c_adapters(&c_sig.sig);
+        self.src.c_adapters(" {\n");
+
+        let optional_adapters = self.optional_adapters(&c_sig, func);
+        let post_return = abi::guest_export_needs_post_return(self.resolve, func);
+        let mut f = FunctionBindgen::new(self, c_sig, "");
+        f.host = Some(HostFunc::Export {
+            name: export_name.into_owned(),
+            post_return,
+        });
+        f.locals.insert("instance").unwrap();
+        for (pointer, param) in f.sig.params.iter() {
+            f.locals.insert(param).unwrap();
+            if *pointer {
+                f.params.push(format!("*{}", param));
+            } else {
+                f.params.push(param.clone());
+            }
+        }
+        for ptr in f.sig.retptrs.iter() {
+            f.locals.insert(ptr).unwrap();
+        }
+        f.src.push_str(&optional_adapters);
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+        );
+        let FunctionBindgen { src, .. } = f;
+        self.src.c_adapters(&src);
+        self.src.c_adapters("}\n");
+    }
+}
+
+impl FunctionBindgen<'_, '_> {
+    /// Emits `inst` if host bindings need to handle it differently from guest
+    /// bindings, returning whether it was emitted.
+    pub(crate) fn emit_host(
+        &mut self,
+        inst: &Instruction<'_>,
+        operands: &[String],
+        results: &mut Vec<String>,
+    ) -> bool {
+        let snake = self.gen.gen.world.to_snake_case();
+        let ptr_ty = self.gen.gen.host_ptr_type();
+        match inst {
+            Instruction::I32Load { offset } => {
+                self.host_load("int32_t", *offset, operands, results)
+            }
+            Instruction::I64Load { offset } => {
+                self.host_load("int64_t", *offset, operands, results)
+            }
+            Instruction::F32Load { offset } => self.host_load("float", *offset, operands, results),
+            Instruction::F64Load { offset } => self.host_load("double", *offset, operands, results),
+            Instruction::PointerLoad { offset } | Instruction::LengthLoad { offset } => {
+                self.host_load(ptr_ty, *offset, operands, results)
+            }
+            Instruction::I32Load8U { offset } => {
+                self.host_load("uint8_t", *offset, operands, results);
+                let result = results.pop().unwrap();
+                results.push(format!("(int32_t) {result}"));
+            }
+            Instruction::I32Load8S { offset } => {
+                self.host_load("int8_t", *offset, operands, results);
+                let result = results.pop().unwrap();
+                results.push(format!("(int32_t) {result}"));
+            }
+            Instruction::I32Load16U { offset } => {
+                self.host_load("uint16_t", *offset, operands, results);
+                let result = results.pop().unwrap();
+                results.push(format!("(int32_t) {result}"));
+            }
+            Instruction::I32Load16S { offset } => {
+                self.host_load("int16_t", *offset, operands, results);
+                let result = results.pop().unwrap();
+                results.push(format!("(int32_t) {result}"));
+            }
+            Instruction::I32Store { offset } => self.host_store("int32_t", *offset, operands),
+            Instruction::I64Store { offset } => self.host_store("int64_t", *offset, operands),
+            Instruction::F32Store { offset } => self.host_store("float", *offset, operands),
+            Instruction::F64Store { offset } => self.host_store("double", *offset, operands),
+            Instruction::I32Store8 { offset } => self.host_store("int8_t", *offset, operands),
+            Instruction::I32Store16 { offset } => self.host_store("int16_t", *offset, operands),
+            Instruction::PointerStore { offset } | Instruction::LengthStore { offset } => {
+                self.host_store(ptr_ty, *offset, operands)
+            }
+
+            Instruction::Malloc { size, align, .. } => {
+                let ptr = self.locals.tmp("ptr");
+                uwriteln!(
+                    self.src,
+                    "{ptr_ty} {ptr} = {snake}_alloc(instance, {align}, {size});"
+                );
+                results.push(ptr);
+            }
+
+            Instruction::StringLower { .. } => {
+                let size = self.host_char_size();
+                self.host_list_canon_lower(size, size, operands, results);
+            }
+            Instruction::ListCanonLower { element, .. } => {
+                let size = self.gen.gen.sizes.size(element);
+                let align = self.gen.gen.sizes.align(element);
+                self.host_list_canon_lower(size, align, operands, results);
+            }
+            Instruction::StringLift => {
+                let list_name = self.gen.gen.type_name(&Type::String);
+                let elem_name = self.gen.gen.char_type();
+                let size = self.host_char_size();
+                self.host_list_canon_lift(&list_name, elem_name, size, operands, results);
+            }
+            Instruction::ListCanonLift { element, ty, .. } => {
+                let list_name = self.gen.gen.type_name(&Type::Id(*ty));
+                let elem_name = self.gen.gen.type_name(element);
+                let size = self.gen.gen.sizes.size(element);
+                self.host_list_canon_lift(&list_name, &elem_name, size, operands, results);
+            }
+
+            Instruction::ListLower { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.is_empty());
+                let size = self.gen.gen.sizes.size(element);
+                let align = self.gen.gen.sizes.align(element);
+                let elem_name = self.gen.gen.type_name(element);
+                let data = self.locals.tmp("data");
+                let len = self.locals.tmp("len");
+                let ptr = self.locals.tmp("ptr");
+                let i = self.locals.tmp("i");
+                let op = &operands[0];
+                uwriteln!(self.src, "{elem_name} *{data} = ({op}).ptr;");
+                uwriteln!(self.src, "size_t {len} = ({op}).len;");
+                uwriteln!(
+                    self.src,
+                    "{ptr_ty} {ptr} = {snake}_alloc(instance, {align}, {len} * {size});"
+                );
+                uwriteln!(self.src, "for (size_t {i} = 0; {i} < {len}; {i}++) {{");
+                uwriteln!(self.src, "{elem_name} e = {data}[{i}];");
+                uwriteln!(self.src, "{ptr_ty} base = {ptr} + {i} * {size};");
+                uwriteln!(self.src, "(void) e;");
+                uwriteln!(self.src, "(void) base;");
+                uwrite!(self.src, "{body}");
+                uwriteln!(self.src, "}}");
+                results.push(ptr);
+                results.push(len);
+            }
+            Instruction::ListLift { element, ty, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert_eq!(body_results.len(), 1);
+                let size = self.gen.gen.sizes.size(element);
+                let list_name = self.gen.gen.type_name(&Type::Id(*ty));
+                let elem_name = self.gen.gen.type_name(element);
+                let ptr = self.locals.tmp("ptr");
+                let len = self.locals.tmp("len");
+                let data = self.locals.tmp("data");
+                let i = self.locals.tmp("i");
+                self.host_list_lift_start(&ptr, &len, &data, &elem_name, size, operands);
+                uwriteln!(self.src, "for (size_t {i} = 0; {i} < {len}; {i}++) {{");
+                uwriteln!(self.src, "{ptr_ty} base = {ptr} + {i} * {size};");
+                uwriteln!(self.src, "(void) base;");
+                uwrite!(self.src, "{body}");
+                uwriteln!(self.src, "{data}[{i}] = {};", body_results[0]);
+                uwriteln!(self.src, "}}");
+                results.push(format!("({list_name}) {{ {data}, {len} }}"));
+            }
+
+            Instruction::CallWasm { sig, .. } => {
+                let Some(HostFunc::Export { name, .. }) = &self.host else {
+                    unreachable!()
+                };
+                let name = name.clone();
+                let args = self.host_vals("args", &sig.params, operands);
+                let rets = if sig.results.is_empty() {
+                    "NULL".to_string()
+                } else {
+                    let rets = self.locals.tmp("rets");
+                    uwriteln!(self.src, "wasmtime_val_t {rets}[{}];", sig.results.len());
+                    rets
+                };
+                let early_return = self.host_early_return();
+                self.src.push_str(&format!(
+                    "if (!{snake}_call(instance, \"{name}\", {args}, {}, {rets}, {})) {{
+                        {early_return}
+                    }}\n",
+                    sig.params.len(),
+                    sig.results.len(),
+                ));
+                for (i, ty) in sig.results.iter().enumerate() {
+                    results.push(self.gen.gen.host_from_val(*ty, &format!("{rets}[{i}]")));
+                }
+                if !sig.results.is_empty() {
+                    self.wasm_return = Some(rets);
+                }
+            }
+
+            Instruction::Return { func, .. } => match &self.host {
+                Some(HostFunc::Trampoline { free_results }) => {
+                    let free_results = free_results.clone();
+                    let sig = self
+                        .gen
+                        .resolve
+                        .wasm_signature(AbiVariant::GuestImport, func);
+                    for (i, (op, ty)) in operands.iter().zip(&sig.results).enumerate() {
+                        let store = self.gen.gen.host_to_val(*ty, op, &format!("results[{i}]"));
+                        self.src.push_str(&store);
+                    }
+                    self.src.push_str(&free_results);
+                    uwriteln!(self.src, "return instance->trap;");
+                }
+                Some(HostFunc::Export { name, post_return }) => {
+                    if *post_return {
+                        let (rets, nrets) = match &self.wasm_return {
+                            Some(rets) => (rets.clone(), 1),
+                            None => ("NULL".to_string(), 0),
+                        };
+                        uwriteln!(
+                            self.src,
+                            "{snake}_call(instance, \"cabi_post_{name}\", {rets}, {nrets}, NULL, 0);"
+                        );
+                    }
+                    self.return_values(operands);
+                }
+                None => unreachable!(),
+            },
+
+            Instruction::GuestDeallocate { .. }
+            | Instruction::GuestDeallocateString
+            | Instruction::GuestDeallocateList { .. }
+            | Instruction::GuestDeallocateVariant { .. } => {
+                unreachable!("host bindings don't deallocate guest memory")
+            }
+
+            // These types are rejected up front by `C::unsupported`.
+            Instruction::HandleLower { .. }
+            | Instruction::HandleLift { .. }
+            | Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("resources, futures, and streams in host bindings")
+            }
+
+            _ => return false,
+        }
+        true
+    }
+
+    fn host_char_size(&self) -> usize {
+        match self.gen.gen.opts.string_encoding {
+            StringEncoding::UTF8 => 1,
+            StringEncoding::UTF16 => 2,
+            // Rejected up front by `C::preprocess`.
+            StringEncoding::CompactUTF16 => unreachable!(),
+        }
+    }
+
+    /// Zeroes the lifted value `name` in host bindings, so that it's still
+    /// valid to free if the guest gave an invalid discriminant for it.
+    pub(crate) fn host_zero(&mut self, name: &str) {
+        if self.host.is_some() {
+            uwriteln!(self.src, "memset(&{name}, 0, sizeof({name}));");
+        }
+    }
+
+    /// Starts a trampoline's call to the host's implementation of an import,
+    /// given the arguments it lifted, returning the statements which free them
+    /// once the call returns.
+    pub(crate) fn host_call_start(&mut self, lifted: &[(Type, String)]) -> String {
+        let free_args = lifted
+            .iter()
+            .map(|(ty, arg)| self.host_free(ty, arg))
+            .collect::<String>();
+        // Lifting arguments may have trapped, in which case the host's
+        // implementation isn't called.
+        uwriteln!(
+            self.src,
+            "if (instance->trap != NULL) {{
+                {free_args}return instance->trap;
+            }}"
+        );
+        free_args
+    }
+
+    /// Finishes a trampoline's call to the host's implementation of `func`,
+    /// which returned `results`.
+    pub(crate) fn host_call_finish(
+        &mut self,
+        func: &Function,
+        results: &[String],
+        free_args: &str,
+    ) {
+        self.src.push_str(free_args);
+        let frees = func
+            .results
+            .iter_types()
+            .zip(results)
+            .map(|(ty, result)| self.host_free(ty, result))
+            .collect::<String>();
+        if let Some(HostFunc::Trampoline { free_results }) = &mut self.host {
+            *free_results = frees;
+        }
+    }
+
+    /// Returns the statement which frees the host's copy of `value`, of type
+    /// `ty`, if it owns any memory.
+    fn host_free(&self, ty: &Type, value: &str) -> String {
+        let dtor = match ty {
+            Type::String => format!("{}_string_free", self.gen.gen.world.to_snake_case()),
+            Type::Id(id) => match self.gen.gen.dtor_funcs.get(id) {
+                Some(dtor) => dtor.clone(),
+                None => return String::new(),
+            },
+            _ => return String::new(),
+        };
+        format!("{dtor}(&{value});\n")
+    }
+
+    fn host_load(&mut self, ty: &str, offset: i32, operands: &[String], results: &mut Vec<String>) {
+        let snake = self.gen.gen.world.to_snake_case();
+        let tmp = self.locals.tmp("load");
+        uwriteln!(self.src, "{ty} {tmp};");
+        uwriteln!(
+            self.src,
+            "{snake}_read(instance, &{tmp}, {} + {offset}, sizeof({tmp}));",
+            operands[0]
+        );
+        results.push(tmp);
+    }
+
+    fn host_store(&mut self, ty: &str, offset: i32, operands: &[String]) {
+        let snake = self.gen.gen.world.to_snake_case();
+        let tmp = self.locals.tmp("store");
+        uwriteln!(self.src, "{ty} {tmp} = ({ty}) ({});", operands[0]);
+        uwriteln!(
+            self.src,
+            "{snake}_write(instance, {} + {offset}, &{tmp}, sizeof({tmp}));",
+            operands[1]
+        );
+    }
+
+    /// Copies a list of `size`-byte elements into memory allocated in the
+    /// guest.
+    fn host_list_canon_lower(
+        &mut self,
+        size: usize,
+        align: usize,
+        operands: &[String],
+        results: &mut Vec<String>,
+    ) {
+        let snake = self.gen.gen.world.to_snake_case();
+        let ptr_ty = self.gen.gen.host_ptr_type();
+        let len = self.locals.tmp("len");
+        let ptr = self.locals.tmp("ptr");
+        let op = &operands[0];
+        self.src.push_str(&format!(
+            "size_t {len} = ({op}).len;
+                {ptr_ty} {ptr} = {snake}_alloc(instance, {align}, {len} * {size});
+                {snake}_write(instance, {ptr}, ({op}).ptr, {len} * {size});
+            "
+        ));
+        results.push(ptr);
+        results.push(len);
+    }
+
+    /// Copies a list of `size`-byte elements out of the guest into memory
+    /// allocated with `malloc`.
+    fn host_list_canon_lift(
+        &mut self,
+        list_name: &str,
+        elem_name: &str,
+        size: usize,
+        operands: &[String],
+        results: &mut Vec<String>,
+    ) {
+        let snake = self.gen.gen.world.to_snake_case();
+        let ptr = self.locals.tmp("ptr");
+        let len = self.locals.tmp("len");
+        let data = self.locals.tmp("data");
+        self.host_list_lift_start(&ptr, &len, &data, elem_name, size, operands);
+        uwriteln!(
+            self.src,
+            "{snake}_read(instance, {data}, {ptr}, {len} * {size});"
+        );
+        results.push(format!("({list_name}) {{ {data}, {len} }}"));
+    }
+
+    /// Declares the pointer and length of a list being lifted, after checking
+    /// that it's in bounds, and allocates host memory for its elements.
+    fn host_list_lift_start(
+        &mut self,
+        ptr: &str,
+        len: &str,
+        data: &str,
+        elem_name: &str,
+        size: usize,
+        operands: &[String],
+    ) {
+        let snake = self.gen.gen.world.to_snake_case();
+        let ptr_ty = self.gen.gen.host_ptr_type();
+        self.src.push_str(&format!(
+            "{ptr_ty} {ptr} = {};
+                size_t {len} = {};
+                if (!{snake}_check(instance, {ptr}, {len}, {size})) {{
+                    {len} = 0;
+                }}
+                {elem_name} *{data} = NULL;
+                if ({len} > 0) {{
+                    {data} = ({elem_name} *) malloc({len} * sizeof({elem_name}));
+                    if (!{data}) abort();
+                }}
+            ",
+            operands[0], operands[1],
+        ));
+    }
+
+    /// Declares an array of `wasmtime_val_t` named after `name` holding
+    /// `operands`, returning its name.
+    fn host_vals(&mut self, name: &str, tys: &[WasmType], operands: &[String]) -> String {
+        if tys.is_empty() {
+            return "NULL".to_string();
+        }
+        let vals = self.locals.tmp(name);
+        uwriteln!(self.src, "wasmtime_val_t {vals}[{}];", tys.len());
+        for (i, (ty, op)) in tys.iter().zip(operands).enumerate() {
+            let store = self.gen.gen.host_to_val(*ty, op, &format!("{vals}[{i}]"));
+            self.src.push_str(&store);
+        }
+        vals
+    }
+
+    /// Returns the statement which returns a zeroed result when calling the
+    /// guest fails.
+    fn host_early_return(&mut self) -> String {
+        match &self.sig.ret.scalar {
+            None | Some(Scalar::Void) => "return;".to_string(),
+            Some(Scalar::OptionBool(_)) | Some(Scalar::ResultBool(..)) => {
+                "return false;".to_string()
+            }
+            Some(Scalar::Type(ty)) => {
+                let ty = self.gen.gen.type_name(ty);
+                let zero = self.locals.tmp("zero");
+                format!("{ty} {zero};\nmemset(&{zero}, 0, sizeof({zero}));\nreturn {zero};")
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod component_type_object;
+mod host;
 
 use anyhow::Result;
 use heck::*;
@@ -7,14 +8,15 @@ use std::fmt::Write;
 use std::mem;
 use wit_bindgen_core::abi::{self, AbiVariant, Bindgen, Bitcast, Instruction, LiftLower, WasmType};
 use wit_bindgen_core::{
-    dealias, uwrite, uwriteln, wit_parser::*, AnonymousTypeGenerator, Direction, Files,
-    InterfaceGenerator as _, Ns, WorldGenerator,
+    dealias, unsupported, uwrite, uwriteln, wit_parser::*, AnonymousTypeGenerator, Direction,
+    Files, InterfaceGenerator as _, Ns, Origin, SourceMap, WorldGenerator,
 };
 use wit_component::StringEncoding;
 
 #[derive(Default)]
 struct C {
     src: Source,
+    source_map: SourceMap,
     opts: Opts,
     h_includes: Vec<String>,
     c_includes: Vec<String>,
@@ -35,6 +37,7 @@ struct C {
     dtor_funcs: HashMap<TypeId, String>,
     type_names: HashMap<TypeId, String>,
     resources: HashMap<TypeId, ResourceInfo>,
+    host_imports: Vec<host::HostImport>,
 }
 
 #[derive(Default)]
@@ -99,6 +102,22 @@ pub struct Opts {
     /// Configure the autodropping of borrows in exported functions.
     #[cfg_attr(feature = "clap", arg(long, default_value_t = Enabled::default()))]
     pub autodrop_borrows: Enabled,
+
+    /// Generate bindings for a 64-bit linear memory, such as for the
+    /// `wasm64-unknown-unknown` target.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub wasm64: bool,
+
+    /// Generate host-side bindings which embed a guest through wasmtime's C
+    /// API, rather than bindings for the guest itself.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub host: bool,
+
+    /// Emit `<world>.h.wit-map.json` and `<world>.c.wit-map.json` files
+    /// alongside the bindings which map their lines back to the WIT items they
+    /// were generated from.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub source_map: bool,
 }
 
 #[cfg(feature = "clap")]
@@ -115,6 +134,10 @@ impl Opts {
     pub fn build(&self) -> Box<dyn WorldGenerator> {
         let mut r = C::default();
         r.opts = self.clone();
+        r.source_map = SourceMap::new(self.source_map);
+        if r.opts.wasm64 {
+            r.sizes = SizeAlign::new(AddressSize::Wasm64);
+        }
         Box::new(r)
     }
 }
@@ -142,7 +165,10 @@ enum Scalar {
 }
 
 impl WorldGenerator for C {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        if self.opts.host && matches!(self.opts.string_encoding, StringEncoding::CompactUTF16) {
+            anyhow::bail!("host bindings don't support the `compact-utf-16` string encoding");
+        }
         self.world = self
             .opts
             .rename_world
@@ -168,6 +194,7 @@ impl WorldGenerator for C {
                 }
             }
         }
+        Ok(())
     }
 
     fn import_interface(
@@ -176,10 +203,12 @@ impl WorldGenerator for C {
         name: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &self.unsupported())?;
         let wasm_import_module = resolve.name_world_key(name);
         let mut gen = self.interface(resolve, true, Some(&wasm_import_module));
         gen.interface = Some((id, name));
+        gen.begin_origin(Origin::interface(resolve, name, id));
         gen.define_interface_types(id);
 
         for (i, (_name, func)) in resolve.interfaces[id].functions.iter().enumerate() {
@@ -188,10 +217,18 @@ impl WorldGenerator for C {
                 uwriteln!(gen.src.h_fns, "\n// Imported Functions from `{name}`");
                 uwriteln!(gen.src.c_fns, "\n// Imported Functions from `{name}`");
             }
-            gen.import(Some(name), func);
+            gen.begin_origin(Origin::interface(resolve, name, id).func(&func.name));
+            if gen.gen.opts.host {
+                gen.host_import(Some(name), func);
+            } else {
+                gen.import(Some(name), func);
+            }
+            gen.end_origin();
         }
 
+        gen.end_origin();
         gen.gen.src.append(&gen.src);
+        Ok(())
     }
 
     fn import_funcs(
@@ -200,7 +237,8 @@ impl WorldGenerator for C {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &self.unsupported())?;
         let name = &resolve.worlds[world].name;
         let mut gen = self.interface(resolve, true, Some("$root"));
         gen.define_function_types(funcs);
@@ -210,10 +248,17 @@ impl WorldGenerator for C {
                 uwriteln!(gen.src.h_fns, "\n// Imported Functions from `{name}`");
                 uwriteln!(gen.src.c_fns, "\n// Imported Functions from `{name}`");
             }
-            gen.import(None, func);
+            gen.begin_origin(Origin::world(resolve, world).func(&func.name));
+            if gen.gen.opts.host {
+                gen.host_import(None, func);
+            } else {
+                gen.import(None, func);
+            }
+            gen.end_origin();
         }
 
         gen.gen.src.append(&gen.src);
+        Ok(())
     }
 
     fn export_interface(
@@ -223,8 +268,10 @@ impl WorldGenerator for C {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &self.unsupported())?;
         let mut gen = self.interface(resolve, false, None);
         gen.interface = Some((id, name));
+        gen.begin_origin(Origin::interface(resolve, name, id));
         gen.define_interface_types(id);
 
         for (i, (_name, func)) in resolve.interfaces[id].functions.iter().enumerate() {
@@ -233,9 +280,16 @@ impl WorldGenerator for C {
                 uwriteln!(gen.src.h_fns, "\n// Exported Functions from `{name}`");
                 uwriteln!(gen.src.c_fns, "\n// Exported Functions from `{name}`");
             }
-            gen.export(func, Some(name));
+            gen.begin_origin(Origin::interface(resolve, name, id).func(&func.name));
+            if gen.gen.opts.host {
+                gen.host_export(func, Some(name));
+            } else {
+                gen.export(func, Some(name));
+            }
+            gen.end_origin();
         }
 
+        gen.end_origin();
         gen.gen.src.append(&gen.src);
         Ok(())
     }
@@ -247,6 +301,7 @@ impl WorldGenerator for C {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &self.unsupported())?;
         let name = &resolve.worlds[world].name;
         let mut gen = self.interface(resolve, false, None);
         gen.define_function_types(funcs);
@@ -256,7 +311,13 @@ impl WorldGenerator for C {
                 uwriteln!(gen.src.h_fns, "\n// Exported Functions from `{name}`");
                 uwriteln!(gen.src.c_fns, "\n// Exported Functions from `{name}`");
             }
-            gen.export(func, None);
+            gen.begin_origin(Origin::world(resolve, world).func(&func.name));
+            if gen.gen.opts.host {
+                gen.host_export(func, None);
+            } else {
+                gen.export(func, None);
+            }
+            gen.end_origin();
         }
 
         gen.gen.src.append(&gen.src);
@@ -266,10 +327,11 @@ impl WorldGenerator for C {
     fn import_types(
         &mut self,
         resolve: &Resolve,
-        _world: WorldId,
+        world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, types, &[], &self.unsupported())?;
         let mut gen = self.interface(resolve, true, Some("$root"));
         let mut live = LiveTypes::default();
         for (_, id) in types {
@@ -277,27 +339,32 @@ impl WorldGenerator for C {
         }
         gen.define_live_types(live);
         gen.gen.src.append(&gen.src);
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
-        let linking_symbol = component_type_object::linking_symbol(&self.world);
         self.c_include("<stdlib.h>");
         let snake = self.world.to_snake_case();
-        uwriteln!(
-            self.src.c_adapters,
-            "\n// Ensure that the *_component_type.o object is linked in"
-        );
-        uwrite!(
-            self.src.c_adapters,
-            "
-               extern void {linking_symbol}(void);
-               void {linking_symbol}_public_use_in_this_compilation_unit(void) {{
-                   {linking_symbol}();
-               }}
-           ",
-        );
+        if self.opts.host {
+            self.finish_host();
+        } else {
+            let linking_symbol = component_type_object::linking_symbol(&self.world);
+            uwriteln!(
+                self.src.c_adapters,
+                "\n// Ensure that the *_component_type.o object is linked in"
+            );
+            uwrite!(
+                self.src.c_adapters,
+                "
+                   extern void {linking_symbol}(void);
+                   void {linking_symbol}_public_use_in_this_compilation_unit(void) {{
+                       {linking_symbol}();
+                   }}
+               ",
+            );
 
-        self.print_intrinsics();
+            self.print_intrinsics();
+        }
 
         if self.needs_string {
             self.c_include("<string.h>");
@@ -326,6 +393,13 @@ impl WorldGenerator for C {
                 StringEncoding::CompactUTF16 => unimplemented!(),
             };
             let ty = self.char_type();
+            // Host bindings own their strings in the host's memory rather
+            // than a guest's.
+            let alloc = if self.opts.host {
+                format!("malloc(ret->len * {size})")
+            } else {
+                format!("cabi_realloc(NULL, 0, {size}, ret->len * {size})")
+            };
             let c_string_ty = match self.opts.string_encoding {
                 StringEncoding::UTF8 => "char",
                 StringEncoding::UTF16 => "char16_t",
@@ -356,7 +430,7 @@ impl WorldGenerator for C {
 
                    void {snake}_string_dup({snake}_string_t *ret, const {c_string_ty} *s) {{
                        ret->len = {strlen};
-                       ret->ptr = ({ty}*) cabi_realloc(NULL, 0, {size}, ret->len * {size});
+                       ret->ptr = ({ty}*) {alloc};
                        memcpy(ret->ptr, s, ret->len * {size});
                    }}
 
@@ -483,9 +557,9 @@ impl WorldGenerator for C {
             #endif"
         );
 
-        files.push(&format!("{snake}.h"), h_str.as_bytes());
-        files.push(&format!("{snake}.c"), c_str.as_bytes());
-        if !self.opts.no_object_file {
+        files.push_mapped(&format!("{snake}.h"), &h_str, &self.source_map);
+        files.push_mapped(&format!("{snake}.c"), &c_str, &self.source_map);
+        if !self.opts.no_object_file && !self.opts.host {
             files.push(
                 &format!("{snake}_component_type.o",),
                 component_type_object::object(
@@ -494,6 +568,7 @@ impl WorldGenerator for C {
                     &self.world,
                     self.opts.string_encoding,
                     self.opts.type_section_suffix.as_deref(),
+                    self.opts.wasm64,
                 )
                 .unwrap()
                 .as_slice(),
@@ -526,6 +601,16 @@ impl C {
         }
     }
 
+    /// Describes the types which bindings can't yet be generated for.
+    fn unsupported(&self) -> impl Fn(&Resolve, &TypeDefKind) -> Option<&'static str> {
+        let host = self.opts.host;
+        move |resolve, kind| match kind {
+            TypeDefKind::Resource if host => Some("resources in host bindings"),
+            TypeDefKind::Flags(f) if f.flags.len() > 64 => Some("flags with more than 64 members"),
+            _ => unsupported::futures_and_streams(resolve, kind),
+        }
+    }
+
     fn h_include(&mut self, s: &str) {
         self.h_includes.push(s.to_string());
     }
@@ -601,6 +686,14 @@ impl C {
 
     fn perform_cast(&mut self, op: &str, cast: &Bitcast) -> String {
         match cast {
+            // Host bindings represent guest pointers as offsets into the
+            // guest's linear memory.
+            Bitcast::P64ToP | Bitcast::I32ToP | Bitcast::LToP if self.opts.host => {
+                format!("({}) {}", self.host_ptr_type(), op)
+            }
+            Bitcast::PToI32 if self.opts.host => format!("(int32_t) {}", op),
+            Bitcast::PToL if self.opts.host => op.to_string(),
+
             Bitcast::I32ToF32 | Bitcast::I64ToF32 => {
                 self.needs_union_int32_float = true;
                 format!("((union int32_float){{ (int32_t) {} }}).b", op)
@@ -620,9 +713,12 @@ impl C {
             Bitcast::I32ToI64 | Bitcast::LToI64 | Bitcast::PToP64 => {
                 format!("(int64_t) {}", op)
             }
-            Bitcast::I64ToI32 | Bitcast::I64ToL => {
+            Bitcast::I64ToI32 => {
                 format!("(int32_t) {}", op)
             }
+            Bitcast::I64ToL => {
+                format!("(size_t) {}", op)
+            }
             // P64 is currently represented as int64_t, so no conversion is needed.
             Bitcast::I64ToP64 | Bitcast::P64ToI64 => {
                 format!("{}", op)
@@ -660,6 +756,7 @@ pub fn imported_types_used_by_exported_interfaces(
                 exported_interfaces.insert(*i);
                 live_export_types.add_interface(resolve, *i)
             }
+            // Rejected up front by `check_world_exports`.
             WorldItem::Type(_) => unreachable!(),
         }
     }
@@ -987,8 +1084,9 @@ impl Return {
             | TypeDefKind::List(_)
             | TypeDefKind::Variant(_) => {}
 
-            TypeDefKind::Future(_) => todo!("return_single for future"),
-            TypeDefKind::Stream(_) => todo!("return_single for stream"),
+            // Rejected up front by `C::unsupported`.
+            TypeDefKind::Future(_) => unreachable!("return_single for future"),
+            TypeDefKind::Stream(_) => unreachable!("return_single for stream"),
             TypeDefKind::Resource => todo!("return_single for resource"),
             TypeDefKind::Unknown => unreachable!(),
         }
@@ -1017,6 +1115,8 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
     }
 
     fn type_resource(&mut self, id: TypeId, name: &str, _docs: &Docs) {
+        // Host bindings reject resources up front, see `C::unsupported`.
+        assert!(!self.gen.opts.host);
         let ns = self.owner_namespace(id);
         let snake = name.to_snake_case();
         let mut own = ns.clone();
@@ -1042,7 +1142,8 @@ extern void {ns}_{snake}_drop_own({own} handle);
         } else {
             let module = match self.interface {
                 Some((_, key)) => self.resolve.name_world_key(key),
-                None => unimplemented!("resource exports from worlds"),
+                // Rejected up front by `check_world_exports`.
+                None => unreachable!("resource exports from worlds"),
             };
             format!("[export]{module}")
         };
@@ -1420,16 +1521,18 @@ impl<'a> wit_bindgen_core::AnonymousTypeGenerator<'a> for InterfaceGenerator<'a>
         self.print_typedef_target(id);
     }
 
-    fn anonymous_type_future(&mut self, id: TypeId, ty: &Option<Type>, docs: &Docs) {
-        todo!("print_anonymous_type for future");
+    // Futures and streams are rejected up front by `C::unsupported`.
+    fn anonymous_type_future(&mut self, _id: TypeId, _ty: &Option<Type>, _docs: &Docs) {
+        unreachable!("print_anonymous_type for future");
     }
 
-    fn anonymous_type_stream(&mut self, id: TypeId, ty: &Stream, docs: &Docs) {
-        todo!("print_anonymous_type for stream");
+    fn anonymous_type_stream(&mut self, _id: TypeId, _ty: &Stream, _docs: &Docs) {
+        unreachable!("print_anonymous_type for stream");
     }
 
-    fn anonymous_typ_type(&mut self, id: TypeId, ty: &Type, docs: &Docs) {
-        todo!("print_anonymous_type for typ");
+    // Type aliases are always named.
+    fn anonymous_typ_type(&mut self, _id: TypeId, _ty: &Type, _docs: &Docs) {
+        unreachable!("print_anonymous_type for typ");
     }
 }
 
@@ -1457,6 +1560,16 @@ pub fn gen_type_name(resolve: &Resolve, ty: TypeId) -> (CTypeNameInfo<'_>, Strin
 }
 
 impl InterfaceGenerator<'_> {
+    /// Marks the code generated from now until `end_origin` as generated from
+    /// `origin` in the source map.
+    fn begin_origin(&mut self, origin: Origin) {
+        self.src.begin_origin(&mut self.gen.source_map, origin);
+    }
+
+    fn end_origin(&mut self) {
+        self.src.end_origin(&self.gen.source_map);
+    }
+
     fn define_interface_types(&mut self, id: InterfaceId) {
         let mut live = LiveTypes::default();
         live.add_interface(self.resolve, id);
@@ -1484,7 +1597,16 @@ impl InterfaceGenerator<'_> {
                     let prev = self.gen.type_names.insert(ty, typedef_name.clone());
                     assert!(prev.is_none());
 
-                    self.define_type(name, ty)
+                    let origin = Origin::of_type(self.resolve, ty);
+                    if let Some(origin) = origin.clone() {
+                        self.begin_origin(origin);
+                    }
+                    self.define_type(name, ty);
+                    self.define_dtor(ty);
+                    if origin.is_some() {
+                        self.end_origin();
+                    }
+                    continue;
                 }
 
                 CTypeNameInfo::Anonymous { is_prim } => {
@@ -1600,8 +1722,9 @@ impl InterfaceGenerator<'_> {
                 }
                 self.src.c_helpers("}\n");
             }
-            TypeDefKind::Future(_) => todo!("print_dtor for future"),
-            TypeDefKind::Stream(_) => todo!("print_dtor for stream"),
+            // Rejected up front by `C::unsupported`.
+            TypeDefKind::Future(_) => unreachable!("print_dtor for future"),
+            TypeDefKind::Stream(_) => unreachable!("print_dtor for stream"),
             TypeDefKind::Resource => {}
             TypeDefKind::Handle(Handle::Borrow(id) | Handle::Own(id)) => {
                 self.free(&Type::Id(*id), "*ptr");
@@ -1703,30 +1826,7 @@ impl InterfaceGenerator<'_> {
         self.src.c_adapters(&c_sig.sig);
         self.src.c_adapters(" {\n");
 
-        // construct optional adapters from maybe pointers to real optional
-        // structs internally
-        let mut optional_adapters = String::from("");
-        if !self.gen.opts.no_sig_flattening {
-            for (i, (_, param)) in c_sig.params.iter().enumerate() {
-                let ty = &func.params[i].1;
-                if let Type::Id(id) = ty {
-                    if let TypeDefKind::Option(_) = &self.resolve.types[*id].kind {
-                        let ty = self.gen.type_name(ty);
-                        uwrite!(
-                            optional_adapters,
-                            "{ty} {param};
-                            {param}.is_some = maybe_{param} != NULL;"
-                        );
-                        uwriteln!(
-                            optional_adapters,
-                            "if (maybe_{param}) {{
-                                {param}.val = *maybe_{param};
-                            }}",
-                        );
-                    }
-                }
-            }
-        }
+        let optional_adapters = self.optional_adapters(&c_sig, func);
 
         let mut f = FunctionBindgen::new(self, c_sig, &import_name);
         for (pointer, param) in f.sig.params.iter() {
@@ -1769,6 +1869,34 @@ impl InterfaceGenerator<'_> {
         self.src.c_adapters("}\n");
     }
 
+    /// Constructs optional adapters from maybe pointers to real optional
+    /// structs internally.
+    fn optional_adapters(&mut self, c_sig: &CSig, func: &Function) -> String {
+        let mut optional_adapters = String::from("");
+        if !self.gen.opts.no_sig_flattening {
+            for (i, (_, param)) in c_sig.params.iter().enumerate() {
+                let ty = &func.params[i].1;
+                if let Type::Id(id) = ty {
+                    if let TypeDefKind::Option(_) = &self.resolve.types[*id].kind {
+                        let ty = self.gen.type_name(ty);
+                        uwrite!(
+                            optional_adapters,
+                            "{ty} {param};
+                            {param}.is_some = maybe_{param} != NULL;"
+                        );
+                        uwriteln!(
+                            optional_adapters,
+                            "if (maybe_{param}) {{
+                                {param}.val = *maybe_{param};
+                            }}",
+                        );
+                    }
+                }
+            }
+        }
+        optional_adapters
+    }
+
     fn export(&mut self, func: &Function, interface_name: Option<&WorldKey>) {
         let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
 
@@ -1883,9 +2011,17 @@ impl InterfaceGenerator<'_> {
         self.src.h_fns(" ");
         self.src.h_fns(&name);
         self.src.h_fns("(");
+        let host = self.gen.opts.host;
+        if host {
+            uwrite!(
+                self.src.h_fns,
+                "{}_instance_t *instance",
+                self.gen.world.to_snake_case()
+            );
+        }
         let mut params = Vec::new();
         for (i, (name, ty)) in func.params.iter().enumerate() {
-            if i > 0 {
+            if i > 0 || host {
                 self.src.h_fns(", ");
             }
             let pointer = is_arg_by_pointer(self.resolve, ty);
@@ -1923,7 +2059,7 @@ impl InterfaceGenerator<'_> {
         let mut retptrs = Vec::new();
         let single_ret = ret.retptrs.len() == 1;
         for (i, ty) in ret.retptrs.iter().enumerate() {
-            if i > 0 || func.params.len() > 0 {
+            if i > 0 || func.params.len() > 0 || host {
                 self.src.h_fns(", ");
             }
             self.print_ty(SourceType::HFns, ty);
@@ -1943,7 +2079,7 @@ impl InterfaceGenerator<'_> {
             self.src.h_fns(&name);
             retptrs.push(name);
         }
-        if func.params.len() == 0 && ret.retptrs.len() == 0 {
+        if func.params.len() == 0 && ret.retptrs.len() == 0 && !host {
             self.src.h_fns("void");
         }
         self.src.h_fns(")");
@@ -2121,6 +2257,10 @@ struct FunctionBindgen<'a, 'b> {
 
     /// Forward declarations for temporary storage of borrow copies.
     borrow_decls: wit_bindgen_core::Source,
+
+    /// Set when generating host bindings, in which case guest memory is
+    /// only accessible through the instance.
+    host: Option<host::HostFunc>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
@@ -2145,6 +2285,7 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             import_return_pointer_area_align: 0,
             borrow_decls: Default::default(),
             borrows: Vec::new(),
+            host: None,
         }
     }
 
@@ -2184,10 +2325,57 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
         self.ret_store_cnt = self.ret_store_cnt + 1;
     }
 
-    fn empty_return_value(&mut self) {
-        // Empty types have no state, so we don't emit stores for them. But we
-        // do need to keep track of which return variable we're looking at.
-        self.ret_store_cnt = self.ret_store_cnt + 1;
+    /// Returns lowered `operands` through the signature of the C function,
+    /// storing anything which isn't returned directly into its return
+    /// pointers.
+    fn return_values(&mut self, operands: &[String]) {
+        match self.sig.ret.scalar {
+            None => {
+                for op in operands.iter() {
+                    self.store_in_retptr(op);
+                }
+            }
+            Some(Scalar::Void) => {
+                assert!(operands.is_empty());
+            }
+            Some(Scalar::Type(_)) => {
+                assert_eq!(operands.len(), 1);
+                self.src.push_str("return ");
+                self.src.push_str(&operands[0]);
+                self.src.push_str(";\n");
+            }
+            Some(Scalar::OptionBool(_)) => {
+                assert_eq!(operands.len(), 1);
+                let variant = &operands[0];
+                self.store_in_retptr(&format!("{}.val", variant));
+                self.src.push_str("return ");
+                self.src.push_str(&variant);
+                self.src.push_str(".is_some;\n");
+            }
+            Some(Scalar::ResultBool(ok, err)) => {
+                assert_eq!(operands.len(), 1);
+                let variant = &operands[0];
+                assert!(self.sig.retptrs.len() <= 2);
+                uwriteln!(self.src, "if (!{}.is_err) {{", variant);
+                if ok.is_some() {
+                    self.store_in_retptr(&format!("{}.val.ok", variant));
+                }
+                uwriteln!(
+                    self.src,
+                    "   return 1;
+                    }} else {{"
+                );
+                if err.is_some() {
+                    self.store_in_retptr(&format!("{}.val.err", variant));
+                }
+                uwriteln!(
+                    self.src,
+                    "   return 0;
+                    }}"
+                );
+                assert_eq!(self.ret_store_cnt, self.sig.retptrs.len());
+            }
+        }
     }
 
     fn assert_no_droppable_borrows(&self, context: &str, ty: &Type) {
@@ -2243,6 +2431,9 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     }
 
     fn is_list_canonical(&self, resolve: &Resolve, ty: &Type) -> bool {
+        if self.host.is_some() {
+            return host::is_list_canonical(ty);
+        }
         resolve.all_bits_valid(ty)
     }
 
@@ -2253,6 +2444,9 @@ impl Bindgen for FunctionBindgen<'_, '_> {
         operands: &mut Vec<String>,
         results: &mut Vec<String>,
     ) {
+        if self.host.is_some() && self.emit_host(inst, operands, results) {
+            return;
+        }
         match inst {
             Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
             Instruction::I32Const { val } => results.push(val.to_string()),
@@ -2437,7 +2631,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 for ty in result_types.iter() {
                     let name = self.locals.tmp("variant");
                     results.push(name.clone());
-                    self.src.push_str(wasm_type(*ty));
+                    self.src.push_str(self.gen.gen.wasm_type(*ty));
                     self.src.push_str(" ");
                     self.src.push_str(&name);
                     self.src.push_str(";\n");
@@ -2517,7 +2711,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 for (i, ty) in result_types.iter().enumerate() {
                     let name = self.locals.tmp("option");
                     results.push(name.clone());
-                    self.src.push_str(wasm_type(*ty));
+                    self.src.push_str(self.gen.gen.wasm_type(*ty));
                     self.src.push_str(" ");
                     self.src.push_str(&name);
                     self.src.push_str(";\n");
@@ -2552,6 +2746,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 let ty = self.gen.gen.type_name(&Type::Id(*ty));
                 let result = self.locals.tmp("option");
                 uwriteln!(self.src, "{ty} {result};");
+                self.host_zero(&result);
                 let op0 = &operands[0];
                 let set_some = format!("{result}.val = {some_result};\n");
                 if none.len() > 0 {
@@ -2592,7 +2787,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 for (i, ty) in result_types.iter().enumerate() {
                     let name = self.locals.tmp("result");
                     results.push(name.clone());
-                    self.src.push_str(wasm_type(*ty));
+                    self.src.push_str(self.gen.gen.wasm_type(*ty));
                     self.src.push_str(" ");
                     self.src.push_str(&name);
                     self.src.push_str(";\n");
@@ -2658,6 +2853,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
                 let ty = self.gen.gen.type_name(&Type::Id(*ty));
                 uwriteln!(self.src, "{ty} {result_tmp};");
+                self.host_zero(&result_tmp);
                 let op0 = &operands[0];
                 uwriteln!(
                     self.src,
@@ -2750,23 +2946,31 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.src.push_str(");\n");
             }
 
-            Instruction::CallInterface { func } => {
+            Instruction::CallInterface { func, .. } => {
                 let mut args = String::new();
+                if self.host.is_some() {
+                    args.push_str("instance");
+                }
+                // The arguments which were lifted into values the callee is
+                // given a pointer to.
+                let mut lifted = Vec::new();
                 for (i, (op, (byref, _))) in operands.iter().zip(&self.sig.params).enumerate() {
-                    if i > 0 {
+                    if !args.is_empty() {
                         args.push_str(", ");
                     }
                     let ty = &func.params[i].1;
                     if *byref {
                         let name = self.locals.tmp("arg");
+                        lifted.push((*ty, name.clone()));
                         let ty = self.gen.gen.type_name(ty);
                         uwriteln!(self.src, "{} {} = {};", ty, name, op);
                         args.push_str("&");
                         args.push_str(&name);
                     } else {
-                        if !self.gen.in_import {
+                        if !self.gen.in_import || self.host.is_some() {
                             if let Type::Id(id) = ty {
                                 if let TypeDefKind::Option(_) = &self.gen.resolve.types[*id].kind {
+                                    lifted.push((*ty, op.clone()));
                                     uwrite!(args, "{op}.is_some ? &({op}.val) : NULL");
                                     continue;
                                 }
@@ -2775,6 +2979,12 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         args.push_str(op);
                     }
                 }
+                let trampoline = matches!(self.host, Some(host::HostFunc::Trampoline { .. }));
+                let free_args = if trampoline {
+                    self.host_call_start(&lifted)
+                } else {
+                    String::new()
+                };
                 match &self.sig.ret.scalar {
                     None => {
                         let mut retptrs = Vec::new();
@@ -2782,7 +2992,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                             let name = self.locals.tmp("ret");
                             let ty = self.gen.gen.type_name(ty);
                             uwriteln!(self.src, "{} {};", ty, name);
-                            if args.len() > 0 {
+                            if !args.is_empty() {
                                 args.push_str(", ");
                             }
                             args.push_str("&");
@@ -2805,7 +3015,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     Some(Scalar::OptionBool(ty)) => {
                         let ret = self.locals.tmp("ret");
                         let val = self.locals.tmp("val");
-                        if args.len() > 0 {
+                        if !args.is_empty() {
                             args.push_str(", ");
                         }
                         args.push_str("&");
@@ -2835,7 +3045,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         let ok_name = if ok.is_some() {
                             if let Some(ty) = ret_iter.next() {
                                 let val = self.locals.tmp("ok");
-                                if args.len() > 0 {
+                                if !args.is_empty() {
                                     uwrite!(args, ", ");
                                 }
                                 uwrite!(args, "&{val}");
@@ -2850,7 +3060,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         };
                         let err_name = if let Some(ty) = ret_iter.next() {
                             let val = self.locals.tmp("err");
-                            if args.len() > 0 {
+                            if !args.is_empty() {
                                 uwrite!(args, ", ")
                             }
                             uwrite!(args, "&{val}");
@@ -2888,62 +3098,11 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         results.push(ret);
                     }
                 }
-            }
-            Instruction::Return { .. } if self.gen.in_import => match self.sig.ret.scalar {
-                None => {
-                    for op in operands.iter() {
-                        self.store_in_retptr(op);
-                    }
-                }
-                Some(Scalar::Void) => {
-                    assert!(operands.is_empty());
-                }
-                Some(Scalar::Type(_)) => {
-                    assert_eq!(operands.len(), 1);
-                    self.src.push_str("return ");
-                    self.src.push_str(&operands[0]);
-                    self.src.push_str(";\n");
-                }
-                Some(Scalar::OptionBool(_)) => {
-                    assert_eq!(operands.len(), 1);
-                    let variant = &operands[0];
-                    self.store_in_retptr(&format!("{}.val", variant));
-                    self.src.push_str("return ");
-                    self.src.push_str(&variant);
-                    self.src.push_str(".is_some;\n");
+                if trampoline {
+                    self.host_call_finish(func, results, &free_args);
                 }
-                Some(Scalar::ResultBool(ok, err)) => {
-                    assert_eq!(operands.len(), 1);
-                    let variant = &operands[0];
-                    assert!(self.sig.retptrs.len() <= 2);
-                    uwriteln!(self.src, "if (!{}.is_err) {{", variant);
-                    if ok.is_some() {
-                        if ok.is_some() {
-                            self.store_in_retptr(&format!("{}.val.ok", variant));
-                        } else {
-                            self.empty_return_value();
-                        }
-                    }
-                    uwriteln!(
-                        self.src,
-                        "   return 1;
-                            }} else {{"
-                    );
-                    if err.is_some() {
-                        if err.is_some() {
-                            self.store_in_retptr(&format!("{}.val.err", variant));
-                        } else {
-                            self.empty_return_value();
-                        }
-                    }
-                    uwriteln!(
-                        self.src,
-                        "   return 0;
-                            }}"
-                    );
-                    assert_eq!(self.ret_store_cnt, self.sig.retptrs.len());
-                }
-            },
+            }
+            Instruction::Return { .. } if self.gen.in_import => self.return_values(operands),
             Instruction::Return { amt, .. } => {
                 // Emit all temporary borrow decls
                 let src = std::mem::replace(&mut self.src, std::mem::take(&mut self.borrow_decls));
@@ -3069,6 +3228,27 @@ impl Source {
             SourceType::HFns => &mut self.h_fns,
         }
     }
+    fn all_mut(&mut self) -> [&mut wit_bindgen_core::Source; 7] {
+        [
+            &mut self.h_defs,
+            &mut self.h_fns,
+            &mut self.h_helpers,
+            &mut self.c_defs,
+            &mut self.c_fns,
+            &mut self.c_helpers,
+            &mut self.c_adapters,
+        ]
+    }
+    fn begin_origin(&mut self, map: &mut SourceMap, origin: Origin) {
+        for src in self.all_mut() {
+            map.begin(src.as_mut_string(), origin.clone());
+        }
+    }
+    fn end_origin(&mut self, map: &SourceMap) {
+        for src in self.all_mut() {
+            map.end(src.as_mut_string());
+        }
+    }
     fn append(&mut self, append_src: &Source) {
         self.h_defs.push_str(&append_src.h_defs);
         self.h_fns.push_str(&append_src.h_fns);
@@ -3150,8 +3330,9 @@ pub fn is_arg_by_pointer(resolve: &Resolve, ty: &Type) -> bool {
             TypeDefKind::Flags(_) => false,
             TypeDefKind::Handle(_) => false,
             TypeDefKind::Tuple(_) | TypeDefKind::Record(_) | TypeDefKind::List(_) => true,
-            TypeDefKind::Future(_) => todo!("is_arg_by_pointer for future"),
-            TypeDefKind::Stream(_) => todo!("is_arg_by_pointer for stream"),
+            // Rejected up front by `C::unsupported`.
+            TypeDefKind::Future(_) => unreachable!("is_arg_by_pointer for future"),
+            TypeDefKind::Stream(_) => unreachable!("is_arg_by_pointer for stream"),
             TypeDefKind::Resource => todo!("is_arg_by_pointer for resource"),
             TypeDefKind::Unknown => unreachable!(),
         },
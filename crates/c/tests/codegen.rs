@@ -146,3 +146,209 @@ void exports_rename2_f() {}
     verify(&dir, "rename-option");
     Ok(())
 }
+
+#[test]
+fn wasm64_layout() -> Result<()> {
+    let opts = wit_bindgen_c::Opts {
+        wasm64: true,
+        ..Default::default()
+    };
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push(UnresolvedPackage::parse(
+        "input.wit".as_ref(),
+        r#"
+            package foo:bar;
+
+            world wasm64 {
+                record r {
+                    a: u8,
+                    b: string,
+                    c: list<u32>,
+                }
+
+                import f: func(x: string) -> r;
+            }
+        "#,
+    )?)?;
+    let world = resolve.select_world(pkg, None)?;
+    let mut files = Default::default();
+    opts.build().generate(&resolve, world, &mut files)?;
+    let (_, src) = files.iter().find(|(name, _)| *name == "wasm64.c").unwrap();
+    let src = std::str::from_utf8(src)?;
+
+    // Pointers and lengths are 8 bytes each, making the record 40 bytes.
+    assert!(src.contains("uint8_t ret_area[40];"));
+    assert!(src.contains("*((uint8_t **) (ptr + 8))"));
+    assert!(src.contains("*((size_t*) (ptr + 16))"));
+    assert!(src.contains("*((uint8_t **) (ptr + 24))"));
+    assert!(src.contains("*((size_t*) (ptr + 32))"));
+
+    // The object file declares a 64-bit memory so it can be linked with the
+    // rest of a wasm64 module.
+    let (_, object) = files
+        .iter()
+        .find(|(name, _)| *name == "wasm64_component_type.o")
+        .unwrap();
+    let mut memories = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(object) {
+        if let wasmparser::Payload::ImportSection(imports) = payload? {
+            for import in imports {
+                let import = import?;
+                if let wasmparser::TypeRef::Memory(ty) = import.ty {
+                    memories.push((import.module, import.name, ty.memory64));
+                }
+            }
+        }
+    }
+    assert_eq!(memories, [("env", "__linear_memory", true)]);
+    Ok(())
+}
+
+#[test]
+fn host_bindings() -> Result<()> {
+    let opts = wit_bindgen_c::Opts {
+        host: true,
+        ..Default::default()
+    };
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push(UnresolvedPackage::parse(
+        "input.wit".as_ref(),
+        r#"
+            package foo:bar;
+
+            world host {
+                import log: func(msg: string);
+                export run: func(args: list<string>) -> u32;
+            }
+        "#,
+    )?)?;
+    let world = resolve.select_world(pkg, None)?;
+    let mut files = Default::default();
+    opts.build().generate(&resolve, world, &mut files)?;
+    assert!(!files.iter().any(|(name, _)| name.ends_with(".o")));
+    let (_, h) = files.iter().find(|(name, _)| *name == "host.h").unwrap();
+    let h = std::str::from_utf8(h)?;
+    let (_, src) = files.iter().find(|(name, _)| *name == "host.c").unwrap();
+    let src = std::str::from_utf8(src)?;
+
+    // The host implements imports and calls exports, passing the instance.
+    assert!(h.contains("#include <wasmtime.h>"));
+    assert!(h.contains("void host_log(host_instance_t *instance, host_string_t *msg);"));
+    assert!(h.contains("uint32_t host_run(host_instance_t *instance, host_list_string_t *args);"));
+    assert!(h.contains("wasmtime_error_t *host_linker_define(wasmtime_linker_t *linker);"));
+
+    // Imports are defined through a trampoline with the core signature.
+    assert!(src.contains("wasm_valkind_t params[] = { WASM_I32, WASM_I32 };"));
+    assert!(src.contains("\"$root\", 5, \"log\", 3"));
+    assert!(src.contains("host_log(instance, &arg);\n  host_string_free(&arg);"));
+
+    // Exports lower their arguments into memory allocated by the guest.
+    assert!(src.contains("host_call(instance, \"run\", "));
+    assert!(src.contains("host_alloc(instance, 4, "));
+    assert!(!src.contains("cabi_realloc(NULL"));
+    assert!(!src.contains("__export_name__"));
+
+    // Host bindings are compiled for the host against wasmtime's C API, when
+    // it's available.
+    if let Some(c_api) = env::var_os("WASMTIME_C_API") {
+        let dir = test_helpers::test_directory("codegen", "host-c", "host-bindings");
+        for (file, contents) in files.iter() {
+            std::fs::write(dir.join(file), contents)?;
+        }
+        let cc = env::var_os("CC").unwrap_or("cc".into());
+        let mut cmd = Command::new(cc);
+        cmd.arg("-I").arg(Path::new(&c_api).join("include"));
+        cmd.args([
+            "-Wall",
+            "-Wextra",
+            "-Werror",
+            "-Wno-unused-parameter",
+            "-c",
+            "-o",
+        ]);
+        cmd.arg(dir.join("host.o"));
+        cmd.arg(dir.join("host.c"));
+        test_helpers::run_command(&mut cmd);
+    }
+
+    // Compact UTF-16 isn't supported on the host.
+    let opts = wit_bindgen_c::Opts {
+        string_encoding: wit_component::StringEncoding::CompactUTF16,
+        ..opts
+    };
+    let err = opts
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "host bindings don't support the `compact-utf-16` string encoding"
+    );
+    Ok(())
+}
+
+#[test]
+fn unsupported_types() -> Result<()> {
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push(UnresolvedPackage::parse(
+        "input.wit".as_ref(),
+        r#"
+            package foo:bar;
+
+            interface events {
+                subscribe: func() -> stream<u32>;
+            }
+
+            world guest {
+                import events;
+            }
+        "#,
+    )?)?;
+    let world = resolve.select_world(pkg, None)?;
+    let err = wit_bindgen_c::Opts::default()
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    let err = err.downcast::<wit_bindgen_core::Unsupported>()?;
+    assert_eq!(
+        err.to_string(),
+        "streams are not supported by this generator: \
+         function `subscribe` in interface `foo:bar/events` uses them"
+    );
+
+    // Host bindings don't support resources yet.
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push(UnresolvedPackage::parse(
+        "input.wit".as_ref(),
+        r#"
+            package foo:bar;
+
+            interface files {
+                resource file;
+                open: func() -> file;
+            }
+
+            world guest {
+                import files;
+            }
+        "#,
+    )?)?;
+    let world = resolve.select_world(pkg, None)?;
+    let opts = wit_bindgen_c::Opts {
+        host: true,
+        ..Default::default()
+    };
+    let err = opts
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    let err = err.downcast::<wit_bindgen_core::Unsupported>()?;
+    assert_eq!(
+        err.to_string(),
+        "resources in host bindings are not supported by this generator: \
+         type `file` in interface `foo:bar/files` uses them"
+    );
+    Ok(())
+}
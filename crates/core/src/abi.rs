@@ -1,9 +1,12 @@
 pub use wit_parser::abi::{AbiVariant, WasmSignature, WasmType};
 use wit_parser::{
     Enum, Flags, FlagsRepr, Function, Handle, Int, Record, Resolve, Result_, Results, SizeAlign,
-    Tuple, Type, TypeDefKind, TypeId, Variant,
+    Stream, Tuple, Type, TypeDefKind, TypeId, Variant,
 };
 
+pub mod interpreter;
+pub mod ir;
+
 // Helper macro for defining instructions without having to have tons of
 // exhaustive `match` statements to update
 macro_rules! def_instruction {
@@ -33,6 +36,15 @@ macro_rules! def_instruction {
         }
 
         impl $name<'_> {
+            /// Returns the name of this instruction, such as `"I32Load"`.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        Self::$variant { .. } => stringify!($variant),
+                    )*
+                }
+            }
+
             /// How many operands does this instruction pop from the stack?
             #[allow(unused_variables)]
             pub fn operands_len(&self) -> usize {
@@ -65,7 +77,7 @@ macro_rules! def_instruction {
 }
 
 def_instruction! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub enum Instruction<'a> {
         /// Acquires the specified parameter and places it on the stack.
         /// Depending on the context this may refer to wasm parameters or
@@ -350,6 +362,30 @@ def_instruction! {
             ty: TypeId,
         } : [1] => [1],
 
+        /// Create an `i32` from a future.
+        FutureLower {
+            payload: &'a Option<Type>,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create a future from an `i32`.
+        FutureLift {
+            payload: &'a Option<Type>,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create an `i32` from a stream.
+        StreamLower {
+            stream: &'a Stream,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create a stream from an `i32`.
+        StreamLift {
+            stream: &'a Stream,
+            ty: TypeId,
+        } : [1] => [1],
+
         /// Pops a tuple value off the stack, decomposes the tuple to all of
         /// its fields, and then pushes the fields onto the stack.
         TupleLower {
@@ -464,13 +500,43 @@ def_instruction! {
             sig: &'a WasmSignature,
         } : [sig.params.len()] => [sig.results.len()],
 
+        /// Represents a call to a raw WebAssembly API using the async
+        /// canonical ABI.
+        ///
+        /// Pops a pointer to memory which holds the lowered parameters at
+        /// offset 0 and has room for the results at `results_offset`. Once
+        /// the call has completed the results are read out of that memory.
+        AsyncCallWasm {
+            name: &'a str,
+            results_offset: usize,
+        } : [1] => [0],
+
         /// Same as `CallWasm`, except the dual where an interface is being
         /// called rather than a raw wasm function.
         ///
-        /// Note that this will be used for async functions.
+        /// When `async_` is set the interface returns a single future which
+        /// is consumed by `AsyncPostCallInterface`.
         CallInterface {
             func: &'a Function,
-        } : [func.params.len()] => [func.results.len()],
+            async_: bool,
+        } : [func.params.len()] => [if *async_ { 1 } else { func.results.len() }],
+
+        /// Pops the future returned by an async `CallInterface` and arranges
+        /// for it to be driven to completion.
+        ///
+        /// Pushes the callback code to return from the export followed by the
+        /// results of the future, which are lowered and handed to
+        /// `AsyncCallReturn` once the future has resolved.
+        AsyncPostCallInterface {
+            func: &'a Function,
+        } : [1] => [func.results.len() + 1],
+
+        /// Delivers the lowered results of an async export to the host by
+        /// calling the `task.return` intrinsic of `name` with `params`.
+        AsyncCallReturn {
+            name: &'a str,
+            params: &'a [WasmType],
+        } : [params.len()] => [0],
 
         /// Returns `amt` values on the stack. This is always the last
         /// instruction.
@@ -522,7 +588,7 @@ def_instruction! {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Bitcast {
     // Upcasts
     F32ToI32,
@@ -684,7 +750,73 @@ pub fn call(
     func: &Function,
     bindgen: &mut impl Bindgen,
 ) {
-    Generator::new(resolve, variant, lift_lower, bindgen).call(func);
+    Generator::new(resolve, variant, lift_lower, bindgen, false).call(func);
+}
+
+/// Same as [`call`] except that the callback-based async canonical ABI is
+/// used for `func` instead of the synchronous one.
+pub fn call_async(
+    resolve: &Resolve,
+    variant: AbiVariant,
+    lift_lower: LiftLower,
+    func: &Function,
+    bindgen: &mut impl Bindgen,
+) {
+    Generator::new(resolve, variant, lift_lower, bindgen, true).call(func);
+}
+
+/// Returns the core wasm signature of `func`, taking into account whether
+/// the async canonical ABI is in use.
+///
+/// Async imports take a pointer to their parameters and a pointer to space
+/// for their results, returning a status code. Async exports take their
+/// parameters as usual but return a callback code, with results delivered
+/// through the `task.return` intrinsic instead.
+pub fn wasm_signature(
+    resolve: &Resolve,
+    variant: AbiVariant,
+    func: &Function,
+    async_: bool,
+) -> WasmSignature {
+    let mut sig = resolve.wasm_signature(variant, func);
+    if !async_ {
+        return sig;
+    }
+    match variant {
+        AbiVariant::GuestImport => {
+            sig.params = vec![WasmType::Pointer, WasmType::Pointer];
+            sig.indirect_params = true;
+            sig.retptr = true;
+        }
+        AbiVariant::GuestExport => {
+            sig.retptr = false;
+        }
+    }
+    sig.results = vec![WasmType::I32];
+    sig
+}
+
+/// Returns the core wasm parameters of the `task.return` intrinsic used to
+/// deliver the results of an async export `func`.
+pub fn task_return_params(resolve: &Resolve, func: &Function) -> Vec<WasmType> {
+    let mut params = Vec::new();
+    for ty in func.results.iter_types() {
+        resolve.push_flat(ty, &mut params);
+    }
+    if params.len() > MAX_FLAT_PARAMS {
+        params = vec![WasmType::Pointer];
+    }
+    params
+}
+
+const MAX_FLAT_PARAMS: usize = 16;
+
+/// Returns the size of a pointer in linear memory laid out by `sizes`, which
+/// is also the size of a list's length and its offset from the pointer.
+pub fn pointer_size(sizes: &SizeAlign) -> usize {
+    // `SizeAlign` doesn't expose the address size it was created with, but a
+    // string is always a pointer/length pair.
+    sizes.size(&Type::String) / 2
 }
 
 /// Used in a similar manner as the `Interface::call` function except is
@@ -699,9 +831,75 @@ pub fn post_return(resolve: &Resolve, func: &Function, bindgen: &mut impl Bindge
         AbiVariant::GuestExport,
         LiftLower::LiftArgsLowerResults,
         bindgen,
+        false,
     )
     .post_return(func);
 }
+
+/// Generates instructions to store the operand `value` of type `ty` into
+/// memory at `address`.
+///
+/// Any lists within `value` are lowered with `cabi_realloc`, so ownership of
+/// them is transferred to the memory. This is used by guest generators to
+/// pass the payloads of futures and streams.
+pub fn lower_to_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    value: B::Operand,
+    ty: &Type,
+) {
+    let mut generator = Generator::new(
+        resolve,
+        AbiVariant::GuestExport,
+        LiftLower::LiftArgsLowerResults,
+        bindgen,
+        false,
+    );
+    generator.stack.push(value);
+    generator.write_to_memory(ty, address, 0);
+}
+
+/// Generates instructions to load a value of type `ty` from memory at
+/// `address`, returning the operand for it.
+///
+/// This is the dual of `lower_to_memory`.
+pub fn lift_from_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    ty: &Type,
+) -> B::Operand {
+    let mut generator = Generator::new(
+        resolve,
+        AbiVariant::GuestImport,
+        LiftLower::LowerArgsLiftResults,
+        bindgen,
+        false,
+    );
+    generator.read_from_memory(ty, address, 0);
+    generator.stack.pop().unwrap()
+}
+
+/// Generates instructions to deallocate any lists stored in memory at
+/// `address` for a value of type `ty`, such as those stored by
+/// `lower_to_memory`.
+pub fn deallocate_lists_in_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    ty: &Type,
+) {
+    let mut generator = Generator::new(
+        resolve,
+        AbiVariant::GuestExport,
+        LiftLower::LiftArgsLowerResults,
+        bindgen,
+        false,
+    );
+    generator.deallocate(ty, address, 0);
+}
+
 /// Returns whether the `Function` specified needs a post-return function to
 /// be generated in guest code.
 ///
@@ -721,6 +919,9 @@ fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
             TypeDefKind::Type(t) => needs_post_return(resolve, t),
             TypeDefKind::Handle(_) => false,
             TypeDefKind::Resource => false,
+            // Futures and streams are passed as handles, so ownership of the
+            // underlying value is transferred along with the handle.
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => false,
             TypeDefKind::Record(r) => r.fields.iter().any(|f| needs_post_return(resolve, &f.ty)),
             TypeDefKind::Tuple(t) => t.types.iter().any(|t| needs_post_return(resolve, t)),
             TypeDefKind::Variant(t) => t
@@ -734,7 +935,6 @@ fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
                 .filter_map(|t| t.as_ref())
                 .any(|t| needs_post_return(resolve, t)),
             TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => false,
-            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => unimplemented!(),
             TypeDefKind::Unknown => unreachable!(),
         },
 
@@ -753,24 +953,99 @@ fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
     }
 }
 
-struct Generator<'a, B: Bindgen> {
+/// The interface through which `Generator` emits instructions.
+///
+/// This is implemented for every `Bindgen`, and additionally by the recorder
+/// in the `ir` module which needs to retain the instructions it's given.
+trait Sink<'a> {
+    type Operand: Clone;
+
+    fn emit(
+        &mut self,
+        resolve: &'a Resolve,
+        inst: &Instruction<'a>,
+        operands: &mut Vec<Self::Operand>,
+        results: &mut Vec<Self::Operand>,
+    );
+    /// Same as `emit`, but `inst` only lives for the duration of the call.
+    fn emit_local(
+        &mut self,
+        resolve: &'a Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<Self::Operand>,
+        results: &mut Vec<Self::Operand>,
+    );
+    fn return_pointer(&mut self, size: usize, align: usize) -> Self::Operand;
+    fn push_block(&mut self);
+    fn finish_block(&mut self, operand: &mut Vec<Self::Operand>);
+    fn sizes(&self) -> &SizeAlign;
+    fn is_list_canonical(&self, resolve: &Resolve, element: &Type) -> bool;
+}
+
+impl<'a, B: Bindgen> Sink<'a> for B {
+    type Operand = B::Operand;
+
+    fn emit(
+        &mut self,
+        resolve: &'a Resolve,
+        inst: &Instruction<'a>,
+        operands: &mut Vec<Self::Operand>,
+        results: &mut Vec<Self::Operand>,
+    ) {
+        Bindgen::emit(self, resolve, inst, operands, results)
+    }
+
+    fn emit_local(
+        &mut self,
+        resolve: &'a Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<Self::Operand>,
+        results: &mut Vec<Self::Operand>,
+    ) {
+        Bindgen::emit(self, resolve, inst, operands, results)
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> Self::Operand {
+        Bindgen::return_pointer(self, size, align)
+    }
+
+    fn push_block(&mut self) {
+        Bindgen::push_block(self)
+    }
+
+    fn finish_block(&mut self, operand: &mut Vec<Self::Operand>) {
+        Bindgen::finish_block(self, operand)
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        Bindgen::sizes(self)
+    }
+
+    fn is_list_canonical(&self, resolve: &Resolve, element: &Type) -> bool {
+        Bindgen::is_list_canonical(self, resolve, element)
+    }
+}
+
+struct Generator<'a, 'g, B: Sink<'a>> {
     variant: AbiVariant,
     lift_lower: LiftLower,
-    bindgen: &'a mut B,
+    bindgen: &'g mut B,
     resolve: &'a Resolve,
     operands: Vec<B::Operand>,
     results: Vec<B::Operand>,
     stack: Vec<B::Operand>,
     return_pointer: Option<B::Operand>,
+    async_: bool,
 }
 
-impl<'a, B: Bindgen> Generator<'a, B> {
+impl<'a, 'g, B: Sink<'a>> Generator<'a, 'g, B> {
     fn new(
         resolve: &'a Resolve,
         variant: AbiVariant,
         lift_lower: LiftLower,
-        bindgen: &'a mut B,
-    ) -> Generator<'a, B> {
+        bindgen: &'g mut B,
+        async_: bool,
+    ) -> Generator<'a, 'g, B> {
         Generator {
             resolve,
             variant,
@@ -780,10 +1055,16 @@ impl<'a, B: Bindgen> Generator<'a, B> {
             results: Vec::new(),
             stack: Vec::new(),
             return_pointer: None,
+            async_,
         }
     }
 
-    fn call(&mut self, func: &Function) {
+    fn call(&mut self, func: &'a Function) {
+        if self.async_ {
+            self.call_async(func);
+            return;
+        }
+
         let sig = self.resolve.wasm_signature(self.variant, func);
 
         match self.lift_lower {
@@ -842,7 +1123,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 // Now that all the wasm args are prepared we can call the
                 // actual wasm function.
                 assert_eq!(self.stack.len(), sig.params.len());
-                self.emit(&Instruction::CallWasm {
+                self.emit_local(&Instruction::CallWasm {
                     name: &func.name,
                     sig: &sig,
                 });
@@ -911,7 +1192,10 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 }
 
                 // ... and that allows us to call the interface types function
-                self.emit(&Instruction::CallInterface { func });
+                self.emit(&Instruction::CallInterface {
+                    func,
+                    async_: false,
+                });
 
                 // This was dynamically allocated by the caller so after
                 // it's been read by the guest we need to deallocate it.
@@ -982,7 +1266,129 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         );
     }
 
-    fn post_return(&mut self, func: &Function) {
+    fn call_async(&mut self, func: &'a Function) {
+        match self.lift_lower {
+            LiftLower::LowerArgsLiftResults => {
+                assert_eq!(self.variant, AbiVariant::GuestImport);
+
+                // Async imports always pass their parameters and results
+                // indirectly. Both live in a single area of memory which stays
+                // alive until the call completes: parameters first, then the
+                // results.
+                let sizes = self.bindgen.sizes();
+                let (params_size, params_align) = sizes.record(func.params.iter().map(|t| &t.1));
+                let (results_size, results_align) = sizes.params(func.results.iter_types());
+                let results_offset = align_to(params_size, results_align);
+                let ptr = self.bindgen.return_pointer(
+                    results_offset + results_size,
+                    params_align.max(results_align),
+                );
+
+                let mut offset = 0usize;
+                for (nth, (_, ty)) in func.params.iter().enumerate() {
+                    self.emit(&Instruction::GetArg { nth });
+                    offset = align_to(offset, self.bindgen.sizes().align(ty));
+                    self.write_to_memory(ty, ptr.clone(), offset as i32);
+                    offset += self.bindgen.sizes().size(ty);
+                }
+
+                self.stack.push(ptr.clone());
+                self.emit(&Instruction::AsyncCallWasm {
+                    name: &func.name,
+                    results_offset,
+                });
+
+                self.read_results_from_memory(&func.results, ptr, results_offset as i32);
+                self.emit(&Instruction::Return {
+                    func,
+                    amt: func.results.len(),
+                });
+            }
+            LiftLower::LiftArgsLowerResults => {
+                assert_eq!(self.variant, AbiVariant::GuestExport);
+
+                // Parameters are lifted exactly as they are for synchronous
+                // exports.
+                let sig = self.resolve.wasm_signature(self.variant, func);
+                if !sig.indirect_params {
+                    let mut offset = 0;
+                    let mut temp = Vec::new();
+                    for (_, ty) in func.params.iter() {
+                        temp.truncate(0);
+                        self.resolve.push_flat(ty, &mut temp);
+                        for _ in 0..temp.len() {
+                            self.emit(&Instruction::GetArg { nth: offset });
+                            offset += 1;
+                        }
+                        self.lift(ty);
+                    }
+                } else {
+                    let mut offset = 0usize;
+                    self.emit(&Instruction::GetArg { nth: 0 });
+                    let ptr = self.stack.pop().unwrap();
+                    for (_, ty) in func.params.iter() {
+                        offset = align_to(offset, self.bindgen.sizes().align(ty));
+                        self.read_from_memory(ty, ptr.clone(), offset as i32);
+                        offset += self.bindgen.sizes().size(ty);
+                    }
+                }
+
+                self.emit(&Instruction::CallInterface { func, async_: true });
+
+                if sig.indirect_params {
+                    let (size, align) = self
+                        .bindgen
+                        .sizes()
+                        .record(func.params.iter().map(|t| &t.1));
+                    self.emit(&Instruction::GetArg { nth: 0 });
+                    self.emit(&Instruction::GuestDeallocate { size, align });
+                }
+
+                self.emit(&Instruction::AsyncPostCallInterface { func });
+
+                // Results are handed to `task.return` as if it were an
+                // imported function taking them as parameters, so they're
+                // lowered without transferring ownership of any memory.
+                let params = task_return_params(self.resolve, func);
+                let results = self
+                    .stack
+                    .drain(self.stack.len() - func.results.len()..)
+                    .collect::<Vec<_>>();
+                if params.len() == 1 && params[0] == WasmType::Pointer {
+                    let (size, align) = self.bindgen.sizes().params(func.results.iter_types());
+                    let ptr = self.bindgen.return_pointer(size, align);
+                    let mut offset = 0usize;
+                    for (ty, result) in func.results.iter_types().zip(results) {
+                        self.stack.push(result);
+                        offset = align_to(offset, self.bindgen.sizes().align(ty));
+                        self.write_to_memory(ty, ptr.clone(), offset as i32);
+                        offset += self.bindgen.sizes().size(ty);
+                    }
+                    self.stack.push(ptr);
+                } else {
+                    for (ty, result) in func.results.iter_types().zip(results) {
+                        self.stack.push(result);
+                        self.lower(ty);
+                    }
+                }
+                self.emit_local(&Instruction::AsyncCallReturn {
+                    name: &func.name,
+                    params: &params,
+                });
+
+                // All that's left is the callback code.
+                self.emit(&Instruction::Return { func, amt: 1 });
+            }
+        }
+
+        assert!(
+            self.stack.is_empty(),
+            "stack has {} items remaining",
+            self.stack.len()
+        );
+    }
+
+    fn post_return(&mut self, func: &'a Function) {
         let sig = self.resolve.wasm_signature(self.variant, func);
 
         // Currently post-return is only used for lists and lists are always
@@ -1010,7 +1416,27 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         );
     }
 
-    fn emit(&mut self, inst: &Instruction<'_>) {
+    fn pointer_size(&self) -> i32 {
+        pointer_size(self.bindgen.sizes()) as i32
+    }
+
+    fn emit(&mut self, inst: &Instruction<'a>) {
+        self.pop_operands(inst);
+        self.bindgen
+            .emit(self.resolve, inst, &mut self.operands, &mut self.results);
+        self.push_results(inst);
+    }
+
+    /// Same as `emit`, but for instructions which borrow from the
+    /// generator's own state rather than from the `Resolve`.
+    fn emit_local(&mut self, inst: &Instruction<'_>) {
+        self.pop_operands(inst);
+        self.bindgen
+            .emit_local(self.resolve, inst, &mut self.operands, &mut self.results);
+        self.push_results(inst);
+    }
+
+    fn pop_operands(&mut self, inst: &Instruction<'_>) {
         self.operands.clear();
         self.results.clear();
 
@@ -1023,10 +1449,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         self.operands
             .extend(self.stack.drain((self.stack.len() - operands_len)..));
         self.results.reserve(inst.results_len());
+    }
 
-        self.bindgen
-            .emit(self.resolve, inst, &mut self.operands, &mut self.results);
-
+    fn push_results(&mut self, inst: &Instruction<'_>) {
         assert_eq!(
             self.results.len(),
             inst.results_len(),
@@ -1138,7 +1563,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 TypeDefKind::Variant(v) => {
                     let results =
                         self.lower_variant_arms(ty, v.cases.iter().map(|c| c.ty.as_ref()));
-                    self.emit(&VariantLower {
+                    self.emit_local(&VariantLower {
                         variant: v,
                         ty: id,
                         results: &results,
@@ -1154,7 +1579,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 }
                 TypeDefKind::Option(t) => {
                     let results = self.lower_variant_arms(ty, [None, Some(t)]);
-                    self.emit(&OptionLower {
+                    self.emit_local(&OptionLower {
                         payload: t,
                         ty: id,
                         results: &results,
@@ -1162,14 +1587,18 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 }
                 TypeDefKind::Result(r) => {
                     let results = self.lower_variant_arms(ty, [r.ok.as_ref(), r.err.as_ref()]);
-                    self.emit(&ResultLower {
+                    self.emit_local(&ResultLower {
                         result: r,
                         ty: id,
                         results: &results,
                     });
                 }
-                TypeDefKind::Future(_) => todo!("lower future"),
-                TypeDefKind::Stream(_) => todo!("lower stream"),
+                TypeDefKind::Future(payload) => {
+                    self.emit(&FutureLower { payload, ty: id });
+                }
+                TypeDefKind::Stream(stream) => {
+                    self.emit(&StreamLower { stream, ty: id });
+                }
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1213,7 +1642,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     casts.push(cast(*actual, *expected));
                 }
                 if casts.iter().any(|c| *c != Bitcast::None) {
-                    self.emit(&Bitcasts { casts: &casts });
+                    self.emit_local(&Bitcasts { casts: &casts });
                 }
             }
 
@@ -1221,7 +1650,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
             // what other variants are pushing then we need to push
             // some zeros.
             if pushed < results.len() {
-                self.emit(&ConstZero {
+                self.emit_local(&ConstZero {
                     tys: &results[pushed..],
                 });
             }
@@ -1233,9 +1662,11 @@ impl<'a, B: Bindgen> Generator<'a, B> {
     fn list_realloc(&self) -> Option<&'static str> {
         // Lowering parameters calling a wasm import means
         // we don't need to pass ownership, but we pass
-        // ownership in all other cases.
+        // ownership in all other cases. The results of an async export are
+        // passed to `task.return` which, like an import, copies them out.
         match (self.variant, self.lift_lower) {
             (AbiVariant::GuestImport, LiftLower::LowerArgsLiftResults) => None,
+            (AbiVariant::GuestExport, LiftLower::LiftArgsLowerResults) if self.async_ => None,
             _ => Some("cabi_realloc"),
         }
     }
@@ -1353,8 +1784,14 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.emit(&ResultLift { result: r, ty: id });
                 }
 
-                TypeDefKind::Future(_) => todo!("lift future"),
-                TypeDefKind::Stream(_) => todo!("lift stream"),
+                TypeDefKind::Future(payload) => {
+                    self.emit(&FutureLift { payload, ty: id });
+                }
+
+                TypeDefKind::Stream(stream) => {
+                    self.emit(&StreamLift { stream, ty: id });
+                }
+
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1390,7 +1827,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     casts.push(cast(*expected, *actual));
                 }
                 if casts.iter().any(|c| *c != Bitcast::None) {
-                    self.emit(&Instruction::Bitcasts { casts: &casts });
+                    self.emit_local(&Instruction::Bitcasts { casts: &casts });
                 }
 
                 // Then recursively lift this variant's payload.
@@ -1512,8 +1949,12 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.store_intrepr(offset, e.tag());
                 }
 
-                TypeDefKind::Future(_) => todo!("write future to memory"),
-                TypeDefKind::Stream(_) => todo!("write stream to memory"),
+                // Futures and streams are represented in memory the same way
+                // as handles, as an `i32` index.
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                    self.lower_and_emit(ty, addr, &I32Store { offset })
+                }
+
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1558,7 +1999,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         // and the length into the high address.
         self.lower(ty);
         self.stack.push(addr.clone());
-        self.emit(&Instruction::LengthStore { offset: offset + 4 });
+        self.emit(&Instruction::LengthStore {
+            offset: offset + self.pointer_size(),
+        });
         self.stack.push(addr);
         self.emit(&Instruction::PointerStore { offset });
     }
@@ -1585,7 +2028,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         }
     }
 
-    fn lower_and_emit(&mut self, ty: &Type, addr: B::Operand, instr: &Instruction) {
+    fn lower_and_emit(&mut self, ty: &Type, addr: B::Operand, instr: &Instruction<'a>) {
         self.lower(ty);
         self.stack.push(addr);
         self.emit(instr);
@@ -1695,8 +2138,10 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.lift(ty);
                 }
 
-                TypeDefKind::Future(_) => todo!("read future from memory"),
-                TypeDefKind::Stream(_) => todo!("read stream from memory"),
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                    self.emit_and_lift(ty, addr, &I32Load { offset })
+                }
+
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1732,7 +2177,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         self.stack.push(addr.clone());
         self.emit(&Instruction::PointerLoad { offset });
         self.stack.push(addr);
-        self.emit(&Instruction::LengthLoad { offset: offset + 4 });
+        self.emit(&Instruction::LengthLoad {
+            offset: offset + self.pointer_size(),
+        });
         self.lift(ty);
     }
 
@@ -1747,7 +2194,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         }
     }
 
-    fn emit_and_lift(&mut self, ty: &Type, addr: B::Operand, instr: &Instruction) {
+    fn emit_and_lift(&mut self, ty: &Type, addr: B::Operand, instr: &Instruction<'a>) {
         self.stack.push(addr);
         self.emit(instr);
         self.lift(ty);
@@ -1785,7 +2232,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 self.stack.push(addr.clone());
                 self.emit(&Instruction::PointerLoad { offset });
                 self.stack.push(addr);
-                self.emit(&Instruction::LengthLoad { offset: offset + 4 });
+                self.emit(&Instruction::LengthLoad {
+                    offset: offset + self.pointer_size(),
+                });
                 self.emit(&Instruction::GuestDeallocateString);
             }
 
@@ -1815,7 +2264,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.stack.push(addr.clone());
                     self.emit(&Instruction::PointerLoad { offset });
                     self.stack.push(addr);
-                    self.emit(&Instruction::LengthLoad { offset: offset + 4 });
+                    self.emit(&Instruction::LengthLoad {
+                        offset: offset + self.pointer_size(),
+                    });
                     self.emit(&Instruction::GuestDeallocateList { element });
                 }
 
@@ -1865,8 +2316,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
 
                 TypeDefKind::Enum(_) => {}
 
-                TypeDefKind::Future(_) => todo!("read future from memory"),
-                TypeDefKind::Stream(_) => todo!("read stream from memory"),
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {}
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1958,3 +2408,444 @@ fn cast(from: WasmType, to: WasmType) -> Bitcast {
 fn align_to(val: usize, align: usize) -> usize {
     (val + align - 1) & !(align - 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use wit_parser::{AddressSize, UnresolvedPackage, WorldItem, WorldKey};
+
+    fn sizes(resolve: &Resolve) -> SizeAlign {
+        let mut sizes = SizeAlign::default();
+        sizes.fill(resolve);
+        sizes
+    }
+
+    fn parse(wit: &str, func: &str) -> (Resolve, Function) {
+        let mut resolve = Resolve::default();
+        let pkg = UnresolvedPackage::parse(Path::new("test.wit"), wit).unwrap();
+        let pkg = resolve.push(pkg).unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let func = match &resolve.worlds[world].imports[&WorldKey::Name(func.to_string())] {
+            WorldItem::Function(f) => f.clone(),
+            _ => unreachable!(),
+        };
+        (resolve, func)
+    }
+
+    fn record(
+        wit: &str,
+        func: &str,
+        variant: AbiVariant,
+        lift_lower: LiftLower,
+        async_: bool,
+    ) -> Vec<&'static str> {
+        let (resolve, func) = parse(wit, func);
+        let sizes = sizes(&resolve);
+        let block = ir::record_call(
+            &resolve,
+            &sizes,
+            variant,
+            lift_lower,
+            &func,
+            async_,
+            &|_, _| false,
+        );
+        let mut names = Vec::new();
+        emitted(&block, &mut names);
+        names
+    }
+
+    /// Pushes the name of each instruction in `block` onto `names`, in the
+    /// order in which `Block::emit` would emit them.
+    fn emitted(block: &ir::Block<'_>, names: &mut Vec<&'static str>) {
+        for node in block.nodes.iter() {
+            for block in node.blocks.iter() {
+                emitted(block, names);
+            }
+            match &node.op {
+                ir::Op::Instruction(inst) => names.push(inst.name()),
+                ir::Op::Owned(inst) => names.push(inst.name()),
+                ir::Op::ReturnPointer { .. } => {}
+            }
+        }
+    }
+
+    #[test]
+    fn future_and_stream_flat() {
+        let insts = record(
+            "package a:b; world w { import f: func(a: future<u32>, b: stream<u8>) -> future; }",
+            "f",
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            false,
+        );
+        assert_eq!(
+            insts,
+            [
+                "GetArg",
+                "FutureLower",
+                "GetArg",
+                "StreamLower",
+                "CallWasm",
+                "FutureLift",
+                "Return"
+            ]
+        );
+    }
+
+    #[test]
+    fn future_and_stream_in_memory() {
+        let insts = record(
+            "package a:b; world w {
+                record r { a: future<string>, b: stream<u8, string> }
+                import f: func(x: list<r>) -> list<r>;
+            }",
+            "f",
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            false,
+        );
+        for expected in ["FutureLift", "StreamLift", "FutureLower", "StreamLower"] {
+            assert!(insts.contains(&expected), "missing {expected}");
+        }
+    }
+
+    #[test]
+    fn async_import() {
+        let insts = record(
+            "package a:b; world w { import f: func(a: u32, b: string) -> string; }",
+            "f",
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            true,
+        );
+        assert_eq!(
+            insts,
+            [
+                "GetArg",
+                "I32FromU32",
+                "I32Store",
+                "GetArg",
+                "StringLower",
+                "LengthStore",
+                "PointerStore",
+                "AsyncCallWasm",
+                "PointerLoad",
+                "LengthLoad",
+                "StringLift",
+                "Return"
+            ]
+        );
+    }
+
+    #[test]
+    fn async_export() {
+        let insts = record(
+            "package a:b; world w { import f: func(a: u32) -> string; }",
+            "f",
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            true,
+        );
+        assert_eq!(
+            insts,
+            [
+                "GetArg",
+                "U32FromI32",
+                "CallInterface",
+                "AsyncPostCallInterface",
+                "StringLower",
+                "AsyncCallReturn",
+                "Return"
+            ]
+        );
+    }
+
+    #[test]
+    fn ir_nests_blocks() {
+        let (resolve, func) = parse(
+            "package a:b; world w { import f: func(x: option<u32>) -> u32; }",
+            "f",
+        );
+        let sizes = sizes(&resolve);
+        let block = ir::record_call(
+            &resolve,
+            &sizes,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            &func,
+            false,
+            &|_, _| false,
+        );
+        let names = block
+            .nodes
+            .iter()
+            .map(|node| match &node.op {
+                ir::Op::Instruction(inst) => inst.name(),
+                ir::Op::Owned(inst) => inst.name(),
+                ir::Op::ReturnPointer { .. } => "ReturnPointer",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["GetArg", "OptionLower", "CallWasm", "U32FromI32", "Return"]
+        );
+
+        let option = &block.nodes[1];
+        assert_eq!(option.operands, [block.nodes[0].results[0]]);
+        assert_eq!(option.blocks.len(), 2);
+        assert_eq!(option.blocks[0].results.len(), 2);
+        assert_eq!(option.blocks[1].results.len(), 2);
+        assert_eq!(block.nodes[2].operands, option.results);
+        assert_eq!(
+            block.to_string(),
+            "\
+v0 = GetArg
+v7, v8 = OptionLower v0
+{
+  v1 = VariantPayloadName
+  v2 = I32Const
+  v3 = ConstZero
+  yield v2, v3
+}
+{
+  v4 = VariantPayloadName
+  v5 = I32Const
+  v6 = I32FromU32 v4
+  yield v5, v6
+}
+v9 = CallWasm v7, v8
+v10 = U32FromI32 v9
+Return v10
+"
+        );
+    }
+
+    #[test]
+    fn wasm64_length_offsets() {
+        let (resolve, func) = parse(
+            "package a:b; world w { import f: func() -> tuple<u8, string>; }",
+            "f",
+        );
+        let mut sizes = SizeAlign::new(AddressSize::Wasm64);
+        sizes.fill(&resolve);
+        assert_eq!(pointer_size(&sizes), 8);
+        let block = ir::record_call(
+            &resolve,
+            &sizes,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            &func,
+            false,
+            &|_, _| false,
+        );
+        let offsets = block
+            .nodes
+            .iter()
+            .filter_map(|node| match &node.op {
+                ir::Op::Instruction(Instruction::PointerLoad { offset }) => Some(("ptr", *offset)),
+                ir::Op::Instruction(Instruction::LengthLoad { offset }) => Some(("len", *offset)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [("ptr", 8), ("len", 16)]);
+    }
+
+    fn sample() -> (Resolve, Function, interpreter::Val) {
+        use interpreter::Val;
+
+        let wit = "package a:b; world w {
+            flags fl { a, b, c }
+            enum e { x, y }
+            variant v { a(list<string>), b(tuple<u8, f64>), c }
+            record r { a: u16, b: list<v>, c: option<fl>, d: result<e, char>, e: s64 }
+            import f: func(x: r, y: r, z: r) -> r;
+        }";
+        let (resolve, func) = parse(wit, "f");
+        let val = Val::Record(vec![
+            Val::U16(0xbeef),
+            Val::List(vec![
+                Val::Variant(
+                    0,
+                    Some(Box::new(Val::List(vec![
+                        Val::String("hello".to_string()),
+                        Val::String(String::new()),
+                    ]))),
+                ),
+                Val::Variant(
+                    1,
+                    Some(Box::new(Val::Tuple(vec![Val::U8(7), Val::F64(1.5)]))),
+                ),
+                Val::Variant(2, None),
+            ]),
+            Val::Option(Some(Box::new(Val::Flags(vec![true, false, true])))),
+            Val::Result(Err(Some(Box::new(Val::Char('☃'))))),
+            Val::S64(-3),
+        ]);
+        (resolve, func, val)
+    }
+
+    #[test]
+    fn interpreter_round_trips_memory() {
+        let (resolve, func, val) = sample();
+        let ty = func.params[0].1;
+        for address_size in [AddressSize::Wasm32, AddressSize::Wasm64] {
+            let mut sizes = SizeAlign::new(address_size);
+            sizes.fill(&resolve);
+            let mut interp = interpreter::Interpreter::new(&sizes);
+            let ptr = interp.lower_to_memory(&resolve, &ty, val.clone());
+            assert!(interp.memory().allocations().count() > 1);
+            assert_eq!(interp.lift_from_memory(&resolve, &ty, ptr), val);
+
+            // Lifting takes ownership of every list, leaving only the value's
+            // own allocation behind.
+            assert_eq!(
+                interp.memory().allocations().collect::<Vec<_>>(),
+                [(ptr, sizes.size(&ty))],
+            );
+
+            let ptr = interp.lower_to_memory(&resolve, &ty, val.clone());
+            let address = interp.operand(interp.pointer(ptr));
+            deallocate_lists_in_memory(&resolve, &mut interp, address, &ty);
+            assert_eq!(interp.memory().allocations().count(), 2);
+        }
+    }
+
+    #[test]
+    fn interpreter_canonical_lists() {
+        use interpreter::Val;
+
+        let (resolve, func) = parse(
+            "package a:b; world w { import f: func(x: tuple<list<u16>, list<f64>>); }",
+            "f",
+        );
+        let ty = func.params[0].1;
+        let sizes = sizes(&resolve);
+        let mut interp = interpreter::Interpreter::new(&sizes);
+        let val = Val::Tuple(vec![
+            Val::List(vec![Val::U16(1), Val::U16(0x203)]),
+            Val::List(vec![Val::F64(0.5)]),
+        ]);
+        let ptr = interp.lower_to_memory(&resolve, &ty, val.clone());
+
+        // The elements are stored as-is rather than lowered one at a time.
+        let load = |offset: u64| u32::from_le_bytes(interp.memory().load(ptr + offset));
+        let (u16s, f64s) = (load(0), load(8));
+        assert_eq!((load(4), load(12)), (2, 1));
+        assert_eq!(interp.memory().slice(u16s.into(), 4), [1, 0, 3, 2]);
+        assert_eq!(interp.memory().slice(f64s.into(), 8), 0.5f64.to_le_bytes());
+        assert_eq!(interp.lift_from_memory(&resolve, &ty, ptr), val);
+    }
+
+    #[test]
+    fn interpreter_rejects_async() {
+        let (resolve, func) = parse(
+            "package a:b; world w { import f: func(a: u32) -> string; }",
+            "f",
+        );
+        let sizes = sizes(&resolve);
+        let mut interp = interpreter::Interpreter::new(&sizes);
+        interp.set_args(vec![interpreter::Val::U32(1)]);
+        call_async(
+            &resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            &func,
+            &mut interp,
+        );
+        assert_eq!(
+            interp.take_results().unwrap_err().to_string(),
+            "the interpreter doesn't support `AsyncCallWasm`"
+        );
+    }
+
+    #[test]
+    fn interpreter_calls_import() {
+        let (resolve, func, val) = sample();
+        let ty = func.params[0].1;
+        let sizes = sizes(&resolve);
+        let size = sizes.size(&ty);
+
+        // There are too many arguments to pass them directly, so the callee
+        // copies the first from where they were spilled into the return area.
+        let mut interp = interpreter::Interpreter::new(&sizes);
+        interp.set_args(vec![val.clone(), val.clone(), val.clone()]);
+        interp.on_call_wasm(|memory, name, args| {
+            assert_eq!(name, "f");
+            let (src, dst) = match args[..] {
+                [interpreter::Val::S32(src), interpreter::Val::S32(dst)] => (src, dst),
+                _ => panic!("unexpected arguments {args:?}"),
+            };
+            let bytes = memory.slice(u64::from(src as u32), size).to_vec();
+            memory.store(u64::from(dst as u32), &bytes);
+            Vec::new()
+        });
+        call(
+            &resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            &func,
+            &mut interp,
+        );
+        assert_eq!(interp.take_results().unwrap(), Some(vec![val]));
+    }
+
+    #[test]
+    fn ir_emit_matches_call() {
+        let (resolve, func, val) = sample();
+        let ty = func.params[0].1;
+        let sizes = sizes(&resolve);
+        let size = sizes.size(&ty);
+        let block = ir::record_call(
+            &resolve,
+            &sizes,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            &func,
+            false,
+            &|_, _| false,
+        );
+
+        // Emitting the recorded block behaves just like the `call` in
+        // `interpreter_calls_import`.
+        let mut interp = interpreter::Interpreter::new(&sizes);
+        interp.set_args(vec![val.clone(), val.clone(), val.clone()]);
+        interp.on_call_wasm(|memory, _name, args| {
+            let (src, dst) = match args[..] {
+                [interpreter::Val::S32(src), interpreter::Val::S32(dst)] => (src, dst),
+                _ => panic!("unexpected arguments {args:?}"),
+            };
+            let bytes = memory.slice(u64::from(src as u32), size).to_vec();
+            memory.store(u64::from(dst as u32), &bytes);
+            Vec::new()
+        });
+        block.emit(&resolve, &mut interp);
+        assert_eq!(interp.take_results().unwrap(), Some(vec![val]));
+    }
+
+    #[test]
+    fn ir_records_post_return() {
+        let (resolve, func) = parse(
+            "package a:b; world w { import f: func() -> list<string>; }",
+            "f",
+        );
+        let block = ir::record_post_return(&resolve, &sizes(&resolve), &func, &|_, _| false);
+        let mut names = Vec::new();
+        emitted(&block, &mut names);
+        assert_eq!(
+            names,
+            [
+                "GetArg",
+                "IterBasePointer",
+                "PointerLoad",
+                "LengthLoad",
+                "GuestDeallocateString",
+                "PointerLoad",
+                "LengthLoad",
+                "GuestDeallocateList",
+                "Return"
+            ]
+        );
+    }
+}
@@ -0,0 +1,1083 @@
+//! A [`Bindgen`] which executes instructions rather than generating code.
+//!
+//! The [`Interpreter`] here serves as a reference implementation of the
+//! canonical ABI: it lowers dynamic [`Val`]s into a simulated linear
+//! [`Memory`] and lifts them back out again, exactly as the instructions
+//! given to it describe. Comparing its behavior with that of a language
+//! generator is a way of testing the generator's handling of each
+//! instruction, and the layouts from `SizeAlign`, without compiling anything.
+//!
+//! Instructions are executed as soon as they're emitted. Instructions within
+//! blocks are buffered until the instruction which uses the block is emitted,
+//! at which point the block is run as many times as that instruction
+//! requires, for example once per element of a list.
+
+use super::{pointer_size, Bindgen, Bitcast, Instruction, WasmType};
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::mem;
+use wit_parser::{FlagsRepr, Resolve, SizeAlign, Type};
+
+/// A dynamically typed value.
+///
+/// Core wasm values use the `S32`, `S64`, `F32`, and `F64` cases. Pointers
+/// and lengths are `S32`s, or `S64`s for 64-bit memories.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val {
+    Bool(bool),
+    U8(u8),
+    S8(i8),
+    U16(u16),
+    S16(i16),
+    U32(u32),
+    S32(i32),
+    U64(u64),
+    S64(i64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    List(Vec<Val>),
+    Record(Vec<Val>),
+    Tuple(Vec<Val>),
+    /// The index of a variant's case along with its payload, if any.
+    Variant(u32, Option<Box<Val>>),
+    Enum(u32),
+    Option(Option<Box<Val>>),
+    Result(Result<Option<Box<Val>>, Option<Box<Val>>>),
+    /// Whether each flag is set.
+    Flags(Vec<bool>),
+    Handle(u32),
+    Future(u32),
+    Stream(u32),
+}
+
+/// The linear memory used by an [`Interpreter`].
+///
+/// Allocations made through `cabi_realloc` are tracked so that frees can be
+/// validated, and so that allocations which were never freed can be found
+/// with [`Memory::allocations`].
+#[derive(Debug, Clone)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    allocations: BTreeMap<u64, (usize, usize)>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory {
+            // Reserve the first few bytes so that null is never a valid
+            // address.
+            bytes: vec![0; 8],
+            allocations: BTreeMap::new(),
+        }
+    }
+}
+
+impl Memory {
+    /// Returns the contents of this memory.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the address and size of each allocation which hasn't been
+    /// freed.
+    pub fn allocations(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.allocations
+            .iter()
+            .map(|(ptr, (size, _))| (*ptr, *size))
+    }
+
+    /// Allocates `size` bytes with alignment `align`, as `cabi_realloc`
+    /// would.
+    pub fn alloc(&mut self, size: usize, align: usize) -> u64 {
+        if size == 0 {
+            return align as u64;
+        }
+        let ptr = self.reserve(size, align);
+        self.allocations.insert(ptr, (size, align));
+        ptr
+    }
+
+    /// Frees the allocation at `ptr`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ptr` wasn't returned from [`Memory::alloc`] with the same
+    /// `size` and `align`, or if it has already been freed.
+    pub fn free(&mut self, ptr: u64, size: usize, align: usize) {
+        if size == 0 {
+            return;
+        }
+        match self.allocations.remove(&ptr) {
+            Some(layout) if layout == (size, align) => {}
+            Some((actual_size, actual_align)) => panic!(
+                "freeing {ptr:#x} with size {size} and align {align}, but it was \
+                 allocated with size {actual_size} and align {actual_align}"
+            ),
+            None => panic!("freeing {ptr:#x} which isn't allocated"),
+        }
+    }
+
+    /// Reserves `size` bytes with alignment `align` which aren't tracked as
+    /// an allocation, such as for a return area.
+    pub fn reserve(&mut self, size: usize, align: usize) -> u64 {
+        let ptr = align_to(self.bytes.len(), align.max(1));
+        self.bytes.resize(ptr + size, 0);
+        ptr as u64
+    }
+
+    /// Reads `N` bytes starting at `addr`.
+    pub fn load<const N: usize>(&self, addr: u64) -> [u8; N] {
+        self.slice(addr, N).try_into().unwrap()
+    }
+
+    /// Writes `bytes` starting at `addr`.
+    pub fn store(&mut self, addr: u64, bytes: &[u8]) {
+        let range = range(addr, bytes.len());
+        match range.and_then(|range| self.bytes.get_mut(range)) {
+            Some(dst) => dst.copy_from_slice(bytes),
+            None => panic!("out-of-bounds store of {} bytes at {addr:#x}", bytes.len()),
+        }
+    }
+
+    /// Returns the `len` bytes starting at `addr`.
+    pub fn slice(&self, addr: u64, len: usize) -> &[u8] {
+        match range(addr, len).and_then(|range| self.bytes.get(range)) {
+            Some(bytes) => bytes,
+            None => panic!("out-of-bounds load of {len} bytes at {addr:#x}"),
+        }
+    }
+
+    /// Releases ownership of the list at `ptr` once it has been lifted.
+    ///
+    /// Lifted lists own their memory in guest bindings, so the allocation is
+    /// freed here if it was tracked.
+    fn release(&mut self, ptr: u64, size: usize, align: usize) {
+        if size != 0 && self.allocations.contains_key(&ptr) {
+            self.free(ptr, size, align);
+        }
+    }
+}
+
+fn range(addr: u64, len: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::try_from(addr).ok()?;
+    Some(start..start.checked_add(len)?)
+}
+
+fn align_to(val: usize, align: usize) -> usize {
+    (val + align - 1) & !(align - 1)
+}
+
+type CallWasm<'a> = dyn FnMut(&mut Memory, &str, Vec<Val>) -> Vec<Val> + 'a;
+type CallInterface<'a> = dyn FnMut(&mut Memory, &str, Vec<Val>) -> Vec<Val> + 'a;
+
+/// State used while running instructions.
+struct State<'a> {
+    memory: Memory,
+    values: Vec<Option<Val>>,
+    args: Vec<Val>,
+    results: Option<Vec<Val>>,
+    /// The element and base pointer of each list currently being iterated
+    /// over, innermost last.
+    iters: Vec<(Option<Val>, u64)>,
+    /// Whether pointers and lengths are 64-bit.
+    memory64: bool,
+    /// The payload of each variant currently being lowered, innermost last.
+    payloads: Vec<Option<Val>>,
+    call_wasm: Option<Box<CallWasm<'a>>>,
+    call_interface: Option<Box<CallInterface<'a>>>,
+}
+
+impl State<'_> {
+    fn get(&self, slot: usize) -> Val {
+        match &self.values[slot] {
+            Some(val) => val.clone(),
+            None => panic!("value {slot} used before it was defined"),
+        }
+    }
+
+    fn set(&mut self, slot: usize, val: Val) {
+        self.values[slot] = Some(val);
+    }
+
+    fn run(&mut self, block: &Block) -> Vec<Val> {
+        for stmt in block.stmts.iter() {
+            stmt(self);
+        }
+        block.results.iter().map(|slot| self.get(*slot)).collect()
+    }
+
+    /// Returns the pointer or length `val` as a core wasm value.
+    fn pointer(&self, val: u64) -> Val {
+        if self.memory64 {
+            Val::S64(val as i64)
+        } else {
+            Val::S32(val as i32)
+        }
+    }
+
+    fn iter(&self) -> &(Option<Val>, u64) {
+        self.iters
+            .last()
+            .expect("list element used outside of a list block")
+    }
+}
+
+type Stmt = Box<dyn Fn(&mut State<'_>)>;
+
+struct Block {
+    stmts: Vec<Stmt>,
+    results: Vec<usize>,
+}
+
+/// A [`Bindgen`] which executes the instructions it's given.
+///
+/// Operands are indices of values known to the interpreter. Arguments for
+/// `GetArg` are provided with [`Interpreter::set_args`], calls to core wasm
+/// functions and interface functions are forwarded to the callbacks given to
+/// [`Interpreter::on_call_wasm`] and [`Interpreter::on_call_interface`], and
+/// the values passed to `Return` are available from
+/// [`Interpreter::take_results`].
+///
+/// Async functions aren't supported, and an error is returned from
+/// [`Interpreter::take_results`] if one is encountered.
+pub struct Interpreter<'a> {
+    state: State<'a>,
+    sizes: &'a SizeAlign,
+    /// The first instruction encountered which isn't supported, after which
+    /// nothing else is run.
+    unsupported: Option<&'static str>,
+    /// Blocks currently being recorded, innermost last.
+    blocks: Vec<Vec<Stmt>>,
+    /// Blocks which have been finished but not yet used by an instruction.
+    finished: Vec<Block>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter for memory laid out according to `sizes`.
+    pub fn new(sizes: &'a SizeAlign) -> Interpreter<'a> {
+        Interpreter {
+            state: State {
+                memory: Memory::default(),
+                values: Vec::new(),
+                args: Vec::new(),
+                results: None,
+                iters: Vec::new(),
+                payloads: Vec::new(),
+                memory64: pointer_size(sizes) == 8,
+                call_wasm: None,
+                call_interface: None,
+            },
+            sizes,
+            unsupported: None,
+            blocks: Vec::new(),
+            finished: Vec::new(),
+        }
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.state.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.state.memory
+    }
+
+    /// Sets the values returned by `GetArg`.
+    pub fn set_args(&mut self, args: Vec<Val>) {
+        self.state.args = args;
+    }
+
+    /// Takes the values passed to the last `Return` instruction.
+    ///
+    /// Returns an error if an instruction which isn't supported was emitted.
+    pub fn take_results(&mut self) -> Result<Option<Vec<Val>>> {
+        if let Some(inst) = self.unsupported {
+            bail!("the interpreter doesn't support `{inst}`");
+        }
+        Ok(self.state.results.take())
+    }
+
+    /// Sets the function used to execute `CallWasm`, which is given the name
+    /// of the core wasm function and its arguments.
+    pub fn on_call_wasm(&mut self, f: impl FnMut(&mut Memory, &str, Vec<Val>) -> Vec<Val> + 'a) {
+        self.state.call_wasm = Some(Box::new(f));
+    }
+
+    /// Sets the function used to execute `CallInterface`, which is given the
+    /// name of the interface function and its arguments.
+    pub fn on_call_interface(
+        &mut self,
+        f: impl FnMut(&mut Memory, &str, Vec<Val>) -> Vec<Val> + 'a,
+    ) {
+        self.state.call_interface = Some(Box::new(f));
+    }
+
+    /// Creates an operand for `val`, for use with functions such as
+    /// [`lower_to_memory`](super::lower_to_memory).
+    pub fn operand(&mut self, val: Val) -> usize {
+        let slot = self.slot();
+        self.state.set(slot, val);
+        slot
+    }
+
+    /// Returns the core wasm value for the pointer or length `val`.
+    pub fn pointer(&self, val: u64) -> Val {
+        self.state.pointer(val)
+    }
+
+    /// Returns the value of `operand`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `operand` was produced within a block or by an instruction
+    /// which hasn't run yet.
+    pub fn value(&self, operand: usize) -> Val {
+        self.state.get(operand)
+    }
+
+    /// Lowers `val` of type `ty` into a new allocation in memory, returning
+    /// its address.
+    pub fn lower_to_memory(&mut self, resolve: &Resolve, ty: &Type, val: Val) -> u64 {
+        let size = self.sizes.size(ty);
+        let align = self.sizes.align(ty);
+        let ptr = self.state.memory.alloc(size, align);
+        let address = self.operand(self.pointer(ptr));
+        let value = self.operand(val);
+        super::lower_to_memory(resolve, self, address, value, ty);
+        ptr
+    }
+
+    /// Lifts a value of type `ty` from memory at `ptr`.
+    pub fn lift_from_memory(&mut self, resolve: &Resolve, ty: &Type, ptr: u64) -> Val {
+        let address = self.operand(self.pointer(ptr));
+        let result = super::lift_from_memory(resolve, self, address, ty);
+        self.value(result)
+    }
+
+    fn slot(&mut self) -> usize {
+        self.state.values.push(None);
+        self.state.values.len() - 1
+    }
+
+    /// Runs `stmt` now if this is the outermost block, and otherwise records
+    /// it to run as part of the current block.
+    fn push(&mut self, stmt: Stmt) {
+        if self.unsupported.is_some() {
+            return;
+        }
+        match self.blocks.last_mut() {
+            Some(block) => block.push(stmt),
+            None => stmt(&mut self.state),
+        }
+    }
+
+    /// Pushes a statement which maps the values of `operands` to the values
+    /// of `results` with `f`.
+    fn op(
+        &mut self,
+        operands: &[usize],
+        results: &[usize],
+        f: impl Fn(&mut State<'_>, Vec<Val>) -> Vec<Val> + 'static,
+    ) {
+        let operands = operands.to_vec();
+        let results = results.to_vec();
+        self.push(Box::new(move |state| {
+            let args = operands.iter().map(|slot| state.get(*slot)).collect();
+            let vals = f(state, args);
+            assert_eq!(vals.len(), results.len());
+            for (slot, val) in results.iter().zip(vals) {
+                state.set(*slot, val);
+            }
+        }));
+    }
+
+    fn take_blocks(&mut self, n: usize) -> Vec<Block> {
+        assert!(self.finished.len() >= n, "not enough blocks");
+        self.finished.split_off(self.finished.len() - n)
+    }
+}
+
+impl Bindgen for Interpreter<'_> {
+    type Operand = usize;
+
+    fn emit(
+        &mut self,
+        _resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<usize>,
+        results: &mut Vec<usize>,
+    ) {
+        use Instruction::*;
+
+        for _ in 0..inst.results_len() {
+            let slot = self.slot();
+            results.push(slot);
+        }
+
+        match inst {
+            GetArg { nth } => {
+                let nth = *nth;
+                self.op(operands, results, move |state, _| {
+                    vec![state.args[nth].clone()]
+                })
+            }
+            I32Const { val } => {
+                let val = *val;
+                self.op(operands, results, move |_, _| vec![Val::S32(val)])
+            }
+            Bitcasts { casts } => {
+                let casts = casts.to_vec();
+                self.op(operands, results, move |state, args| {
+                    args.into_iter()
+                        .zip(casts.iter())
+                        .map(|(arg, cast)| bitcast(cast, arg, state.memory64))
+                        .collect()
+                })
+            }
+            ConstZero { tys } => {
+                let tys = tys.to_vec();
+                self.op(operands, results, move |state, _| {
+                    tys.iter().map(|ty| zero(*ty, state.memory64)).collect()
+                })
+            }
+
+            I32Load { offset } => self.load(operands, results, *offset, |b: [u8; 4]| {
+                Val::S32(i32::from_le_bytes(b))
+            }),
+            PointerLoad { offset } | LengthLoad { offset } if self.state.memory64 => {
+                self.load(operands, results, *offset, |b: [u8; 8]| {
+                    Val::S64(i64::from_le_bytes(b))
+                })
+            }
+            PointerLoad { offset } | LengthLoad { offset } => {
+                self.load(operands, results, *offset, |b: [u8; 4]| {
+                    Val::S32(i32::from_le_bytes(b))
+                })
+            }
+            I32Load8U { offset } => self.load(operands, results, *offset, |b: [u8; 1]| {
+                Val::S32(i32::from(b[0]))
+            }),
+            I32Load8S { offset } => self.load(operands, results, *offset, |b: [u8; 1]| {
+                Val::S32(i32::from(b[0] as i8))
+            }),
+            I32Load16U { offset } => self.load(operands, results, *offset, |b: [u8; 2]| {
+                Val::S32(i32::from(u16::from_le_bytes(b)))
+            }),
+            I32Load16S { offset } => self.load(operands, results, *offset, |b: [u8; 2]| {
+                Val::S32(i32::from(i16::from_le_bytes(b)))
+            }),
+            I64Load { offset } => self.load(operands, results, *offset, |b: [u8; 8]| {
+                Val::S64(i64::from_le_bytes(b))
+            }),
+            F32Load { offset } => self.load(operands, results, *offset, |b: [u8; 4]| {
+                Val::F32(f32::from_le_bytes(b))
+            }),
+            F64Load { offset } => self.load(operands, results, *offset, |b: [u8; 8]| {
+                Val::F64(f64::from_le_bytes(b))
+            }),
+
+            I32Store { offset } => {
+                self.store(operands, *offset, |v| i32_of(&v).to_le_bytes().to_vec())
+            }
+            PointerStore { offset } | LengthStore { offset } if self.state.memory64 => {
+                self.store(operands, *offset, |v| i64_of(&v).to_le_bytes().to_vec())
+            }
+            PointerStore { offset } | LengthStore { offset } => {
+                self.store(operands, *offset, |v| i32_of(&v).to_le_bytes().to_vec())
+            }
+            I32Store8 { offset } => self.store(operands, *offset, |v| vec![i32_of(&v) as u8]),
+            I32Store16 { offset } => self.store(operands, *offset, |v| {
+                (i32_of(&v) as u16).to_le_bytes().to_vec()
+            }),
+            I64Store { offset } => {
+                self.store(operands, *offset, |v| i64_of(&v).to_le_bytes().to_vec())
+            }
+            F32Store { offset } => self.store(operands, *offset, |v| match v {
+                Val::F32(f) => f.to_le_bytes().to_vec(),
+                other => panic!("expected an f32, found {other:?}"),
+            }),
+            F64Store { offset } => self.store(operands, *offset, |v| match v {
+                Val::F64(f) => f.to_le_bytes().to_vec(),
+                other => panic!("expected an f64, found {other:?}"),
+            }),
+
+            I32FromChar => self.map(operands, results, |v| match v {
+                Val::Char(c) => Val::S32(c as i32),
+                other => panic!("expected a char, found {other:?}"),
+            }),
+            I64FromU64 => self.map(operands, results, |v| match v {
+                Val::U64(x) => Val::S64(x as i64),
+                other => panic!("expected a u64, found {other:?}"),
+            }),
+            I64FromS64 => self.map(operands, results, |v| match v {
+                Val::S64(x) => Val::S64(x),
+                other => panic!("expected an s64, found {other:?}"),
+            }),
+            I32FromU32 => self.map(operands, results, |v| match v {
+                Val::U32(x) => Val::S32(x as i32),
+                other => panic!("expected a u32, found {other:?}"),
+            }),
+            I32FromS32 => self.map(operands, results, |v| match v {
+                Val::S32(x) => Val::S32(x),
+                other => panic!("expected an s32, found {other:?}"),
+            }),
+            I32FromU16 => self.map(operands, results, |v| match v {
+                Val::U16(x) => Val::S32(i32::from(x)),
+                other => panic!("expected a u16, found {other:?}"),
+            }),
+            I32FromS16 => self.map(operands, results, |v| match v {
+                Val::S16(x) => Val::S32(i32::from(x)),
+                other => panic!("expected an s16, found {other:?}"),
+            }),
+            I32FromU8 => self.map(operands, results, |v| match v {
+                Val::U8(x) => Val::S32(i32::from(x)),
+                other => panic!("expected a u8, found {other:?}"),
+            }),
+            I32FromS8 => self.map(operands, results, |v| match v {
+                Val::S8(x) => Val::S32(i32::from(x)),
+                other => panic!("expected an s8, found {other:?}"),
+            }),
+            I32FromBool => self.map(operands, results, |v| match v {
+                Val::Bool(b) => Val::S32(i32::from(b)),
+                other => panic!("expected a bool, found {other:?}"),
+            }),
+            CoreF32FromF32 | F32FromCoreF32 => self.map(operands, results, |v| match v {
+                Val::F32(x) => Val::F32(x),
+                other => panic!("expected an f32, found {other:?}"),
+            }),
+            CoreF64FromF64 | F64FromCoreF64 => self.map(operands, results, |v| match v {
+                Val::F64(x) => Val::F64(x),
+                other => panic!("expected an f64, found {other:?}"),
+            }),
+            S8FromI32 => self.map(operands, results, |v| Val::S8(i32_of(&v) as i8)),
+            U8FromI32 => self.map(operands, results, |v| Val::U8(i32_of(&v) as u8)),
+            S16FromI32 => self.map(operands, results, |v| Val::S16(i32_of(&v) as i16)),
+            U16FromI32 => self.map(operands, results, |v| Val::U16(i32_of(&v) as u16)),
+            S32FromI32 => self.map(operands, results, |v| Val::S32(i32_of(&v))),
+            U32FromI32 => self.map(operands, results, |v| Val::U32(i32_of(&v) as u32)),
+            S64FromI64 => self.map(operands, results, |v| Val::S64(i64_of(&v))),
+            U64FromI64 => self.map(operands, results, |v| Val::U64(i64_of(&v) as u64)),
+            CharFromI32 => self.map(operands, results, |v| {
+                let x = i32_of(&v) as u32;
+                match char::from_u32(x) {
+                    Some(c) => Val::Char(c),
+                    None => panic!("invalid char {x:#x}"),
+                }
+            }),
+            BoolFromI32 => self.map(operands, results, |v| Val::Bool(i32_of(&v) != 0)),
+
+            StringLower { realloc } => {
+                let owned = realloc.is_some();
+                self.op(operands, results, move |state, args| {
+                    let s = match &args[0] {
+                        Val::String(s) => s.clone(),
+                        other => panic!("expected a string, found {other:?}"),
+                    };
+                    let ptr = alloc(&mut state.memory, owned, s.len(), 1);
+                    state.memory.store(ptr, s.as_bytes());
+                    vec![state.pointer(ptr), state.pointer(s.len() as u64)]
+                })
+            }
+            StringLift => self.op(operands, results, |state, args| {
+                let ptr = addr_of(&args[0]);
+                let len = addr_of(&args[1]) as usize;
+                let bytes = state.memory.slice(ptr, len).to_vec();
+                state.memory.release(ptr, len, 1);
+                match String::from_utf8(bytes) {
+                    Ok(s) => vec![Val::String(s)],
+                    Err(e) => panic!("invalid UTF-8 string at {ptr:#x}: {e}"),
+                }
+            }),
+
+            ListCanonLower { element, realloc } | ListLower { element, realloc } => {
+                let owned = realloc.is_some();
+                let size = self.sizes.size(element);
+                let align = self.sizes.align(element);
+                let canonical = matches!(inst, ListCanonLower { .. });
+                let element = **element;
+                let block = if canonical {
+                    None
+                } else {
+                    self.take_blocks(1).pop()
+                };
+                self.op(operands, results, move |state, args| {
+                    let vals = match &args[0] {
+                        Val::List(vals) => vals.clone(),
+                        other => panic!("expected a list, found {other:?}"),
+                    };
+                    let len = vals.len();
+                    let ptr = alloc(&mut state.memory, owned, size * len, align);
+                    for (i, val) in vals.into_iter().enumerate() {
+                        let base = ptr + (i * size) as u64;
+                        match &block {
+                            Some(block) => {
+                                state.iters.push((Some(val), base));
+                                state.run(block);
+                                state.iters.pop();
+                            }
+                            None => store_canonical(&mut state.memory, &element, base, val),
+                        }
+                    }
+                    vec![state.pointer(ptr), state.pointer(len as u64)]
+                })
+            }
+            ListCanonLift { element, .. } | ListLift { element, .. } => {
+                let size = self.sizes.size(element);
+                let align = self.sizes.align(element);
+                let canonical = matches!(inst, ListCanonLift { .. });
+                let element = **element;
+                let block = if canonical {
+                    None
+                } else {
+                    self.take_blocks(1).pop()
+                };
+                self.op(operands, results, move |state, args| {
+                    let ptr = addr_of(&args[0]);
+                    let len = addr_of(&args[1]) as usize;
+                    let mut vals = Vec::with_capacity(len);
+                    for i in 0..len {
+                        let base = ptr + (i * size) as u64;
+                        match &block {
+                            Some(block) => {
+                                state.iters.push((None, base));
+                                vals.extend(state.run(block));
+                                state.iters.pop();
+                            }
+                            None => vals.push(load_canonical(&state.memory, &element, base)),
+                        }
+                    }
+                    state.memory.release(ptr, size * len, align);
+                    vec![Val::List(vals)]
+                })
+            }
+            IterElem { .. } => self.op(operands, results, |state, _| match &state.iter().0 {
+                Some(val) => vec![val.clone()],
+                None => panic!("`IterElem` used while lifting a list"),
+            }),
+            IterBasePointer => self.op(operands, results, |state, _| {
+                vec![state.pointer(state.iter().1)]
+            }),
+
+            RecordLower { .. } | TupleLower { .. } => {
+                self.op(operands, results, |_, args| match &args[0] {
+                    Val::Record(fields) | Val::Tuple(fields) => fields.clone(),
+                    other => panic!("expected a record or tuple, found {other:?}"),
+                })
+            }
+            RecordLift { .. } => self.op(operands, results, |_, args| vec![Val::Record(args)]),
+            TupleLift { .. } => self.op(operands, results, |_, args| vec![Val::Tuple(args)]),
+
+            HandleLower { .. } => self.map(operands, results, |v| match v {
+                Val::Handle(h) => Val::S32(h as i32),
+                other => panic!("expected a handle, found {other:?}"),
+            }),
+            HandleLift { .. } => self.map(operands, results, |v| Val::Handle(i32_of(&v) as u32)),
+            FutureLower { .. } => self.map(operands, results, |v| match v {
+                Val::Future(h) => Val::S32(h as i32),
+                other => panic!("expected a future, found {other:?}"),
+            }),
+            FutureLift { .. } => self.map(operands, results, |v| Val::Future(i32_of(&v) as u32)),
+            StreamLower { .. } => self.map(operands, results, |v| match v {
+                Val::Stream(h) => Val::S32(h as i32),
+                other => panic!("expected a stream, found {other:?}"),
+            }),
+            StreamLift { .. } => self.map(operands, results, |v| Val::Stream(i32_of(&v) as u32)),
+
+            FlagsLower { flags, .. } => {
+                let count = flags.repr().count();
+                let repr = flags.repr();
+                self.op(operands, results, move |_, args| {
+                    let set = match &args[0] {
+                        Val::Flags(set) => set.clone(),
+                        other => panic!("expected flags, found {other:?}"),
+                    };
+                    let mut words = vec![0u32; count];
+                    for (i, _) in set.iter().enumerate().filter(|(_, set)| **set) {
+                        words[i / 32] |= 1 << (i % 32);
+                    }
+                    if let FlagsRepr::U8 | FlagsRepr::U16 = repr {
+                        assert!(words.len() == 1);
+                    }
+                    words.into_iter().map(|w| Val::S32(w as i32)).collect()
+                })
+            }
+            FlagsLift { flags, .. } => {
+                let n = flags.flags.len();
+                self.op(operands, results, move |_, args| {
+                    let set = (0..n)
+                        .map(|i| (i32_of(&args[i / 32]) as u32) & (1 << (i % 32)) != 0)
+                        .collect();
+                    vec![Val::Flags(set)]
+                })
+            }
+
+            EnumLower { .. } => self.map(operands, results, |v| match v {
+                Val::Enum(i) => Val::S32(i as i32),
+                other => panic!("expected an enum, found {other:?}"),
+            }),
+            EnumLift { enum_, .. } => {
+                let n = enum_.cases.len();
+                self.map(operands, results, move |v| {
+                    let i = i32_of(&v) as u32;
+                    assert!((i as usize) < n, "invalid enum discriminant {i}");
+                    Val::Enum(i)
+                })
+            }
+
+            VariantPayloadName => self.op(operands, results, |state, _| {
+                let payload = state
+                    .payloads
+                    .last()
+                    .expect("variant payload used outside of a variant");
+                // Cases without a payload still name it, so use a
+                // placeholder which lowers to nothing.
+                vec![payload.clone().unwrap_or(Val::Tuple(Vec::new()))]
+            }),
+            VariantLower { variant, .. } => {
+                let blocks = self.take_blocks(variant.cases.len());
+                self.lower_variant(operands, results, blocks, |v| match v {
+                    Val::Variant(i, payload) => (i, payload.map(|p| *p)),
+                    other => panic!("expected a variant, found {other:?}"),
+                })
+            }
+            OptionLower { .. } => {
+                let blocks = self.take_blocks(2);
+                self.lower_variant(operands, results, blocks, |v| match v {
+                    Val::Option(None) => (0, None),
+                    Val::Option(Some(p)) => (1, Some(*p)),
+                    other => panic!("expected an option, found {other:?}"),
+                })
+            }
+            ResultLower { .. } => {
+                let blocks = self.take_blocks(2);
+                self.lower_variant(operands, results, blocks, |v| match v {
+                    Val::Result(Ok(p)) => (0, p.map(|p| *p)),
+                    Val::Result(Err(p)) => (1, p.map(|p| *p)),
+                    other => panic!("expected a result, found {other:?}"),
+                })
+            }
+            VariantLift { variant, .. } => {
+                let blocks = self.take_blocks(variant.cases.len());
+                self.lift_variant(operands, results, blocks, |i, payload| {
+                    Val::Variant(i, payload.map(Box::new))
+                })
+            }
+            OptionLift { .. } => {
+                let blocks = self.take_blocks(2);
+                self.lift_variant(operands, results, blocks, |i, payload| {
+                    Val::Option(if i == 0 { None } else { payload.map(Box::new) })
+                })
+            }
+            ResultLift { .. } => {
+                let blocks = self.take_blocks(2);
+                self.lift_variant(operands, results, blocks, |i, payload| {
+                    let payload = payload.map(Box::new);
+                    Val::Result(if i == 0 { Ok(payload) } else { Err(payload) })
+                })
+            }
+
+            CallWasm { name, .. } => {
+                let name = name.to_string();
+                self.op(operands, results, move |state, args| {
+                    let call = state
+                        .call_wasm
+                        .as_mut()
+                        .expect("`CallWasm` used without `on_call_wasm`");
+                    call(&mut state.memory, &name, args)
+                })
+            }
+            CallInterface { async_: true, .. }
+            | AsyncCallWasm { .. }
+            | AsyncPostCallInterface { .. }
+            | AsyncCallReturn { .. } => {
+                self.unsupported.get_or_insert(inst.name());
+            }
+            CallInterface { func, .. } => {
+                let name = func.name.clone();
+                self.op(operands, results, move |state, args| {
+                    let call = state
+                        .call_interface
+                        .as_mut()
+                        .expect("`CallInterface` used without `on_call_interface`");
+                    call(&mut state.memory, &name, args)
+                })
+            }
+            Return { .. } => self.op(operands, results, |state, args| {
+                state.results = Some(args);
+                Vec::new()
+            }),
+
+            Malloc { size, align, .. } => {
+                let (size, align) = (*size, *align);
+                self.op(operands, results, move |state, _| {
+                    let ptr = state.memory.alloc(size, align);
+                    vec![state.pointer(ptr)]
+                })
+            }
+            GuestDeallocate { size, align } => {
+                let (size, align) = (*size, *align);
+                self.op(operands, results, move |state, args| {
+                    state.memory.free(addr_of(&args[0]), size, align);
+                    Vec::new()
+                })
+            }
+            GuestDeallocateString => self.op(operands, results, |state, args| {
+                let len = addr_of(&args[1]) as usize;
+                state.memory.free(addr_of(&args[0]), len, 1);
+                Vec::new()
+            }),
+            GuestDeallocateList { element } => {
+                let size = self.sizes.size(element);
+                let align = self.sizes.align(element);
+                let block = self.take_blocks(1).pop().unwrap();
+                self.op(operands, results, move |state, args| {
+                    let ptr = addr_of(&args[0]);
+                    let len = addr_of(&args[1]) as usize;
+                    for i in 0..len {
+                        state.iters.push((None, ptr + (i * size) as u64));
+                        state.run(&block);
+                        state.iters.pop();
+                    }
+                    state.memory.free(ptr, size * len, align);
+                    Vec::new()
+                })
+            }
+            GuestDeallocateVariant { blocks } => {
+                let blocks = self.take_blocks(*blocks);
+                self.op(operands, results, move |state, args| {
+                    let i = i32_of(&args[0]) as usize;
+                    match blocks.get(i) {
+                        Some(block) => state.run(block),
+                        None => panic!("invalid variant discriminant {i}"),
+                    };
+                    Vec::new()
+                })
+            }
+        }
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> usize {
+        let ptr = self.state.memory.reserve(size, align);
+        self.operand(self.pointer(ptr))
+    }
+
+    fn push_block(&mut self) {
+        self.blocks.push(Vec::new());
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<usize>) {
+        let stmts = self.blocks.pop().unwrap();
+        self.finished.push(Block {
+            stmts,
+            results: mem::take(operands),
+        });
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        self.sizes
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, element: &Type) -> bool {
+        // Lists of numbers are stored as-is, as with `store_canonical`.
+        matches!(
+            element,
+            Type::U8
+                | Type::S8
+                | Type::U16
+                | Type::S16
+                | Type::U32
+                | Type::S32
+                | Type::U64
+                | Type::S64
+                | Type::F32
+                | Type::F64
+        )
+    }
+}
+
+impl Interpreter<'_> {
+    fn map(&mut self, operands: &[usize], results: &[usize], f: impl Fn(Val) -> Val + 'static) {
+        self.op(operands, results, move |_, mut args| {
+            vec![f(args.remove(0))]
+        })
+    }
+
+    fn load<const N: usize>(
+        &mut self,
+        operands: &[usize],
+        results: &[usize],
+        offset: i32,
+        f: impl Fn([u8; N]) -> Val + 'static,
+    ) {
+        self.op(operands, results, move |state, args| {
+            let addr = addr_of(&args[0]).wrapping_add(offset as i64 as u64);
+            vec![f(state.memory.load(addr))]
+        })
+    }
+
+    fn store(&mut self, operands: &[usize], offset: i32, f: impl Fn(Val) -> Vec<u8> + 'static) {
+        self.op(operands, &[], move |state, mut args| {
+            let addr = addr_of(&args[1]).wrapping_add(offset as i64 as u64);
+            let bytes = f(args.remove(0));
+            state.memory.store(addr, &bytes);
+            Vec::new()
+        })
+    }
+
+    fn lower_variant(
+        &mut self,
+        operands: &[usize],
+        results: &[usize],
+        blocks: Vec<Block>,
+        case: impl Fn(Val) -> (u32, Option<Val>) + 'static,
+    ) {
+        self.op(operands, results, move |state, mut args| {
+            let (i, payload) = case(args.remove(0));
+            let block = match blocks.get(i as usize) {
+                Some(block) => block,
+                None => panic!("invalid variant case {i}"),
+            };
+            state.payloads.push(payload);
+            let results = state.run(block);
+            state.payloads.pop();
+            results
+        })
+    }
+
+    fn lift_variant(
+        &mut self,
+        operands: &[usize],
+        results: &[usize],
+        blocks: Vec<Block>,
+        val: impl Fn(u32, Option<Val>) -> Val + 'static,
+    ) {
+        self.op(operands, results, move |state, args| {
+            let i = i32_of(&args[0]) as u32;
+            let block = match blocks.get(i as usize) {
+                Some(block) => block,
+                None => panic!("invalid variant discriminant {i}"),
+            };
+            let payload = state.run(block).pop();
+            vec![val(i, payload)]
+        })
+    }
+}
+
+fn alloc(memory: &mut Memory, owned: bool, size: usize, align: usize) -> u64 {
+    // Without a `realloc` the memory is only borrowed for the duration of
+    // the call, so it's not tracked as an allocation.
+    if owned {
+        memory.alloc(size, align)
+    } else {
+        memory.reserve(size, align)
+    }
+}
+
+fn i32_of(val: &Val) -> i32 {
+    match val {
+        Val::S32(x) => *x,
+        other => panic!("expected an i32, found {other:?}"),
+    }
+}
+
+/// Returns the pointer or length `val`, which is 32-bit or 64-bit depending
+/// on the memory.
+fn addr_of(val: &Val) -> u64 {
+    match val {
+        Val::S32(x) => u64::from(*x as u32),
+        Val::S64(x) => *x as u64,
+        other => panic!("expected a pointer or length, found {other:?}"),
+    }
+}
+
+fn i64_of(val: &Val) -> i64 {
+    match val {
+        Val::S64(x) => *x,
+        other => panic!("expected an i64, found {other:?}"),
+    }
+}
+
+fn zero(ty: WasmType, memory64: bool) -> Val {
+    match ty {
+        WasmType::Pointer | WasmType::Length if memory64 => Val::S64(0),
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => Val::S32(0),
+        WasmType::I64 | WasmType::PointerOrI64 => Val::S64(0),
+        WasmType::F32 => Val::F32(0.0),
+        WasmType::F64 => Val::F64(0.0),
+    }
+}
+
+fn bitcast(cast: &Bitcast, val: Val, memory64: bool) -> Val {
+    use Bitcast::*;
+
+    let pointer = |x: u64| {
+        if memory64 {
+            Val::S64(x as i64)
+        } else {
+            Val::S32(x as i32)
+        }
+    };
+
+    match cast {
+        F32ToI32 => match val {
+            Val::F32(f) => Val::S32(f.to_bits() as i32),
+            other => panic!("expected an f32, found {other:?}"),
+        },
+        F64ToI64 => match val {
+            Val::F64(f) => Val::S64(f.to_bits() as i64),
+            other => panic!("expected an f64, found {other:?}"),
+        },
+        F32ToI64 => match val {
+            Val::F32(f) => Val::S64(i64::from(f.to_bits())),
+            other => panic!("expected an f32, found {other:?}"),
+        },
+        I32ToF32 => Val::F32(f32::from_bits(i32_of(&val) as u32)),
+        I64ToF64 => Val::F64(f64::from_bits(i64_of(&val) as u64)),
+        I64ToF32 => Val::F32(f32::from_bits(i64_of(&val) as u32)),
+        I32ToI64 => Val::S64(i64::from(i32_of(&val) as u32)),
+        I64ToI32 => Val::S32(i64_of(&val) as i32),
+        PToP64 | LToI64 => Val::S64(addr_of(&val) as i64),
+        PToI32 | LToI32 => Val::S32(addr_of(&val) as i32),
+        P64ToP | I64ToL => pointer(i64_of(&val) as u64),
+        I32ToP | I32ToL => pointer(u64::from(i32_of(&val) as u32)),
+        PToL | LToP => pointer(addr_of(&val)),
+        P64ToI64 | I64ToP64 => Val::S64(i64_of(&val)),
+        Sequence(casts) => bitcast(&casts[1], bitcast(&casts[0], val, memory64), memory64),
+        None => val,
+    }
+}
+
+fn store_canonical(memory: &mut Memory, ty: &Type, addr: u64, val: Val) {
+    let bytes = match (ty, val) {
+        (Type::U8, Val::U8(x)) => x.to_le_bytes().to_vec(),
+        (Type::S8, Val::S8(x)) => x.to_le_bytes().to_vec(),
+        (Type::U16, Val::U16(x)) => x.to_le_bytes().to_vec(),
+        (Type::S16, Val::S16(x)) => x.to_le_bytes().to_vec(),
+        (Type::U32, Val::U32(x)) => x.to_le_bytes().to_vec(),
+        (Type::S32, Val::S32(x)) => x.to_le_bytes().to_vec(),
+        (Type::U64, Val::U64(x)) => x.to_le_bytes().to_vec(),
+        (Type::S64, Val::S64(x)) => x.to_le_bytes().to_vec(),
+        (Type::F32, Val::F32(x)) => x.to_le_bytes().to_vec(),
+        (Type::F64, Val::F64(x)) => x.to_le_bytes().to_vec(),
+        (ty, val) => panic!("cannot store {val:?} as canonical {ty:?}"),
+    };
+    memory.store(addr, &bytes);
+}
+
+fn load_canonical(memory: &Memory, ty: &Type, addr: u64) -> Val {
+    match ty {
+        Type::U8 => Val::U8(u8::from_le_bytes(memory.load(addr))),
+        Type::S8 => Val::S8(i8::from_le_bytes(memory.load(addr))),
+        Type::U16 => Val::U16(u16::from_le_bytes(memory.load(addr))),
+        Type::S16 => Val::S16(i16::from_le_bytes(memory.load(addr))),
+        Type::U32 => Val::U32(u32::from_le_bytes(memory.load(addr))),
+        Type::S32 => Val::S32(i32::from_le_bytes(memory.load(addr))),
+        Type::U64 => Val::U64(u64::from_le_bytes(memory.load(addr))),
+        Type::S64 => Val::S64(i64::from_le_bytes(memory.load(addr))),
+        Type::F32 => Val::F32(f32::from_le_bytes(memory.load(addr))),
+        Type::F64 => Val::F64(f64::from_le_bytes(memory.load(addr))),
+        ty => panic!("cannot load canonical {ty:?}"),
+    }
+}
@@ -0,0 +1,457 @@
+//! An owned representation of the instructions generated for a function.
+//!
+//! The functions in the parent module stream instructions into a [`Bindgen`]
+//! implementation as they're generated. The [`record_call`] and
+//! [`record_post_return`] functions here instead record those instructions
+//! into a [`Block`], a tree which can be inspected, transformed, or printed
+//! before it's emitted. Values flowing between instructions are named with
+//! [`Value`]s, and the blocks used by instructions such as `VariantLower` or
+//! `ListLift` are nested within the [`Node`] for that instruction.
+//! Instructions which borrow from the generator's temporary state rather
+//! than from the [`Resolve`] are recorded as an [`OwnedInstruction`].
+//!
+//! A recorded block can be fed to a [`Bindgen`] later on with
+//! [`Block::emit`], which produces the same sequence of calls that passing
+//! the `Bindgen` to [`call`](super::call) would have.
+
+use super::{
+    AbiVariant, Bindgen, Bitcast, Generator, Instruction, LiftLower, Sink, WasmSignature, WasmType,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use wit_parser::{Function, Resolve, SizeAlign, Type, TypeDefKind, TypeId};
+
+/// A value produced by one [`Node`] and consumed by others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+/// A sequence of instructions, along with the values it produces when it's
+/// used as the block of another instruction.
+#[derive(Debug, Clone, Default)]
+pub struct Block<'a> {
+    pub nodes: Vec<Node<'a>>,
+    /// The values passed to `Bindgen::finish_block` for this block.
+    ///
+    /// This is always empty for the outermost block of a function.
+    pub results: Vec<Value>,
+}
+
+/// A single operation within a [`Block`].
+#[derive(Debug, Clone)]
+pub struct Node<'a> {
+    pub op: Op<'a>,
+    /// The values this operation consumes.
+    pub operands: Vec<Value>,
+    /// The values this operation produces.
+    pub results: Vec<Value>,
+    /// Blocks consumed by this operation, in the order they were generated.
+    ///
+    /// For example `VariantLower` has one block per case of the variant and
+    /// `ListLower` has a single block for lowering each element.
+    pub blocks: Vec<Block<'a>>,
+}
+
+/// The kinds of operation recorded in a [`Node`].
+#[derive(Debug, Clone)]
+pub enum Op<'a> {
+    /// An instruction passed to `Bindgen::emit`.
+    Instruction(Instruction<'a>),
+    /// An instruction passed to `Bindgen::emit` whose data is owned here.
+    Owned(OwnedInstruction),
+    /// A call to `Bindgen::return_pointer`, producing a single pointer.
+    ReturnPointer { size: usize, align: usize },
+}
+
+/// The instructions which borrow data that only lives as long as the call to
+/// `Bindgen::emit`, with that data owned instead.
+#[derive(Debug, Clone)]
+pub enum OwnedInstruction {
+    Bitcasts { casts: Vec<Bitcast> },
+    ConstZero { tys: Vec<WasmType> },
+    VariantLower { ty: TypeId, results: Vec<WasmType> },
+    OptionLower { ty: TypeId, results: Vec<WasmType> },
+    ResultLower { ty: TypeId, results: Vec<WasmType> },
+    CallWasm { name: String, sig: WasmSignature },
+    AsyncCallReturn { name: String, params: Vec<WasmType> },
+}
+
+impl OwnedInstruction {
+    /// Converts `inst` to its owned form.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inst` isn't one of the instructions listed here.
+    pub fn new(inst: &Instruction<'_>) -> OwnedInstruction {
+        match inst {
+            Instruction::Bitcasts { casts } => OwnedInstruction::Bitcasts {
+                casts: casts.to_vec(),
+            },
+            Instruction::ConstZero { tys } => OwnedInstruction::ConstZero { tys: tys.to_vec() },
+            Instruction::VariantLower { ty, results, .. } => OwnedInstruction::VariantLower {
+                ty: *ty,
+                results: results.to_vec(),
+            },
+            Instruction::OptionLower { ty, results, .. } => OwnedInstruction::OptionLower {
+                ty: *ty,
+                results: results.to_vec(),
+            },
+            Instruction::ResultLower { ty, results, .. } => OwnedInstruction::ResultLower {
+                ty: *ty,
+                results: results.to_vec(),
+            },
+            Instruction::CallWasm { name, sig } => OwnedInstruction::CallWasm {
+                name: name.to_string(),
+                sig: (*sig).clone(),
+            },
+            Instruction::AsyncCallReturn { name, params } => OwnedInstruction::AsyncCallReturn {
+                name: name.to_string(),
+                params: params.to_vec(),
+            },
+            _ => panic!("no owned form of {}", inst.name()),
+        }
+    }
+
+    /// Returns the name of the instruction, such as `"CallWasm"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OwnedInstruction::Bitcasts { .. } => "Bitcasts",
+            OwnedInstruction::ConstZero { .. } => "ConstZero",
+            OwnedInstruction::VariantLower { .. } => "VariantLower",
+            OwnedInstruction::OptionLower { .. } => "OptionLower",
+            OwnedInstruction::ResultLower { .. } => "ResultLower",
+            OwnedInstruction::CallWasm { .. } => "CallWasm",
+            OwnedInstruction::AsyncCallReturn { .. } => "AsyncCallReturn",
+        }
+    }
+
+    /// Returns the instruction this was created from, borrowing any types it
+    /// refers to from `resolve`.
+    pub fn instruction<'b>(&'b self, resolve: &'b Resolve) -> Instruction<'b> {
+        let kind = |ty: &TypeId| &resolve.types[*ty].kind;
+        match self {
+            OwnedInstruction::Bitcasts { casts } => Instruction::Bitcasts { casts },
+            OwnedInstruction::ConstZero { tys } => Instruction::ConstZero { tys },
+            OwnedInstruction::VariantLower { ty, results } => match kind(ty) {
+                TypeDefKind::Variant(variant) => Instruction::VariantLower {
+                    variant,
+                    name: resolve.types[*ty].name.as_deref().unwrap(),
+                    ty: *ty,
+                    results,
+                },
+                _ => unreachable!(),
+            },
+            OwnedInstruction::OptionLower { ty, results } => match kind(ty) {
+                TypeDefKind::Option(payload) => Instruction::OptionLower {
+                    payload,
+                    ty: *ty,
+                    results,
+                },
+                _ => unreachable!(),
+            },
+            OwnedInstruction::ResultLower { ty, results } => match kind(ty) {
+                TypeDefKind::Result(result) => Instruction::ResultLower {
+                    result,
+                    ty: *ty,
+                    results,
+                },
+                _ => unreachable!(),
+            },
+            OwnedInstruction::CallWasm { name, sig } => Instruction::CallWasm { name, sig },
+            OwnedInstruction::AsyncCallReturn { name, params } => {
+                Instruction::AsyncCallReturn { name, params }
+            }
+        }
+    }
+}
+
+/// Records the instructions which [`call`](super::call) would generate for
+/// `func`, with `sizes` giving the layout of types in memory.
+///
+/// The `is_list_canonical` callback takes the place of
+/// `Bindgen::is_list_canonical` for the language being targeted.
+pub fn record_call<'a>(
+    resolve: &'a Resolve,
+    sizes: &SizeAlign,
+    variant: AbiVariant,
+    lift_lower: LiftLower,
+    func: &'a Function,
+    async_: bool,
+    is_list_canonical: &dyn Fn(&Resolve, &Type) -> bool,
+) -> Block<'a> {
+    let mut recorder = Recorder::new(sizes, is_list_canonical);
+    Generator::new(resolve, variant, lift_lower, &mut recorder, async_).call(func);
+    recorder.finish()
+}
+
+/// Records the instructions which [`post_return`](super::post_return) would
+/// generate for `func`.
+pub fn record_post_return<'a>(
+    resolve: &'a Resolve,
+    sizes: &SizeAlign,
+    func: &'a Function,
+    is_list_canonical: &dyn Fn(&Resolve, &Type) -> bool,
+) -> Block<'a> {
+    let mut recorder = Recorder::new(sizes, is_list_canonical);
+    Generator::new(
+        resolve,
+        AbiVariant::GuestExport,
+        LiftLower::LiftArgsLowerResults,
+        &mut recorder,
+        false,
+    )
+    .post_return(func);
+    recorder.finish()
+}
+
+struct Recorder<'a, 'c> {
+    sizes: &'c SizeAlign,
+    is_list_canonical: &'c dyn Fn(&Resolve, &Type) -> bool,
+    next_value: usize,
+    frames: Vec<Frame<'a>>,
+}
+
+#[derive(Default)]
+struct Frame<'a> {
+    nodes: Vec<Node<'a>>,
+    /// Blocks which have been finished but not yet consumed by an
+    /// instruction in this frame.
+    finished: Vec<Block<'a>>,
+}
+
+impl<'a, 'c> Recorder<'a, 'c> {
+    fn new(sizes: &'c SizeAlign, is_list_canonical: &'c dyn Fn(&Resolve, &Type) -> bool) -> Self {
+        Recorder {
+            sizes,
+            is_list_canonical,
+            next_value: 0,
+            frames: vec![Frame::default()],
+        }
+    }
+
+    fn value(&mut self) -> Value {
+        self.next_value += 1;
+        Value(self.next_value - 1)
+    }
+
+    fn frame(&mut self) -> &mut Frame<'a> {
+        self.frames.last_mut().unwrap()
+    }
+
+    /// Records `op`, which produces `len` results, as the next node.
+    fn push(&mut self, op: Op<'a>, len: usize, operands: &[Value], results: &mut Vec<Value>) {
+        for _ in 0..len {
+            let value = self.value();
+            results.push(value);
+        }
+        let frame = self.frame();
+        let node = Node {
+            op,
+            operands: operands.to_vec(),
+            results: results.clone(),
+            blocks: mem::take(&mut frame.finished),
+        };
+        frame.nodes.push(node);
+    }
+
+    fn finish(mut self) -> Block<'a> {
+        assert_eq!(self.frames.len(), 1, "unfinished blocks remaining");
+        let frame = self.frames.pop().unwrap();
+        assert!(frame.finished.is_empty(), "unconsumed blocks remaining");
+        Block {
+            nodes: frame.nodes,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Sink<'a> for Recorder<'a, '_> {
+    type Operand = Value;
+
+    fn emit(
+        &mut self,
+        _resolve: &'a Resolve,
+        inst: &Instruction<'a>,
+        operands: &mut Vec<Value>,
+        results: &mut Vec<Value>,
+    ) {
+        self.push(
+            Op::Instruction(inst.clone()),
+            inst.results_len(),
+            operands,
+            results,
+        );
+    }
+
+    fn emit_local(
+        &mut self,
+        _resolve: &'a Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<Value>,
+        results: &mut Vec<Value>,
+    ) {
+        self.push(
+            Op::Owned(OwnedInstruction::new(inst)),
+            inst.results_len(),
+            operands,
+            results,
+        );
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> Value {
+        let value = self.value();
+        self.frame().nodes.push(Node {
+            op: Op::ReturnPointer { size, align },
+            operands: Vec::new(),
+            results: vec![value],
+            blocks: Vec::new(),
+        });
+        value
+    }
+
+    fn push_block(&mut self) {
+        self.frames.push(Frame::default());
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<Value>) {
+        let frame = self.frames.pop().unwrap();
+        assert!(frame.finished.is_empty(), "unconsumed blocks remaining");
+        self.frame().finished.push(Block {
+            nodes: frame.nodes,
+            results: operands.clone(),
+        });
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        self.sizes
+    }
+
+    fn is_list_canonical(&self, resolve: &Resolve, element: &Type) -> bool {
+        (self.is_list_canonical)(resolve, element)
+    }
+}
+
+impl<'a> Block<'a> {
+    /// Feeds the operations within this block to `bindgen`.
+    ///
+    /// This is intended to be called on the outermost block returned by
+    /// [`record_call`] or [`record_post_return`], possibly after it's been
+    /// modified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a [`Value`] is used before it's defined.
+    pub fn emit<B: Bindgen>(&self, resolve: &'a Resolve, bindgen: &mut B) {
+        let mut values = HashMap::new();
+        self.emit_nodes(resolve, bindgen, &mut values);
+    }
+
+    fn emit_nodes<B: Bindgen>(
+        &self,
+        resolve: &'a Resolve,
+        bindgen: &mut B,
+        values: &mut HashMap<Value, B::Operand>,
+    ) {
+        for node in self.nodes.iter() {
+            for block in node.blocks.iter() {
+                bindgen.push_block();
+                block.emit_nodes(resolve, bindgen, values);
+                let mut operands = lookup(values, &block.results);
+                bindgen.finish_block(&mut operands);
+            }
+
+            let owned;
+            let inst = match &node.op {
+                Op::Instruction(inst) => inst,
+                Op::Owned(inst) => {
+                    owned = inst.instruction(resolve);
+                    &owned
+                }
+                Op::ReturnPointer { size, align } => {
+                    let pointer = bindgen.return_pointer(*size, *align);
+                    values.insert(node.results[0], pointer);
+                    continue;
+                }
+            };
+            let mut operands = lookup(values, &node.operands);
+            let mut results = Vec::with_capacity(node.results.len());
+            bindgen.emit(resolve, inst, &mut operands, &mut results);
+            assert_eq!(
+                results.len(),
+                node.results.len(),
+                "{inst:?} expected {} results, got {}",
+                node.results.len(),
+                results.len(),
+            );
+            values.extend(node.results.iter().copied().zip(results));
+        }
+    }
+}
+
+fn lookup<T: Clone>(values: &HashMap<Value, T>, keys: &[Value]) -> Vec<T> {
+    keys.iter()
+        .map(|key| match values.get(key) {
+            Some(value) => value.clone(),
+            None => panic!("{key} used before it was defined"),
+        })
+        .collect()
+}
+
+impl fmt::Display for Block<'_> {
+    /// Prints one operation per line, with blocks nested within braces
+    /// beneath the operation they belong to.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.print(f, 0)
+    }
+}
+
+impl Block<'_> {
+    fn print(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        for node in self.nodes.iter() {
+            write!(f, "{:indent$}", "")?;
+            if !node.results.is_empty() {
+                write_values(f, &node.results)?;
+                write!(f, " = ")?;
+            }
+            match &node.op {
+                Op::Instruction(inst) => write!(f, "{}", inst.name())?,
+                Op::Owned(inst) => write!(f, "{}", inst.name())?,
+                Op::ReturnPointer { size, align } => {
+                    write!(f, "ReturnPointer {{ size: {size}, align: {align} }}")?
+                }
+            }
+            if !node.operands.is_empty() {
+                write!(f, " ")?;
+                write_values(f, &node.operands)?;
+            }
+            writeln!(f)?;
+            for block in node.blocks.iter() {
+                writeln!(f, "{:indent$}{{", "")?;
+                block.print(f, indent + 2)?;
+                if !block.results.is_empty() {
+                    write!(f, "{:indent$}  yield ", "")?;
+                    write_values(f, &block.results)?;
+                    writeln!(f)?;
+                }
+                writeln!(f, "{:indent$}}}", "")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_values(f: &mut fmt::Formatter<'_>, values: &[Value]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{value}")?;
+    }
+    Ok(())
+}
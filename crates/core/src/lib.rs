@@ -8,10 +8,15 @@ mod ns;
 pub use ns::Ns;
 pub mod source;
 pub use source::{Files, Source};
+mod locate;
+pub mod source_map;
+pub use source_map::{Origin, SourceMap};
 mod types;
 pub use types::{TypeInfo, Types};
 mod path;
 pub use path::name_package_module;
+pub mod unsupported;
+pub use unsupported::Unsupported;
 
 #[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Direction {
@@ -23,7 +28,8 @@ pub enum Direction {
 pub trait WorldGenerator {
     fn generate(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
         let world = &resolve.worlds[id];
-        self.preprocess(resolve, id);
+        unsupported::check_world_exports(resolve, id)?;
+        self.preprocess(resolve, id)?;
 
         fn unwrap_name(key: &WorldKey) -> &str {
             match key {
@@ -37,19 +43,19 @@ pub trait WorldGenerator {
         for (name, import) in world.imports.iter() {
             match import {
                 WorldItem::Function(f) => funcs.push((unwrap_name(name), f)),
-                WorldItem::Interface(id) => self.import_interface(resolve, name, *id, files),
+                WorldItem::Interface(id) => self.import_interface(resolve, name, *id, files)?,
                 WorldItem::Type(id) => types.push((unwrap_name(name), *id)),
             }
         }
         if !types.is_empty() {
-            self.import_types(resolve, id, &types, files);
+            self.import_types(resolve, id, &types, files)?;
         }
         if !funcs.is_empty() {
-            self.import_funcs(resolve, id, &funcs, files);
+            self.import_funcs(resolve, id, &funcs, files)?;
         }
         funcs.clear();
 
-        self.finish_imports(resolve, id, files);
+        self.finish_imports(resolve, id, files)?;
 
         // First generate bindings for any freestanding functions, if any. If
         // these refer to types defined in the world they need to refer to the
@@ -62,6 +68,7 @@ pub trait WorldGenerator {
             match export {
                 WorldItem::Function(f) => funcs.push((unwrap_name(name), f)),
                 WorldItem::Interface(id) => interfaces.push((name, id)),
+                // Rejected up front by `check_world_exports`.
                 WorldItem::Type(_) => unreachable!(),
             }
         }
@@ -77,12 +84,39 @@ pub trait WorldGenerator {
         self.finish(resolve, id, files)
     }
 
-    fn finish_imports(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
+    /// Generates bindings for each of `worlds` together.
+    ///
+    /// Generators which support this emit the bindings for an interface
+    /// shared by several of the worlds once, with the other worlds referring
+    /// to it, rather than duplicating it for each world. By default only a
+    /// single world is supported.
+    fn generate_worlds(
+        &mut self,
+        resolve: &Resolve,
+        worlds: &[WorldId],
+        files: &mut Files,
+    ) -> Result<()> {
+        match worlds {
+            [world] => self.generate(resolve, *world, files),
+            _ => anyhow::bail!(
+                "this generator doesn't support generating bindings for multiple worlds at once"
+            ),
+        }
+    }
+
+    fn finish_imports(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        files: &mut Files,
+    ) -> Result<()> {
         let _ = (resolve, world, files);
+        Ok(())
     }
 
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         let _ = (resolve, world);
+        Ok(())
     }
 
     fn import_interface(
@@ -91,7 +125,7 @@ pub trait WorldGenerator {
         name: &WorldKey,
         iface: InterfaceId,
         files: &mut Files,
-    );
+    ) -> Result<()>;
 
     /// Called before any exported interfaces are generated.
     fn pre_export_interface(&mut self, resolve: &Resolve, files: &mut Files) -> Result<()> {
@@ -112,7 +146,7 @@ pub trait WorldGenerator {
         world: WorldId,
         funcs: &[(&str, &Function)],
         files: &mut Files,
-    );
+    ) -> Result<()>;
     fn export_funcs(
         &mut self,
         resolve: &Resolve,
@@ -126,7 +160,7 @@ pub trait WorldGenerator {
         world: WorldId,
         types: &[(&str, TypeId)],
         files: &mut Files,
-    );
+    ) -> Result<()>;
     fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()>;
 }
 
@@ -0,0 +1,293 @@
+//! Finding where interfaces, worlds, and their items are declared in WIT
+//! sources, which `wit-parser` doesn't record.
+
+use crate::unsupported::Item;
+use std::path::PathBuf;
+use wit_parser::*;
+
+/// The package an interface or world is declared in, and the declarations
+/// enclosing the items declared within it.
+#[derive(Debug, Clone)]
+pub(crate) struct Scope {
+    pub(crate) package: Option<String>,
+    pub(crate) path: Vec<Decl>,
+}
+
+/// A declaration with a body containing other declarations.
+#[derive(Debug, Clone)]
+pub(crate) enum Decl {
+    /// A declaration such as `interface name { ... }` or `world name { ... }`.
+    Named(&'static str, String),
+    /// An interface declared within a world, as in
+    /// `import name: interface { ... }`.
+    Inline(String),
+}
+
+/// The declaration of an item within its scope.
+enum Target<'a> {
+    Type(&'a str),
+    Function(&'a str),
+    Constructor,
+    /// The declaration of the scope itself.
+    Decl(&'a Decl),
+}
+
+impl Scope {
+    /// Returns the scope of the interface `id`, named `name` in a world.
+    pub(crate) fn interface(resolve: &Resolve, name: &WorldKey, id: InterfaceId) -> Option<Scope> {
+        let iface = &resolve.interfaces[id];
+        match (name, &iface.name) {
+            (WorldKey::Interface(_), Some(iface_name)) => Some(Scope {
+                package: package_of(resolve, iface.package),
+                path: vec![Decl::Named("interface", iface_name.clone())],
+            }),
+            // Interfaces without a name of their own are declared within the
+            // world which imports or exports them.
+            (WorldKey::Name(key), _) => resolve
+                .worlds
+                .iter()
+                .map(|(_, world)| world)
+                .find(|world| {
+                    let items = world.imports.get(name).into_iter();
+                    items
+                        .chain(world.exports.get(name))
+                        .any(|item| matches!(item, WorldItem::Interface(i) if *i == id))
+                })
+                .map(|world| Scope {
+                    package: package_of(resolve, world.package),
+                    path: vec![
+                        Decl::Named("world", world.name.clone()),
+                        Decl::Inline(key.clone()),
+                    ],
+                }),
+            (WorldKey::Interface(_), None) => None,
+        }
+    }
+
+    /// Returns the scope of the world `world`.
+    pub(crate) fn world(resolve: &Resolve, world: WorldId) -> Scope {
+        let world = &resolve.worlds[world];
+        Scope {
+            package: package_of(resolve, world.package),
+            path: vec![Decl::Named("world", world.name.clone())],
+        }
+    }
+
+    /// Searches `files` for the declaration of `item` within this scope, or
+    /// of the scope itself if `item` is `None`.
+    pub(crate) fn locate(
+        &self,
+        item: Option<&Item>,
+        files: &[PathBuf],
+    ) -> Option<(PathBuf, usize, usize)> {
+        files.iter().find_map(|file| {
+            let contents = std::fs::read_to_string(file).ok()?;
+            let (line, col) = self.find(item, &contents)?;
+            Some((file.clone(), line, col))
+        })
+    }
+
+    /// Returns the line and column of the declaration of `item` in
+    /// `contents`.
+    pub(crate) fn find(&self, item: Option<&Item>, contents: &str) -> Option<(usize, usize)> {
+        let mut path = self.path.clone();
+        let decl;
+        // Methods are declared within their resource by their item name.
+        let target = match item {
+            Some(Item::Type(name)) => Target::Type(name),
+            Some(Item::Function(name)) => match name.strip_prefix('[') {
+                Some(name) => {
+                    let (kind, name) = name.split_once(']')?;
+                    let (resource, target) = match kind {
+                        "constructor" => (name, Target::Constructor),
+                        _ => {
+                            let (resource, name) = name.split_once('.')?;
+                            (resource, Target::Function(name))
+                        }
+                    };
+                    path.push(Decl::Named("resource", resource.to_string()));
+                    target
+                }
+                None => Target::Function(name),
+            },
+            None => {
+                decl = path.pop()?;
+                Target::Decl(&decl)
+            }
+        };
+
+        let tokens = tokenize(contents);
+        let text = |i: usize| tokens.get(i).map(ident);
+        let mut package = None;
+        // The tokens preceding each `{` which encloses the current token.
+        let mut bodies = Vec::new();
+        // The first token of the current declaration.
+        let mut start = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            match token.text {
+                "{" => bodies.push(&tokens[start..i]),
+                "}" => drop(bodies.pop()),
+                ";" if bodies.is_empty() && text(start) == Some("package") => {
+                    package = Some(package_name(&tokens[start + 1..i]));
+                }
+                _ => {}
+            }
+            if matches!(token.text, "{" | "}" | ";") {
+                start = i + 1;
+                continue;
+            }
+
+            let declared = match target {
+                Target::Type(name) => {
+                    text(i) == Some(name)
+                        && i > start
+                        && matches!(
+                            tokens[i - 1].text,
+                            "type" | "record" | "variant" | "enum" | "flags" | "resource"
+                        )
+                }
+                Target::Function(name) => {
+                    text(i) == Some(name)
+                        && text(i + 1) == Some(":")
+                        && (text(i + 2) == Some("func")
+                            || (text(i + 2) == Some("async") && text(i + 3) == Some("func")))
+                }
+                Target::Constructor => text(i) == Some("constructor") && text(i + 1) == Some("("),
+                Target::Decl(Decl::Named(keyword, name)) => {
+                    text(i) == Some(name) && i > start && tokens[i - 1].text == *keyword
+                }
+                Target::Decl(Decl::Inline(name)) => {
+                    text(i) == Some(name)
+                        && text(i + 1) == Some(":")
+                        && text(i + 2) == Some("interface")
+                }
+            };
+            if declared && self.encloses(package.as_deref(), &bodies, &path) {
+                return Some((token.line, token.col));
+            }
+        }
+        None
+    }
+
+    /// Returns whether the bodies enclosing a declaration, in a file declaring
+    /// the package `file_package`, are the bodies in `path`.
+    fn encloses(&self, file_package: Option<&str>, bodies: &[&[Token<'_>]], path: &[Decl]) -> bool {
+        let mut package = file_package.map(str::to_string);
+        let mut decls = Vec::new();
+        for body in bodies {
+            // Packages may also be declared with a body of their own.
+            match body.first() {
+                Some(token) if token.text == "package" => package = Some(package_name(&body[1..])),
+                _ => decls.push(body),
+            }
+        }
+        if let (Some(expected), Some(package)) = (&self.package, &package) {
+            if expected != package {
+                return false;
+            }
+        }
+        decls.len() == path.len()
+            && decls.iter().zip(path).all(|(body, decl)| match decl {
+                Decl::Named(keyword, name) => body
+                    .windows(2)
+                    .any(|w| w[0].text == *keyword && ident(&w[1]) == name),
+                Decl::Inline(name) => body
+                    .windows(3)
+                    .any(|w| ident(&w[0]) == name && w[1].text == ":" && w[2].text == "interface"),
+            })
+    }
+}
+
+/// A token of WIT source, at a 1-based line and column.
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    col: usize,
+}
+
+/// Splits `contents` into tokens, skipping whitespace and comments.
+fn tokenize(contents: &str) -> Vec<Token<'_>> {
+    let is_ident = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '%');
+    let mut tokens = Vec::new();
+    let mut chars = contents.char_indices().peekable();
+    let (mut line, mut col) = (1, 1);
+    while let Some((i, c)) = chars.next() {
+        let (start_line, start_col) = (line, col);
+        let mut end = i + c.len_utf8();
+        let mut advance = |c: char| {
+            if c == '\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        };
+        advance(c);
+        let next = chars.peek().map(|(_, c)| *c);
+        match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('/', Some('/')) => {
+                while let Some((_, c)) = chars.next_if(|(_, c)| *c != '\n') {
+                    advance(c);
+                }
+                continue;
+            }
+            // Block comments may be nested.
+            ('/', Some('*')) => {
+                let mut depth = 0;
+                let mut c = c;
+                loop {
+                    match (c, chars.peek().map(|(_, c)| *c)) {
+                        ('/', Some('*')) => depth += 1,
+                        ('*', Some('/')) => depth -= 1,
+                        (_, None) => break,
+                        _ => {
+                            c = chars.next().unwrap().1;
+                            advance(c);
+                            continue;
+                        }
+                    }
+                    advance(chars.next().unwrap().1);
+                    if depth == 0 {
+                        break;
+                    }
+                    c = ' ';
+                }
+                continue;
+            }
+            ('-', Some('>')) => {
+                let (j, c) = chars.next().unwrap();
+                advance(c);
+                end = j + 1;
+            }
+            (c, _) if is_ident(c) => {
+                while let Some((j, c)) = chars.next_if(|(_, c)| is_ident(*c)) {
+                    advance(c);
+                    end = j + c.len_utf8();
+                }
+            }
+            _ => {}
+        }
+        tokens.push(Token {
+            text: &contents[i..end],
+            line: start_line,
+            col: start_col,
+        });
+    }
+    tokens
+}
+
+/// Returns the text of `token` without the `%` which escapes identifiers
+/// that are also keywords.
+fn ident<'a>(token: &Token<'a>) -> &'a str {
+    token.text.trim_start_matches('%')
+}
+
+/// Returns the name of a package from the tokens which follow `package`.
+fn package_name(tokens: &[Token<'_>]) -> String {
+    tokens.iter().map(|t| t.text).collect()
+}
+
+fn package_of(resolve: &Resolve, package: Option<PackageId>) -> Option<String> {
+    package.map(|id| resolve.packages[id].name.to_string())
+}
@@ -1,11 +1,16 @@
+use crate::source_map::{self, Mapping, SourceMap};
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::fmt::{self, Write};
 use std::ops::Deref;
+use std::path::PathBuf;
 
 #[derive(Default)]
 pub struct Files {
     files: BTreeMap<String, Vec<u8>>,
+    /// The lines of each generated file which are mapped back to WIT, for
+    /// files pushed with a source map.
+    mappings: BTreeMap<String, Vec<Mapping>>,
 }
 
 impl Files {
@@ -20,6 +25,44 @@ impl Files {
         }
     }
 
+    /// Pushes `contents`, which were generated with `map`, and a
+    /// `<name>.wit-map.json` sidecar file mapping their lines back to WIT if
+    /// `map` is enabled.
+    pub fn push_mapped(&mut self, name: &str, contents: &str, map: &SourceMap) {
+        if !map.is_enabled() {
+            return self.push(name, contents.as_bytes());
+        }
+        let offset = self
+            .files
+            .get(name)
+            .map_or(0, |prev| prev.iter().filter(|b| **b == b'\n').count());
+        let (contents, mappings) = map.strip(contents, offset);
+        self.push(name, contents.as_bytes());
+        self.mappings
+            .entry(name.to_owned())
+            .or_default()
+            .extend(mappings);
+        self.render_mappings(name);
+    }
+
+    /// Searches `sources`, the files that the WIT was parsed from, for the
+    /// declarations which source maps refer to and records their locations.
+    pub fn locate(&mut self, sources: &[PathBuf]) {
+        let names = self.mappings.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            for mapping in self.mappings.get_mut(&name).unwrap() {
+                mapping.locate(sources);
+            }
+            self.render_mappings(&name);
+        }
+    }
+
+    fn render_mappings(&mut self, name: &str) {
+        let contents = source_map::render(name, &self.mappings[name]);
+        self.files
+            .insert(format!("{name}.wit-map.json"), contents.into_bytes());
+    }
+
     pub fn get_size(&mut self, name: &str) -> Option<usize> {
         self.files.get(name).map(|data| data.len())
     }
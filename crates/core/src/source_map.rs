@@ -0,0 +1,408 @@
+//! Mapping lines of generated files back to the WIT items they were generated
+//! from.
+//!
+//! Generators write markers into their output with [`SourceMap::begin`] and
+//! [`SourceMap::end`] around the code for each interface, type, and function.
+//! Markers are comments which survive the string manipulation generators do
+//! and formatters such as `rustfmt`, and are removed again by
+//! [`Files::push_mapped`](crate::Files::push_mapped) which records the lines
+//! between them in a sidecar file next to the generated file.
+
+use crate::locate::Scope;
+use crate::unsupported::{Item, Owner};
+use crate::{uwrite, uwriteln};
+use std::fmt::{self, Write};
+use std::path::PathBuf;
+use wit_parser::*;
+
+const BEGIN: &str = "//@wit-bindgen-origin ";
+const END: &str = "//@wit-bindgen-origin-end";
+
+/// Tracks the WIT items which a generator's output is generated from.
+///
+/// When created disabled no markers are written, so generated files are
+/// unchanged and no sidecar files are produced.
+#[derive(Default)]
+pub struct SourceMap {
+    enabled: bool,
+    origins: Vec<Origin>,
+}
+
+/// An interface or world, or a type or function within one, which generated
+/// code originates from.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    /// The interface or world which the code is generated for.
+    pub owner: Owner,
+    /// The type or function within `owner`, or `None` for `owner` itself.
+    pub item: Option<Item>,
+    scope: Option<Scope>,
+}
+
+/// A range of lines in a generated file which originates from `origin`.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    /// The first and last 1-based line generated from `origin`.
+    pub lines: (usize, usize),
+    pub origin: Origin,
+    /// The file, line, and column where `origin` is declared, if known.
+    pub location: Option<(PathBuf, usize, usize)>,
+}
+
+impl SourceMap {
+    pub fn new(enabled: bool) -> SourceMap {
+        SourceMap {
+            enabled,
+            origins: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Marks the lines written to `dst` from now until the matching
+    /// [`SourceMap::end`] as generated from `origin`.
+    ///
+    /// Markers must be written at the start of a line and may be nested.
+    pub fn begin(&mut self, dst: &mut String, origin: Origin) {
+        if !self.enabled {
+            return;
+        }
+        uwriteln!(dst, "{BEGIN}{}", self.origins.len());
+        self.origins.push(origin);
+    }
+
+    /// Ends the lines started by the most recent [`SourceMap::begin`].
+    ///
+    /// Blank lines at the start and end of the lines are left outside of
+    /// them, since formatters treat blank lines next to comments differently,
+    /// and if nothing but blank lines were written the marker is removed
+    /// instead so generators can keep checking whether they've written
+    /// anything to `dst`.
+    pub fn end(&self, dst: &mut String) {
+        if !self.enabled {
+            return;
+        }
+        // Find the matching `begin` by walking back over nested markers.
+        let mut depth = 0;
+        let mut end = dst.len();
+        let begin = loop {
+            if end == 0 {
+                return;
+            }
+            let start = dst[..end - 1].rfind('\n').map_or(0, |i| i + 1);
+            let line = dst[start..end].trim();
+            if line == END {
+                depth += 1;
+            } else if line.starts_with(BEGIN) {
+                if depth == 0 {
+                    break start..end;
+                }
+                depth -= 1;
+            }
+            end = start;
+        };
+
+        let is_blank = |line: &str| line.trim().is_empty();
+        let lines = dst[begin.end..].split_inclusive('\n').collect::<Vec<_>>();
+        let leading = lines.iter().take_while(|l| is_blank(l)).count();
+        if leading == lines.len() {
+            dst.replace_range(begin, "");
+            return;
+        }
+        let trailing = lines.iter().rev().take_while(|l| is_blank(l)).count();
+        let len = |lines: &[&str]| lines.iter().map(|l| l.len()).sum::<usize>();
+        let end_at = dst.len() - len(&lines[lines.len() - trailing..]);
+        let begin_at = begin.end + len(&lines[..leading]);
+        dst.insert_str(end_at, &format!("{END}\n"));
+        let marker = dst[begin.clone()].to_string();
+        dst.insert_str(begin_at, &marker);
+        dst.replace_range(begin, "");
+    }
+
+    /// Removes the markers from `contents`, returning the remaining contents
+    /// and the lines between each pair of markers. Line numbers start after
+    /// the `offset` lines which precede `contents` in its file.
+    pub(crate) fn strip(&self, contents: &str, offset: usize) -> (String, Vec<Mapping>) {
+        let mut stripped = String::with_capacity(contents.len());
+        let mut mappings = Vec::new();
+        let mut open = Vec::new();
+        let mut line = offset;
+        let mut close = |origin: usize, start: usize, end: usize| {
+            // Items which didn't generate any code have nothing to map.
+            if start <= end {
+                mappings.push(Mapping {
+                    lines: (start, end),
+                    origin: self.origins[origin].clone(),
+                    location: None,
+                });
+            }
+        };
+        for text in contents.split_inclusive('\n') {
+            let trimmed = text.trim();
+            if let Some(Ok(origin)) = trimmed.strip_prefix(BEGIN).map(str::parse::<usize>) {
+                open.push((origin, line + 1));
+                continue;
+            }
+            if trimmed == END {
+                if let Some((origin, start)) = open.pop() {
+                    close(origin, start, line);
+                }
+                continue;
+            }
+            stripped.push_str(text);
+            line += 1;
+        }
+        while let Some((origin, start)) = open.pop() {
+            close(origin, start, line);
+        }
+        // List enclosing items before the items within them.
+        mappings.sort_by_key(|m| (m.lines.0, std::cmp::Reverse(m.lines.1)));
+        (stripped, mappings)
+    }
+}
+
+impl Origin {
+    /// Returns the origin of code generated for the interface `id`, named
+    /// `name` in a world.
+    pub fn interface(resolve: &Resolve, name: &WorldKey, id: InterfaceId) -> Origin {
+        Origin {
+            owner: Owner::Interface(resolve.name_world_key(name)),
+            item: None,
+            scope: Scope::interface(resolve, name, id),
+        }
+    }
+
+    /// Returns the origin of code generated for the world `world` itself, or
+    /// for the types and functions it imports and exports directly.
+    pub fn world(resolve: &Resolve, world: WorldId) -> Origin {
+        Origin {
+            owner: Owner::World(resolve.worlds[world].name.clone()),
+            item: None,
+            scope: Some(Scope::world(resolve, world)),
+        }
+    }
+
+    /// Returns the origin of code generated for the named type `id`, within
+    /// the interface or world which defines it.
+    pub fn of_type(resolve: &Resolve, id: TypeId) -> Option<Origin> {
+        let ty = &resolve.types[id];
+        let name = ty.name.as_ref()?;
+        let owner = match ty.owner {
+            TypeOwner::Interface(iface) if resolve.interfaces[iface].name.is_some() => {
+                Origin::interface(resolve, &WorldKey::Interface(iface), iface)
+            }
+            // Interfaces without a name of their own are named by the world
+            // which imports or exports them.
+            TypeOwner::Interface(iface) => {
+                let key = resolve.worlds.iter().find_map(|(_, world)| {
+                    let mut items = world.imports.iter().chain(world.exports.iter());
+                    items.find_map(|(key, item)| match item {
+                        WorldItem::Interface(i) if *i == iface => Some(key.clone()),
+                        _ => None,
+                    })
+                })?;
+                Origin::interface(resolve, &key, iface)
+            }
+            TypeOwner::World(world) => Origin::world(resolve, world),
+            TypeOwner::None => return None,
+        };
+        Some(owner.ty(name))
+    }
+
+    /// Returns the origin of code generated for the type `name` within this
+    /// origin's interface or world.
+    pub fn ty(&self, name: &str) -> Origin {
+        Origin {
+            item: Some(Item::Type(name.to_string())),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the origin of code generated for the function `name` within
+    /// this origin's interface or world.
+    pub fn func(&self, name: &str) -> Origin {
+        Origin {
+            item: Some(Item::Function(name.to_string())),
+            ..self.clone()
+        }
+    }
+}
+
+impl Mapping {
+    /// Searches `files`, the sources that the WIT was parsed from, for the
+    /// declaration of this mapping's origin and records its location.
+    pub fn locate(&mut self, files: &[PathBuf]) {
+        if let Some(scope) = &self.origin.scope {
+            self.location = scope.locate(self.origin.item.as_ref(), files);
+        }
+    }
+}
+
+/// Renders the sidecar file for the generated file `file` as JSON.
+pub(crate) fn render(file: &str, mappings: &[Mapping]) -> String {
+    let mut out = String::new();
+    out.push_str("{\n");
+    uwrite!(out, "  \"file\": {},\n", Json(file));
+    out.push_str("  \"mappings\": [");
+    for (i, mapping) in mappings.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let (start, end) = mapping.lines;
+        uwrite!(out, "\n    {{\"lines\": [{start}, {end}]");
+        match &mapping.origin.owner {
+            Owner::Interface(name) => uwrite!(out, ", \"interface\": {}", Json(name)),
+            Owner::World(name) => uwrite!(out, ", \"world\": {}", Json(name)),
+        }
+        match &mapping.origin.item {
+            Some(Item::Type(name)) => uwrite!(out, ", \"type\": {}", Json(name)),
+            Some(Item::Function(name)) => {
+                uwrite!(out, ", \"function\": {}", Json(name))
+            }
+            None => {}
+        }
+        if let Some((path, line, col)) = &mapping.location {
+            let path = path.display().to_string();
+            uwrite!(
+                out,
+                ", \"wit\": {{\"path\": {}, \"line\": {line}, \"column\": {col}}}",
+                Json(&path)
+            );
+        }
+        out.push('}');
+    }
+    if !mappings.is_empty() {
+        out.push_str("\n  ");
+    }
+    out.push_str("]\n}\n");
+    out
+}
+
+/// Displays a string as a JSON string literal.
+struct Json<'a>(&'a str);
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Files, Source};
+    use std::path::Path;
+
+    fn wit() -> &'static str {
+        "package foo:bar;
+
+        interface i {
+            record r {
+                x: u32,
+            }
+            f: func();
+        }
+
+        world w {
+            import i;
+            export g: func();
+        }
+        "
+    }
+
+    fn resolve() -> (Resolve, WorldId) {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse(Path::new("foo.wit"), wit()).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        (resolve, world)
+    }
+
+    #[test]
+    fn maps_lines_to_items() {
+        let (resolve, world) = resolve();
+        let (key, _) = resolve.worlds[world].imports.first().unwrap();
+        let WorldKey::Interface(id) = key else {
+            unreachable!()
+        };
+
+        let mut map = SourceMap::new(true);
+        let mut src = Source::default();
+        let iface = Origin::interface(&resolve, key, *id);
+        src.push_str("// header\n");
+        map.begin(src.as_mut_string(), iface.clone());
+        src.push_str("mod i {\n");
+        map.begin(src.as_mut_string(), iface.ty("r"));
+        src.push_str("struct R {\nx: u32,\n}\n");
+        map.end(src.as_mut_string());
+        map.begin(src.as_mut_string(), iface.func("f"));
+        map.end(src.as_mut_string());
+        src.push_str("}\n");
+        map.end(src.as_mut_string());
+        map.begin(
+            src.as_mut_string(),
+            Origin::world(&resolve, world).func("g"),
+        );
+        src.push_str("fn g() {}\n");
+        map.end(src.as_mut_string());
+
+        let mut files = Files::default();
+        files.push_mapped("w.rs", &src, &map);
+        let dir = std::env::temp_dir().join(format!("wit-bindgen-map-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wit_file = dir.join("foo.wit");
+        std::fs::write(&wit_file, wit()).unwrap();
+        files.locate(&[wit_file.clone()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let files = files.iter().collect::<Vec<_>>();
+        assert_eq!(files[0].0, "w.rs");
+        assert_eq!(
+            std::str::from_utf8(files[0].1).unwrap(),
+            "// header\nmod i {\n  struct R {\n    x: u32,\n  }\n}\nfn g() {}\n"
+        );
+        let path = Json(&wit_file.display().to_string()).to_string();
+        assert_eq!(files[1].0, "w.rs.wit-map.json");
+        assert_eq!(
+            std::str::from_utf8(files[1].1).unwrap(),
+            format!(
+                r#"{{
+  "file": "w.rs",
+  "mappings": [
+    {{"lines": [2, 6], "interface": "foo:bar/i", "wit": {{"path": {path}, "line": 3, "column": 19}}}},
+    {{"lines": [3, 5], "interface": "foo:bar/i", "type": "r", "wit": {{"path": {path}, "line": 4, "column": 20}}}},
+    {{"lines": [7, 7], "world": "w", "function": "g", "wit": {{"path": {path}, "line": 12, "column": 20}}}}
+  ]
+}}
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn disabled_maps_are_unmarked() {
+        let (resolve, world) = resolve();
+        let mut map = SourceMap::new(false);
+        let mut src = Source::default();
+        map.begin(src.as_mut_string(), Origin::world(&resolve, world));
+        src.push_str("x\n");
+        map.end(src.as_mut_string());
+        let mut files = Files::default();
+        files.push_mapped("x.c", &src, &map);
+        assert_eq!(files.iter().count(), 1);
+        assert_eq!(&*src, "x\n");
+    }
+}
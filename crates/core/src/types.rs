@@ -193,12 +193,11 @@ impl Types {
                 info = self.optional_type_info(resolve, r.ok.as_ref());
                 info |= self.optional_type_info(resolve, r.err.as_ref());
             }
-            TypeDefKind::Future(ty) => {
-                info = self.optional_type_info(resolve, ty.as_ref());
-            }
-            TypeDefKind::Stream(stream) => {
-                info = self.optional_type_info(resolve, stream.element.as_ref());
-                info |= self.optional_type_info(resolve, stream.end.as_ref());
+            // Futures and streams are passed around as owned handles, so their
+            // payloads don't affect how the type itself is used.
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                info.has_resource = true;
+                info.has_own_handle = true;
             }
             TypeDefKind::Unknown => unreachable!(),
         }
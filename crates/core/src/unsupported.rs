@@ -0,0 +1,507 @@
+use crate::locate::Scope;
+use anyhow::Result;
+use std::fmt;
+use std::path::PathBuf;
+use wit_parser::*;
+
+/// An error for a WIT construct which a generator doesn't support.
+///
+/// This names the interface or world and the type or function which uses the
+/// construct, and where it's declared once [`Unsupported::locate`] has been
+/// given the WIT sources, so the WIT can be changed accordingly.
+#[derive(Debug)]
+pub struct Unsupported {
+    /// A description of what isn't supported, such as "futures".
+    pub feature: String,
+    /// The name of the interface or world which contains the construct.
+    pub owner: Owner,
+    /// The type or function which uses the construct.
+    pub item: Item,
+    /// The file, line, and column where `item` is declared, if known.
+    pub location: Option<(PathBuf, usize, usize)>,
+    /// Where `owner` is declared, if it's known how to find it.
+    scope: Option<Scope>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Owner {
+    Interface(String),
+    World(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Type(String),
+    Function(String),
+}
+
+impl Unsupported {
+    /// Searches `files`, the sources that the WIT was parsed from, for the
+    /// declaration of the item and records its location.
+    pub fn locate(&mut self, files: &[PathBuf]) {
+        if let Some(scope) = &self.scope {
+            self.location = scope.locate(Some(&self.item), files);
+        }
+    }
+
+    #[cfg(test)]
+    fn find(&self, contents: &str) -> Option<(usize, usize)> {
+        self.scope.as_ref()?.find(Some(&self.item), contents)
+    }
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} are not supported by this generator: ", self.feature)?;
+        match &self.item {
+            Item::Type(name) => write!(f, "type `{name}`")?,
+            Item::Function(name) => write!(f, "function `{name}`")?,
+        }
+        match &self.owner {
+            Owner::Interface(name) => write!(f, " in interface `{name}` uses them")?,
+            Owner::World(name) => write!(f, " in world `{name}` uses them")?,
+        }
+        if let Some((path, line, col)) = &self.location {
+            write!(f, "\n     --> {}:{line}:{col}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Describes a kind of type which a generator doesn't support, returning
+/// `None` for supported types.
+pub type Unsupports<'a> = &'a dyn Fn(&Resolve, &TypeDefKind) -> Option<&'static str>;
+
+/// Checks the types and functions of the interface `id`, named `name` in a
+/// world, returning an [`Unsupported`] error for the first use of a type that
+/// `unsupported` describes.
+pub fn check_interface(
+    resolve: &Resolve,
+    name: &WorldKey,
+    id: InterfaceId,
+    unsupported: Unsupports<'_>,
+) -> Result<()> {
+    let iface = &resolve.interfaces[id];
+    let owner = Owner::Interface(resolve.name_world_key(name));
+    let scope = Scope::interface(resolve, name, id);
+    check(
+        resolve,
+        &owner,
+        scope,
+        iface.types.iter().map(|(name, id)| (name.as_str(), *id)),
+        iface.functions.values(),
+        unsupported,
+    )
+}
+
+/// Checks the types and functions which the world `world` imports or exports
+/// directly, rather than through interfaces.
+pub fn check_world_items<'a>(
+    resolve: &'a Resolve,
+    world: WorldId,
+    types: &[(&'a str, TypeId)],
+    funcs: &[(&'a str, &'a Function)],
+    unsupported: Unsupports<'_>,
+) -> Result<()> {
+    let owner = Owner::World(resolve.worlds[world].name.clone());
+    let scope = Scope::world(resolve, world);
+    check(
+        resolve,
+        &owner,
+        Some(scope),
+        types.iter().copied(),
+        funcs.iter().map(|(_, func)| *func),
+        unsupported,
+    )
+}
+
+/// Checks that the world `world` only exports interfaces and functions.
+///
+/// WIT has no syntax for a world exporting types itself, but a world decoded
+/// from a component which exports a resource at its root does so. Neither
+/// the generators nor `wit-component`, which encodes the world into the
+/// bindings, support that yet.
+pub fn check_world_exports(resolve: &Resolve, world: WorldId) -> Result<()> {
+    let w = &resolve.worlds[world];
+    for (key, item) in w.exports.iter() {
+        if let WorldItem::Type(_) = item {
+            return Err(Unsupported {
+                feature: "types exported from worlds".to_string(),
+                owner: Owner::World(w.name.clone()),
+                item: Item::Type(resolve.name_world_key(key)),
+                location: None,
+                scope: Some(Scope::world(resolve, world)),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Returns an [`Unsupported`] error for `item` of the interface `id`, named
+/// `name` in a world, which uses `feature`.
+///
+/// This is for constructs which the `check_*` functions can't find by the
+/// kinds of types alone, such as instructions of the async canonical ABI.
+pub fn interface_item(
+    resolve: &Resolve,
+    name: &WorldKey,
+    id: InterfaceId,
+    item: Item,
+    feature: &str,
+) -> anyhow::Error {
+    Unsupported {
+        feature: feature.to_string(),
+        owner: Owner::Interface(resolve.name_world_key(name)),
+        item,
+        location: None,
+        scope: Scope::interface(resolve, name, id),
+    }
+    .into()
+}
+
+/// Same as [`interface_item`] for an item which the world `world` imports or
+/// exports directly.
+pub fn world_item(resolve: &Resolve, world: WorldId, item: Item, feature: &str) -> anyhow::Error {
+    Unsupported {
+        feature: feature.to_string(),
+        owner: Owner::World(resolve.worlds[world].name.clone()),
+        item,
+        location: None,
+        scope: Some(Scope::world(resolve, world)),
+    }
+    .into()
+}
+
+fn check<'a>(
+    resolve: &'a Resolve,
+    owner: &Owner,
+    scope: Option<Scope>,
+    types: impl Iterator<Item = (&'a str, TypeId)>,
+    funcs: impl Iterator<Item = &'a Function>,
+    unsupported: Unsupports<'_>,
+) -> Result<()> {
+    let error = |feature: &str, item: Item| Unsupported {
+        feature: feature.to_string(),
+        owner: owner.clone(),
+        item,
+        location: None,
+        scope: scope.clone(),
+    };
+    for (name, id) in types {
+        // `InterfaceGenerator::define_type` can't define these for any
+        // generator, so they're only usable without a name of their own.
+        let named = match resolve.types[id].kind {
+            TypeDefKind::Handle(_) => Some("type aliases of handles"),
+            TypeDefKind::Future(_) => Some("type aliases of futures"),
+            TypeDefKind::Stream(_) => Some("type aliases of streams"),
+            _ => None,
+        };
+        if let Some(feature) = named.or_else(|| find(resolve, &Type::Id(id), unsupported)) {
+            return Err(error(feature, Item::Type(name.to_string())).into());
+        }
+    }
+    for func in funcs {
+        let mut tys = func.params.iter().map(|(_, ty)| ty);
+        let mut results = func.results.iter_types();
+        if let Some(feature) = tys
+            .by_ref()
+            .chain(results.by_ref())
+            .find_map(|ty| find(resolve, ty, unsupported))
+        {
+            return Err(error(feature, Item::Function(func.name.clone())).into());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the first description from `unsupported` for `ty` or any type it
+/// refers to.
+fn find(resolve: &Resolve, ty: &Type, unsupported: Unsupports<'_>) -> Option<&'static str> {
+    let Type::Id(id) = ty else {
+        return None;
+    };
+    let kind = &resolve.types[*id].kind;
+    if let Some(feature) = unsupported(resolve, kind) {
+        return Some(feature);
+    }
+    let find = |ty: &Type| find(resolve, ty, unsupported);
+    match kind {
+        TypeDefKind::Record(r) => r.fields.iter().find_map(|f| find(&f.ty)),
+        TypeDefKind::Tuple(t) => t.types.iter().find_map(find),
+        TypeDefKind::Variant(v) => v.cases.iter().find_map(|c| c.ty.as_ref().and_then(find)),
+        TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => find(ty),
+        TypeDefKind::Result(r) => {
+            r.ok.as_ref()
+                .and_then(find)
+                .or_else(|| r.err.as_ref().and_then(find))
+        }
+        TypeDefKind::Future(ty) => ty.as_ref().and_then(find),
+        TypeDefKind::Stream(s) => s
+            .element
+            .as_ref()
+            .and_then(find)
+            .or_else(|| s.end.as_ref().and_then(find)),
+        TypeDefKind::Handle(Handle::Own(id) | Handle::Borrow(id)) => find(&Type::Id(*id)),
+        TypeDefKind::Resource
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Unknown => None,
+    }
+}
+
+/// Returns a description of futures and streams, which most generators don't
+/// support yet.
+pub fn futures_and_streams(_resolve: &Resolve, kind: &TypeDefKind) -> Option<&'static str> {
+    match kind {
+        TypeDefKind::Future(_) => Some("futures"),
+        TypeDefKind::Stream(_) => Some("streams"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locate::Decl;
+    use std::path::Path;
+
+    fn wit() -> &'static str {
+        "package foo:bar;
+
+        interface i {
+            record r {
+                x: future<u32>,
+            }
+            f: func(x: stream<u8>);
+            g: func() -> r;
+        }
+
+        world w {
+            import i;
+            import h: func(x: future);
+        }
+        "
+    }
+
+    fn resolve() -> (Resolve, WorldId) {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse(Path::new("foo.wit"), wit()).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        (resolve, world)
+    }
+
+    #[test]
+    fn names_and_locates_items() {
+        let (resolve, world) = resolve();
+        let (key, item) = resolve.worlds[world].imports.first().unwrap();
+        let WorldItem::Interface(id) = item else {
+            unreachable!()
+        };
+
+        let unsupported = |err: anyhow::Error| err.downcast::<Unsupported>().unwrap();
+        let mut err =
+            unsupported(check_interface(&resolve, key, *id, &futures_and_streams).unwrap_err());
+        let Item::Type(name) = &err.item else {
+            unreachable!()
+        };
+        assert_eq!(name, "r");
+        assert_eq!(err.find(wit()), Some((4, 20)));
+        assert_eq!(
+            err.to_string(),
+            "futures are not supported by this generator: type `r` in interface `foo:bar/i` uses them"
+        );
+
+        // Only the function which uses a stream directly is reported when
+        // records are allowed.
+        let streams = |_: &Resolve, kind: &TypeDefKind| match kind {
+            TypeDefKind::Stream(_) => Some("streams"),
+            _ => None,
+        };
+        err = unsupported(check_interface(&resolve, key, *id, &streams).unwrap_err());
+        assert!(matches!(&err.item, Item::Function(name) if name == "f"));
+        assert_eq!(err.find(wit()), Some((7, 13)));
+
+        let (name, item) = resolve.worlds[world].imports.last().unwrap();
+        let (WorldKey::Name(name), WorldItem::Function(func)) = (name, item) else {
+            unreachable!()
+        };
+        err = unsupported(
+            check_world_items(
+                &resolve,
+                world,
+                &[],
+                &[(name.as_str(), func)],
+                &futures_and_streams,
+            )
+            .unwrap_err(),
+        );
+        assert_eq!(err.find(wit()), Some((13, 20)));
+    }
+
+    #[test]
+    fn exported_types() {
+        let mut resolve = Resolve::default();
+        let wit = "package foo:bar; world w { resource r; export f: func(); }";
+        let pkg = resolve
+            .push(UnresolvedPackage::parse(Path::new("foo.wit"), wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        assert!(check_world_exports(&resolve, world).is_ok());
+
+        // Only worlds decoded from components export types, so move the
+        // resource over as decoding would.
+        let w = &mut resolve.worlds[world];
+        let (key, item) = w.imports.pop().unwrap();
+        w.exports.insert(key, item);
+        assert_eq!(
+            check_world_exports(&resolve, world)
+                .unwrap_err()
+                .to_string(),
+            "types exported from worlds are not supported by this generator: \
+             type `r` in world `w` uses them"
+        );
+    }
+
+    #[test]
+    fn items() {
+        let (resolve, world) = resolve();
+        let (key, item) = resolve.worlds[world].imports.first().unwrap();
+        let WorldItem::Interface(id) = item else {
+            unreachable!()
+        };
+        let f = Item::Function("f".to_string());
+        let err = interface_item(&resolve, key, *id, f, "async functions")
+            .downcast::<Unsupported>()
+            .unwrap();
+        assert_eq!(err.find(wit()), Some((7, 13)));
+        assert_eq!(
+            err.to_string(),
+            "async functions are not supported by this generator: \
+             function `f` in interface `foo:bar/i` uses them"
+        );
+
+        let h = Item::Function("h".to_string());
+        let err = world_item(&resolve, world, h, "async functions")
+            .downcast::<Unsupported>()
+            .unwrap();
+        assert_eq!(err.find(wit()), Some((13, 20)));
+    }
+
+    fn item(package: &str, path: Vec<Decl>, item: Item) -> Unsupported {
+        Unsupported {
+            feature: "futures".to_string(),
+            owner: Owner::World(String::new()),
+            item,
+            location: None,
+            scope: Some(Scope {
+                package: Some(package.to_string()),
+                path,
+            }),
+        }
+    }
+
+    #[test]
+    fn locates_items_by_their_scope() {
+        let contents = "// Braces in comments { are skipped
+            package foo:bar@1.0.0;
+
+            /* interface i { record r { } }
+               /* nested */ } */
+            interface other {
+                record r {}
+                f: func();
+            }
+
+            interface i {
+                use other.{r as s};
+                record
+                    r {
+                    x: u32,
+                }
+                resource res {
+                    constructor();
+                    get: func() -> u32;
+                }
+                f: func(
+                    x: u32,
+                );
+            }
+
+            world w {
+                import inline: interface {
+                    f: func();
+                }
+                export f: func();
+            }
+
+            package foo:baz {
+                interface i {
+                    f: func();
+                }
+            }
+        ";
+        let i = Decl::Named("interface", "i".to_string());
+        let w = Decl::Named("world", "w".to_string());
+        let find =
+            |package: &str, path: Vec<Decl>, it: Item| item(package, path, it).find(contents);
+        let ty = |name: &str| Item::Type(name.to_string());
+        let func = |name: &str| Item::Function(name.to_string());
+
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![i.clone()], ty("r")),
+            Some((14, 21))
+        );
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![i.clone()], func("f")),
+            Some((21, 17))
+        );
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![i.clone()], func("[constructor]res")),
+            Some((18, 21))
+        );
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![i.clone()], func("[method]res.get")),
+            Some((19, 21))
+        );
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![w.clone()], func("f")),
+            Some((30, 24))
+        );
+        let inline = Decl::Inline("inline".to_string());
+        assert_eq!(
+            find("foo:bar@1.0.0", vec![w.clone(), inline], func("f")),
+            Some((28, 21))
+        );
+        assert_eq!(find("foo:baz", vec![i.clone()], func("f")), Some((35, 21)));
+        assert_eq!(find("foo:qux", vec![i.clone()], func("f")), None);
+        assert_eq!(find("foo:bar@1.0.0", vec![i], func("g")), None);
+    }
+
+    #[test]
+    fn locates_items_across_files() {
+        let dir = std::env::temp_dir().join(format!("wit-bindgen-locate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [dir.join("a.wit"), dir.join("b.wit")];
+        std::fs::write(
+            &files[0],
+            "package foo:baz;\ninterface i {\n  type t = u32;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &files[1],
+            "package foo:bar;\n\ninterface i {\n  type t = u32;\n}\n",
+        )
+        .unwrap();
+
+        let i = Decl::Named("interface", "i".to_string());
+        let mut err = item("foo:bar", vec![i], Item::Type("t".to_string()));
+        err.locate(&files);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(err.location, Some((files[1].clone(), 4, 8)));
+    }
+}
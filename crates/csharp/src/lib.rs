@@ -15,7 +15,7 @@ use wit_bindgen_core::{
     Direction,
 };
 use wit_bindgen_core::{
-    uwrite, uwriteln,
+    unsupported, uwrite, uwriteln,
     wit_parser::{
         Docs, Enum, Flags, FlagsRepr, Function, FunctionKind, Handle, Int, InterfaceId, Record,
         Resolve, Result_, SizeAlign, Tuple, Type, TypeDefKind, TypeId, TypeOwner, Variant, WorldId,
@@ -184,10 +184,11 @@ impl CSharp {
 }
 
 impl WorldGenerator for CSharp {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         let name = &resolve.worlds[world].name;
         self.name = name.to_string();
         self.sizes.fill(resolve);
+        Ok(())
     }
 
     fn import_interface(
@@ -196,7 +197,8 @@ impl WorldGenerator for CSharp {
         key: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, key, id, &unsupported::futures_and_streams)?;
         let name = interface_name(self, resolve, key, Direction::Import);
         self.interface_names.insert(id, name.clone());
         let mut gen = self.interface(resolve, &name, Direction::Import);
@@ -220,7 +222,15 @@ impl WorldGenerator for CSharp {
 
             let import_module_name = &resolve.name_world_key(key);
             for func in funcs {
-                gen.import(import_module_name, func);
+                gen.import(import_module_name, func).map_err(|feature| {
+                    unsupported::interface_item(
+                        resolve,
+                        key,
+                        id,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
             }
 
             if resource.is_some() {
@@ -232,6 +242,7 @@ impl WorldGenerator for CSharp {
         gen.define_interface_types(id);
 
         gen.add_interface_fragment(false);
+        Ok(())
     }
 
     fn import_funcs(
@@ -240,7 +251,14 @@ impl WorldGenerator for CSharp {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(
+            resolve,
+            world,
+            &[],
+            funcs,
+            &unsupported::futures_and_streams,
+        )?;
         self.import_funcs_called = true;
 
         let name = &format!("{}-world", resolve.worlds[world].name).to_upper_camel_case();
@@ -256,7 +274,14 @@ impl WorldGenerator for CSharp {
             }
 
             for func in funcs {
-                gen.import("$root", func);
+                gen.import("$root", func).map_err(|feature| {
+                    unsupported::world_item(
+                        resolve,
+                        world,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
             }
 
             if resource.is_some() {
@@ -265,6 +290,7 @@ impl WorldGenerator for CSharp {
         }
 
         gen.add_world_fragment();
+        Ok(())
     }
 
     fn export_interface(
@@ -274,6 +300,7 @@ impl WorldGenerator for CSharp {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, key, id, &unsupported::futures_and_streams)?;
         let name = interface_name(self, resolve, key, Direction::Export);
         self.interface_names.insert(id, name.clone());
         let mut gen = self.interface(resolve, &name, Direction::Export);
@@ -296,7 +323,15 @@ impl WorldGenerator for CSharp {
             }
 
             for func in funcs {
-                gen.export(func, Some(key));
+                gen.export(func, Some(key)).map_err(|feature| {
+                    unsupported::interface_item(
+                        resolve,
+                        key,
+                        id,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
             }
 
             if resource.is_some() {
@@ -318,6 +353,13 @@ impl WorldGenerator for CSharp {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(
+            resolve,
+            world,
+            &[],
+            funcs,
+            &unsupported::futures_and_streams,
+        )?;
         let name = &format!("{}-world", resolve.worlds[world].name).to_upper_camel_case();
         let name = &format!("{name}.I{name}");
         let mut gen = self.interface(resolve, name, Direction::Export);
@@ -328,7 +370,14 @@ impl WorldGenerator for CSharp {
             }
 
             for func in funcs {
-                gen.export(func, None);
+                gen.export(func, None).map_err(|feature| {
+                    unsupported::world_item(
+                        resolve,
+                        world,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
             }
 
             if resource.is_some() {
@@ -346,7 +395,14 @@ impl WorldGenerator for CSharp {
         world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(
+            resolve,
+            world,
+            types,
+            &[],
+            &unsupported::futures_and_streams,
+        )?;
         let name = &format!("{}-world", resolve.worlds[world].name).to_upper_camel_case();
         let name = &format!("{name}.I{name}");
         let mut gen = self.interface(resolve, name, Direction::Import);
@@ -361,12 +417,13 @@ impl WorldGenerator for CSharp {
         gen.gen.world_resources = new_resources;
 
         gen.add_world_fragment();
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
         if !self.import_funcs_called {
             // Ensure that we emit type declarations for any top-level imported resource types:
-            self.import_funcs(resolve, id, &[], files);
+            self.import_funcs(resolve, id, &[], files)?;
         }
 
         let world = &resolve.worlds[id];
@@ -906,7 +963,9 @@ impl InterfaceGenerator<'_> {
         });
     }
 
-    fn import(&mut self, import_module_name: &str, func: &Function) {
+    /// Generates the bindings of the imported function `func`, returning the
+    /// feature it uses which isn't supported, if any.
+    fn import(&mut self, import_module_name: &str, func: &Function) -> Result<(), &'static str> {
         let (camel_name, modifiers) = match &func.kind {
             FunctionKind::Freestanding | FunctionKind::Static(_) => {
                 (func.item_name().to_upper_camel_case(), "static")
@@ -983,6 +1042,9 @@ impl InterfaceGenerator<'_> {
             func,
             &mut bindgen,
         );
+        if let Some(feature) = bindgen.unsupported {
+            return Err(feature);
+        }
 
         let src = bindgen.src;
 
@@ -1038,9 +1100,15 @@ impl InterfaceGenerator<'_> {
                 }}
             "#
         );
+        Ok(())
     }
 
-    fn export(&mut self, func: &Function, interface_name: Option<&WorldKey>) {
+    /// Same as `import`, but for the exported function `func`.
+    fn export(
+        &mut self,
+        func: &Function,
+        interface_name: Option<&WorldKey>,
+    ) -> Result<(), &'static str> {
         let (camel_name, modifiers) = match &func.kind {
             FunctionKind::Freestanding | FunctionKind::Static(_) => {
                 (func.item_name().to_upper_camel_case(), "static abstract")
@@ -1067,6 +1135,9 @@ impl InterfaceGenerator<'_> {
             func,
             &mut bindgen,
         );
+        if let Some(feature) = bindgen.unsupported {
+            return Err(feature);
+        }
 
         assert!(!bindgen.needs_cleanup_list);
 
@@ -1178,6 +1249,7 @@ impl InterfaceGenerator<'_> {
                 "#
             );
         }
+        Ok(())
     }
 
     fn type_name(&mut self, ty: &Type) -> String {
@@ -1784,8 +1856,8 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         self.type_name(&Type::Id(id));
     }
 
-    fn type_builtin(&mut self, _id: TypeId, _name: &str, _ty: &Type, _docs: &Docs) {
-        unimplemented!();
+    fn type_builtin(&mut self, id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        self.type_alias(id, name, ty, docs)
     }
 
     fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
@@ -1843,6 +1915,8 @@ struct FunctionBindgen<'a, 'b> {
     import_return_pointer_area_align: usize,
     fixed: usize, // Number of `fixed` blocks that need to be closed.
     resource_drops: Vec<(String, String)>,
+    /// The first feature used by the function which isn't supported.
+    unsupported: Option<&'static str>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
@@ -1874,6 +1948,7 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             import_return_pointer_area_align: 0,
             fixed: 0,
             resource_drops: Vec::new(),
+            unsupported: None,
         }
     }
 
@@ -2547,7 +2622,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 );
             }
 
-            Instruction::CallInterface { func } => {
+            Instruction::CallInterface { func, .. } => {
                 let module = self.gen.name;
                 let func_name = self.func_name.to_upper_camel_case();
                 let interface_name = CSharp::get_class_name_from_qualified_name(module).1;
@@ -2754,6 +2829,20 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 }
                 results.push(resource);
             }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                // Rejected up front by `unsupported::futures_and_streams`.
+                unreachable!("future and stream handles")
+            }
+
+            Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. } => {
+                self.unsupported.get_or_insert("async functions");
+            }
         }
     }
 
@@ -161,3 +161,32 @@ fn mono_verify(dir: &Path, name: &str) {
         _ => {}
     }
 }
+
+#[test]
+fn unsupported_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(
+            UnresolvedPackage::parse(
+                "input.wit".as_ref(),
+                "package foo:bar;
+                world guest {
+                    export next: func() -> future<u32>;
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let world = resolve.select_world(pkg, None).unwrap();
+    let err = wit_bindgen_csharp::Opts::default()
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "futures are not supported by this generator: \
+         function `next` in world `guest` uses them"
+    );
+}
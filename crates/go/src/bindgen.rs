@@ -317,8 +317,9 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
                             self.lower_src.push_str("}\n");
                         }
                     }
-                    TypeDefKind::Future(_) => todo!("impl future"),
-                    TypeDefKind::Stream(_) => todo!("impl stream"),
+                    // Rejected up front by `unsupported_types`.
+                    TypeDefKind::Future(_) => unreachable!("impl future"),
+                    TypeDefKind::Stream(_) => unreachable!("impl stream"),
                     TypeDefKind::Resource => todo!("impl resource"),
                     TypeDefKind::Handle(h) => {
                         match self.interface.direction {
@@ -607,8 +608,9 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
                             self.lift_src.push_str("}\n");
                         }
                     }
-                    TypeDefKind::Future(_) => todo!("impl future"),
-                    TypeDefKind::Stream(_) => todo!("impl stream"),
+                    // Rejected up front by `unsupported_types`.
+                    TypeDefKind::Future(_) => unreachable!("impl future"),
+                    TypeDefKind::Stream(_) => unreachable!("impl stream"),
                     TypeDefKind::Resource => todo!("impl resource"),
                     TypeDefKind::Handle(h) => {
                         match self.interface.direction {
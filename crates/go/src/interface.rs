@@ -10,7 +10,7 @@ use wit_bindgen_core::wit_parser::{
     Docs, Enum, Field, Flags, Function, FunctionKind, Handle, InterfaceId, LiveTypes, Record,
     Resolve, Result_, Tuple, Type, TypeDefKind, TypeId, TypeOwner, Variant, WorldKey,
 };
-use wit_bindgen_core::{uwriteln, Direction, InterfaceGenerator as _, Source};
+use wit_bindgen_core::{uwriteln, Direction, InterfaceGenerator as _, Origin, Source};
 
 use super::{avoid_keyword, bindgen, TinyGo};
 
@@ -32,6 +32,16 @@ pub(crate) struct InterfaceGenerator<'a> {
 }
 
 impl InterfaceGenerator<'_> {
+    /// Marks the code generated from now until `end_origin` as generated from
+    /// `origin` in the source map.
+    pub(crate) fn begin_origin(&mut self, origin: Origin) {
+        self.gen.source_map.begin(self.src.as_mut_string(), origin);
+    }
+
+    pub(crate) fn end_origin(&mut self) {
+        self.gen.source_map.end(self.src.as_mut_string());
+    }
+
     pub(crate) fn define_interface_types(&mut self, id: InterfaceId) {
         let mut live = LiveTypes::default();
         live.add_interface(self.resolve, id);
@@ -77,7 +87,14 @@ impl InterfaceGenerator<'_> {
 
             // define Go types
             match &self.resolve.types[ty].name {
-                Some(name) => self.define_type(name, ty),
+                Some(name) => match Origin::of_type(self.resolve, ty) {
+                    Some(origin) => {
+                        self.begin_origin(origin);
+                        self.define_type(name, ty);
+                        self.end_origin();
+                    }
+                    None => self.define_type(name, ty),
+                },
                 None => self.anonymous_type(ty),
             }
         }
@@ -676,8 +693,9 @@ impl InterfaceGenerator<'_> {
                 // although handles are anonymous types, they are generated in the
                 // `type_resource` function as part of the resource type generation.
             }
-            TypeDefKind::Future(_) => todo!("anonymous_type for future"),
-            TypeDefKind::Stream(_) => todo!("anonymous_type for stream"),
+            // Rejected up front by `unsupported_types`.
+            TypeDefKind::Future(_) => unreachable!("anonymous_type for future"),
+            TypeDefKind::Stream(_) => unreachable!("anonymous_type for stream"),
             TypeDefKind::Unknown => unreachable!(),
         }
     }
@@ -1265,7 +1283,7 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         // no impl since these types are generated as anonymous types
     }
 
-    fn type_builtin(&mut self, _id: TypeId, _name: &str, _ty: &Type, _docs: &Docs) {
-        todo!("type_builtin")
+    fn type_builtin(&mut self, id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        self.type_alias(id, name, ty, docs)
     }
 }
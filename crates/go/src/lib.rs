@@ -7,9 +7,10 @@ use anyhow::Result;
 use heck::ToSnakeCase;
 use wit_bindgen_c::imported_types_used_by_exported_interfaces;
 use wit_bindgen_core::wit_parser::{
-    Function, InterfaceId, LiveTypes, Resolve, SizeAlign, Type, TypeId, WorldId, WorldKey,
+    Function, InterfaceId, LiveTypes, Resolve, SizeAlign, Type, TypeDefKind, TypeId, WorldId,
+    WorldKey,
 };
-use wit_bindgen_core::{Direction, Files, Source, WorldGenerator};
+use wit_bindgen_core::{unsupported, Direction, Files, Origin, Source, SourceMap, WorldGenerator};
 
 mod bindgen;
 mod imports;
@@ -26,6 +27,11 @@ pub struct Opts {
     /// Rename the Go package in the generated source code.
     #[cfg_attr(feature = "clap", arg(long))]
     pub rename_package: Option<String>,
+
+    /// Emit `.wit-map.json` files alongside the Go and C bindings which map
+    /// their lines back to the WIT items they were generated from.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub source_map: bool,
 }
 
 impl Default for Opts {
@@ -33,6 +39,7 @@ impl Default for Opts {
         Self {
             gofmt: true,
             rename_package: None,
+            source_map: false,
         } // Set the default value of gofmt to true
     }
 }
@@ -41,6 +48,7 @@ impl Opts {
     pub fn build(&self) -> Box<dyn WorldGenerator> {
         Box::new(TinyGo {
             opts: self.clone(),
+            source_map: SourceMap::new(self.source_map),
             ..TinyGo::default()
         })
     }
@@ -50,6 +58,7 @@ impl Opts {
 pub struct TinyGo {
     opts: Opts,
     src: Source,
+    source_map: SourceMap,
 
     // the parts immediately precede the import of "C"
     preamble: Source,
@@ -154,7 +163,7 @@ impl TinyGo {
 }
 
 impl WorldGenerator for TinyGo {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         self.world = self
             .opts
             .rename_package
@@ -162,6 +171,7 @@ impl WorldGenerator for TinyGo {
             .unwrap_or_else(|| resolve.worlds[world].name.clone());
         self.sizes.fill(resolve);
         self.world_id = Some(world);
+        Ok(())
     }
 
     fn import_interface(
@@ -170,7 +180,8 @@ impl WorldGenerator for TinyGo {
         name: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &unsupported_types)?;
         let name_raw = &resolve.name_world_key(name);
         self.src
             .push_str(&format!("// Import functions from {name_raw}\n"));
@@ -178,16 +189,21 @@ impl WorldGenerator for TinyGo {
 
         let mut gen = self.interface(resolve, Direction::Import, Some(name_raw));
         gen.interface = Some((id, name));
+        gen.begin_origin(Origin::interface(resolve, name, id));
         gen.define_interface_types(id);
 
         for (_name, func) in resolve.interfaces[id].functions.iter() {
+            gen.begin_origin(Origin::interface(resolve, name, id).func(&func.name));
             gen.import(resolve, func);
+            gen.end_origin();
         }
 
+        gen.end_origin();
         let src = mem::take(&mut gen.src);
         let preamble = mem::take(&mut gen.preamble);
         self.src.push_str(&src);
         self.preamble.append_src(&preamble);
+        Ok(())
     }
 
     fn import_funcs(
@@ -196,7 +212,8 @@ impl WorldGenerator for TinyGo {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         let name = &resolve.worlds[world].name;
         self.src
             .push_str(&format!("// Import functions from {name}\n"));
@@ -205,12 +222,15 @@ impl WorldGenerator for TinyGo {
         gen.define_function_types(funcs);
 
         for (_name, func) in funcs.iter() {
+            gen.begin_origin(Origin::world(resolve, world).func(&func.name));
             gen.import(resolve, func);
+            gen.end_origin();
         }
         let src = mem::take(&mut gen.src);
         let preamble = mem::take(&mut gen.preamble);
         self.src.push_str(&src);
         self.preamble.append_src(&preamble);
+        Ok(())
     }
 
     fn pre_export_interface(&mut self, resolve: &Resolve, _files: &mut Files) -> Result<()> {
@@ -231,6 +251,7 @@ impl WorldGenerator for TinyGo {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &unsupported_types)?;
         self.interface_names.insert(id, name.clone());
         let name_raw = &resolve.name_world_key(name);
         self.src
@@ -238,13 +259,17 @@ impl WorldGenerator for TinyGo {
 
         let mut gen = self.interface(resolve, Direction::Export, None);
         gen.interface = Some((id, name));
+        gen.begin_origin(Origin::interface(resolve, name, id));
         gen.define_interface_types(id);
 
         for (_name, func) in resolve.interfaces[id].functions.iter() {
+            gen.begin_origin(Origin::interface(resolve, name, id).func(&func.name));
             gen.export(resolve, func);
+            gen.end_origin();
         }
 
         gen.finish();
+        gen.end_origin();
 
         let src = mem::take(&mut gen.src);
         let preamble = mem::take(&mut gen.preamble);
@@ -260,6 +285,7 @@ impl WorldGenerator for TinyGo {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         let name = &resolve.worlds[world].name;
         self.src
             .push_str(&format!("// Export functions from {name}\n"));
@@ -268,7 +294,9 @@ impl WorldGenerator for TinyGo {
         gen.define_function_types(funcs);
 
         for (_name, func) in funcs.iter() {
+            gen.begin_origin(Origin::world(resolve, world).func(&func.name));
             gen.export(resolve, func);
+            gen.end_origin();
         }
 
         gen.finish();
@@ -283,10 +311,11 @@ impl WorldGenerator for TinyGo {
     fn import_types(
         &mut self,
         resolve: &Resolve,
-        _world: WorldId,
+        world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, types, &[], &unsupported_types)?;
         let mut gen = self.interface(resolve, Direction::Import, Some("$root"));
         let mut live = LiveTypes::default();
         for (_, id) in types {
@@ -295,6 +324,7 @@ impl WorldGenerator for TinyGo {
         gen.define_live_types(&live);
         let src = mem::take(&mut gen.src);
         self.src.push_str(&src);
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
@@ -350,9 +380,10 @@ impl WorldGenerator for TinyGo {
             let status = child.wait().expect("failed to wait on gofmt");
             assert!(status.success());
         }
-        files.push(&format!("{}.go", world), self.src.as_bytes());
+        files.push_mapped(&format!("{}.go", world), &self.src, &self.source_map);
 
         let mut opts = wit_bindgen_c::Opts::default();
+        opts.source_map = self.opts.source_map;
         opts.no_sig_flattening = true;
         opts.no_object_file = true;
         opts.rename_world = self.opts.rename_package.clone();
@@ -364,6 +395,14 @@ impl WorldGenerator for TinyGo {
     }
 }
 
+/// Describes the types which bindings can't yet be generated for.
+fn unsupported_types(resolve: &Resolve, kind: &TypeDefKind) -> Option<&'static str> {
+    match kind {
+        TypeDefKind::Flags(f) if f.flags.len() > 64 => Some("flags with more than 64 members"),
+        _ => unsupported::futures_and_streams(resolve, kind),
+    }
+}
+
 fn avoid_keyword(s: &str) -> String {
     if GOKEYWORDS.contains(&s) {
         format!("_{s}")
@@ -87,3 +87,32 @@ fn verify(dir: &Path, name: &str) {
     cmd.current_dir(dir);
     test_helpers::run_command(&mut cmd);
 }
+
+#[test]
+fn unsupported_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let flags = (0..65).map(|i| format!("b{i}")).collect::<Vec<_>>();
+    let wit = format!(
+        "package foo:bar;
+        world guest {{
+            flags wide {{ {} }}
+            import get: func() -> wide;
+        }}",
+        flags.join(", ")
+    );
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(UnresolvedPackage::parse("input.wit".as_ref(), &wit).unwrap())
+        .unwrap();
+    let world = resolve.select_world(pkg, None).unwrap();
+    let err = wit_bindgen_go::Opts::default()
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "flags with more than 64 members are not supported by this generator: \
+         type `wide` in world `guest` uses them"
+    );
+}
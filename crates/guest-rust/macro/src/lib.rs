@@ -7,7 +7,7 @@ use syn::parse::{Error, Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::{braced, token, Token};
 use wit_bindgen_core::wit_parser::{PackageId, Resolve, UnresolvedPackage, WorldId};
-use wit_bindgen_rust::{Opts, Ownership};
+use wit_bindgen_rust::{AsyncConfig, Opts, Ownership};
 
 #[proc_macro]
 pub fn generate(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -85,6 +85,9 @@ impl Parse for Config {
                     Opt::RawStrings => opts.raw_strings = true,
                     Opt::Ownership(ownership) => opts.ownership = ownership,
                     Opt::Skip(list) => opts.skip.extend(list.iter().map(|i| i.value())),
+                    Opt::ResultBuffers(list) => {
+                        opts.result_buffers.extend(list.iter().map(|i| i.value()))
+                    }
                     Opt::RuntimePath(path) => opts.runtime_path = Some(path.value()),
                     Opt::BitflagsPath(path) => opts.bitflags_path = Some(path.value()),
                     Opt::Stubs => {
@@ -116,6 +119,16 @@ impl Parse for Config {
                     Opt::GenerateUnusedTypes(enable) => {
                         opts.generate_unused_types = enable.value();
                     }
+                    Opt::Async(async_) => opts.async_ = async_,
+                    Opt::Wasm64(enable) => opts.wasm64 = enable.value(),
+                    Opt::Mock(enable) => opts.mock = enable.value(),
+                    Opt::Serde(enable) => opts.serde = enable.value(),
+                    Opt::Instrument(enable) => opts.instrument = enable.value(),
+                    Opt::Arbitrary(enable) => opts.arbitrary = enable.value(),
+                    Opt::FuzzExports(enable) => opts.fuzz_exports = enable.value(),
+                    Opt::StatefulExports(enable) => opts.stateful_exports = enable.value(),
+                    Opt::InstrumentDebug(enable) => opts.instrument_debug = enable.value(),
+                    Opt::Arena(enable) => opts.arena = enable.value(),
                 }
             }
         } else {
@@ -218,6 +231,7 @@ mod kw {
     syn::custom_keyword!(std_feature);
     syn::custom_keyword!(raw_strings);
     syn::custom_keyword!(skip);
+    syn::custom_keyword!(result_buffers);
     syn::custom_keyword!(world);
     syn::custom_keyword!(path);
     syn::custom_keyword!(inline);
@@ -235,6 +249,16 @@ mod kw {
     syn::custom_keyword!(export_macro_name);
     syn::custom_keyword!(pub_export_macro);
     syn::custom_keyword!(generate_unused_types);
+    syn::custom_keyword!(imports);
+    syn::custom_keyword!(wasm64);
+    syn::custom_keyword!(mock);
+    syn::custom_keyword!(serde);
+    syn::custom_keyword!(instrument);
+    syn::custom_keyword!(instrument_debug);
+    syn::custom_keyword!(arbitrary);
+    syn::custom_keyword!(fuzz_exports);
+    syn::custom_keyword!(stateful_exports);
+    syn::custom_keyword!(arena);
 }
 
 #[derive(Clone)]
@@ -271,6 +295,7 @@ enum Opt {
     UseStdFeature,
     RawStrings,
     Skip(Vec<syn::LitStr>),
+    ResultBuffers(Vec<syn::LitStr>),
     Ownership(Ownership),
     RuntimePath(syn::LitStr),
     BitflagsPath(syn::LitStr),
@@ -285,6 +310,16 @@ enum Opt {
     ExportMacroName(syn::LitStr),
     PubExportMacro(syn::LitBool),
     GenerateUnusedTypes(syn::LitBool),
+    Async(AsyncConfig),
+    Wasm64(syn::LitBool),
+    Mock(syn::LitBool),
+    Serde(syn::LitBool),
+    Instrument(syn::LitBool),
+    InstrumentDebug(syn::LitBool),
+    Arbitrary(syn::LitBool),
+    FuzzExports(syn::LitBool),
+    StatefulExports(syn::LitBool),
+    Arena(syn::LitBool),
 }
 
 impl Parse for Opt {
@@ -353,6 +388,13 @@ impl Parse for Opt {
             syn::bracketed!(contents in input);
             let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
             Ok(Opt::Skip(list.iter().cloned().collect()))
+        } else if l.peek(kw::result_buffers) {
+            input.parse::<kw::result_buffers>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            syn::bracketed!(contents in input);
+            let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
+            Ok(Opt::ResultBuffers(list.iter().cloned().collect()))
         } else if l.peek(kw::runtime_path) {
             input.parse::<kw::runtime_path>()?;
             input.parse::<Token![:]>()?;
@@ -407,12 +449,89 @@ impl Parse for Opt {
             input.parse::<kw::generate_unused_types>()?;
             input.parse::<Token![:]>()?;
             Ok(Opt::GenerateUnusedTypes(input.parse()?))
+        } else if l.peek(kw::wasm64) {
+            input.parse::<kw::wasm64>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Wasm64(input.parse()?))
+        } else if l.peek(kw::mock) {
+            input.parse::<kw::mock>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Mock(input.parse()?))
+        } else if l.peek(kw::serde) {
+            input.parse::<kw::serde>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Serde(input.parse()?))
+        } else if l.peek(kw::instrument) {
+            input.parse::<kw::instrument>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Instrument(input.parse()?))
+        } else if l.peek(kw::instrument_debug) {
+            input.parse::<kw::instrument_debug>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::InstrumentDebug(input.parse()?))
+        } else if l.peek(kw::arbitrary) {
+            input.parse::<kw::arbitrary>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Arbitrary(input.parse()?))
+        } else if l.peek(kw::fuzz_exports) {
+            input.parse::<kw::fuzz_exports>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::FuzzExports(input.parse()?))
+        } else if l.peek(kw::stateful_exports) {
+            input.parse::<kw::stateful_exports>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::StatefulExports(input.parse()?))
+        } else if l.peek(kw::arena) {
+            input.parse::<kw::arena>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Arena(input.parse()?))
+        } else if l.peek(Token![async]) {
+            input.parse::<Token![async]>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Async(parse_async_config(input)?))
         } else {
             Err(l.error())
         }
     }
 }
 
+fn parse_async_config(input: ParseStream<'_>) -> Result<AsyncConfig> {
+    if input.peek(syn::LitBool) {
+        return Ok(if input.parse::<syn::LitBool>()?.value {
+            AsyncConfig::All
+        } else {
+            AsyncConfig::None
+        });
+    }
+
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+    let contents;
+    braced!(contents in input);
+    while !contents.is_empty() {
+        let l = contents.lookahead1();
+        let list = if l.peek(kw::imports) {
+            contents.parse::<kw::imports>()?;
+            &mut imports
+        } else if l.peek(kw::exports) {
+            contents.parse::<kw::exports>()?;
+            &mut exports
+        } else {
+            return Err(l.error());
+        };
+        contents.parse::<Token![:]>()?;
+        let names;
+        syn::bracketed!(names in contents);
+        let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+        list.extend(names.iter().map(|name| name.value()));
+        if contents.is_empty() {
+            break;
+        }
+        contents.parse::<Token![,]>()?;
+    }
+    Ok(AsyncConfig::Some { imports, exports })
+}
+
 fn with_field_parse(input: ParseStream<'_>) -> Result<(String, String)> {
     let interface = input.parse::<syn::LitStr>()?.value();
     input.parse::<Token![:]>()?;
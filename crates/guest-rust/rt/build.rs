@@ -10,6 +10,12 @@ fn main() {
         return;
     }
 
+    // There's no prebuilt object for wasm64, so `cabi_realloc` is defined
+    // directly in Rust instead.
+    if target_arch == "wasm64" {
+        return;
+    }
+
     if target_arch != "wasm32" {
         panic!("only wasm32 supports cabi-realloc right now");
     }
@@ -0,0 +1,153 @@
+//! An arena which `cabi_realloc` allocates from with the `arena` feature.
+//!
+//! The host allocates the arguments of exports, and the results of imports,
+//! with `cabi_realloc`. Serving those allocations from an arena turns them
+//! into little more than a pointer bump, and the arena is reset at the end of
+//! each export call rather than each allocation being freed.
+//!
+//! Memory in the arena never outlives the call which allocated it, so
+//! bindings borrow top-level string and list arguments of exports from it,
+//! copy other lists and strings out of it with [`lift_vec`], and free
+//! temporary allocations with [`dealloc`], which leaves memory in the arena
+//! alone. Bindings generated with the `arena` option do so.
+//!
+//! Enabling the feature alone doesn't make `cabi_realloc` use the arena, as
+//! Cargo may enable it on behalf of another crate. Bindings generated with
+//! the option [`enable`] it when their exports are called, and until then
+//! allocations are made as usual. Bindings generated without the option
+//! don't support the arena, so all bindings of a component which uses it
+//! must be generated with the option.
+//!
+//! Like the rest of the canonical ABI support this is single-threaded.
+
+use alloc::alloc::{self as global, Layout};
+use alloc::vec::Vec;
+use core::ptr;
+
+/// The size of the chunks which the arena allocates from, unless a single
+/// allocation needs a larger one.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct Arena {
+    /// The memory of the arena, which is kept for later calls when it's
+    /// reset.
+    chunks: Vec<(*mut u8, Layout)>,
+    /// The index of the chunk currently being allocated from.
+    current: usize,
+    /// The number of bytes of the current chunk which are in use.
+    used: usize,
+}
+
+static mut ARENA: Arena = Arena {
+    chunks: Vec::new(),
+    current: 0,
+    used: 0,
+};
+
+/// Whether `cabi_realloc` allocates from the arena.
+static mut ENABLED: bool = false;
+
+/// Makes `cabi_realloc` allocate from the arena with the `arena` feature.
+pub fn enable() {
+    // SAFETY: the arena is only used from a single thread.
+    unsafe { ENABLED = true }
+}
+
+/// Returns whether `cabi_realloc` allocates from the arena.
+pub fn enabled() -> bool {
+    // SAFETY: the arena is only used from a single thread.
+    cfg!(feature = "arena") && unsafe { ENABLED }
+}
+
+unsafe fn arena() -> &'static mut Arena {
+    &mut *ptr::addr_of_mut!(ARENA)
+}
+
+/// Allocates `size` bytes aligned to `align` from the arena.
+///
+/// # Safety
+///
+/// `align` must be a power of two and `size` non-zero.
+pub unsafe fn alloc(size: usize, align: usize) -> *mut u8 {
+    let arena = arena();
+    loop {
+        if let Some((chunk, layout)) = arena.chunks.get(arena.current) {
+            let start = (*chunk as usize + arena.used).next_multiple_of(align) - *chunk as usize;
+            if start + size <= layout.size() {
+                arena.used = start + size;
+                return chunk.add(start);
+            }
+            if arena.current + 1 < arena.chunks.len() {
+                arena.current += 1;
+                arena.used = 0;
+                continue;
+            }
+        }
+        let layout = Layout::from_size_align_unchecked(CHUNK_SIZE.max(size), align.max(16));
+        let chunk = global::alloc(layout);
+        if chunk.is_null() {
+            global::handle_alloc_error(layout);
+        }
+        arena.chunks.push((chunk, layout));
+        arena.current = arena.chunks.len() - 1;
+        arena.used = 0;
+    }
+}
+
+/// Returns whether `ptr` points into the memory of the arena which is in use.
+pub fn contains(ptr: *const u8) -> bool {
+    // SAFETY: the arena is only used from a single thread.
+    let arena = unsafe { arena() };
+    let addr = ptr as usize;
+    arena.chunks.iter().enumerate().any(|(i, (chunk, layout))| {
+        let used = match i {
+            i if i < arena.current => layout.size(),
+            i if i == arena.current => arena.used,
+            _ => 0,
+        };
+        (*chunk as usize..*chunk as usize + used).contains(&addr)
+    })
+}
+
+/// Frees all memory allocated from the arena, so that it can be reused.
+///
+/// # Safety
+///
+/// Nothing may refer to memory allocated from the arena afterwards.
+pub unsafe fn reset() {
+    let arena = arena();
+    arena.current = 0;
+    arena.used = 0;
+}
+
+/// Returns the `len` elements at `ptr` allocated by `cabi_realloc` as a
+/// `Vec`, which takes ownership of the allocation unless it's in the arena,
+/// in which case the elements are copied out.
+///
+/// # Safety
+///
+/// `ptr` must be an allocation of `len` elements made by `cabi_realloc`, and
+/// isn't used afterwards.
+pub unsafe fn lift_vec<T>(ptr: *mut T, len: usize) -> Vec<T> {
+    if !contains(ptr.cast()) {
+        return Vec::from_raw_parts(ptr, len, len);
+    }
+    let mut vec = Vec::with_capacity(len);
+    ptr::copy_nonoverlapping(ptr, vec.as_mut_ptr(), len);
+    vec.set_len(len);
+    vec
+}
+
+/// Frees the allocation of `size` bytes aligned to `align` at `ptr`, unless
+/// it's in the arena.
+///
+/// # Safety
+///
+/// `ptr` must have been allocated by `cabi_realloc` or the global allocator
+/// with the given size and alignment.
+pub unsafe fn dealloc(ptr: *mut u8, size: usize, align: usize) {
+    if size == 0 || contains(ptr) {
+        return;
+    }
+    global::dealloc(ptr, Layout::from_size_align_unchecked(size, align));
+}
@@ -0,0 +1,450 @@
+//! Runtime support for the callback-based async canonical ABI.
+//!
+//! Bindings generated for async exports hand the future returned by the
+//! user's implementation to [`first_poll`]. If it doesn't complete
+//! immediately it's stored in a per-call task and the host invokes the
+//! export's callback, which forwards to [`callback`], whenever an event the
+//! task is waiting on arrives. Async imports register themselves with the
+//! task polling them to be woken up when their subtask makes progress.
+//!
+//! Everything here is single-threaded: only one task is polled at a time,
+//! and futures find the task polling them through a scoped global which is
+//! only set for the duration of that poll.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use core::task::{Context, Poll, Waker};
+
+const STATUS_STARTING: u32 = 0;
+const STATUS_STARTED: u32 = 1;
+const STATUS_RETURNED: u32 = 2;
+
+const EVENT_NONE: u32 = 0;
+const EVENT_SUBTASK: u32 = 1;
+
+const CALLBACK_CODE_EXIT: u32 = 0;
+const CALLBACK_CODE_YIELD: u32 = 1;
+const CALLBACK_CODE_WAIT: u32 = 2;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+/// The state of a single waitable a task is waiting on.
+#[derive(Default)]
+struct Waiting {
+    /// Payload of the event for this waitable, once it has arrived.
+    code: Cell<Option<u32>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+/// The parts of a task which the futures it polls have access to.
+#[derive(Default)]
+struct Shared {
+    /// Futures passed to [`spawn`] since the task's futures were last polled.
+    spawned: RefCell<Vec<BoxFuture>>,
+    /// Set all waitables of the task are joined to, created on demand.
+    waitable_set: Cell<Option<u32>>,
+    waiting: RefCell<BTreeMap<u32, Rc<Waiting>>>,
+}
+
+/// The state of a single call to an async export.
+struct Task {
+    /// The export's own future plus anything passed to [`spawn`].
+    futures: Vec<BoxFuture>,
+    shared: Rc<Shared>,
+    woken: Arc<Woken>,
+}
+
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Relaxed);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Relaxed);
+    }
+}
+
+impl Task {
+    fn new(future: BoxFuture) -> Task {
+        Task {
+            futures: alloc::vec![future],
+            shared: Rc::default(),
+            woken: Arc::new(Woken(AtomicBool::new(false))),
+        }
+    }
+
+    /// Polls every future of this task, returning the callback code to hand
+    /// back to the host.
+    fn poll(&mut self) -> u32 {
+        let waker = Waker::from(self.woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            self.woken.0.store(false, Relaxed);
+            {
+                let _current = current::enter(self.shared.clone());
+                let mut i = 0;
+                while i < self.futures.len() {
+                    match self.futures[i].as_mut().poll(&mut cx) {
+                        Poll::Ready(()) => drop(self.futures.swap_remove(i)),
+                        Poll::Pending => i += 1,
+                    }
+                }
+            }
+
+            let spawned = mem::take(&mut *self.shared.spawned.borrow_mut());
+            let any_spawned = !spawned.is_empty();
+            self.futures.extend(spawned);
+
+            if self.futures.is_empty() {
+                return CALLBACK_CODE_EXIT;
+            }
+            if !any_spawned && !self.woken.0.load(Relaxed) {
+                break;
+            }
+        }
+
+        match self.shared.waitable_set.get() {
+            Some(set) => CALLBACK_CODE_WAIT | (set << 4),
+            None => CALLBACK_CODE_YIELD,
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        // Drop any futures first as they may still be waiting on waitables
+        // joined to the set.
+        self.futures.clear();
+        self.shared.spawned.borrow_mut().clear();
+        if let Some(set) = self.shared.waitable_set.get() {
+            unsafe { waitable_set_drop(set) }
+        }
+    }
+}
+
+impl Shared {
+    /// Registers interest in the next event for `waitable`.
+    fn wait_for(&self, waitable: u32) -> Rc<Waiting> {
+        let set = match self.waitable_set.get() {
+            Some(set) => set,
+            None => {
+                let set = unsafe { waitable_set_new() };
+                self.waitable_set.set(Some(set));
+                set
+            }
+        };
+        unsafe { waitable_join(waitable, set) };
+        let waiting = Rc::new(Waiting::default());
+        self.waiting.borrow_mut().insert(waitable, waiting.clone());
+        waiting
+    }
+
+    /// Stops waiting on `waitable`, if this task was waiting on it.
+    fn forget(&self, waitable: u32) {
+        if self.waiting.borrow_mut().remove(&waitable).is_some() {
+            unsafe { waitable_join(waitable, 0) };
+        }
+    }
+
+    /// Delivers the payload `code` of an event for `waitable`.
+    fn deliver(&self, waitable: u32, code: u32) {
+        let waiting = self.waiting.borrow_mut().remove(&waitable);
+        let Some(waiting) = waiting else {
+            return;
+        };
+        unsafe { waitable_join(waitable, 0) };
+        waiting.code.set(Some(code));
+        let waker = waiting.waker.borrow_mut().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Tracks the task whose futures are being polled.
+///
+/// Components are single-threaded, so the task is kept in a global which is
+/// set while the task polls its futures and restored afterwards. Tasks only
+/// exist on such targets, so elsewhere there's never a current task.
+#[cfg(all(target_family = "wasm", not(target_feature = "atomics")))]
+mod current {
+    use super::Shared;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    struct Current(RefCell<Option<Rc<Shared>>>);
+
+    // SAFETY: there's only a single thread on this target.
+    unsafe impl Sync for Current {}
+
+    static CURRENT: Current = Current(RefCell::new(None));
+
+    /// Restores the previously current task when dropped.
+    pub struct Enter(Option<Rc<Shared>>);
+
+    pub fn enter(task: Rc<Shared>) -> Enter {
+        Enter(CURRENT.0.replace(Some(task)))
+    }
+
+    impl Drop for Enter {
+        fn drop(&mut self) {
+            *CURRENT.0.borrow_mut() = self.0.take();
+        }
+    }
+
+    pub fn get() -> Option<Rc<Shared>> {
+        CURRENT.0.borrow().clone()
+    }
+}
+
+#[cfg(not(all(target_family = "wasm", not(target_feature = "atomics"))))]
+mod current {
+    use super::Shared;
+    use alloc::rc::Rc;
+
+    pub struct Enter;
+
+    pub fn enter(_task: Rc<Shared>) -> Enter {
+        unreachable!("async exports are only supported on single-threaded wasm targets")
+    }
+
+    pub fn get() -> Option<Rc<Shared>> {
+        None
+    }
+}
+
+fn current() -> Rc<Shared> {
+    current::get().expect("async operations must be run from within an async export")
+}
+
+/// A future which resolves with the payload of the next event for a
+/// waitable, ceasing to wait on it if dropped beforehand.
+struct WaitFor {
+    waitable: u32,
+    waiting: Option<(Rc<Shared>, Rc<Waiting>)>,
+}
+
+fn wait_for(waitable: u32) -> WaitFor {
+    WaitFor {
+        waitable,
+        waiting: None,
+    }
+}
+
+impl Future for WaitFor {
+    type Output = u32;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+        let waitable = self.waitable;
+        let (_, waiting) = self.waiting.get_or_insert_with(|| {
+            let task = current();
+            let waiting = task.wait_for(waitable);
+            (task, waiting)
+        });
+        match waiting.code.take() {
+            Some(code) => {
+                self.waiting = None;
+                Poll::Ready(code)
+            }
+            None => {
+                *waiting.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for WaitFor {
+    fn drop(&mut self) {
+        if let Some((task, _)) = self.waiting.take() {
+            task.forget(self.waitable);
+        }
+    }
+}
+
+/// An in-progress call to an async import, which is cancelled if dropped
+/// before it returns.
+struct Subtask(Option<WaitFor>);
+
+impl Future for Subtask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        while let Some(wait) = &mut self.0 {
+            let status = match Pin::new(&mut *wait).poll(cx) {
+                Poll::Ready(status) => status,
+                Poll::Pending => return Poll::Pending,
+            };
+            let subtask = wait.waitable;
+            if status == STATUS_RETURNED {
+                self.0 = None;
+                unsafe { subtask_drop(subtask) }
+            } else {
+                *wait = wait_for(subtask);
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Subtask {
+    fn drop(&mut self) {
+        let Some(wait) = self.0.take() else {
+            return;
+        };
+        // The import may still read its parameters or write its results,
+        // both of which live in the future being dropped, so block until
+        // it's been cancelled or has returned before that memory is freed.
+        let subtask = wait.waitable;
+        drop(wait);
+        unsafe {
+            subtask_cancel(subtask);
+            subtask_drop(subtask);
+        }
+    }
+}
+
+/// Polls `future` for the first time on behalf of an async export, calling
+/// `fun` with its output once it resolves.
+///
+/// Returns the callback code to return from the export.
+///
+/// # Safety
+///
+/// Must only be called from the lifting function of an async export, and at
+/// most once per call to it.
+#[doc(hidden)]
+pub unsafe fn first_poll<T: 'static>(
+    future: impl Future<Output = T> + 'static,
+    fun: impl FnOnce(T) + 'static,
+) -> i32 {
+    let mut task = Box::new(Task::new(Box::pin(async move { fun(future.await) })));
+    let code = task.poll();
+    if code != CALLBACK_CODE_EXIT {
+        context_set(Box::into_raw(task) as usize as u32);
+    }
+    code as i32
+}
+
+/// Handles an event delivered to the callback of an async export.
+///
+/// Returns the callback code to return from the callback.
+///
+/// # Safety
+///
+/// Must only be called from the callback of an async export whose call to
+/// [`first_poll`] didn't exit.
+#[doc(hidden)]
+pub unsafe fn callback(event0: u32, event1: u32, event2: u32) -> i32 {
+    let task = context_get() as usize as *mut Task;
+    assert!(!task.is_null());
+    match event0 {
+        EVENT_NONE => {}
+        EVENT_SUBTASK => (*task).shared.deliver(event1, event2),
+        _ => unreachable!("unexpected event: {event0}"),
+    }
+    let code = (*task).poll();
+    if code == CALLBACK_CODE_EXIT {
+        context_set(0);
+        drop(Box::from_raw(task));
+    }
+    code as i32
+}
+
+/// Waits for an async import to complete given the status `code` it
+/// returned.
+///
+/// If the returned future is dropped before the import returns, the import
+/// is cancelled and this blocks until it's no longer using its parameters
+/// or results.
+///
+/// # Safety
+///
+/// The memory for the import's parameters and results must stay valid until
+/// the returned future resolves or is dropped.
+#[doc(hidden)]
+pub unsafe fn await_result(code: u32) -> impl Future<Output = ()> {
+    let status = code & 0xf;
+    let subtask = code >> 4;
+    match status {
+        STATUS_STARTING | STATUS_STARTED => Subtask(Some(wait_for(subtask))),
+        STATUS_RETURNED => Subtask(None),
+        _ => unreachable!("unexpected subtask status: {status}"),
+    }
+}
+
+/// Runs `future` concurrently with the rest of the current async export.
+///
+/// The export's task is only finished once `future` has resolved as well.
+///
+/// # Panics
+///
+/// Panics if not called from within an async export.
+pub fn spawn(future: impl Future<Output = ()> + 'static) {
+    current().spawned.borrow_mut().push(Box::pin(future));
+}
+
+#[cfg(target_family = "wasm")]
+#[link(wasm_import_module = "$root")]
+extern "C" {
+    #[link_name = "[context-get-0]"]
+    fn context_get() -> u32;
+    #[link_name = "[context-set-0]"]
+    fn context_set(value: u32);
+    #[link_name = "[waitable-set-new]"]
+    fn waitable_set_new() -> u32;
+    #[link_name = "[waitable-set-drop]"]
+    fn waitable_set_drop(set: u32);
+    #[link_name = "[waitable-join]"]
+    fn waitable_join(waitable: u32, set: u32);
+    #[link_name = "[subtask-cancel]"]
+    fn subtask_cancel(subtask: u32) -> u32;
+    #[link_name = "[subtask-drop]"]
+    fn subtask_drop(subtask: u32);
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn context_get() -> u32 {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn context_set(_value: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn waitable_set_new() -> u32 {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn waitable_set_drop(_set: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn waitable_join(_waitable: u32, _set: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn subtask_cancel(_subtask: u32) -> u32 {
+    unreachable!()
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn subtask_drop(_subtask: u32) {
+    unreachable!()
+}
@@ -2,6 +2,9 @@
 
 extern crate alloc;
 
+use alloc::vec::Vec;
+use core::ptr;
+
 // Re-export `bitflags` so that we can reference it from macros.
 #[cfg(feature = "bitflags")]
 #[doc(hidden)]
@@ -11,6 +14,11 @@ pub use bitflags;
 #[cfg(not(target_env = "p2"))]
 mod cabi_realloc;
 
+#[cfg(feature = "async")]
+pub mod async_support;
+
+pub mod arena;
+
 /// This function is called from generated bindings and will be deleted by
 /// the linker. The purpose of this function is to force a reference to the
 /// symbol `cabi_realloc` to make its way through to the final linker
@@ -64,8 +72,34 @@ pub unsafe fn cabi_realloc(
         if new_len == 0 {
             return align as *mut u8;
         }
+        if let Some(buf) = &mut *ptr::addr_of_mut!(RESULT_BUFFER) {
+            if !buf.taken && new_len <= buf.capacity && (buf.ptr as usize) & (align - 1) == 0 {
+                buf.taken = true;
+                return buf.ptr;
+            }
+        }
+        if arena::enabled() {
+            return arena::alloc(new_len, align);
+        }
         layout = Layout::from_size_align_unchecked(new_len, align);
         alloc::alloc(layout)
+    } else if let Some(buf) = (*ptr::addr_of!(RESULT_BUFFER))
+        .as_ref()
+        .filter(|buf| buf.ptr == old_ptr)
+    {
+        // The caller's buffer is owned by its `Vec`, so it can grow in place
+        // up to its capacity but is otherwise copied into a new allocation.
+        if new_len <= buf.capacity {
+            return old_ptr;
+        }
+        let ptr = cabi_realloc(ptr::null_mut(), 0, align, new_len);
+        core::ptr::copy_nonoverlapping(old_ptr, ptr, old_len);
+        return ptr;
+    } else if arena::contains(old_ptr) {
+        // Memory in the arena can't grow in place, so it's moved to the end.
+        let ptr = arena::alloc(new_len, align);
+        core::ptr::copy_nonoverlapping(old_ptr, ptr, old_len.min(new_len));
+        return ptr;
     } else {
         debug_assert_ne!(new_len, 0, "non-zero old_len requires non-zero new_len!");
         layout = Layout::from_size_align_unchecked(old_len, align);
@@ -87,13 +121,86 @@ pub unsafe fn cabi_realloc(
     return ptr;
 }
 
+/// Exports `cabi_realloc` on wasm64, where there's no prebuilt object to do
+/// so as there is for wasm32.
+///
+/// Unlike the prebuilt object's definition this symbol isn't weak, so it's
+/// only exported with the `realloc` feature to leave room for other
+/// definitions. For more information about this see
+/// `./ci/rebuild-libcabi-realloc.sh`.
+#[cfg(all(target_arch = "wasm64", feature = "realloc"))]
+#[export_name = "cabi_realloc"]
+unsafe extern "C" fn cabi_realloc_wasm64(
+    old_ptr: *mut u8,
+    old_len: usize,
+    align: usize,
+    new_len: usize,
+) -> *mut u8 {
+    cabi_realloc(old_ptr, old_len, align, new_len)
+}
+
+/// Conversions between the WIT type `T` and a Rust type it's remapped to with
+/// `with`, used when the Rust type is passed to or returned from functions.
+pub trait WitConvert<T> {
+    /// Converts a value of the WIT type lifted from a call.
+    fn from_wit(value: T) -> Self;
+
+    /// Converts this value to the WIT type to be lowered into a call.
+    fn into_wit(self) -> T;
+}
+
+/// The allocation of a caller's buffer which `cabi_realloc` returns for the
+/// result of an import if it fits.
+struct ResultBuffer {
+    ptr: *mut u8,
+    /// The capacity of the buffer in bytes.
+    capacity: usize,
+    /// Whether `cabi_realloc` has returned the buffer for the result yet.
+    taken: bool,
+}
+
+static mut RESULT_BUFFER: Option<ResultBuffer> = None;
+
+/// Starts a call to an import whose result is written into `buf`, clearing it
+/// so that `cabi_realloc` can allocate the result in its place.
+///
+/// # Safety
+///
+/// The result must be the only allocation the import makes, and the call must
+/// be ended with `finish_result_buffer`.
+pub unsafe fn start_result_buffer<T>(buf: &mut Vec<T>) {
+    buf.clear();
+    RESULT_BUFFER = Some(ResultBuffer {
+        ptr: buf.as_mut_ptr().cast(),
+        capacity: buf.capacity() * core::mem::size_of::<T>(),
+        taken: false,
+    });
+}
+
+/// Ends a call started with `start_result_buffer`, making `buf` hold the `len`
+/// elements at `ptr` which the import returned.
+///
+/// # Safety
+///
+/// `ptr` and `len` must be the list returned by the import.
+pub unsafe fn finish_result_buffer<T>(buf: &mut Vec<T>, ptr: *mut T, len: usize) {
+    RESULT_BUFFER = None;
+    // The result is either in the buffer already, or was allocated separately
+    // and replaces it.
+    if ptr == buf.as_mut_ptr() {
+        buf.set_len(len);
+    } else if len > 0 {
+        *buf = arena::lift_vec(ptr, len);
+    }
+}
+
 /// Provide a hook for generated export functions to run static constructors at
 /// most once.
 ///
 /// wit-bindgen-rust generates a call to this function at the start of all
 /// component export functions. Importantly, it is not called as part of
 /// `cabi_realloc`, which is a *core* export func, but should not execute ctors.
-#[cfg(target_arch = "wasm32")]
+#[cfg(target_family = "wasm")]
 pub fn run_ctors_once() {
     static mut RUN: bool = false;
     unsafe {
@@ -684,9 +684,19 @@
 ///     // indicator that any further references to types defined in these
 ///     // interfaces should use the upstream paths specified here instead.
 ///     //
+///     // Individual types can be remapped as well, with keys of the form
+///     // `<interface>/<type>` or just `<type>` for types imported by the
+///     // world. Functions taking or returning the type directly then use the
+///     // Rust type instead, and the `WitConvert` trait, re-exported at the
+///     // root of the bindings, must be implemented to convert between the
+///     // two, as in `impl WitConvert<my::pkg::geometry::Point> for glam::Vec2`.
+///     // The type can't be used within other types, such as a record field
+///     // or `list<T>`, and resources can't be remapped this way.
+///     //
 ///     // Any unused keys in this map are considered an error.
 ///     with: {
 ///         "wasi:io/poll": wasi::io::poll,
+///         "my:pkg/geometry/point": glam::Vec2,
 ///     },
 ///
 ///     // An optional list of function names to skip generating bindings for.
@@ -694,6 +704,17 @@
 ///     // of the function.
 ///     skip: ["foo", "bar", "baz"],
 ///
+///     // An optional list of names of imported functions returning a `string`
+///     // or a list of numbers which additionally get a `<name>_into` binding,
+///     // taking a `buf: &mut String` or `buf: &mut Vec<T>` parameter which
+///     // the result is written into instead of returned. The allocation of
+///     // `buf` is reused for the result if it's large enough, which avoids
+///     // allocating in loops calling the import repeatedly. Functions in
+///     // interfaces are named `<interface>#<function>`, like the keys of
+///     // `with`, and functions in the world by their name only. Async
+///     // imports aren't supported.
+///     result_buffers: ["wasi:io/streams#[method]input-stream.read"],
+///
 ///     // Configuration of how Rust types are generated.
 ///     //
 ///     // This option will change how WIT types are mapped to Rust types. There
@@ -790,6 +811,72 @@
 ///     // By default, they will not be generated unless they are used as input
 ///     // or return value of a function.
 ///     generate_unused_types: false,
+///
+///     // Which functions use the async canonical ABI, making them `async fn`s
+///     // in Rust. May be `true` to make all functions async, or list the
+///     // imports and exports which should be async. Names are either the name
+///     // of a world-level function or `<interface>#<function>`.
+///     // Requires the `async` feature of this crate.
+///     async: {
+///         imports: ["wasi:http/types@0.2.0#[method]body.finish"],
+///         exports: ["handle"],
+///     },
+///
+///     // Generate bindings for a 64-bit linear memory, for use with the
+///     // `wasm64-unknown-unknown` target.
+///     wasm64: false,
+///
+///     // When compiling for targets other than wasm, such as for `cargo test`
+///     // on the host, make imported functions call a mock implementing the
+///     // `Mock` trait generated for each interface (and the world itself),
+///     // installed for the current thread with the generated `set_mock`.
+///     mock: false,
+///
+///     // Implement `serde::Serialize` and `serde::Deserialize` for generated
+///     // records, variants, enums and flags, serialized with their WIT names.
+///     // Types containing resources are skipped, and types borrowing their
+///     // contents are only `Serialize`. Requires a dependency on `serde` with
+///     // its `derive` feature.
+///     serde: false,
+///
+///     // Trace each call to a synchronous import and of a synchronous export
+///     // in a `tracing` span named after its WIT interface and function, like
+///     // `my:pkg/iface#func`. Requires a dependency on `tracing`.
+///     instrument: false,
+///
+///     // Also record the `Debug` representation of arguments and results in
+///     // the spans of `instrument`, which this implies. Results are recorded
+///     // in the `wit_bindgen.result` field.
+///     instrument_debug: false,
+///
+///     // Implement `arbitrary::Arbitrary` for generated records, variants,
+///     // enums and flags which don't contain resources or borrow their
+///     // contents. Requires a dependency on `arbitrary` with its `derive`
+///     // feature.
+///     arbitrary: false,
+///
+///     // Emit a `fuzz_exports::<T>(data)` function at the root of the
+///     // bindings which decodes `data` into a sequence of calls to the
+///     // exports implemented by `T`, for use in a `cargo fuzz` target. This
+///     // implies `arbitrary`.
+///     fuzz_exports: false,
+///
+///     // Call the functions exported by the world on an instance of the type
+///     // passed to `export!`, taking `&mut self`, instead of on the type
+///     // itself. The instance is constructed when an export is first called,
+///     // with `Default` for `export!(MyType)` or with the given constructor
+///     // for `export!(MyType with MyType::new)`.
+///     stateful_exports: false,
+///
+///     // Support the arena which `cabi_realloc` allocates from with the
+///     // `arena` feature of this crate: exports borrow top-level string and
+///     // list parameters from it as `&str` and `&[T]`, other lists and
+///     // strings are copied out of it, and it's reset when each export
+///     // returns. Async exports aren't supported then. The feature only
+///     // makes `cabi_realloc` use the arena once these exports are called,
+///     // so every invocation of this macro in a component using it must set
+///     // this option.
+///     arena: false,
 /// });
 /// ```
 ///
@@ -812,7 +899,7 @@ pub mod rt {
     // Re-export `bitflags` so that we can reference it from macros.
     pub use wit_bindgen_rt::bitflags;
 
-    #[cfg(target_arch = "wasm32")]
+    #[cfg(target_family = "wasm")]
     pub use wit_bindgen_rt::run_ctors_once;
 
     pub fn maybe_link_cabi_realloc() {
@@ -823,5 +910,14 @@ pub mod rt {
     #[cfg(all(feature = "realloc", not(target_env = "p2")))]
     pub use wit_bindgen_rt::cabi_realloc;
 
+    pub use wit_bindgen_rt::{finish_result_buffer, start_result_buffer};
+
+    pub use wit_bindgen_rt::arena;
+
+    pub use wit_bindgen_rt::WitConvert;
+
+    #[cfg(feature = "async")]
+    pub use wit_bindgen_rt::async_support;
+
     pub use crate::pre_wit_bindgen_0_20_0::*;
 }
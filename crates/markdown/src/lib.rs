@@ -4,7 +4,7 @@ use pulldown_cmark::{html, Event, LinkType, Parser, Tag};
 use std::collections::HashMap;
 use std::fmt::Write;
 use wit_bindgen_core::{
-    uwriteln, wit_parser, Files, InterfaceGenerator as _, Source, WorldGenerator,
+    unsupported, uwriteln, wit_parser, Files, InterfaceGenerator as _, Source, WorldGenerator,
 };
 use wit_parser::*;
 
@@ -37,7 +37,7 @@ impl Opts {
 }
 
 impl WorldGenerator for Markdown {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         self.sizes.fill(resolve);
 
         let world = &resolve.worlds[world];
@@ -109,6 +109,7 @@ impl WorldGenerator for Markdown {
             }
         }
         gen.push_str("\n");
+        Ok(())
     }
 
     fn import_interface(
@@ -117,7 +118,8 @@ impl WorldGenerator for Markdown {
         name: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &supports_all)?;
         let name = resolve.name_world_key(name);
         uwriteln!(
             self.src,
@@ -131,6 +133,7 @@ impl WorldGenerator for Markdown {
         gen.push_str("\n");
         gen.types(id);
         gen.funcs(id);
+        Ok(())
     }
 
     fn import_funcs(
@@ -139,13 +142,15 @@ impl WorldGenerator for Markdown {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &supports_all)?;
         let name = &resolve.worlds[world].name;
         uwriteln!(self.src, "## Imported functions to world `{name}`\n");
         let mut gen = self.interface(resolve);
         for (_, func) in funcs {
             gen.func(func);
         }
+        Ok(())
     }
 
     fn export_interface(
@@ -155,6 +160,7 @@ impl WorldGenerator for Markdown {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &supports_all)?;
         let name = resolve.name_world_key(name);
         uwriteln!(
             self.src,
@@ -176,6 +182,7 @@ impl WorldGenerator for Markdown {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &supports_all)?;
         let name = &resolve.worlds[world].name;
         uwriteln!(self.src, "## Exported functions from world `{name}`\n");
         let mut gen = self.interface(resolve);
@@ -191,13 +198,15 @@ impl WorldGenerator for Markdown {
         world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, types, &[], &supports_all)?;
         let name = &resolve.worlds[world].name;
         uwriteln!(self.src, "## Exported types from world `{name}`\n");
         let mut gen = self.interface(resolve);
         for (name, ty) in types {
             gen.define_type(name, *ty);
         }
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
@@ -232,6 +241,12 @@ impl WorldGenerator for Markdown {
     }
 }
 
+/// Markdown documents every kind of type, so only the checks which
+/// `unsupported::check_interface` makes for all generators apply.
+fn supports_all(_resolve: &Resolve, _kind: &TypeDefKind) -> Option<&'static str> {
+    None
+}
+
 impl Markdown {
     fn interface<'a>(&'a mut self, resolve: &'a Resolve) -> InterfaceGenerator<'_> {
         InterfaceGenerator {
@@ -672,3 +687,39 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         self.type_alias(id, name, ty, docs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Resolve, UnresolvedPackage};
+
+    #[test]
+    fn rejects_handle_aliases() {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "input.wit".as_ref(),
+                    "package foo:bar;
+                    interface files {
+                        resource file;
+                        type owned = own<file>;
+                    }
+                    world docs {
+                        import files;
+                    }",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let err = super::Opts::default()
+            .build()
+            .generate(&resolve, world, &mut Default::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "type aliases of handles are not supported by this generator: \
+             type `owned` in interface `foo:bar/files` uses them"
+        );
+    }
+}
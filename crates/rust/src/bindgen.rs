@@ -1,5 +1,7 @@
+use crate::interface::{record_span_field, RESULT_SPAN_FIELD};
 use crate::{int_repr, to_rust_ident, wasm_type, InterfaceGenerator, RustFlagsRepr};
 use heck::*;
+use std::collections::HashMap;
 use std::fmt::Write as _;
 use std::mem;
 use wit_bindgen_core::abi::{Bindgen, Instruction, LiftLower, WasmType};
@@ -17,6 +19,19 @@ pub(super) struct FunctionBindgen<'a, 'b> {
     pub import_return_pointer_area_size: usize,
     pub import_return_pointer_area_align: usize,
     pub handle_decls: Vec<String>,
+    /// Offset into `src` of the start of the closure which lowers the
+    /// results of an async export.
+    pub async_result_start: Option<usize>,
+    /// Whether the result of an import is written into the caller's `buf`
+    /// rather than returned.
+    pub result_buffer: bool,
+    /// Whether arguments and results are recorded in the span which the call
+    /// is traced in with `instrument_debug`.
+    pub record_values: bool,
+    /// Lists and strings lifted with the `arena` option, by the expression
+    /// copying them out of the memory the host allocated them in, mapped to
+    /// the expression borrowing them from it and the statement freeing it.
+    arena_lifts: HashMap<String, (String, String)>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
@@ -36,6 +51,10 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             import_return_pointer_area_size: 0,
             import_return_pointer_area_align: 0,
             handle_decls: Vec::new(),
+            async_result_start: None,
+            result_buffer: false,
+            record_values: false,
+            arena_lifts: HashMap::new(),
         }
     }
 
@@ -78,23 +97,53 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             sig.push_str(" -> ");
             sig.push_str(wasm_type(*result));
         }
+        let wasm = self.gen.gen.wasm_cfg();
         uwrite!(
             self.src,
             "
-                #[cfg(target_arch = \"wasm32\")]
+                #[cfg({wasm})]
                 #[link(wasm_import_module = \"{module_name}\")]
                 extern \"C\" {{
                     #[link_name = \"{name}\"]
                     fn wit_import{sig};
                 }}
 
-                #[cfg(not(target_arch = \"wasm32\"))]
+                #[cfg(not({wasm}))]
                 fn wit_import{sig} {{ unreachable!() }}
             "
         );
         "wit_import".to_string()
     }
 
+    /// Rebinds each of the `results` of `func` whose type is remapped with
+    /// `with`, converting it to the WIT type if `into_wit` is set or from it
+    /// otherwise.
+    fn convert_remapped_results(&mut self, func: &Function, results: &[String], into_wit: bool) {
+        for (result, ty) in results.iter().zip(func.results.iter_types()) {
+            if let Some(converted) = self.gen.convert_remapped(ty, result, into_wit) {
+                uwriteln!(self.src, "let {result} = {converted};");
+            }
+        }
+    }
+
+    /// Records `results`, the Rust values of the results of `func`, in the
+    /// `result` field of the span which the call is traced in.
+    fn record_results(&mut self, results: &[String]) {
+        let value = match results {
+            [] => return,
+            [result] => result.clone(),
+            _ => format!(
+                "({})",
+                results
+                    .iter()
+                    .map(|r| format!("&{r}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        self.push_str(&record_span_field(RESULT_SPAN_FIELD, &value));
+    }
+
     fn let_results(&mut self, amt: usize, results: &mut Vec<String>) {
         match amt {
             0 => {}
@@ -258,6 +307,9 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     }
 
     fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        if size == 0 {
+            return "::core::ptr::null_mut::<u8>()".to_string();
+        }
         let tmp = self.tmp();
 
         // Imports get a per-function return area to facilitate using the
@@ -389,21 +441,36 @@ impl Bindgen for FunctionBindgen<'_, '_> {
             Instruction::FlagsLower { flags, .. } => {
                 let tmp = self.tmp();
                 self.push_str(&format!("let flags{} = {};\n", tmp, operands[0]));
+                let words = matches!(RustFlagsRepr::new(flags), RustFlagsRepr::Words(_));
                 for i in 0..flags.repr().count() {
-                    results.push(format!("(flags{}.bits() >> {}) as i32", tmp, i * 32));
+                    if words {
+                        results.push(format!("flags{tmp}.bits().0[{i}] as i32"));
+                    } else {
+                        results.push(format!("(flags{}.bits() >> {}) as i32", tmp, i * 32));
+                    }
                 }
             }
             Instruction::FlagsLift { flags, ty, .. } => {
                 let repr = RustFlagsRepr::new(flags);
                 let name = self.gen.type_path(*ty, true);
-                let mut result = format!("{name}::empty()");
-                for (i, op) in operands.iter().enumerate() {
-                    result.push_str(&format!(
-                        " | {name}::from_bits_retain((({op} as {repr}) << {}) as _)",
-                        i * 32
-                    ));
+                if let RustFlagsRepr::Words(_) = repr {
+                    let bits = self.gen.path_to_flags_words();
+                    let words = operands
+                        .iter()
+                        .map(|op| format!("{op} as u32"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    results.push(format!("{name}::from_bits_retain({bits}([{words}]))"));
+                } else {
+                    let mut result = format!("{name}::empty()");
+                    for (i, op) in operands.iter().enumerate() {
+                        result.push_str(&format!(
+                            " | {name}::from_bits_retain((({op} as {repr}) << {}) as _)",
+                            i * 32
+                        ));
+                    }
+                    results.push(result);
                 }
-                results.push(result);
             }
 
             Instruction::HandleLower {
@@ -456,6 +523,14 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(result);
             }
 
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                // Rejected up front by `unsupported_types`.
+                unreachable!("future and stream handles")
+            }
+
             Instruction::RecordLower { ty, record, .. } => {
                 self.record_lower(*ty, record, &operands[0], results);
             }
@@ -653,15 +728,35 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(len);
             }
 
-            Instruction::ListCanonLift { .. } => {
+            Instruction::ListCanonLift { element, .. } => {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 self.push_str(&format!("let {} = {};\n", len, operands[1]));
-                let vec = self.gen.path_to_vec();
-                let result = format!(
-                    "{vec}::from_raw_parts({}.cast(), {1}, {1})",
-                    operands[0], len
-                );
+                if self.result_buffer {
+                    let finish = self.gen.path_to_finish_result_buffer();
+                    uwriteln!(self.src, "{finish}(buf, {}.cast(), {len});", operands[0]);
+                    results.push("()".to_string());
+                    return;
+                }
+                let result = if self.gen.gen.opts.arena {
+                    let arena = self.gen.path_to_arena();
+                    let ptr = format!("ptr{tmp}");
+                    uwriteln!(self.src, "let {ptr} = {};", operands[0]);
+                    let result = format!("{arena}::lift_vec({ptr}.cast(), {len})");
+                    let borrowed = format!("::core::slice::from_raw_parts({ptr}.cast(), {len})");
+                    let size = self.gen.sizes.size(element);
+                    let align = self.gen.sizes.align(element);
+                    let dealloc =
+                        format!("{arena}::dealloc({ptr}.cast(), {len} * {size}, {align});");
+                    self.arena_lifts.insert(result.clone(), (borrowed, dealloc));
+                    result
+                } else {
+                    let vec = self.gen.path_to_vec();
+                    format!(
+                        "{vec}::from_raw_parts({}.cast(), {len}, {len})",
+                        operands[0]
+                    )
+                };
                 results.push(result);
             }
 
@@ -686,20 +781,58 @@ impl Bindgen for FunctionBindgen<'_, '_> {
             }
 
             Instruction::StringLift => {
-                let vec = self.gen.path_to_vec();
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 uwriteln!(self.src, "let {len} = {};", operands[1]);
-                uwriteln!(
-                    self.src,
-                    "let bytes{tmp} = {vec}::from_raw_parts({}.cast(), {len}, {len});",
-                    operands[0],
-                );
-                if self.gen.gen.opts.raw_strings {
-                    results.push(format!("bytes{tmp}"));
+                if self.result_buffer {
+                    let finish = self.gen.path_to_finish_result_buffer();
+                    let buf = self.gen.result_buffer_vec(&Type::String);
+                    uwriteln!(self.src, "{finish}({buf}, {}.cast(), {len});", operands[0]);
+                    if !self.gen.gen.opts.raw_strings {
+                        self.push_str("if cfg!(debug_assertions) {\n");
+                        self.push_str("::core::str::from_utf8(buf.as_bytes()).unwrap();\n");
+                        self.push_str("}\n");
+                    }
+                    results.push("()".to_string());
+                    return;
+                }
+                let bytes = if self.gen.gen.opts.arena {
+                    let arena = self.gen.path_to_arena();
+                    let ptr = format!("ptr{tmp}");
+                    uwriteln!(self.src, "let {ptr} = {}.cast::<u8>();", operands[0]);
+                    format!("{arena}::lift_vec({ptr}, {len})")
                 } else {
-                    results.push(format!("{}(bytes{tmp})", self.gen.path_to_string_lift()));
+                    let vec = self.gen.path_to_vec();
+                    uwriteln!(
+                        self.src,
+                        "let bytes{tmp} = {vec}::from_raw_parts({}.cast(), {len}, {len});",
+                        operands[0],
+                    );
+                    format!("bytes{tmp}")
+                };
+                let result = if self.gen.gen.opts.raw_strings {
+                    bytes
+                } else {
+                    format!("{}({bytes})", self.gen.path_to_string_lift())
+                };
+                if self.gen.gen.opts.arena {
+                    let arena = self.gen.path_to_arena();
+                    let bytes = format!("::core::slice::from_raw_parts(ptr{tmp}, {len})");
+                    let borrowed = if self.gen.gen.opts.raw_strings {
+                        bytes
+                    } else {
+                        format!(
+                            "if cfg!(debug_assertions) {{\n\
+                                 ::core::str::from_utf8({bytes}).unwrap()\n\
+                             }} else {{\n\
+                                 ::core::str::from_utf8_unchecked({bytes})\n\
+                             }}"
+                        )
+                    };
+                    let dealloc = format!("{arena}::dealloc(ptr{tmp}, {len}, 1);");
+                    self.arena_lifts.insert(result.clone(), (borrowed, dealloc));
                 }
+                results.push(result);
             }
 
             Instruction::ListLower { element, realloc } => {
@@ -797,17 +930,35 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.push_str(");\n");
             }
 
-            Instruction::CallInterface { func, .. } => {
-                self.let_results(func.results.len(), results);
+            Instruction::AsyncCallWasm {
+                name,
+                results_offset,
+            } => {
+                let func = self.declare_import(
+                    self.gen.wasm_import_module.unwrap(),
+                    &format!("[async-lower]{name}"),
+                    &[WasmType::Pointer, WasmType::Pointer],
+                    &[WasmType::I32],
+                );
+                let async_support = self.gen.path_to_async_support();
+                let ptr = &operands[0];
+                uwriteln!(
+                    self.src,
+                    "{async_support}::await_result({func}({ptr}, {ptr}.add({results_offset})) as u32).await;"
+                );
+            }
+
+            Instruction::CallInterface { func, async_ } => {
+                let mut call = String::new();
                 match &func.kind {
                     FunctionKind::Freestanding => {
-                        self.push_str(&format!("T::{}", to_rust_ident(&func.name)));
+                        call.push_str(&format!("T::{}", to_rust_ident(&func.name)));
                     }
                     FunctionKind::Method(_) | FunctionKind::Static(_) => {
-                        self.push_str(&format!("T::{}", to_rust_ident(func.item_name())));
+                        call.push_str(&format!("T::{}", to_rust_ident(func.item_name())));
                     }
                     FunctionKind::Constructor(ty) => {
-                        self.push_str(&format!(
+                        call.push_str(&format!(
                             "{}::new(T::new",
                             resolve.types[*ty]
                                 .name
@@ -817,30 +968,139 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         ));
                     }
                 }
-                self.push_str("(");
-                for (i, operand) in operands.iter().enumerate() {
+                call.push('(');
+                if self.gen.is_stateful(func, *async_) {
+                    call.push_str("T::_instance()");
+                    if !func.params.is_empty() {
+                        call.push_str(", ");
+                    }
+                }
+                let mut deallocs = Vec::new();
+                for (i, (operand, (name, ty))) in operands.iter().zip(&func.params).enumerate() {
                     if i > 0 {
-                        self.push_str(", ");
+                        call.push_str(", ");
                     }
 
-                    self.push_str(operand);
+                    let mut operand = operand.clone();
+                    if self.gen.arena_borrows(ty) {
+                        if let Some((borrowed, dealloc)) = self.arena_lifts.remove(&operand) {
+                            operand = borrowed;
+                            deallocs.push(dealloc);
+                        }
+                    }
+                    let is_self = i == 0 && matches!(func.kind, FunctionKind::Method(_));
+                    if self.record_values && !*async_ && !is_self {
+                        let tmp = self.tmp();
+                        uwriteln!(self.src, "let value{tmp} = {operand};");
+                        operand = format!("value{tmp}");
+                        self.push_str(&record_span_field(name, &operand));
+                    }
+
+                    match self.gen.convert_remapped(ty, &operand, false) {
+                        Some(remapped) => call.push_str(&remapped),
+                        None => call.push_str(&operand),
+                    }
 
                     // Automatically convert `Borrow<'_, AResource>` to
                     // `&Self` since traits have `&self` as their
                     // first arguments.
-                    if i == 0 && matches!(func.kind, FunctionKind::Method(_)) {
-                        self.push_str(".get()")
+                    if is_self {
+                        call.push_str(".get()")
                     }
                 }
-                self.push_str(")");
+                call.push(')');
+                if *async_ {
+                    call.push_str(".await");
+                }
                 if let FunctionKind::Constructor(_) = &func.kind {
-                    self.push_str(")");
+                    call.push(')');
+                }
+
+                if *async_ {
+                    // Borrowed handles are created within the future since
+                    // it outlives this function.
+                    let tmp = self.tmp();
+                    let decls = mem::take(&mut self.handle_decls).join("\n");
+                    uwriteln!(
+                        self.src,
+                        "let result{tmp} = async move {{\n{decls}\n{call}\n}};"
+                    );
+                    results.push(format!("result{tmp}"));
+                } else {
+                    self.let_results(func.results.len(), results);
+                    self.push_str(&call);
+                    self.push_str(";\n");
+                    // Borrowed arguments are freed unless they're in the
+                    // arena, which is reset as the export returns.
+                    for dealloc in deallocs {
+                        uwriteln!(self.src, "{dealloc}");
+                    }
+                    self.convert_remapped_results(func, results, true);
+                    if self.record_values {
+                        self.record_results(results);
+                    }
+                }
+            }
+
+            Instruction::AsyncPostCallInterface { func } => {
+                let async_support = self.gen.path_to_async_support();
+                let tmp = self.tmp();
+                let mut names = Vec::new();
+                for i in 0..func.results.len() {
+                    names.push(format!("result{tmp}_{i}"));
                 }
-                self.push_str(";\n");
+                let pattern = match names.len() {
+                    1 => names[0].clone(),
+                    _ => format!("({})", names.join(", ")),
+                };
+                uwriteln!(
+                    self.src,
+                    "let ret{tmp} = {async_support}::first_poll({}, move |{pattern}| {{",
+                    operands[0]
+                );
+                self.async_result_start = Some(self.src.len());
+                self.convert_remapped_results(func, &names, true);
+                results.push(format!("ret{tmp}"));
+                results.extend(names);
             }
 
-            Instruction::Return { amt, .. } => {
+            Instruction::AsyncCallReturn { name, params } => {
+                let module = self.gen.async_export_module();
+                let func =
+                    self.declare_import(&module, &format!("[task-return]{name}"), params, &[]);
+                uwriteln!(self.src, "{func}({});", operands.join(", "));
                 self.emit_cleanup();
+                self.push_str("});\n");
+            }
+
+            Instruction::Return { amt, func } => {
+                if self.async_result_start.is_none() {
+                    self.emit_cleanup();
+                    // Nothing refers to the arguments of a synchronous export
+                    // once it's lowered its results.
+                    if !self.gen.in_import && self.gen.gen.opts.arena {
+                        let arena = self.gen.path_to_arena();
+                        uwriteln!(self.src, "{arena}::reset();");
+                    }
+                }
+                // The results of imports are converted to any Rust types
+                // they're remapped to as they're returned.
+                let mut operands = operands.to_vec();
+                if self.gen.in_import && self.record_values && !self.result_buffer {
+                    for operand in operands.iter_mut() {
+                        let tmp = self.tmp();
+                        uwriteln!(self.src, "let result{tmp} = {operand};");
+                        *operand = format!("result{tmp}");
+                    }
+                    self.record_results(&operands);
+                }
+                if self.gen.in_import && !self.result_buffer {
+                    for (operand, ty) in operands.iter_mut().zip(func.results.iter_types()) {
+                        if let Some(converted) = self.gen.convert_remapped(ty, operand, false) {
+                            *operand = converted;
+                        }
+                    }
+                }
                 match amt {
                     0 => {}
                     1 => {
@@ -10,7 +10,8 @@ use std::fmt::Write as _;
 use std::mem;
 use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
 use wit_bindgen_core::{
-    dealias, uwrite, uwriteln, wit_parser::*, AnonymousTypeGenerator, Source, TypeInfo,
+    dealias, unsupported, uwrite, uwriteln, wit_parser::*, AnonymousTypeGenerator,
+    InterfaceGenerator as _, Origin, Source, TypeInfo,
 };
 
 pub struct InterfaceGenerator<'a> {
@@ -155,6 +156,21 @@ impl InterfaceGenerator<'_> {
             if self.gen.skip.contains(&func.name) {
                 continue;
             }
+            // The arena is reset when an export returns, which is before an
+            // async export completes.
+            if self.gen.opts.arena && self.is_async(func) {
+                let feature = "async exports with the `arena` option";
+                let item = unsupported::Item::Function(func.name.clone());
+                return Err(match (interface, &self.identifier) {
+                    (Some((id, key)), _) => {
+                        unsupported::interface_item(self.resolve, key, id, item, feature)
+                    }
+                    (None, Identifier::World(world)) => {
+                        unsupported::world_item(self.resolve, *world, item, feature)
+                    }
+                    (None, Identifier::Interface(..)) => unreachable!(),
+                });
+            }
 
             let resource = match func.kind {
                 FunctionKind::Freestanding => None,
@@ -165,9 +181,15 @@ impl InterfaceGenerator<'_> {
 
             funcs_to_export.push((func, resource));
             let (trait_name, methods) = traits.get_mut(&resource).unwrap();
+            let origin = self.origin().func(&func.name);
+            self.gen
+                .source_map
+                .begin(self.src.as_mut_string(), origin.clone());
             self.generate_guest_export(func, &trait_name);
+            self.gen.source_map.end(self.src.as_mut_string());
 
             let prev = mem::take(&mut self.src);
+            self.gen.source_map.begin(self.src.as_mut_string(), origin);
             let mut sig = FnSig {
                 use_item_name: true,
                 private: true,
@@ -177,8 +199,16 @@ impl InterfaceGenerator<'_> {
                 sig.self_arg = Some("&self".into());
                 sig.self_is_first_param = true;
             }
+            sig.async_ = self.is_async(func);
+            if self.is_stateful(func, sig.async_) {
+                sig.self_arg = Some("&mut self".into());
+            }
+            if sig.async_ {
+                self.src.push_str("#[allow(async_fn_in_trait)]\n");
+            }
             self.print_signature(func, true, &sig);
             self.src.push_str(";\n");
+            self.gen.source_map.end(self.src.as_mut_string());
             let trait_method = mem::replace(&mut self.src, prev);
             methods.push(trait_method);
         }
@@ -200,6 +230,22 @@ impl InterfaceGenerator<'_> {
             let resource_name = self.resolve.types[resource].name.as_ref().unwrap();
             let (_, interface_name) = interface.unwrap();
             let module = self.resolve.name_world_key(interface_name);
+            let wasm = self.gen.wasm_cfg();
+            // `fuzz_exports` calls the exports on the host, where handles are
+            // made up by the bindings.
+            let (host_new, host_rep) = if self.gen.opts.fuzz_exports {
+                let camel = resource_name.to_upper_camel_case();
+                let handles = host_handles_module(resource_name);
+                (
+                    format!("{handles}::new(val, {camel}::dtor::<Self>)"),
+                    format!("{handles}::rep(handle)"),
+                )
+            } else {
+                (
+                    "let _ = val;\n        unreachable!();".to_string(),
+                    "let _ = handle;\n        unreachable!();".to_string(),
+                )
+            };
             uwriteln!(
                 self.src,
                 r#"
@@ -207,13 +253,12 @@ impl InterfaceGenerator<'_> {
 unsafe fn _resource_new(val: *mut u8) -> u32
     where Self: Sized
 {{
-    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg(not({wasm}))]
     {{
-        let _ = val;
-        unreachable!();
+        {host_new}
     }}
 
-    #[cfg(target_arch = "wasm32")]
+    #[cfg({wasm})]
     {{
         #[link(wasm_import_module = "[export]{module}")]
         extern "C" {{
@@ -228,13 +273,12 @@ unsafe fn _resource_new(val: *mut u8) -> u32
 fn _resource_rep(handle: u32) -> *mut u8
     where Self: Sized
 {{
-    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg(not({wasm}))]
     {{
-        let _ = handle;
-        unreachable!();
+        {host_rep}
     }}
 
-    #[cfg(target_arch = "wasm32")]
+    #[cfg({wasm})]
     {{
         #[link(wasm_import_module = "[export]{module}")]
         extern "C" {{
@@ -357,9 +401,138 @@ macro_rules! {macro_name} {{
         uwriteln!(self.src, "}}");
     }
 
-    pub fn generate_imports<'a>(&mut self, funcs: impl Iterator<Item = &'a Function>) {
-        for func in funcs {
+    pub fn generate_imports<'a>(&mut self, funcs: impl Iterator<Item = &'a Function> + Clone) {
+        for func in funcs.clone() {
+            let origin = self.origin().func(&func.name);
+            self.gen.source_map.begin(self.src.as_mut_string(), origin);
             self.generate_guest_import(func);
+            self.gen.source_map.end(self.src.as_mut_string());
+        }
+        if self.gen.opts.mock {
+            let funcs = funcs
+                .filter(|func| !self.gen.skip.contains(&func.name))
+                .collect::<Vec<_>>();
+            if !funcs.is_empty() {
+                self.generate_mock(&funcs);
+            }
+        }
+    }
+
+    /// Generates the `Mock` trait which imported functions call on hosts
+    /// other than wasm, along with `set_mock` to install one.
+    fn generate_mock(&mut self, funcs: &[&Function]) {
+        let wasm = self.gen.wasm_cfg();
+        let owner = match self.identifier {
+            Identifier::Interface(_, key) => {
+                format!("interface `{}`", self.resolve.name_world_key(key))
+            }
+            Identifier::World(world) => format!("world `{}`", self.resolve.worlds[world].name),
+        };
+        uwriteln!(
+            self.src,
+            "
+                /// The functions imported by the {owner}, which call a mock of
+                /// this trait instead when compiled for the host.
+                ///
+                /// Mocks create the imported resources which they return with
+                /// `from_handle`, and dropping those on the host does nothing.
+                #[cfg(not({wasm}))]
+                pub trait Mock {{
+            "
+        );
+        for func in funcs {
+            self.rustdoc(&func.docs);
+            uwrite!(self.src, "fn {}(&self", self.mock_method_name(func));
+            for (i, (name, ty)) in func.params.iter().enumerate() {
+                if let (0, FunctionKind::Method(id)) = (i, &func.kind) {
+                    let resource = self.resolve.types[*id].name.as_ref().unwrap();
+                    uwrite!(self.src, ", this: &{}", resource.to_upper_camel_case());
+                    continue;
+                }
+                uwrite!(self.src, ", {}: ", to_rust_ident(name));
+                let mode = self.type_mode_for(ty, self.import_param_style(), "'_");
+                self.print_signature_ty(ty, mode);
+            }
+            self.push_str(")");
+            if let FunctionKind::Constructor(id) = &func.kind {
+                let resource = self.resolve.types[*id].name.as_ref().unwrap();
+                uwrite!(self.src, " -> {}", resource.to_upper_camel_case());
+            } else {
+                self.print_results(&func.results);
+            }
+            self.push_str(";\n");
+        }
+        let missing = format!("no mock of the {owner} was installed with `set_mock`");
+        uwriteln!(
+            self.src,
+            r#"
+                }}
+
+                #[cfg(not({wasm}))]
+                pub use __mock::set_mock;
+
+                #[cfg(not({wasm}))]
+                #[doc(hidden)]
+                pub mod __mock {{
+                    extern crate std;
+                    use std::cell::RefCell;
+                    use std::rc::Rc;
+
+                    std::thread_local! {{
+                        static MOCK: RefCell<Option<Rc<dyn super::Mock>>> = RefCell::new(None);
+                    }}
+
+                    /// Installs `mock` for the current thread as the
+                    /// implementation of the functions imported by the
+                    /// {owner}, replacing any installed before.
+                    pub fn set_mock(mock: impl super::Mock + 'static) {{
+                        MOCK.with(|m| *m.borrow_mut() = Some(Rc::new(mock)));
+                    }}
+
+                    pub fn with<T>(f: impl FnOnce(&dyn super::Mock) -> T) -> T {{
+                        let mock = MOCK.with(|m| m.borrow().clone());
+                        f(&*mock.expect("{missing}"))
+                    }}
+                }}
+            "#
+        );
+    }
+
+    /// Returns the name of the method of `Mock` called by the imported
+    /// function `func`, which is prefixed with the name of its resource, if
+    /// any.
+    fn mock_method_name(&self, func: &Function) -> String {
+        let name = match func.kind {
+            FunctionKind::Freestanding => func.name.to_snake_case(),
+            FunctionKind::Method(id) | FunctionKind::Static(id) => {
+                let resource = self.resolve.types[id].name.as_ref().unwrap();
+                format!(
+                    "{}_{}",
+                    resource.to_snake_case(),
+                    func.item_name().to_snake_case()
+                )
+            }
+            FunctionKind::Constructor(id) => {
+                let resource = self.resolve.types[id].name.as_ref().unwrap();
+                format!("{}_new", resource.to_snake_case())
+            }
+        };
+        to_rust_ident(&name)
+    }
+
+    /// Defines the type `name`, marking its definition in the source map.
+    pub fn define_mapped_type(&mut self, name: &str, id: TypeId) {
+        let origin = self.origin().ty(name);
+        self.gen.source_map.begin(self.src.as_mut_string(), origin);
+        self.define_type(name, id);
+        self.gen.source_map.end(self.src.as_mut_string());
+    }
+
+    /// Returns the origin of code generated for this interface or world.
+    fn origin(&self) -> Origin {
+        match self.identifier {
+            Identifier::Interface(id, key) => Origin::interface(self.resolve, key, id),
+            Identifier::World(world) => Origin::world(self.resolve, world),
         }
     }
 
@@ -388,7 +561,7 @@ macro_rules! {macro_name} {{
         src
     }
 
-    fn path_to_root(&self) -> String {
+    pub(crate) fn path_to_root(&self) -> String {
         let mut path_to_root = String::new();
 
         if let Identifier::Interface(_, key) = self.identifier {
@@ -425,32 +598,61 @@ macro_rules! {macro_name} {{
     pub fn finish_append_submodule(mut self, snake: &str, module_path: Vec<String>) {
         let module = self.finish();
         let path_to_root = self.path_to_root();
-        let module = format!(
+        let wasm = self.gen.wasm_cfg();
+        let mut mapped = String::new();
+        self.gen.source_map.begin(&mut mapped, self.origin());
+        uwrite!(
+            mapped,
             "\
                 #[allow(dead_code, clippy::all)]
                 pub mod {snake} {{
                     #[used]
                     #[doc(hidden)]
-                    #[cfg(target_arch = \"wasm32\")]
+                    #[cfg({wasm})]
                     static __FORCE_SECTION_REF: fn() = {path_to_root}__link_custom_section_describing_imports;
                     {module}
                 }}
 ",
         );
+        self.gen.source_map.end(&mut mapped);
         let map = if self.in_import {
             &mut self.gen.import_modules
         } else {
             &mut self.gen.export_modules
         };
-        map.push((module, module_path))
+        map.push((mapped, module_path))
     }
 
     fn generate_guest_import(&mut self, func: &Function) {
         if self.gen.skip.contains(&func.name) {
             return;
         }
+        self.generate_guest_import_fn(func, None);
+        let key = match self.identifier {
+            Identifier::Interface(_, key) => Some(key),
+            Identifier::World(_) => None,
+        };
+        let name = super::result_buffer_key(self.resolve, key, func);
+        if self.gen.opts.result_buffers.contains(&name) {
+            let ty = super::result_buffer_type(self.resolve, func).unwrap();
+            self.generate_guest_import_fn(func, Some(ty));
+        }
+    }
 
-        let mut sig = FnSig::default();
+    /// Generates the binding of an imported function, or its `<name>_into`
+    /// variant writing its result into a buffer of `result_buffer`'s type.
+    fn generate_guest_import_fn(&mut self, func: &Function, result_buffer: Option<Type>) {
+        let async_ = self.is_async(func);
+
+        let mut sig = FnSig {
+            async_,
+            ..Default::default()
+        };
+        if let Some(ty) = &result_buffer {
+            let prev = mem::take(&mut self.src);
+            self.print_ty(ty, TypeMode::owned());
+            sig.result_buffer = Some(mem::replace(&mut self.src, prev).into());
+        }
         match func.kind {
             FunctionKind::Freestanding => {}
             FunctionKind::Method(id) | FunctionKind::Static(id) | FunctionKind::Constructor(id) => {
@@ -464,19 +666,123 @@ macro_rules! {macro_name} {{
                 }
             }
         }
-        self.src.push_str("#[allow(unused_unsafe, clippy::all)]\n");
+        if self.gen.opts.mock {
+            self.src
+                .push_str("#[allow(unused_unsafe, unreachable_code, clippy::all)]\n");
+        } else {
+            self.src.push_str("#[allow(unused_unsafe, clippy::all)]\n");
+        }
         let params = self.print_signature(func, false, &sig);
         self.src.push_str("{\n");
+        let instrument = self.gen.opts.instrument && !async_;
+        let record_values = instrument && self.gen.opts.instrument_debug;
+        let params_to_record = func
+            .params
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                record_values && !(*i == 0 && matches!(func.kind, FunctionKind::Method(_)))
+            })
+            .map(|(_, param)| param)
+            .collect::<Vec<_>>();
+        if instrument {
+            let fields = params_to_record
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            let result = record_values && result_buffer.is_none() && func.results.len() > 0;
+            self.enter_span(func, &fields, result);
+            // Arguments of remapped types are recorded once they're
+            // converted to their WIT types below.
+            for (name, ty) in params_to_record.iter() {
+                if self.remapped_type(ty).is_none() {
+                    self.src
+                        .push_str(&record_span_field(name, &to_rust_ident(name)));
+                }
+            }
+        }
+        if self.gen.opts.mock {
+            let wasm = self.gen.wasm_cfg();
+            let args = func
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| match (i, &func.kind) {
+                    (0, FunctionKind::Method(_)) => "self".to_string(),
+                    _ => to_rust_ident(name),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let call = format!(
+                "__mock::with(|mock| mock.{}({args}))",
+                self.mock_method_name(func)
+            );
+            if result_buffer.is_some() {
+                uwriteln!(
+                    self.src,
+                    "#[cfg(not({wasm}))]
+                    return *buf = {call};"
+                );
+            } else if record_values
+                && func.results.len() > 0
+                && func
+                    .results
+                    .iter_types()
+                    .all(|ty| self.remapped_type(ty).is_none())
+            {
+                let record = record_span_field(RESULT_SPAN_FIELD, "result");
+                uwriteln!(
+                    self.src,
+                    "#[cfg(not({wasm}))]
+                    {{
+                        let result = {call};
+                        {record}
+                        return result;
+                    }}"
+                );
+            } else {
+                uwriteln!(
+                    self.src,
+                    "#[cfg(not({wasm}))]
+                    return {call};"
+                );
+            }
+        }
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            if let (0, FunctionKind::Method(_)) = (i, &func.kind) {
+                continue;
+            }
+            let name = to_rust_ident(name);
+            if let Some(wit) = self.convert_remapped(ty, &name, true) {
+                uwriteln!(self.src, "let {name} = {wit};");
+                if record_values {
+                    self.src
+                        .push_str(&record_span_field(&func.params[i].0, &name));
+                }
+            }
+        }
         self.src.push_str("unsafe {\n");
 
         let mut f = FunctionBindgen::new(self, params);
-        abi::call(
-            f.gen.resolve,
-            AbiVariant::GuestImport,
-            LiftLower::LowerArgsLiftResults,
-            func,
-            &mut f,
-        );
+        f.result_buffer = result_buffer.is_some();
+        f.record_values = record_values;
+        if async_ {
+            abi::call_async(
+                f.gen.resolve,
+                AbiVariant::GuestImport,
+                LiftLower::LowerArgsLiftResults,
+                func,
+                &mut f,
+            );
+        } else {
+            abi::call(
+                f.gen.resolve,
+                AbiVariant::GuestImport,
+                LiftLower::LowerArgsLiftResults,
+                func,
+                &mut f,
+            );
+        }
         let FunctionBindgen {
             needs_cleanup_list,
             src,
@@ -501,6 +807,11 @@ macro_rules! {macro_name} {{
 ",
             );
         }
+        if let Some(ty) = &result_buffer {
+            let start = self.path_to_start_result_buffer();
+            let buf = self.result_buffer_vec(ty);
+            uwriteln!(self.src, "{start}({buf});");
+        }
         self.src.push_str(&String::from(src));
 
         self.src.push_str("}\n");
@@ -515,16 +826,27 @@ macro_rules! {macro_name} {{
     }
 
     fn generate_guest_export(&mut self, func: &Function, trait_name: &str) {
+        let async_ = self.is_async(func);
+
         let name_snake = func.name.to_snake_case().replace('.', "_");
+        // The future of an async export outlives the call which starts it,
+        // so it can't borrow from the implementation's type.
+        let bound = if async_ {
+            " + 'static".to_string()
+        } else if self.is_stateful(func, async_) {
+            format!(" + {}ExportInstance", self.path_to_root())
+        } else {
+            String::new()
+        };
         uwrite!(
             self.src,
             "\
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
-                pub unsafe fn _export_{name_snake}_cabi<T: {trait_name}>\
+                pub unsafe fn _export_{name_snake}_cabi<T: {trait_name}{bound}>\
 ",
         );
-        let params = self.print_export_sig(func);
+        let params = self.print_export_sig(func, async_);
         self.push_str(" {");
 
         if !self.gen.opts.disable_run_ctors_once_workaround {
@@ -537,35 +859,87 @@ macro_rules! {macro_name} {{
             // See
             // https://github.com/bytecodealliance/preview2-prototyping/issues/99
             // for more details.
-            uwrite!(
-                self.src,
-                "#[cfg(target_arch=\"wasm32\")]\n{run_ctors_once}();",
-            );
+            let wasm = self.gen.wasm_cfg();
+            uwrite!(self.src, "#[cfg({wasm})]\n{run_ctors_once}();");
+        }
+        if self.gen.opts.arena {
+            let arena = self.path_to_arena();
+            uwriteln!(self.src, "{arena}::enable();");
+        }
+
+        // Async exports aren't traced, as their spans would be entered
+        // across awaits.
+        let instrument = self.gen.opts.instrument && !async_;
+        let record_values = instrument && self.gen.opts.instrument_debug;
+        if instrument {
+            let fields = func
+                .params
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    record_values && !(*i == 0 && matches!(func.kind, FunctionKind::Method(_)))
+                })
+                .map(|(_, (name, _))| name.as_str())
+                .collect::<Vec<_>>();
+            self.enter_span(func, &fields, record_values && func.results.len() > 0);
         }
 
         let mut f = FunctionBindgen::new(self, params);
-        abi::call(
-            f.gen.resolve,
-            AbiVariant::GuestExport,
-            LiftLower::LiftArgsLowerResults,
-            func,
-            &mut f,
-        );
+        f.record_values = record_values;
+        if async_ {
+            abi::call_async(
+                f.gen.resolve,
+                AbiVariant::GuestExport,
+                LiftLower::LiftArgsLowerResults,
+                func,
+                &mut f,
+            );
+        } else {
+            abi::call(
+                f.gen.resolve,
+                AbiVariant::GuestExport,
+                LiftLower::LiftArgsLowerResults,
+                func,
+                &mut f,
+            );
+        }
         let FunctionBindgen {
             needs_cleanup_list,
             src,
             handle_decls,
+            async_result_start,
             ..
         } = f;
-        assert!(!needs_cleanup_list);
+        let mut src = String::from(src);
+        match async_result_start {
+            // The results of an async export are lowered within a closure
+            // so that's where any cleanup list needs to live.
+            Some(start) if needs_cleanup_list => {
+                let vec = self.path_to_vec();
+                src.insert_str(start, &format!("let mut cleanup_list = {vec}::new();\n"));
+            }
+            _ => assert!(!needs_cleanup_list),
+        }
         for decl in handle_decls {
             self.src.push_str(&decl);
             self.src.push_str("\n");
         }
-        self.src.push_str(&String::from(src));
+        self.src.push_str(&src);
         self.src.push_str("}\n");
 
-        if abi::guest_export_needs_post_return(self.resolve, func) {
+        if async_ {
+            let async_support = self.path_to_async_support();
+            uwriteln!(
+                self.src,
+                "\
+                    #[doc(hidden)]
+                    #[allow(non_snake_case)]
+                    pub unsafe fn __callback_{name_snake}(event0: i32, event1: i32, event2: i32) -> i32 {{
+                        {async_support}::callback(event0 as u32, event1 as u32, event2 as u32)
+                    }}
+"
+            );
+        } else if abi::guest_export_needs_post_return(self.resolve, func) {
             uwrite!(
                 self.src,
                 "\
@@ -592,6 +966,58 @@ macro_rules! {macro_name} {{
         }
     }
 
+    /// Enters the `tracing` span which a call to `func` is traced in with
+    /// the `instrument` option, with empty `fields` for its arguments and,
+    /// if `result` is set, its result to be recorded in.
+    fn enter_span(&mut self, func: &Function, fields: &[&str], result: bool) {
+        let interface = match self.identifier {
+            Identifier::Interface(_, key) => Some(self.resolve.name_world_key(key)),
+            Identifier::World(_) => None,
+        };
+        let name = func.core_export_name(interface.as_deref());
+        let mut fields = fields
+            .iter()
+            .map(|field| format!(", \"{field}\" = ::tracing::field::Empty"))
+            .collect::<String>();
+        if result {
+            fields.push_str(&format!(
+                ", \"{RESULT_SPAN_FIELD}\" = ::tracing::field::Empty"
+            ));
+        }
+        uwriteln!(
+            self.src,
+            "let _span = ::tracing::trace_span!(\"{name}\"{fields}).entered();"
+        );
+    }
+
+    /// Returns whether the export `func` is called on the instance of the
+    /// type implementing it with the `stateful_exports` option.
+    pub(crate) fn is_stateful(&self, func: &Function, async_: bool) -> bool {
+        self.gen.opts.stateful_exports && !async_ && matches!(func.kind, FunctionKind::Freestanding)
+    }
+
+    fn is_async(&self, func: &Function) -> bool {
+        let key = match self.identifier {
+            Identifier::Interface(_, key) => Some(key),
+            Identifier::World(_) => None,
+        };
+        self.gen
+            .opts
+            .async_
+            .is_async(self.resolve, key, func, self.in_import)
+    }
+
+    /// Returns the core wasm module that intrinsics for this exported
+    /// interface, such as `task.return`, are imported from.
+    pub(crate) fn async_export_module(&self) -> String {
+        match self.identifier {
+            Identifier::Interface(_, key) => {
+                format!("[export]{}", self.resolve.name_world_key(key))
+            }
+            Identifier::World(_) => "[export]$root".to_string(),
+        }
+    }
+
     fn generate_raw_cabi_export(&mut self, func: &Function, ty: &str, path_to_self: &str) {
         let name_snake = func.name.to_snake_case().replace('.', "_");
         let wasm_module_export_name = match self.identifier {
@@ -600,15 +1026,17 @@ macro_rules! {macro_name} {{
         };
         let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
         let export_name = func.core_export_name(wasm_module_export_name.as_deref());
+        let async_ = self.is_async(func);
+        let async_prefix = if async_ { "[async-lift]" } else { "" };
         uwrite!(
             self.src,
             "\
-                #[export_name = \"{export_prefix}{export_name}\"]
+                #[export_name = \"{export_prefix}{async_prefix}{export_name}\"]
                 unsafe extern \"C\" fn export_{name_snake}\
 ",
         );
 
-        let params = self.print_export_sig(func);
+        let params = self.print_export_sig(func, async_);
         self.push_str(" {\n");
         uwriteln!(
             self.src,
@@ -617,7 +1045,18 @@ macro_rules! {macro_name} {{
         );
         self.push_str("}\n");
 
-        if abi::guest_export_needs_post_return(self.resolve, func) {
+        if async_ {
+            let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
+            uwriteln!(
+                self.src,
+                "\
+                    #[export_name = \"{export_prefix}[callback][async-lift]{export_name}\"]
+                    unsafe extern \"C\" fn _callback_{name_snake}(event0: i32, event1: i32, event2: i32) -> i32 {{
+                        {path_to_self}::__callback_{name_snake}(event0, event1, event2)
+                    }}
+"
+            );
+        } else if abi::guest_export_needs_post_return(self.resolve, func) {
             let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
             uwrite!(
                 self.src,
@@ -637,9 +1076,9 @@ macro_rules! {macro_name} {{
         }
     }
 
-    fn print_export_sig(&mut self, func: &Function) -> Vec<String> {
+    fn print_export_sig(&mut self, func: &Function, async_: bool) -> Vec<String> {
         self.src.push_str("(");
-        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+        let sig = abi::wasm_signature(self.resolve, AbiVariant::GuestExport, func, async_);
         let mut params = Vec::new();
         for (i, param) in sig.params.iter().enumerate() {
             let name = format!("arg{}", i);
@@ -694,7 +1133,12 @@ macro_rules! {macro_name} {{
 
                     let resource_methods = funcs.remove(&Some(*id)).unwrap_or(Vec::new());
                     let trait_name = format!("{path}::Guest{camel}");
-                    self.generate_stub_impl(&trait_name, "", &resource_methods);
+                    self.generate_stub_impl(
+                        &trait_name,
+                        "",
+                        &resource_methods,
+                        interface.map(|i| i.1),
+                    );
                 }
                 format!("{path}::Guest")
             }
@@ -705,7 +1149,12 @@ macro_rules! {macro_name} {{
         };
 
         if !root_methods.is_empty() || !extra_trait_items.is_empty() {
-            self.generate_stub_impl(&guest_trait, &extra_trait_items, &root_methods);
+            self.generate_stub_impl(
+                &guest_trait,
+                &extra_trait_items,
+                &root_methods,
+                interface.map(|i| i.1),
+            );
         }
     }
 
@@ -714,6 +1163,7 @@ macro_rules! {macro_name} {{
         trait_name: &str,
         extra_trait_items: &str,
         funcs: &[&Function],
+        interface: Option<&WorldKey>,
     ) {
         uwriteln!(self.src, "impl {trait_name} for Stub {{");
         self.src.push_str(extra_trait_items);
@@ -731,6 +1181,14 @@ macro_rules! {macro_name} {{
                 sig.self_arg = Some("&self".into());
                 sig.self_is_first_param = true;
             }
+            sig.async_ = self
+                .gen
+                .opts
+                .async_
+                .is_async(self.resolve, interface, func, false);
+            if self.is_stateful(func, sig.async_) {
+                sig.self_arg = Some("&mut self".into());
+            }
             self.print_signature(func, true, &sig);
             self.src.push_str("{ unreachable!() }\n");
         }
@@ -793,7 +1251,7 @@ macro_rules! {macro_name} {{
         let params = self.print_docs_and_params(func, params_owned, sig);
         if let FunctionKind::Constructor(_) = &func.kind {
             self.push_str(" -> Self")
-        } else {
+        } else if sig.result_buffer.is_none() {
             self.print_results(&func.results);
         }
         params
@@ -806,6 +1264,12 @@ macro_rules! {macro_name} {{
         sig: &FnSig,
     ) -> Vec<String> {
         self.rustdoc(&func.docs);
+        if sig.result_buffer.is_some() {
+            if func.docs.contents.is_some() {
+                self.push_str("///\n");
+            }
+            self.push_str("/// The result is written into `buf`, reusing its allocation.\n");
+        }
         self.rustdoc_params(&func.params, "Parameters");
         // TODO: re-add this when docs are back
         // self.rustdoc_params(&func.results, "Return");
@@ -829,7 +1293,11 @@ macro_rules! {macro_name} {{
         } else {
             &func.name
         };
-        self.push_str(&to_rust_ident(func_name));
+        if sig.result_buffer.is_some() {
+            self.push_str(&to_rust_ident(&format!("{func_name}-into")));
+        } else {
+            self.push_str(&to_rust_ident(func_name));
+        }
         if let Some(generics) = &sig.generics {
             self.push_str(generics);
         }
@@ -862,16 +1330,18 @@ macro_rules! {macro_name} {{
             // In the `Borrowing` mode however a different tradeoff is made. The
             // types are generated differently meaning that a borrowed version
             // is used.
-            let style = if params_owned {
-                TypeOwnershipStyle::Owned
+            //
+            // With the `arena` option top-level strings and lists of exports
+            // are also borrowed, from the memory the host allocated them in.
+            let style = if !params_owned {
+                self.import_param_style()
+            } else if self.arena_borrows(param) {
+                TypeOwnershipStyle::Borrowed
             } else {
-                match self.gen.opts.ownership {
-                    Ownership::Owning => TypeOwnershipStyle::OnlyTopBorrowed,
-                    Ownership::Borrowing { .. } => TypeOwnershipStyle::Borrowed,
-                }
+                TypeOwnershipStyle::Owned
             };
             let mode = self.type_mode_for(param, style, "'_");
-            self.print_ty(param, mode);
+            self.print_signature_ty(param, mode);
             self.push_str(",");
 
             // Depending on the style of this request vs what we got perhaps
@@ -902,10 +1372,44 @@ macro_rules! {macro_name} {{
                 params.push(format!("&{name}"));
             }
         }
+        if let Some(buf) = &sig.result_buffer {
+            uwrite!(self.src, "buf: &mut {buf},");
+        }
         self.push_str(")");
         params
     }
 
+    /// Returns whether a parameter of type `ty` of an export is borrowed from
+    /// the memory the host allocated it in with the `arena` option, rather
+    /// than copied out of it, which is the case for strings and lists which
+    /// can be lifted as they are.
+    pub(crate) fn arena_borrows(&self, ty: &Type) -> bool {
+        if !self.gen.opts.arena || self.in_import {
+            return false;
+        }
+        match ty {
+            Type::String => true,
+            Type::Id(id) => match &self.resolve.types[*id].kind {
+                TypeDefKind::List(element) => {
+                    self.resolve.types[*id].name.is_none()
+                        && self.resolve.all_bits_valid(element)
+                        && !matches!(element, Type::Id(e) if self.info(*e).has_resource)
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the style which the parameters of imported functions are
+    /// passed in, according to the `ownership` option.
+    fn import_param_style(&self) -> TypeOwnershipStyle {
+        match self.gen.opts.ownership {
+            Ownership::Owning => TypeOwnershipStyle::OnlyTopBorrowed,
+            Ownership::Borrowing { .. } => TypeOwnershipStyle::Borrowed,
+        }
+    }
+
     fn print_results(&mut self, results: &Results) {
         match results.len() {
             0 => {}
@@ -914,14 +1418,14 @@ macro_rules! {macro_name} {{
                 let ty = results.iter_types().next().unwrap();
                 let mode = self.type_mode_for(ty, TypeOwnershipStyle::Owned, "'INVALID");
                 assert!(mode.lifetime.is_none());
-                self.print_ty(ty, mode);
+                self.print_signature_ty(ty, mode);
             }
             _ => {
                 self.push_str(" -> (");
                 for ty in results.iter_types() {
                     let mode = self.type_mode_for(ty, TypeOwnershipStyle::Owned, "'INVALID");
                     assert!(mode.lifetime.is_none());
-                    self.print_ty(ty, mode);
+                    self.print_signature_ty(ty, mode);
                     self.push_str(", ")
                 }
                 self.push_str(")")
@@ -929,6 +1433,39 @@ macro_rules! {macro_name} {{
         }
     }
 
+    /// Prints the type of a function's parameter or result, which is the Rust
+    /// type it's remapped to with `with` if there is one.
+    fn print_signature_ty(&mut self, ty: &Type, mode: TypeMode) {
+        match self.remapped_type(ty) {
+            Some(path) => self.push_str(&path),
+            None => self.print_ty(ty, mode),
+        }
+    }
+
+    /// Returns the path to the Rust type which `ty` is remapped to with
+    /// `with`, if it is.
+    fn remapped_type(&self, ty: &Type) -> Option<String> {
+        let Type::Id(id) = ty else {
+            return None;
+        };
+        let name = self.gen.with_types.get(id)?;
+        Some(format!("{}{name}", self.path_to_root()))
+    }
+
+    /// Returns the conversion of `expr` from the WIT type `ty` to the Rust
+    /// type it's remapped to with `with`, or back again if `into_wit` is set,
+    /// if `ty` is remapped.
+    pub(crate) fn convert_remapped(&self, ty: &Type, expr: &str, into_wit: bool) -> Option<String> {
+        let remapped = self.remapped_type(ty)?;
+        let Type::Id(id) = ty else { unreachable!() };
+        let wit = self.type_path(*id, true);
+        let root = self.path_to_root();
+        let method = if into_wit { "into_wit" } else { "from_wit" };
+        Some(format!(
+            "<{remapped} as {root}WitConvert<{wit}>>::{method}({expr})"
+        ))
+    }
+
     /// Calculates the `TypeMode` to be used for the `ty` specified.
     ///
     /// This takes a `style` argument which is the requested style of ownership
@@ -984,6 +1521,7 @@ macro_rules! {macro_name} {{
         // type parameter.
 
         let info = self.info(ty);
+        let remapped = self.gen.with_types.contains_key(&ty);
         let lifetime = if info.has_borrow_handle {
             // Borrowed handles always have a lifetime associated with them so
             // thread it through.
@@ -993,7 +1531,7 @@ macro_rules! {macro_name} {{
             // doesn't have any borrowed handles, then no lifetimes are needed
             // since any internal lists will be their owned version.
             None
-        } else if info.has_own_handle || !info.has_list {
+        } else if info.has_own_handle || remapped || !info.has_list {
             // At this point there are no borrowed handles and a borrowed style
             // of type is requested. In this situation there's two cases where a
             // lifetime is never used:
@@ -1006,8 +1544,11 @@ macro_rules! {macro_name} {{
             // * There are no lists present - here the lifetime parameter won't
             //   be used for anything because there's no borrows or lists, so
             //   it's skipped.
+            //
+            // * The type is remapped with `with` - conversions to and from the
+            //   Rust type it's remapped to work with owned values.
             None
-        } else if !info.owned || self.uses_two_names(&info) {
+        } else if !info.owned || self.uses_two_names(ty) {
             // This next layer things get a little more interesting. To recap,
             // so far we know that there's no borrowed handles, a borrowed mode
             // is requested, there's no own handles, and there's a list. In that
@@ -1063,7 +1604,7 @@ macro_rules! {macro_name} {{
             // because there's no option but to take interior types by ownership
             // as that statically shows that the ownership of the value is being
             // lost.
-            style: if info.has_own_handle {
+            style: if info.has_own_handle || remapped {
                 TypeOwnershipStyle::Owned
             } else {
                 style
@@ -1258,7 +1799,7 @@ macro_rules! {macro_name} {{
         let a = self.type_mode_for_id(ty, TypeOwnershipStyle::Owned, "'a");
         let b = self.type_mode_for_id(ty, TypeOwnershipStyle::Borrowed, "'a");
 
-        if self.uses_two_names(&info) {
+        if self.uses_two_names(ty) {
             // If this type uses two names then, well, it uses two names. In
             // this situation both modes are returned.
             assert!(a != b);
@@ -1299,6 +1840,10 @@ macro_rules! {macro_name} {{
             } else if info.is_clone() {
                 derives.insert("Clone".to_string());
             }
+            let serde = self.serde_derives(id, mode);
+            derives.extend(serde.iter().map(|s| s.to_string()));
+            let arbitrary = self.arbitrary_derives(id, mode);
+            derives.extend(arbitrary.iter().map(|s| s.to_string()));
             if !derives.is_empty() {
                 self.push_str("#[derive(");
                 self.push_str(&derives.into_iter().collect::<Vec<_>>().join(", "));
@@ -1309,6 +1854,9 @@ macro_rules! {macro_name} {{
             self.push_str(" {\n");
             for field in record.fields.iter() {
                 self.rustdoc(&field.docs);
+                if !serde.is_empty() {
+                    uwriteln!(self.src, "#[serde(rename = \"{}\")]", field.name);
+                }
                 self.push_str("pub ");
                 self.push_str(&to_rust_ident(&field.name));
                 self.push_str(": ");
@@ -1371,7 +1919,7 @@ macro_rules! {macro_name} {{
             variant
                 .cases
                 .iter()
-                .map(|c| (c.name.to_upper_camel_case(), &c.docs, c.ty.as_ref())),
+                .map(|c| (c.name.as_str(), &c.docs, c.ty.as_ref())),
             docs,
         );
     }
@@ -1379,7 +1927,7 @@ macro_rules! {macro_name} {{
     fn print_rust_enum<'b>(
         &mut self,
         id: TypeId,
-        cases: impl IntoIterator<Item = (String, &'b Docs, Option<&'b Type>)> + Clone,
+        cases: impl IntoIterator<Item = (&'b str, &'b Docs, Option<&'b Type>)> + Clone,
         docs: &Docs,
     ) where
         Self: Sized,
@@ -1401,6 +1949,10 @@ macro_rules! {macro_name} {{
             } else if info.is_clone() {
                 derives.insert("Clone".to_string());
             }
+            let serde = self.serde_derives(id, mode);
+            derives.extend(serde.iter().map(|s| s.to_string()));
+            let arbitrary = self.arbitrary_derives(id, mode);
+            derives.extend(arbitrary.iter().map(|s| s.to_string()));
             if !derives.is_empty() {
                 self.push_str("#[derive(");
                 self.push_str(&derives.into_iter().collect::<Vec<_>>().join(", "));
@@ -1411,7 +1963,10 @@ macro_rules! {macro_name} {{
             self.push_str(" {\n");
             for (case_name, docs, payload) in cases.clone() {
                 self.rustdoc(docs);
-                self.push_str(&case_name);
+                if !serde.is_empty() {
+                    uwriteln!(self.src, "#[serde(rename = \"{case_name}\")]");
+                }
+                self.push_str(&case_name.to_upper_camel_case());
                 if let Some(ty) = payload {
                     self.push_str("(");
                     let mode = self.filter_mode(ty, mode);
@@ -1428,7 +1983,7 @@ macro_rules! {macro_name} {{
                 cases
                     .clone()
                     .into_iter()
-                    .map(|(name, _docs, ty)| (name, ty)),
+                    .map(|(name, _docs, ty)| (name.to_upper_camel_case(), ty)),
             );
 
             if info.error {
@@ -1555,12 +2110,19 @@ macro_rules! {macro_name} {{
                 .into_iter()
                 .map(|s| s.to_string()),
         );
+        let serde = self.serde_derives(id, TypeMode::owned());
+        derives.extend(serde.iter().map(|s| s.to_string()));
+        let arbitrary = self.arbitrary_derives(id, TypeMode::owned());
+        derives.extend(arbitrary.iter().map(|s| s.to_string()));
         self.push_str("#[derive(");
         self.push_str(&derives.into_iter().collect::<Vec<_>>().join(", "));
         self.push_str(")]\n");
         self.push_str(&format!("pub enum {name} {{\n"));
         for case in enum_.cases.iter() {
             self.rustdoc(&case.docs);
+            if !serde.is_empty() {
+                uwriteln!(self.src, "#[serde(rename = \"{}\")]", case.name);
+            }
             self.push_str(&case_attr(case));
             self.push_str(&case.name.to_upper_camel_case());
             self.push_str(",\n");
@@ -1669,9 +2231,8 @@ macro_rules! {macro_name} {{
     }
 
     fn param_name(&self, ty: TypeId) -> String {
-        let info = self.info(ty);
         let name = to_upper_camel_case(self.resolve.types[ty].name.as_ref().unwrap());
-        if self.uses_two_names(&info) {
+        if self.uses_two_names(ty) {
             format!("{}Param", name)
         } else {
             name
@@ -1679,16 +2240,16 @@ macro_rules! {macro_name} {{
     }
 
     fn result_name(&self, ty: TypeId) -> String {
-        let info = self.info(ty);
         let name = to_upper_camel_case(self.resolve.types[ty].name.as_ref().unwrap());
-        if self.uses_two_names(&info) {
+        if self.uses_two_names(ty) {
             format!("{}Result", name)
         } else {
             name
         }
     }
 
-    fn uses_two_names(&self, info: &TypeInfo) -> bool {
+    fn uses_two_names(&self, ty: TypeId) -> bool {
+        let info = self.info(ty);
         // Types are only duplicated if explicitly requested ...
         matches!(
             self.gen.opts.ownership,
@@ -1704,6 +2265,9 @@ macro_rules! {macro_name} {{
             // ... and if there's NOT an `own` handle since those are always
             // done by ownership.
             && !info.has_own_handle
+            // ... and if the type isn't remapped with `with`, since those are
+            // always owned too.
+            && !self.gen.with_types.contains_key(&ty)
     }
 
     fn path_to_interface(&self, interface: InterfaceId) -> Option<String> {
@@ -1854,6 +2418,489 @@ macro_rules! {macro_name} {{
         self.path_from_runtime_module(RuntimeItem::StdAllocModule, "alloc")
     }
 
+    pub fn path_to_flags_words(&mut self) -> String {
+        self.path_from_runtime_module(RuntimeItem::FlagsWords, "FlagsWords")
+    }
+
+    pub fn path_to_start_result_buffer(&mut self) -> String {
+        self.path_from_runtime_module(RuntimeItem::ResultBuffer, "start_result_buffer")
+    }
+
+    pub fn path_to_finish_result_buffer(&mut self) -> String {
+        self.path_from_runtime_module(RuntimeItem::ResultBuffer, "finish_result_buffer")
+    }
+
+    /// Returns the `Vec` underlying `buf`, the buffer of type `ty` which the
+    /// result of an import is written into.
+    pub(crate) fn result_buffer_vec(&self, ty: &Type) -> &'static str {
+        match ty {
+            Type::String if !self.gen.opts.raw_strings => "buf.as_mut_vec()",
+            _ => "buf",
+        }
+    }
+
+    pub fn path_to_arena(&mut self) -> String {
+        self.path_from_runtime_module(RuntimeItem::Arena, "arena")
+    }
+
+    pub fn path_to_async_support(&mut self) -> String {
+        self.path_from_runtime_module(RuntimeItem::AsyncSupport, "async_support")
+    }
+
+    fn print_bitflags(&mut self, name: &str, flags: &Flags, docs: &Docs) {
+        self.src.push_str(&format!(
+            "{bitflags}::bitflags! {{\n",
+            bitflags = self.gen.bitflags_path()
+        ));
+        self.rustdoc(docs);
+        let repr = RustFlagsRepr::new(flags);
+        self.src.push_str(&format!(
+            "#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]\npub struct {}: {repr} {{\n",
+            name.to_upper_camel_case(),
+        ));
+        for (i, flag) in flags.flags.iter().enumerate() {
+            self.rustdoc(&flag.docs);
+            self.src.push_str(&format!(
+                "const {} = 1 << {};\n",
+                flag.name.to_shouty_snake_case(),
+                i,
+            ));
+        }
+        self.src.push_str("}\n");
+        self.src.push_str("}\n");
+    }
+
+    /// Returns the serde traits to derive for the type `id` printed in `mode`
+    /// when the `serde` option is enabled.
+    fn serde_derives(&self, id: TypeId, mode: TypeMode) -> &'static [&'static str] {
+        if !self.gen.opts.serde || self.info(id).has_resource {
+            &[]
+        } else if mode.lifetime.is_some() {
+            // Borrowed strings and lists can't generally be deserialized.
+            &["::serde::Serialize"]
+        } else {
+            &["::serde::Serialize", "::serde::Deserialize"]
+        }
+    }
+
+    /// Implements serde's traits for a flags type, which is serialized as a
+    /// list of the names of the flags which are set.
+    fn print_flags_serde(&mut self, name: &str, flags: &Flags) {
+        let name = name.to_upper_camel_case();
+        let vec = self.path_to_vec();
+        let string = self.path_to_string();
+        let mut pairs = String::new();
+        let mut cases = String::new();
+        for flag in flags.flags.iter() {
+            let constant = flag.name.to_shouty_snake_case();
+            uwrite!(pairs, "(Self::{constant}, \"{}\"), ", flag.name);
+            uwriteln!(cases, "\"{}\" => Self::{constant},", flag.name);
+        }
+        let names = flags
+            .flags
+            .iter()
+            .map(|flag| format!("\"{}\"", flag.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        uwriteln!(
+            self.src,
+            r#"
+                impl ::serde::Serialize for {name} {{
+                    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {{
+                        use ::serde::ser::SerializeSeq;
+                        let flags = [{pairs}];
+                        let set = flags.iter().filter(|(flag, _)| self.contains(*flag));
+                        let mut seq = serializer.serialize_seq(Some(set.clone().count()))?;
+                        for (_, name) in set {{
+                            seq.serialize_element(name)?;
+                        }}
+                        seq.end()
+                    }}
+                }}
+
+                impl<'de> ::serde::Deserialize<'de> for {name} {{
+                    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {{
+                        let mut flags = Self::empty();
+                        for name in <{vec}<{string}> as ::serde::Deserialize>::deserialize(deserializer)? {{
+                            flags |= match name.as_str() {{
+                                {cases}
+                                _ => {{
+                                    const NAMES: &[&str] = &[{names}];
+                                    return Err(<D::Error as ::serde::de::Error>::unknown_variant(&name, NAMES));
+                                }}
+                            }};
+                        }}
+                        Ok(flags)
+                    }}
+                }}
+            "#
+        );
+    }
+
+    /// Returns the `arbitrary::Arbitrary` derive for the type `id` printed
+    /// with `mode`, if the `arbitrary` option is enabled and it can be
+    /// derived.
+    fn arbitrary_derives(&self, id: TypeId, mode: TypeMode) -> &'static [&'static str] {
+        // Handles can't be made up, and borrowed lists other than bytes
+        // don't implement `Arbitrary`.
+        if !self.gen.opts.arbitrary || self.info(id).has_resource || mode.lifetime.is_some() {
+            &[]
+        } else {
+            &["::arbitrary::Arbitrary"]
+        }
+    }
+
+    /// Implements `arbitrary::Arbitrary` for a flags type, setting each flag
+    /// with an arbitrary `bool`.
+    fn print_flags_arbitrary(&mut self, name: &str, flags: &Flags) {
+        let name = name.to_upper_camel_case();
+        let constants = flags
+            .flags
+            .iter()
+            .map(|flag| format!("Self::{}", flag.name.to_shouty_snake_case()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        uwriteln!(
+            self.src,
+            r#"
+                impl<'a> ::arbitrary::Arbitrary<'a> for {name} {{
+                    fn arbitrary(u: &mut ::arbitrary::Unstructured<'a>) -> ::arbitrary::Result<Self> {{
+                        let mut flags = Self::empty();
+                        for flag in [{constants}] {{
+                            if u.arbitrary()? {{
+                                flags |= flag;
+                            }}
+                        }}
+                        Ok(flags)
+                    }}
+                }}
+            "#
+        );
+    }
+
+    /// Returns calls to those of `funcs`, exported through `trait_path`,
+    /// which `fuzz_exports` can make, with arguments taken from the
+    /// `arbitrary::Unstructured` named `u`.
+    pub(crate) fn fuzz_calls<'b>(
+        &self,
+        key: Option<&WorldKey>,
+        trait_path: &str,
+        funcs: impl Iterator<Item = &'b Function>,
+    ) -> Vec<String> {
+        let mut calls = Vec::new();
+        for func in funcs {
+            // Handles can't be made up, and futures can't be driven here,
+            // but handles which are returned are dropped.
+            let has_resource = |ty: &Type| match ty {
+                Type::Id(id) => self.info(*id).has_resource,
+                _ => false,
+            };
+            if self.gen.skip.contains(&func.name)
+                || !matches!(func.kind, FunctionKind::Freestanding)
+                || self
+                    .gen
+                    .opts
+                    .async_
+                    .is_async(self.resolve, key, func, false)
+                || func.params.iter().any(|(_, ty)| has_resource(ty))
+            {
+                continue;
+            }
+            let mut args = func
+                .params
+                .iter()
+                .map(|(_, ty)| {
+                    if self.arena_borrows(ty) {
+                        return match ty {
+                            Type::String if !self.gen.opts.raw_strings => {
+                                "&u.arbitrary::<String>()?".to_string()
+                            }
+                            _ => "&u.arbitrary::<Vec<_>>()?".to_string(),
+                        };
+                    }
+                    self.convert_remapped(ty, "u.arbitrary()?", false)
+                        .unwrap_or_else(|| "u.arbitrary()?".to_string())
+                })
+                .collect::<Vec<_>>();
+            if self.gen.opts.stateful_exports {
+                args.insert(0, "instance".to_string());
+            }
+            let args = args.join(", ");
+            calls.push(format!(
+                "let _ = <T as {trait_path}>::{}({args});",
+                to_rust_ident(&func.name)
+            ));
+        }
+        calls
+    }
+
+    /// Prints the module handing out handles to the exported resource `name`
+    /// on the host, where `fuzz_exports` calls the exports, instead of the
+    /// component model.
+    fn print_host_handles(&mut self, name: &str) {
+        let wasm = self.gen.wasm_cfg();
+        let module = host_handles_module(name);
+        uwriteln!(
+            self.src,
+            r#"
+                #[cfg(not({wasm}))]
+                #[doc(hidden)]
+                pub mod {module} {{
+                    extern crate std;
+                    use std::cell::RefCell;
+                    use std::vec::Vec;
+
+                    std::thread_local! {{
+                        /// The representations of the live handles, which are
+                        /// their indices plus one, with their destructors.
+                        static HANDLES: RefCell<Vec<Option<(*mut u8, unsafe fn(*mut u8))>>> =
+                            RefCell::new(Vec::new());
+                    }}
+
+                    pub fn new(rep: *mut u8, dtor: unsafe fn(*mut u8)) -> u32 {{
+                        HANDLES.with(|handles| {{
+                            let mut handles = handles.borrow_mut();
+                            let index = match handles.iter().position(Option::is_none) {{
+                                Some(index) => index,
+                                None => {{
+                                    handles.push(None);
+                                    handles.len() - 1
+                                }}
+                            }};
+                            handles[index] = Some((rep, dtor));
+                            index as u32 + 1
+                        }})
+                    }}
+
+                    pub fn rep(handle: u32) -> *mut u8 {{
+                        HANDLES.with(|handles| handles.borrow()[handle as usize - 1])
+                            .expect("handle was dropped")
+                            .0
+                    }}
+
+                    pub unsafe fn drop(handle: u32) {{
+                        let (rep, dtor) = HANDLES
+                            .with(|handles| handles.borrow_mut()[handle as usize - 1].take())
+                            .expect("handle was dropped");
+                        dtor(rep);
+                    }}
+                }}
+            "#
+        );
+    }
+
+    /// Prints a flags type too large for the `bitflags!` macro, which only
+    /// supports integer bits, as a wrapper of `_rt::FlagsWords` with the same
+    /// API as the types it generates.
+    fn print_flags_words(&mut self, name: &str, flags: &Flags, words: usize, docs: &Docs) {
+        let bitflags = self.gen.bitflags_path();
+        let bits = self.path_to_flags_words();
+        let name = name.to_upper_camel_case();
+        self.rustdoc(docs);
+        uwriteln!(
+            self.src,
+            "#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+            pub struct {name}({bits}<{words}>);"
+        );
+        uwriteln!(self.src, "impl {name} {{");
+        for (i, flag) in flags.flags.iter().enumerate() {
+            self.rustdoc(&flag.docs);
+            uwriteln!(
+                self.src,
+                "pub const {}: Self = Self({bits}::bit({i}));",
+                flag.name.to_shouty_snake_case(),
+            );
+        }
+        uwriteln!(
+            self.src,
+            r#"
+                pub const fn bits(&self) -> {bits}<{words}> {{
+                    self.0
+                }}
+
+                pub const fn from_bits_retain(bits: {bits}<{words}>) -> Self {{
+                    Self(bits)
+                }}
+
+                pub fn empty() -> Self {{
+                    <Self as {bitflags}::Flags>::empty()
+                }}
+
+                pub fn all() -> Self {{
+                    <Self as {bitflags}::Flags>::all()
+                }}
+
+                pub fn from_bits(bits: {bits}<{words}>) -> Option<Self> {{
+                    <Self as {bitflags}::Flags>::from_bits(bits)
+                }}
+
+                pub fn from_bits_truncate(bits: {bits}<{words}>) -> Self {{
+                    <Self as {bitflags}::Flags>::from_bits_truncate(bits)
+                }}
+
+                pub fn from_name(name: &str) -> Option<Self> {{
+                    <Self as {bitflags}::Flags>::from_name(name)
+                }}
+
+                pub fn iter(&self) -> {bitflags}::iter::Iter<Self> {{
+                    <Self as {bitflags}::Flags>::iter(self)
+                }}
+
+                pub fn iter_names(&self) -> {bitflags}::iter::IterNames<Self> {{
+                    <Self as {bitflags}::Flags>::iter_names(self)
+                }}
+
+                pub fn is_empty(&self) -> bool {{
+                    <Self as {bitflags}::Flags>::is_empty(self)
+                }}
+
+                pub fn is_all(&self) -> bool {{
+                    <Self as {bitflags}::Flags>::is_all(self)
+                }}
+
+                pub fn intersects(&self, other: Self) -> bool {{
+                    <Self as {bitflags}::Flags>::intersects(self, other)
+                }}
+
+                pub fn contains(&self, other: Self) -> bool {{
+                    <Self as {bitflags}::Flags>::contains(self, other)
+                }}
+
+                pub fn insert(&mut self, other: Self) {{
+                    <Self as {bitflags}::Flags>::insert(self, other)
+                }}
+
+                pub fn remove(&mut self, other: Self) {{
+                    <Self as {bitflags}::Flags>::remove(self, other)
+                }}
+
+                pub fn toggle(&mut self, other: Self) {{
+                    <Self as {bitflags}::Flags>::toggle(self, other)
+                }}
+
+                pub fn set(&mut self, other: Self, value: bool) {{
+                    <Self as {bitflags}::Flags>::set(self, other, value)
+                }}
+
+                #[must_use]
+                pub fn intersection(self, other: Self) -> Self {{
+                    <Self as {bitflags}::Flags>::intersection(self, other)
+                }}
+
+                #[must_use]
+                pub fn union(self, other: Self) -> Self {{
+                    <Self as {bitflags}::Flags>::union(self, other)
+                }}
+
+                #[must_use]
+                pub fn difference(self, other: Self) -> Self {{
+                    <Self as {bitflags}::Flags>::difference(self, other)
+                }}
+
+                #[must_use]
+                pub fn symmetric_difference(self, other: Self) -> Self {{
+                    <Self as {bitflags}::Flags>::symmetric_difference(self, other)
+                }}
+
+                #[must_use]
+                pub fn complement(self) -> Self {{
+                    <Self as {bitflags}::Flags>::complement(self)
+                }}
+            }}
+            "#
+        );
+
+        uwriteln!(self.src, "impl {bitflags}::Flags for {name} {{");
+        uwriteln!(
+            self.src,
+            "const FLAGS: &'static [{bitflags}::Flag<Self>] = &["
+        );
+        for flag in flags.flags.iter() {
+            uwriteln!(
+                self.src,
+                "{bitflags}::Flag::new(\"{}\", Self::{}),",
+                flag.name.to_shouty_snake_case(),
+                flag.name.to_shouty_snake_case(),
+            );
+        }
+        uwriteln!(
+            self.src,
+            r#"
+                ];
+                type Bits = {bits}<{words}>;
+
+                fn bits(&self) -> Self::Bits {{
+                    self.0
+                }}
+
+                fn from_bits_retain(bits: Self::Bits) -> Self {{
+                    Self(bits)
+                }}
+            }}
+            "#
+        );
+
+        for (op, lower, method) in [
+            ("BitOr", "bitor", "union"),
+            ("BitAnd", "bitand", "intersection"),
+            ("BitXor", "bitxor", "symmetric_difference"),
+            ("Sub", "sub", "difference"),
+        ] {
+            uwriteln!(
+                self.src,
+                r#"
+                    impl core::ops::{op} for {name} {{
+                        type Output = Self;
+                        fn {lower}(self, other: Self) -> Self {{
+                            self.{method}(other)
+                        }}
+                    }}
+
+                    impl core::ops::{op}Assign for {name} {{
+                        fn {lower}_assign(&mut self, other: Self) {{
+                            *self = self.{method}(other);
+                        }}
+                    }}
+                "#
+            );
+        }
+        uwriteln!(
+            self.src,
+            r#"
+                impl core::ops::Not for {name} {{
+                    type Output = Self;
+                    fn not(self) -> Self {{
+                        self.complement()
+                    }}
+                }}
+
+                impl core::iter::Extend<{name}> for {name} {{
+                    fn extend<T: IntoIterator<Item = Self>>(&mut self, iterator: T) {{
+                        for flags in iterator {{
+                            self.insert(flags);
+                        }}
+                    }}
+                }}
+
+                impl core::iter::FromIterator<{name}> for {name} {{
+                    fn from_iter<T: IntoIterator<Item = Self>>(iterator: T) -> Self {{
+                        let mut flags = Self::empty();
+                        flags.extend(iterator);
+                        flags
+                    }}
+                }}
+
+                impl core::iter::IntoIterator for {name} {{
+                    type Item = Self;
+                    type IntoIter = {bitflags}::iter::Iter<Self>;
+                    fn into_iter(self) -> Self::IntoIter {{
+                        self.iter()
+                    }}
+                }}
+            "#
+        );
+    }
+
     fn path_from_runtime_module(
         &mut self,
         item: RuntimeItem,
@@ -1870,6 +2917,12 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         self.resolve
     }
 
+    fn types(&mut self, iface: InterfaceId) {
+        for (name, id) in self.resolve.interfaces[iface].types.iter() {
+            self.define_mapped_type(name, *id);
+        }
+    }
+
     fn type_record(&mut self, id: TypeId, _name: &str, record: &Record, docs: &Docs) {
         self.print_typedef_record(id, record, docs);
     }
@@ -1915,7 +2968,8 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         } else {
             let module = match self.identifier {
                 Identifier::Interface(_, key) => self.resolve.name_world_key(key),
-                Identifier::World(_) => unimplemented!("resource exports from worlds"),
+                // Rejected up front by `check_world_exports`.
+                Identifier::World(_) => unreachable!("resource exports from worlds"),
             };
             let box_path = self.path_to_box();
             uwriteln!(
@@ -2047,16 +3101,27 @@ impl<'a> {camel}Borrow<'a>{{
         };
 
         let wasm_resource = self.path_to_wasm_resource();
+        let wasm = self.gen.wasm_cfg();
+        // Mocks hand out handles to imported resources which aren't backed
+        // by anything on the host.
+        let host_drop = if self.in_import && self.gen.opts.mock {
+            "return;".to_string()
+        } else if !self.in_import && self.gen.opts.fuzz_exports {
+            self.print_host_handles(name);
+            format!("{}::drop(_handle);", host_handles_module(name))
+        } else {
+            "unreachable!();".to_string()
+        };
         uwriteln!(
             self.src,
             r#"
                 unsafe impl {wasm_resource} for {camel} {{
                      #[inline]
                      unsafe fn drop(_handle: u32) {{
-                         #[cfg(not(target_arch = "wasm32"))]
-                         unreachable!();
+                         #[cfg(not({wasm}))]
+                         {host_drop}
 
-                         #[cfg(target_arch = "wasm32")]
+                         #[cfg({wasm})]
                          {{
                              #[link(wasm_import_module = "{wasm_import_module}")]
                              extern "C" {{
@@ -2087,27 +3152,18 @@ impl<'a> {camel}Borrow<'a>{{
         }
     }
 
-    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
-        self.src.push_str(&format!(
-            "{bitflags}::bitflags! {{\n",
-            bitflags = self.gen.bitflags_path()
-        ));
-        self.rustdoc(docs);
-        let repr = RustFlagsRepr::new(flags);
-        self.src.push_str(&format!(
-            "#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]\npub struct {}: {repr} {{\n",
-            name.to_upper_camel_case(),
-        ));
-        for (i, flag) in flags.flags.iter().enumerate() {
-            self.rustdoc(&flag.docs);
-            self.src.push_str(&format!(
-                "const {} = 1 << {};\n",
-                flag.name.to_shouty_snake_case(),
-                i,
-            ));
+    fn type_flags(&mut self, id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+        if let RustFlagsRepr::Words(n) = RustFlagsRepr::new(flags) {
+            self.print_flags_words(name, flags, n, docs);
+        } else {
+            self.print_bitflags(name, flags, docs);
+        }
+        if !self.serde_derives(id, TypeMode::owned()).is_empty() {
+            self.print_flags_serde(name, flags);
+        }
+        if !self.arbitrary_derives(id, TypeMode::owned()).is_empty() {
+            self.print_flags_arbitrary(name, flags);
         }
-        self.src.push_str("}\n");
-        self.src.push_str("}\n");
     }
 
     fn type_variant(&mut self, id: TypeId, _name: &str, variant: &Variant, docs: &Docs) {
@@ -2271,3 +3327,20 @@ impl<'a, 'b> wit_bindgen_core::AnonymousTypeGenerator<'a> for AnonTypeGenerator<
         self.interface.push_str(">");
     }
 }
+
+/// The field of the spans entered by `InterfaceGenerator::enter_span` which
+/// results are recorded in, which can't clash with the name of a parameter as
+/// WIT names can't contain `.` or `_`.
+pub(crate) const RESULT_SPAN_FIELD: &str = "wit_bindgen.result";
+
+/// Returns the statement recording the `Debug` representation of `expr` in
+/// `field` of the span entered by `InterfaceGenerator::enter_span`.
+pub(crate) fn record_span_field(field: &str, expr: &str) -> String {
+    format!("_span.record(\"{field}\", ::tracing::field::debug(&{expr}));\n")
+}
+
+/// Returns the name of the module which hands out handles to the exported
+/// resource `name` on the host with `fuzz_exports`.
+fn host_handles_module(name: &str) -> String {
+    format!("__{}_handles", name.to_snake_case())
+}
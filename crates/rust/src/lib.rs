@@ -10,8 +10,8 @@ use std::process::{Command, Stdio};
 use std::str::FromStr;
 use wit_bindgen_core::abi::{Bitcast, WasmType};
 use wit_bindgen_core::{
-    name_package_module, uwrite, uwriteln, wit_parser::*, Files, InterfaceGenerator as _, Source,
-    Types, WorldGenerator,
+    dealias, name_package_module, unsupported, uwrite, uwriteln, wit_parser::*, Files,
+    InterfaceGenerator as _, Source, SourceMap, Types, WorldGenerator,
 };
 
 mod bindgen;
@@ -30,6 +30,7 @@ struct InterfaceName {
 struct RustWasm {
     types: Types,
     src: Source,
+    source_map: SourceMap,
     opts: Opts,
     import_modules: Vec<(String, Vec<String>)>,
     export_modules: Vec<(String, Vec<String>)>,
@@ -47,6 +48,13 @@ struct RustWasm {
     rt_module: IndexSet<RuntimeItem>,
     export_macros: Vec<(String, String)>,
     with: HashMap<String, String>,
+    /// Types remapped to Rust types through `with`, along with the names
+    /// those Rust types are imported as at the root of the bindings.
+    with_types: HashMap<TypeId, String>,
+    /// The export traits, and the calls to their functions, which the
+    /// `fuzz_exports` function is generated from.
+    fuzz_traits: Vec<String>,
+    fuzz_calls: Vec<String>,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
@@ -67,6 +75,10 @@ enum RuntimeItem {
     AsF64,
     ResourceType,
     BoxType,
+    AsyncSupport,
+    FlagsWords,
+    ResultBuffer,
+    Arena,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -106,6 +118,15 @@ pub struct Opts {
     #[cfg_attr(feature = "clap", arg(long))]
     pub skip: Vec<String>,
 
+    /// Names of imported functions returning a `string` or a list of numbers
+    /// which get an additional `<name>_into` binding writing the result into a
+    /// buffer passed by the caller, reusing its allocation.
+    ///
+    /// Functions in interfaces are named like `a:b/io#read`, and functions in
+    /// the world by their name only. Async imports aren't supported.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub result_buffers: Vec<String>,
+
     /// If true, generate stub implementations for any exported functions,
     /// interfaces, and/or resources.
     #[cfg_attr(feature = "clap", arg(long))]
@@ -154,6 +175,12 @@ pub struct Opts {
 
     /// Remapping of interface names to rust module names.
     ///
+    /// Keys of the form `<interface>/<type>`, or just `<type>` for types
+    /// imported by the world, instead remap a single type to a Rust type.
+    /// Functions then take and return the Rust type, converting it with the
+    /// `WitConvert` trait, re-exported at the root of the bindings, which must
+    /// be implemented for it. Such types can't be used within other types.
+    ///
     /// Argument must be of the form `k=v` and this option can be passed
     /// multiple times or one option can be comma separated, for example
     /// `k1=v1,k2=v2`.
@@ -187,20 +214,134 @@ pub struct Opts {
     /// Whether to generate unused structures, not generated by default (false)
     #[cfg_attr(feature = "clap", arg(long))]
     pub generate_unused_types: bool,
+
+    /// Which functions use the callback-based async canonical ABI.
+    ///
+    /// Valid values include:
+    ///
+    /// - `none`: all functions are synchronous.
+    ///
+    /// - `all`: all functions are async.
+    ///
+    /// - a comma-separated list of `import:<name>` and `export:<name>`
+    ///   entries, where `<name>` is either the name of a world-level function
+    ///   or `<interface>#<function>`.
+    #[cfg_attr(feature = "clap", arg(long = "async", default_value_t = AsyncConfig::None))]
+    pub async_: AsyncConfig,
+
+    /// Generate bindings for a 64-bit linear memory, such as for the
+    /// `wasm64-unknown-unknown` target.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub wasm64: bool,
+
+    /// On targets other than wasm, make imported functions call a mock
+    /// installed at runtime with the generated `set_mock` functions, so that
+    /// code using the bindings can be tested on the host.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub mock: bool,
+
+    /// Implement `serde::Serialize` and `serde::Deserialize` for generated
+    /// records, variants, enums and flags, using their WIT names as the
+    /// serialized names.
+    ///
+    /// Types containing resources aren't serializable, and types borrowing
+    /// their contents only implement `Serialize`. The crate using the
+    /// bindings must depend on `serde` with its `derive` feature.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub serde: bool,
+
+    /// Trace each call to an import, and each call of an export, in a
+    /// `tracing` span named after its WIT interface and function, such as
+    /// `my:pkg/iface#func`.
+    ///
+    /// Spans are entered at the `TRACE` level. Async imports and exports
+    /// aren't traced, as their spans would be entered across awaits. The
+    /// crate using the bindings must depend on `tracing`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub instrument: bool,
+
+    /// Record the `Debug` representation of arguments and results in the
+    /// spans of `instrument`, which this implies. Arguments are recorded in
+    /// fields named after their parameters, and results in the
+    /// `wit_bindgen.result` field.
+    ///
+    /// Arguments and results of types remapped with `with` are only recorded
+    /// where they're converted to their WIT types, and streams and futures
+    /// can't be recorded.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub instrument_debug: bool,
+
+    /// Implement `arbitrary::Arbitrary` for generated records, variants, enums
+    /// and flags, so that arbitrary values of them, and the options, results
+    /// and lists containing them, can be generated for fuzzing.
+    ///
+    /// Types containing resources, and types borrowing their contents, don't
+    /// implement it. The crate using the bindings must depend on `arbitrary`
+    /// with its `derive` feature.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub arbitrary: bool,
+
+    /// Emit a `fuzz_exports` function at the root of the bindings, which
+    /// decodes arbitrary bytes into a sequence of calls to the exports of the
+    /// world on the host, for use as the body of a fuzz target. This implies
+    /// `arbitrary`.
+    ///
+    /// Exports taking resources, resource methods, and async exports, aren't
+    /// called. Resources returned by exports are dropped, with handles to
+    /// exported resources made up on the host. Imports called by the exports
+    /// need to be mocked with `mock`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub fuzz_exports: bool,
+
+    /// Implement the functions exported by the world on an instance of the
+    /// type passed to `export!`, rather than on the type itself.
+    ///
+    /// The functions of the generated `Guest` traits take `&mut self`, and
+    /// the instance is constructed the first time an export is called, with
+    /// the constructor passed as `export!(MyType with MyType::new)`, or with
+    /// `Default` as `export!(MyType)`. Resources and async exports aren't
+    /// called on the instance.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub stateful_exports: bool,
+
+    /// Make the bindings support the arena which `cabi_realloc` allocates
+    /// from with the `arena` feature of `wit-bindgen`.
+    ///
+    /// Exports borrow their top-level string and list parameters from the
+    /// arena, as `&str` and `&[T]`, other lists and strings are copied out of
+    /// it when they're lifted, and the arena is reset when each export
+    /// returns. Async exports aren't supported with this option.
+    ///
+    /// The feature only makes `cabi_realloc` allocate from the arena once an
+    /// export of these bindings is called. Bindings generated without this
+    /// option don't support the arena, so all bindings of a component using
+    /// it must be generated with this option.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub arena: bool,
+
+    /// Whether to emit a `<world>.rs.wit-map.json` file alongside the
+    /// bindings which maps their lines back to the WIT items they were
+    /// generated from.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub source_map: bool,
 }
 
 impl Opts {
     pub fn build(self) -> Box<dyn WorldGenerator> {
-        let mut r = RustWasm::new();
-        r.skip = self.skip.iter().cloned().collect();
-        r.opts = self;
-        Box::new(r)
+        Box::new(RustWasm::new(self))
     }
 }
 
 impl RustWasm {
-    fn new() -> RustWasm {
-        RustWasm::default()
+    fn new(mut opts: Opts) -> RustWasm {
+        opts.instrument |= opts.instrument_debug;
+        opts.arbitrary |= opts.fuzz_exports;
+        RustWasm {
+            skip: opts.skip.iter().cloned().collect(),
+            source_map: SourceMap::new(opts.source_map),
+            opts,
+            ..Default::default()
+        }
     }
 
     fn interface<'a>(
@@ -210,7 +351,11 @@ impl RustWasm {
         resolve: &'a Resolve,
         in_import: bool,
     ) -> InterfaceGenerator<'a> {
-        let mut sizes = SizeAlign::default();
+        let mut sizes = SizeAlign::new(if self.opts.wasm64 {
+            AddressSize::Wasm64
+        } else {
+            AddressSize::Wasm32
+        });
         sizes.fill(resolve);
 
         InterfaceGenerator {
@@ -262,6 +407,18 @@ impl RustWasm {
         }
     }
 
+    /// Returns the `cfg` predicate for code which only compiles for wasm.
+    ///
+    /// 64-bit bindings aren't limited to `wasm32`, but otherwise bindings are
+    /// kept to the target they've always been used with.
+    fn wasm_cfg(&self) -> &'static str {
+        if self.opts.wasm64 {
+            "target_family = \"wasm\""
+        } else {
+            "target_arch = \"wasm32\""
+        }
+    }
+
     fn runtime_path(&self) -> &str {
         self.opts
             .runtime_path
@@ -308,6 +465,267 @@ impl RustWasm {
         remapped
     }
 
+    /// Finds the types remapped with `with`, which are named by keys of the
+    /// form `<interface>/<type>`, or just `<type>` for types imported by the
+    /// world itself.
+    fn remap_types(&mut self, resolve: &Resolve, world_id: WorldId) -> Result<()> {
+        let world = &resolve.worlds[world_id];
+        let mut types = Vec::new();
+        for (key, item) in world.imports.iter().chain(world.exports.iter()) {
+            match item {
+                WorldItem::Interface(id) => {
+                    let interface = resolve.name_world_key(key);
+                    for (name, ty) in resolve.interfaces[*id].types.iter() {
+                        types.push((format!("{interface}/{name}"), *ty));
+                    }
+                }
+                WorldItem::Type(ty) => types.push((resolve.name_world_key(key), *ty)),
+                WorldItem::Function(_) => {}
+            }
+        }
+        for (key, ty) in types {
+            let Some(path) = self.with.get(&key) else {
+                continue;
+            };
+            if self.with_types.contains_key(&ty) {
+                continue;
+            }
+            if matches!(resolve.types[ty].kind, TypeDefKind::Resource)
+                || self.types.get(ty).has_borrow_handle
+            {
+                bail!("cannot remap `{key}` with `with`: resources and types borrowing them can't be remapped");
+            }
+            let name = format!("__with_name{}", self.with_name_counter);
+            self.with_name_counter += 1;
+            uwriteln!(self.src, "use {path} as {name};");
+            self.used_with_opts.insert(key);
+            self.with_types.insert(ty, name);
+        }
+        if self.with_types.is_empty() {
+            return Ok(());
+        }
+
+        // Only the types of function parameters and results are converted,
+        // so remapped types can't be used within other types.
+        let feature = "types nested in other types and remapped with `with`";
+        for (key, item) in world.imports.iter().chain(world.exports.iter()) {
+            let item = match item {
+                WorldItem::Interface(id) => {
+                    if self.with.contains_key(&resolve.name_world_key(key)) {
+                        continue;
+                    }
+                    let iface = &resolve.interfaces[*id];
+                    let types = iface.types.iter().map(|(name, ty)| (name.as_str(), *ty));
+                    let item = self.nested_remapped_item(resolve, types, iface.functions.values());
+                    match item {
+                        Some(item) => unsupported::interface_item(resolve, key, *id, item, feature),
+                        None => continue,
+                    }
+                }
+                WorldItem::Type(ty) => {
+                    let name = resolve.name_world_key(key);
+                    match self.nested_remapped_item(resolve, [(name.as_str(), *ty)], None) {
+                        Some(item) => unsupported::world_item(resolve, world_id, item, feature),
+                        None => continue,
+                    }
+                }
+                WorldItem::Function(func) => {
+                    match self.nested_remapped_item(resolve, [], Some(func)) {
+                        Some(item) => unsupported::world_item(resolve, world_id, item, feature),
+                        None => continue,
+                    }
+                }
+            };
+            return Err(item);
+        }
+        Ok(())
+    }
+
+    /// Returns the first of `types` and `funcs` which uses a type remapped
+    /// with `with` other than as a function parameter or result.
+    fn nested_remapped_item<'a>(
+        &self,
+        resolve: &Resolve,
+        types: impl IntoIterator<Item = (&'a str, TypeId)>,
+        funcs: impl IntoIterator<Item = &'a Function>,
+    ) -> Option<unsupported::Item> {
+        for (name, id) in types {
+            if self.with_types.contains_key(&id) {
+                continue;
+            }
+            if type_refs(resolve, id)
+                .iter()
+                .any(|ty| self.uses_remapped(resolve, ty))
+            {
+                return Some(unsupported::Item::Type(name.to_string()));
+            }
+        }
+        for func in funcs {
+            let mut tys = func.params.iter().map(|(_, ty)| ty);
+            let mut results = func.results.iter_types();
+            let nested = |ty: &Type| match ty {
+                Type::Id(id) if resolve.types[*id].name.is_none() => {
+                    self.uses_remapped(resolve, ty)
+                }
+                _ => false,
+            };
+            if tys.by_ref().chain(results.by_ref()).any(nested) {
+                return Some(unsupported::Item::Function(func.name.clone()));
+            }
+        }
+        None
+    }
+
+    /// Returns whether `ty` is remapped with `with` or is an anonymous type,
+    /// such as `list<T>`, which refers to one.
+    fn uses_remapped(&self, resolve: &Resolve, ty: &Type) -> bool {
+        let Type::Id(id) = ty else {
+            return false;
+        };
+        if self.with_types.contains_key(id) {
+            return true;
+        }
+        resolve.types[*id].name.is_none()
+            && type_refs(resolve, *id)
+                .iter()
+                .any(|ty| self.uses_remapped(resolve, ty))
+    }
+
+    /// Checks that each of the `result_buffers` names an imported function
+    /// whose result can be written into a buffer.
+    fn check_result_buffers(&self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        let mut imports = Vec::new();
+        for (key, item) in resolve.worlds[world].imports.iter() {
+            match item {
+                WorldItem::Function(func) => imports.push((None, func)),
+                WorldItem::Interface(id) => imports.extend(
+                    resolve.interfaces[*id]
+                        .functions
+                        .values()
+                        .map(|func| (Some(key), func)),
+                ),
+                WorldItem::Type(_) => {}
+            }
+        }
+        for name in self.opts.result_buffers.iter() {
+            let Some((key, func)) = imports
+                .iter()
+                .find(|(key, func)| result_buffer_key(resolve, *key, func) == *name)
+            else {
+                bail!("no imported function named `{name}` to write the result of into a buffer");
+            };
+            let remapped = match func.results.iter_types().next() {
+                Some(Type::Id(id)) => self.with_types.contains_key(id),
+                _ => false,
+            };
+            if result_buffer_type(resolve, func).is_none() || remapped {
+                bail!(
+                    "the result of `{name}` can't be written into a buffer, as only \
+                     `string` and lists of numbers which aren't remapped with `with` can"
+                );
+            }
+            if self.opts.async_.is_async(resolve, *key, func, true) {
+                bail!("the result of `{name}` can't be written into a buffer, as it's async");
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-exports the trait converting between WIT types and the Rust types
+    /// they're remapped to with `with`, for users to implement.
+    fn finish_with_types(&mut self) {
+        if self.with_types.is_empty() {
+            return;
+        }
+        let rt = self.runtime_path().to_string();
+        uwriteln!(self.src, "pub use {rt}::WitConvert;");
+    }
+
+    fn finish_export_instance(&mut self) {
+        if !self.opts.stateful_exports || self.export_macros.is_empty() {
+            return;
+        }
+        self.src.push_str(
+            "
+/// Implemented by `export!` for the type whose instance the functions exported
+/// by the world are called on.
+pub trait ExportInstance: 'static {
+    /// Returns the instance, constructing it if this is the first call.
+    ///
+    /// # Safety
+    ///
+    /// The instance must not be borrowed already.
+    #[doc(hidden)]
+    unsafe fn _instance() -> &'static mut Self;
+}
+",
+        );
+    }
+
+    fn add_fuzz_calls(&mut self, trait_path: String, calls: Vec<String>) {
+        if !calls.is_empty() {
+            self.fuzz_traits.push(trait_path);
+            self.fuzz_calls.extend(calls);
+        }
+    }
+
+    fn finish_fuzz_exports(&mut self) {
+        if !self.opts.fuzz_exports {
+            return;
+        }
+        // The exports are called on an instance with `stateful_exports`.
+        let (doc, instance, instance_arg) = if self.opts.stateful_exports {
+            ("`instance`", "instance: &mut T, ", "instance, ")
+        } else {
+            ("`T`", "", "")
+        };
+        let mut calls = String::new();
+        for (i, call) in self.fuzz_calls.iter().enumerate() {
+            uwriteln!(calls, "{i} => {{ {call} }}");
+        }
+        let n = self.fuzz_calls.len();
+        let (generics, note, body) = if n == 0 {
+            (
+                "T".to_string(),
+                "\n///\n/// The world has no exports which can be called here, as only functions\n\
+                 /// which aren't async, resource methods or taking resources are, so\n\
+                 /// this does nothing.",
+                format!("let _ = ({instance_arg}data);"),
+            )
+        } else {
+            let bounds = self.fuzz_traits.join(" + ");
+            let body = format!(
+                "
+    let mut u = ::arbitrary::Unstructured::new(data);
+    while !u.is_empty() {{
+        let len = u.len();
+        match u.choose_index({n})? {{
+            {calls}
+            _ => unreachable!(),
+        }}
+        // A call which consumes no data, such as the only one without
+        // arguments, would otherwise be repeated forever.
+        if u.len() == len {{
+            break;
+        }}
+    }}"
+            );
+            (format!("T: {bounds}"), "", body)
+        };
+        self.src.push_str(&format!(
+            "
+/// Decodes `data` into a sequence of calls to the exports implemented by {doc},
+/// with arguments generated with `arbitrary`, for use as the body of a fuzz
+/// target.{note}
+#[allow(dead_code, clippy::all)]
+pub fn fuzz_exports<{generics}>({instance}data: &[u8]) -> ::arbitrary::Result<()> {{
+    {body}
+    Ok(())
+}}
+",
+        ));
+    }
+
     fn finish_runtime_module(&mut self) {
         if self.rt_module.is_empty() {
             return;
@@ -346,6 +764,17 @@ impl RustWasm {
                 uwriteln!(self.src, "pub use alloc_crate::vec::Vec;");
             }
 
+            RuntimeItem::CabiDealloc if self.opts.arena => {
+                let rt = self.runtime_path().to_string();
+                self.src.push_str(&format!(
+                    "
+pub unsafe fn cabi_dealloc(ptr: *mut u8, size: usize, align: usize) {{
+    {rt}::arena::dealloc(ptr, size, align);
+}}
+                    ",
+                ));
+            }
+
             RuntimeItem::CabiDealloc => {
                 self.rt_module.insert(RuntimeItem::StdAllocModule);
                 self.src.push_str(
@@ -422,11 +851,85 @@ pub unsafe fn bool_lift(val: u8) -> bool {
                 );
             }
 
+            RuntimeItem::FlagsWords => {
+                let bitflags = self.bitflags_path();
+                uwriteln!(
+                    self.src,
+                    r#"
+/// The bits of a flags type with more than 128 flags, stored as the 32-bit
+/// words they're passed as in the canonical ABI.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct FlagsWords<const N: usize>(pub [u32; N]);
+
+impl<const N: usize> FlagsWords<N> {{
+    /// Returns the bits with only the bit for flag `i` set.
+    pub const fn bit(i: usize) -> Self {{
+        let mut words = [0; N];
+        words[i / 32] = 1 << (i % 32);
+        FlagsWords(words)
+    }}
+}}
+
+impl<const N: usize> core::ops::BitAnd for FlagsWords<N> {{
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self {{
+        FlagsWords(core::array::from_fn(|i| self.0[i] & other.0[i]))
+    }}
+}}
+
+impl<const N: usize> core::ops::BitOr for FlagsWords<N> {{
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {{
+        FlagsWords(core::array::from_fn(|i| self.0[i] | other.0[i]))
+    }}
+}}
+
+impl<const N: usize> core::ops::BitXor for FlagsWords<N> {{
+    type Output = Self;
+    fn bitxor(self, other: Self) -> Self {{
+        FlagsWords(core::array::from_fn(|i| self.0[i] ^ other.0[i]))
+    }}
+}}
+
+impl<const N: usize> core::ops::Not for FlagsWords<N> {{
+    type Output = Self;
+    fn not(self) -> Self {{
+        FlagsWords(self.0.map(|word| !word))
+    }}
+}}
+
+impl<const N: usize> {bitflags}::Bits for FlagsWords<N> {{
+    const EMPTY: Self = FlagsWords([0; N]);
+    const ALL: Self = FlagsWords([!0; N]);
+}}
+                    "#
+                );
+            }
+
+            RuntimeItem::AsyncSupport => {
+                let rt = self.runtime_path().to_string();
+                uwriteln!(self.src, "pub use {rt}::async_support;");
+            }
+
+            RuntimeItem::ResultBuffer => {
+                let rt = self.runtime_path().to_string();
+                uwriteln!(
+                    self.src,
+                    "pub use {rt}::{{finish_result_buffer, start_result_buffer}};"
+                );
+            }
+
+            RuntimeItem::Arena => {
+                let rt = self.runtime_path().to_string();
+                uwriteln!(self.src, "pub use {rt}::arena;");
+            }
+
             RuntimeItem::RunCtorsOnce => {
                 let rt = self.runtime_path();
+                let wasm = self.wasm_cfg();
                 self.src.push_str(&format!(
                     r#"
-#[cfg(target_arch = "wasm32")]
+#[cfg({wasm})]
 pub fn run_ctors_once() {{
     {rt}::run_ctors_once();
 }}
@@ -646,9 +1149,48 @@ impl As{upcase} for {to_convert} {{
 #[doc(hidden)]
 {macro_export}
 macro_rules! __export_{world_name}_impl {{
-    ($ty:ident) => ({default_bindings_module}::{export_macro_name}!($ty with_types_in {default_bindings_module}););
-    ($ty:ident with_types_in $($path_to_types_root:tt)*) => ("#
+    ($ty:ident) => ({default_bindings_module}::{export_macro_name}!($ty with_types_in {default_bindings_module}););"#
         );
+        if self.opts.stateful_exports {
+            uwriteln!(
+                self.src,
+                "($ty:ident with $ctor:expr) => \
+                    ({default_bindings_module}::{export_macro_name}!($ty with $ctor; with_types_in {default_bindings_module}););"
+            );
+            self.src
+                .push_str("($ty:ident with_types_in $($path_to_types_root:tt)*) => (");
+            self.export_macro_body(
+                resolve,
+                world_id,
+                Some("<$ty as ::core::default::Default>::default"),
+            );
+            self.src.push_str(");\n");
+            self.src.push_str(
+                "($ty:ident with $ctor:expr; with_types_in $($path_to_types_root:tt)*) => (",
+            );
+            self.export_macro_body(resolve, world_id, Some("$ctor"));
+        } else {
+            self.src
+                .push_str("($ty:ident with_types_in $($path_to_types_root:tt)*) => (");
+            self.export_macro_body(resolve, world_id, None);
+        }
+        uwriteln!(self.src, ")\n}}");
+
+        uwriteln!(
+            self.src,
+            "#[doc(inline)]\n\
+            {use_vis} use __export_{world_name}_impl as {export_macro_name};"
+        );
+
+        if self.opts.stubs {
+            uwriteln!(self.src, "export!(Stub);");
+        }
+    }
+
+    /// Prints the body of the export macro, which exports the type `$ty` by
+    /// invoking the export macro of each interface. With `stateful_exports`,
+    /// `ctor` is the constructor of its instance.
+    fn export_macro_body(&mut self, resolve: &Resolve, world_id: WorldId, ctor: Option<&str>) {
         for (name, path_to_types) in self.export_macros.iter() {
             let mut path = "$($path_to_types_root)*".to_string();
             if !path_to_types.is_empty() {
@@ -658,24 +1200,28 @@ macro_rules! __export_{world_name}_impl {{
             uwriteln!(self.src, "{path}::{name}!($ty with_types_in {path});");
         }
 
+        if let Some(ctor) = ctor {
+            uwriteln!(
+                self.src,
+                "const _: () = {{
+                    static mut INSTANCE: ::core::option::Option<$ty> = ::core::option::Option::None;
+
+                    impl $($path_to_types_root)*::ExportInstance for $ty {{
+                        unsafe fn _instance() -> &'static mut Self {{
+                            let instance = &mut *::core::ptr::addr_of_mut!(INSTANCE);
+                            instance.get_or_insert_with(|| ({ctor})())
+                        }}
+                    }}
+                }};"
+            );
+        }
+
         // See comments in `finish` for why this conditionally happens here.
         if self.opts.pub_export_macro {
             uwriteln!(self.src, "const _: () = {{");
             self.emit_custom_section(resolve, world_id, "imports and exports", None);
             uwriteln!(self.src, "}};");
         }
-
-        uwriteln!(self.src, ")\n}}");
-
-        uwriteln!(
-            self.src,
-            "#[doc(inline)]\n\
-            {use_vis} use __export_{world_name}_impl as {export_macro_name};"
-        );
-
-        if self.opts.stubs {
-            uwriteln!(self.src, "export!(Stub);");
-        }
     }
 
     /// Generates a `#[link_section]` custom section to get smuggled through
@@ -695,7 +1241,8 @@ macro_rules! __export_{world_name}_impl {{
         section_suffix: &str,
         func_name: Option<&str>,
     ) {
-        self.src.push_str("\n#[cfg(target_arch = \"wasm32\")]\n");
+        let wasm = self.wasm_cfg();
+        uwriteln!(self.src, "\n#[cfg({wasm})]");
 
         // The custom section name here must start with "component-type" but
         // otherwise is attempted to be unique here to ensure that this doesn't get
@@ -766,12 +1313,13 @@ macro_rules! __export_{world_name}_impl {{
 
         if let Some(func_name) = func_name {
             let rt = self.runtime_path().to_string();
+            let wasm = self.wasm_cfg();
             uwriteln!(
                 self.src,
                 "
                 #[inline(never)]
                 #[doc(hidden)]
-                #[cfg(target_arch = \"wasm32\")]
+                #[cfg({wasm})]
                 pub fn {func_name}() {{
                     {rt}::maybe_link_cabi_realloc();
                 }}
@@ -782,7 +1330,7 @@ macro_rules! __export_{world_name}_impl {{
 }
 
 impl WorldGenerator for RustWasm {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         wit_bindgen_core::generated_preamble(&mut self.src, env!("CARGO_PKG_VERSION"));
 
         // Render some generator options to assist with debugging and/or to help
@@ -797,6 +1345,13 @@ impl WorldGenerator for RustWasm {
         if !self.opts.skip.is_empty() {
             uwriteln!(self.src, "//   * skip: {:?}", self.opts.skip);
         }
+        if !self.opts.result_buffers.is_empty() {
+            uwriteln!(
+                self.src,
+                "//   * result-buffers: {:?}",
+                self.opts.result_buffers
+            );
+        }
         if !matches!(self.opts.ownership, Ownership::Owning) {
             uwriteln!(self.src, "//   * ownership: {:?}", self.opts.ownership);
         }
@@ -822,12 +1377,83 @@ impl WorldGenerator for RustWasm {
         if self.opts.pub_export_macro {
             uwriteln!(self.src, "//   * pub-export-macro");
         }
+        if !matches!(self.opts.async_, AsyncConfig::None) {
+            uwriteln!(self.src, "//   * async: {}", self.opts.async_);
+        }
         self.types.analyze(resolve);
         self.world = Some(world);
 
         for (k, v) in self.opts.with.iter() {
             self.with.insert(k.clone(), v.clone());
         }
+        self.remap_types(resolve, world)?;
+        self.check_result_buffers(resolve, world)
+    }
+
+    /// Generates a `<world>.rs` file for each world, which are expected to be
+    /// included as sibling modules named after their worlds.
+    ///
+    /// Interfaces imported by several worlds are generated by the first world
+    /// which imports them, and the other worlds refer to them as though they
+    /// had been passed to `with`.
+    ///
+    /// Everything else is generated by each world which uses it: exported
+    /// interfaces, as `with` only applies to imports, interfaces which a
+    /// world both imports and exports, and types defined in worlds.
+    fn generate_worlds(
+        &mut self,
+        resolve: &Resolve,
+        worlds: &[WorldId],
+        files: &mut Files,
+    ) -> Result<()> {
+        if let [world] = worlds {
+            return self.generate(resolve, *world, files);
+        }
+
+        // Paths to already-generated interfaces from a sibling module.
+        let mut shared = HashMap::<String, String>::new();
+        for world in worlds {
+            let imports = resolve.worlds[*world]
+                .imports
+                .iter()
+                .filter_map(|(key, item)| match (key, item) {
+                    // Interfaces which are also exported are generated along
+                    // with their exports by each world.
+                    (WorldKey::Interface(_), WorldItem::Interface(id))
+                        if !resolve.worlds[*world].exports.contains_key(key) =>
+                    {
+                        Some((key, *id))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let mut opts = self.opts.clone();
+            for (key, _) in imports.iter() {
+                let name = resolve.name_world_key(key);
+                if opts.with.iter().any(|(k, _)| *k == name) {
+                    continue;
+                }
+                if let Some(path) = shared.get(&name) {
+                    opts.with.push((name, path.clone()));
+                }
+            }
+
+            let mut gen = RustWasm::new(opts);
+            gen.generate(resolve, *world, files)?;
+
+            let module = to_rust_ident(&resolve.worlds[*world].name.to_snake_case());
+            for (key, id) in imports {
+                if gen.interface_names[&id].remapped {
+                    continue;
+                }
+                let path = compute_module_path(key, resolve, false).join("::");
+                shared
+                    .entry(resolve.name_world_key(key))
+                    .or_insert_with(|| format!("super::{module}::{path}"));
+            }
+        }
+        Ok(())
     }
 
     fn import_interface(
@@ -836,7 +1462,8 @@ impl WorldGenerator for RustWasm {
         name: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &unsupported_types)?;
         self.interface_last_seen_as_import.insert(id, true);
         let wasm_import_module = resolve.name_world_key(name);
         let mut gen = self.interface(
@@ -847,13 +1474,14 @@ impl WorldGenerator for RustWasm {
         );
         let (snake, module_path) = gen.start_append_submodule(name);
         if gen.gen.name_interface(resolve, id, name, false) {
-            return;
+            return Ok(());
         }
         gen.types(id);
 
         gen.generate_imports(resolve.interfaces[id].functions.values());
 
         gen.finish_append_submodule(&snake, module_path);
+        Ok(())
     }
 
     fn import_funcs(
@@ -862,7 +1490,8 @@ impl WorldGenerator for RustWasm {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         self.import_funcs_called = true;
 
         let mut gen = self.interface(Identifier::World(world), Some("$root"), resolve, true);
@@ -871,6 +1500,7 @@ impl WorldGenerator for RustWasm {
 
         let src = gen.finish();
         self.src.push_str(&src);
+        Ok(())
     }
 
     fn export_interface(
@@ -880,6 +1510,7 @@ impl WorldGenerator for RustWasm {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, name, id, &unsupported_types)?;
         self.interface_last_seen_as_import.insert(id, false);
         let mut gen = self.interface(Identifier::Interface(id, name), None, resolve, false);
         let (snake, module_path) = gen.start_append_submodule(name);
@@ -893,6 +1524,18 @@ impl WorldGenerator for RustWasm {
         self.export_macros
             .push((macro_name, self.interface_names[&id].path.clone()));
 
+        if self.opts.fuzz_exports {
+            let world_id = self.world.unwrap();
+            let trait_path = format!("{}::Guest", self.interface_names[&id].path);
+            let gen = self.interface(Identifier::World(world_id), None, resolve, false);
+            let calls = gen.fuzz_calls(
+                Some(name),
+                &trait_path,
+                resolve.interfaces[id].functions.values(),
+            );
+            self.add_fuzz_calls(trait_path, calls);
+        }
+
         if self.opts.stubs {
             let world_id = self.world.unwrap();
             let mut gen = self.interface(Identifier::World(world_id), None, resolve, false);
@@ -910,12 +1553,19 @@ impl WorldGenerator for RustWasm {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         let mut gen = self.interface(Identifier::World(world), None, resolve, false);
         let macro_name = gen.generate_exports(None, funcs.iter().map(|f| f.1))?;
         let src = gen.finish();
         self.src.push_str(&src);
         self.export_macros.push((macro_name, String::new()));
 
+        if self.opts.fuzz_exports {
+            let gen = self.interface(Identifier::World(world), None, resolve, false);
+            let calls = gen.fuzz_calls(None, "Guest", funcs.iter().map(|f| f.1));
+            self.add_fuzz_calls("Guest".to_string(), calls);
+        }
+
         if self.opts.stubs {
             let mut gen = self.interface(Identifier::World(world), None, resolve, false);
             gen.generate_stub(None, funcs.iter().map(|f| f.1));
@@ -931,22 +1581,30 @@ impl WorldGenerator for RustWasm {
         world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, types, &[], &unsupported_types)?;
         let mut gen = self.interface(Identifier::World(world), Some("$root"), resolve, true);
         for (name, ty) in types {
-            gen.define_type(name, *ty);
+            gen.define_mapped_type(name, *ty);
         }
         let src = gen.finish();
         self.src.push_str(&src);
+        Ok(())
     }
 
-    fn finish_imports(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
+    fn finish_imports(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        files: &mut Files,
+    ) -> Result<()> {
         if !self.import_funcs_called {
             // We call `import_funcs` even if the world doesn't import any
             // functions since one of the side effects of that method is to
             // generate `struct`s for any imported resources.
-            self.import_funcs(resolve, world, &[], files);
+            self.import_funcs(resolve, world, &[], files)?;
         }
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
@@ -957,6 +1615,9 @@ impl WorldGenerator for RustWasm {
         let exports = mem::take(&mut self.export_modules);
         self.emit_modules(exports);
 
+        self.finish_with_types();
+        self.finish_export_instance();
+        self.finish_fuzz_exports();
         self.finish_runtime_module();
         self.finish_export_macro(resolve, world);
 
@@ -1018,7 +1679,12 @@ impl WorldGenerator for RustWasm {
         );
 
         if self.opts.stubs {
-            self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
+            if self.opts.stateful_exports {
+                self.src
+                    .push_str("\n#[derive(Debug, Default)]\npub struct Stub;\n");
+            } else {
+                self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
+            }
         }
 
         let mut src = mem::take(&mut self.src);
@@ -1047,7 +1713,7 @@ impl WorldGenerator for RustWasm {
         }
 
         let module_name = name.to_snake_case();
-        files.push(&format!("{module_name}.rs"), src.as_bytes());
+        files.push_mapped(&format!("{module_name}.rs"), &src, &self.source_map);
 
         let remapping_keys = self.with.keys().cloned().collect::<HashSet<String>>();
 
@@ -1159,6 +1825,92 @@ impl fmt::Display for Ownership {
     }
 }
 
+#[derive(Default, Debug, Clone)]
+pub enum AsyncConfig {
+    /// All functions are synchronous.
+    #[default]
+    None,
+
+    /// Only the listed functions are async.
+    ///
+    /// Names are either the name of a world-level function or
+    /// `<interface>#<function>`.
+    Some {
+        imports: Vec<String>,
+        exports: Vec<String>,
+    },
+
+    /// All functions are async.
+    All,
+}
+
+impl AsyncConfig {
+    fn is_async(
+        &self,
+        resolve: &Resolve,
+        key: Option<&WorldKey>,
+        func: &Function,
+        import: bool,
+    ) -> bool {
+        match self {
+            AsyncConfig::None => false,
+            AsyncConfig::All => true,
+            AsyncConfig::Some { imports, exports } => {
+                let name = match key {
+                    Some(key) => format!("{}#{}", resolve.name_world_key(key), func.name),
+                    None => func.name.clone(),
+                };
+                let list = if import { imports } else { exports };
+                list.contains(&name)
+            }
+        }
+    }
+}
+
+impl FromStr for AsyncConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => return Ok(Self::None),
+            "all" => return Ok(Self::All),
+            _ => {}
+        }
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        for item in s.split(',') {
+            if let Some(name) = item.strip_prefix("import:") {
+                imports.push(name.to_string());
+            } else if let Some(name) = item.strip_prefix("export:") {
+                exports.push(name.to_string());
+            } else {
+                return Err(format!(
+                    "unrecognized async setting: `{item}`; \
+                     expected `none`, `all`, `import:<name>`, or `export:<name>`"
+                ));
+            }
+        }
+        Ok(Self::Some { imports, exports })
+    }
+}
+
+impl fmt::Display for AsyncConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsyncConfig::None => f.write_str("none"),
+            AsyncConfig::All => f.write_str("all"),
+            AsyncConfig::Some { imports, exports } => {
+                let items = imports
+                    .iter()
+                    .map(|name| format!("import:{name}"))
+                    .chain(exports.iter().map(|name| format!("export:{name}")))
+                    .collect::<Vec<_>>();
+                f.write_str(&items.join(","))
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 struct FnSig {
     async_: bool,
@@ -1168,6 +1920,60 @@ struct FnSig {
     generics: Option<String>,
     self_arg: Option<String>,
     self_is_first_param: bool,
+    /// The type of the buffer which the function's result is written into,
+    /// passed as a trailing `buf` parameter, instead of returning it.
+    result_buffer: Option<String>,
+}
+
+/// Returns the name of the imported `func` in `result_buffers`, which is
+/// `<interface>#<function>` for functions in interfaces, like the keys of
+/// `with`, or only the function's name for functions in the world.
+fn result_buffer_key(resolve: &Resolve, key: Option<&WorldKey>, func: &Function) -> String {
+    match key {
+        Some(key) => format!("{}#{}", resolve.name_world_key(key), func.name),
+        None => func.name.clone(),
+    }
+}
+
+/// Returns the type which `func` returns if it can be written into a buffer
+/// by the `<name>_into` binding generated for imports in `result_buffers`,
+/// which is either a `string` or a list of numbers, with aliases resolved.
+fn result_buffer_type(resolve: &Resolve, func: &Function) -> Option<Type> {
+    if func.results.len() != 1 {
+        return None;
+    }
+    let unalias = |ty: Type| match ty {
+        Type::Id(id) => {
+            let id = dealias(resolve, id);
+            match resolve.types[id].kind {
+                TypeDefKind::Type(t) => t,
+                _ => Type::Id(id),
+            }
+        }
+        _ => ty,
+    };
+    let ty = unalias(*func.results.iter_types().next()?);
+    let element = match ty {
+        Type::String => return Some(ty),
+        Type::Id(id) => match &resolve.types[id].kind {
+            TypeDefKind::List(element) => unalias(*element),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match element {
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::F32
+        | Type::F64 => Some(ty),
+        _ => None,
+    }
 }
 
 pub fn to_rust_ident(name: &str) -> String {
@@ -1327,12 +2133,37 @@ fn perform_cast(operand: &str, cast: &Bitcast) -> String {
     }
 }
 
+/// Returns the types which the definition of `id` refers to directly.
+fn type_refs(resolve: &Resolve, id: TypeId) -> Vec<Type> {
+    match &resolve.types[id].kind {
+        TypeDefKind::Record(r) => r.fields.iter().map(|f| f.ty).collect(),
+        TypeDefKind::Tuple(t) => t.types.clone(),
+        TypeDefKind::Variant(v) => v.cases.iter().filter_map(|c| c.ty).collect(),
+        TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => vec![*ty],
+        TypeDefKind::Result(r) => r.ok.iter().chain(r.err.iter()).copied().collect(),
+        TypeDefKind::Future(ty) => ty.iter().copied().collect(),
+        TypeDefKind::Stream(s) => s.element.iter().chain(s.end.iter()).copied().collect(),
+        TypeDefKind::Handle(_)
+        | TypeDefKind::Resource
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Unknown => Vec::new(),
+    }
+}
+
+/// Describes the types which bindings can't yet be generated for.
+fn unsupported_types(resolve: &Resolve, kind: &TypeDefKind) -> Option<&'static str> {
+    unsupported::futures_and_streams(resolve, kind)
+}
+
 enum RustFlagsRepr {
     U8,
     U16,
     U32,
     U64,
     U128,
+    /// Flags which don't fit in an integer, stored as `_rt::FlagsWords`.
+    Words(usize),
 }
 
 impl RustFlagsRepr {
@@ -1343,7 +2174,7 @@ impl RustFlagsRepr {
             FlagsRepr::U32(1) => RustFlagsRepr::U32,
             FlagsRepr::U32(2) => RustFlagsRepr::U64,
             FlagsRepr::U32(3 | 4) => RustFlagsRepr::U128,
-            FlagsRepr::U32(n) => panic!("unsupported number of flags: {}", n * 32),
+            FlagsRepr::U32(n) => RustFlagsRepr::Words(n),
         }
     }
 }
@@ -1356,6 +2187,7 @@ impl fmt::Display for RustFlagsRepr {
             RustFlagsRepr::U32 => "u32".fmt(f),
             RustFlagsRepr::U64 => "u64".fmt(f),
             RustFlagsRepr::U128 => "u128".fmt(f),
+            RustFlagsRepr::Words(n) => write!(f, "[u32; {n}]"),
         }
     }
 }
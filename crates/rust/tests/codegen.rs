@@ -48,6 +48,83 @@ mod codegen_tests {
                     #[test]
                     fn works() {}
                 }
+
+                mod mocked {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[mocked]",
+                        mock: true,
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod serde {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[serde]",
+                        serde: true,
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod instrumented {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[instrumented]",
+                        instrument_debug: true,
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod fuzzed {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[fuzzed]",
+                        fuzz_exports: true,
+                    });
+
+                    #[test]
+                    fn works() {
+                        // The stubs panic when they're called, which is fine
+                        // as long as decoding the calls terminates.
+                        let data = (0..=255).collect::<Vec<u8>>();
+                        let _ = std::panic::catch_unwind(|| fuzz_exports::<Stub>(&data));
+                    }
+                }
+
+                mod stateful {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[stateful]",
+                        stateful_exports: true,
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod arena {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_prefix: "[arena]",
+                        arena: true,
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
             }
 
         };
@@ -55,6 +132,10 @@ mod codegen_tests {
     test_helpers::codegen_tests!();
 }
 
+/// Serializes tests using the global state of the runtime, which expects to be
+/// used from a single thread.
+static RT_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
 mod strings {
     wit_bindgen::generate!({
         inline: "
@@ -227,6 +308,356 @@ mod alternative_bitflags_path {
     }
 }
 
+mod wide_flags {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+            world foo {
+                flags wide {
+                    b0, b1, b2, b3, b4, b5, b6, b7, b8, b9,
+                    b10, b11, b12, b13, b14, b15, b16, b17, b18, b19,
+                    b20, b21, b22, b23, b24, b25, b26, b27, b28, b29,
+                    b30, b31, b32, b33, b34, b35, b36, b37, b38, b39,
+                    b40, b41, b42, b43, b44, b45, b46, b47, b48, b49,
+                    b50, b51, b52, b53, b54, b55, b56, b57, b58, b59,
+                    b60, b61, b62, b63, b64, b65, b66, b67, b68, b69,
+                    b70, b71, b72, b73, b74, b75, b76, b77, b78, b79,
+                    b80, b81, b82, b83, b84, b85, b86, b87, b88, b89,
+                    b90, b91, b92, b93, b94, b95, b96, b97, b98, b99,
+                    b100, b101, b102, b103, b104, b105, b106, b107, b108, b109,
+                    b110, b111, b112, b113, b114, b115, b116, b117, b118, b119,
+                    b120, b121, b122, b123, b124, b125, b126, b127, b128, b129,
+                }
+                import roundtrip: func(a: wide) -> wide;
+                export toggle: func(a: wide) -> wide;
+            }
+        ",
+    });
+
+    struct Component;
+
+    export!(Component);
+
+    impl Guest for Component {
+        fn toggle(a: Wide) -> Wide {
+            roundtrip(!a)
+        }
+    }
+
+    #[test]
+    fn api() {
+        let mut flags = Wide::B0 | Wide::B129;
+        assert!(flags.contains(Wide::B129));
+        assert!(!flags.contains(Wide::B64));
+        assert_eq!(flags.bits().0, [1, 0, 0, 0, 2]);
+        flags.remove(Wide::B0);
+        flags.insert(Wide::B100);
+        assert_eq!(flags.iter().collect::<Vec<_>>(), [Wide::B100, Wide::B129]);
+        assert_eq!(
+            flags.iter_names().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["B100", "B129"]
+        );
+        assert_eq!((!flags).iter().count(), 128);
+        assert_eq!(Wide::all() - Wide::all(), Wide::empty());
+        assert_eq!(Wide::from_name("B3"), Some(Wide::B3));
+    }
+}
+
+mod mock {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+            interface store {
+                resource counter {
+                    constructor(start: u32);
+                    bump: func(by: u32) -> u32;
+                }
+                get: func(key: string) -> option<list<u8>>;
+            }
+            world foo {
+                import store;
+                import log: func(msg: string);
+                export run: func(key: string) -> u32;
+            }
+        ",
+        mock: true,
+    });
+
+    use my::inline::store;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Component;
+
+    export!(Component);
+
+    impl Guest for Component {
+        fn run(key: String) -> u32 {
+            let counter = store::Counter::new(1);
+            let len = store::get(&key).map(|v| v.len() as u32).unwrap_or(0);
+            log(&format!("{key} has {len} bytes"));
+            counter.bump(len)
+        }
+    }
+
+    struct Store;
+
+    impl store::Mock for Store {
+        fn counter_new(&self, start: u32) -> store::Counter {
+            unsafe { store::Counter::from_handle(start) }
+        }
+
+        fn counter_bump(&self, this: &store::Counter, by: u32) -> u32 {
+            this.handle() + by
+        }
+
+        fn get(&self, key: &str) -> Option<Vec<u8>> {
+            (key == "a").then(|| vec![1, 2, 3])
+        }
+    }
+
+    struct Log(Rc<RefCell<Vec<String>>>);
+
+    impl Mock for Log {
+        fn log(&self, msg: &str) {
+            self.0.borrow_mut().push(msg.to_string());
+        }
+    }
+
+    #[test]
+    fn mocked_imports() {
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        store::set_mock(Store);
+        set_mock(Log(logged.clone()));
+        assert_eq!(<Component as Guest>::run("a".to_string()), 4);
+        assert_eq!(<Component as Guest>::run("b".to_string()), 1);
+        assert_eq!(*logged.borrow(), ["a has 3 bytes", "b has 0 bytes"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no mock of the world `foo` was installed")]
+    fn missing_mock() {
+        log("hello");
+    }
+}
+
+mod result_buffers {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+            interface io {
+                type bytes = list<u8>;
+                resource file {
+                    read: func(len: u32) -> bytes;
+                }
+                /// The name of the host.
+                name: func() -> string;
+                samples: func() -> list<f64>;
+            }
+            world foo {
+                import io;
+            }
+        ",
+        result_buffers: [
+            "my:inline/io#name",
+            "my:inline/io#samples",
+            "my:inline/io#[method]file.read",
+        ],
+        mock: true,
+    });
+
+    use my::inline::io;
+
+    struct Io;
+
+    impl io::Mock for Io {
+        fn file_read(&self, _: &io::File, len: u32) -> Vec<u8> {
+            vec![7; len as usize]
+        }
+
+        fn name(&self) -> String {
+            "host".to_string()
+        }
+
+        fn samples(&self) -> Vec<f64> {
+            vec![0.5, 1.5]
+        }
+    }
+
+    #[test]
+    fn into_buffers() {
+        io::set_mock(Io);
+        let mut name = String::from("previous");
+        io::name_into(&mut name);
+        assert_eq!(name, "host");
+        let mut samples = Vec::new();
+        io::samples_into(&mut samples);
+        assert_eq!(samples, [0.5, 1.5]);
+        let file = unsafe { io::File::from_handle(1) };
+        let mut bytes = Vec::with_capacity(16);
+        file.read_into(3, &mut bytes);
+        assert_eq!(bytes, [7, 7, 7]);
+    }
+
+    #[test]
+    fn realloc_reuses_buffer() {
+        use wit_bindgen::rt::{cabi_realloc, finish_result_buffer, start_result_buffer};
+        let _rt = super::RT_LOCK.lock().unwrap();
+        unsafe {
+            // Results which fit are allocated in the buffer.
+            let mut buf = vec![1u32, 2, 3, 4];
+            let ptr = buf.as_mut_ptr();
+            start_result_buffer(&mut buf);
+            let result = cabi_realloc(std::ptr::null_mut(), 0, 4, 8).cast::<u32>();
+            assert_eq!(result, ptr);
+            result.write(5);
+            result.add(1).write(6);
+            finish_result_buffer(&mut buf, result, 2);
+            assert_eq!(buf, [5, 6]);
+            assert_eq!(buf.as_mut_ptr(), ptr);
+
+            // ... and larger ones replace it.
+            start_result_buffer(&mut buf);
+            let result = cabi_realloc(std::ptr::null_mut(), 0, 4, 32).cast::<u32>();
+            assert_ne!(result, ptr);
+            for i in 0..8 {
+                result.add(i).write(i as u32);
+            }
+            finish_result_buffer(&mut buf, result, 8);
+            assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7]);
+        }
+    }
+
+    #[test]
+    fn realloc_grows_buffer() {
+        use wit_bindgen::rt::{cabi_realloc, finish_result_buffer, start_result_buffer};
+        let _rt = super::RT_LOCK.lock().unwrap();
+        unsafe {
+            let mut buf = Vec::<u8>::with_capacity(8);
+            let ptr = buf.as_mut_ptr();
+            start_result_buffer(&mut buf);
+            let result = cabi_realloc(std::ptr::null_mut(), 0, 1, 4);
+            assert_eq!(result, ptr);
+            result.copy_from(b"abcd".as_ptr(), 4);
+
+            // The buffer grows in place up to its capacity...
+            let result = cabi_realloc(result, 4, 1, 8);
+            assert_eq!(result, ptr);
+            result.add(4).copy_from(b"efgh".as_ptr(), 4);
+
+            // ... and past it the contents are copied into a new allocation,
+            // leaving the buffer to its `Vec`.
+            let result = cabi_realloc(result, 8, 1, 12);
+            assert_ne!(result, ptr);
+            result.add(8).copy_from(b"ijkl".as_ptr(), 4);
+            finish_result_buffer(&mut buf, result, 12);
+            assert_eq!(buf, b"abcdefghijkl");
+        }
+    }
+
+    #[test]
+    fn rejects_unqualified_and_async_imports() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+        let mut resolve = Resolve::default();
+        let wit = "
+            package foo:bar;
+            interface io { read: func() -> string; }
+            world w { import io; }
+        ";
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("input.wit".as_ref(), wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let generate = |name: &str, async_| {
+            let opts = wit_bindgen_rust::Opts {
+                result_buffers: vec![name.to_string()],
+                async_,
+                ..Default::default()
+            };
+            opts.build()
+                .generate(&resolve, world, &mut Default::default())
+                .map_err(|e| e.to_string())
+        };
+        assert!(generate("foo:bar/io#read", wit_bindgen_rust::AsyncConfig::None).is_ok());
+        assert_eq!(
+            generate("read", wit_bindgen_rust::AsyncConfig::None).unwrap_err(),
+            "no imported function named `read` to write the result of into a buffer"
+        );
+        assert_eq!(
+            generate("foo:bar/io#read", wit_bindgen_rust::AsyncConfig::All).unwrap_err(),
+            "the result of `foo:bar/io#read` can't be written into a buffer, as it's async"
+        );
+    }
+}
+
+mod serde_support {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+            interface types {
+                resource handle;
+                record point {
+                    x-pos: s32,
+                    y-pos: s32,
+                }
+                variant shape {
+                    dot(point),
+                    line(tuple<point, point>),
+                    empty,
+                }
+                enum color { dark-red, light-blue }
+                flags perms { read, write, run-now }
+                record drawing {
+                    name: string,
+                    shapes: list<shape>,
+                    color: option<color>,
+                    perms: perms,
+                    status: result<u8, string>,
+                }
+                record owner {
+                    handle: handle,
+                }
+                draw: func(d: drawing) -> drawing;
+            }
+            world foo {
+                import types;
+            }
+        ",
+        serde: true,
+    });
+
+    use my::inline::types::*;
+
+    #[test]
+    fn round_trip() {
+        let drawing = Drawing {
+            name: "lines".to_string(),
+            shapes: vec![
+                Shape::Dot(Point { x_pos: 1, y_pos: 2 }),
+                Shape::Line((Point { x_pos: 0, y_pos: 0 }, Point { x_pos: 3, y_pos: 4 })),
+                Shape::Empty,
+            ],
+            color: Some(Color::DarkRed),
+            perms: Perms::READ | Perms::RUN_NOW,
+            status: Err("late".to_string()),
+        };
+        let json = serde_json::to_string(&drawing).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"lines","shapes":[{"dot":{"x-pos":1,"y-pos":2}},{"line":[{"x-pos":0,"y-pos":0},{"x-pos":3,"y-pos":4}]},"empty"],"color":"dark-red","perms":["read","run-now"],"status":{"Err":"late"}}"#
+        );
+        let back: Drawing = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn unknown_flag() {
+        let err = serde_json::from_str::<Perms>(r#"["read","exec"]"#).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `exec`"));
+    }
+}
+
 mod owned_resource_deref_mut {
     wit_bindgen::generate!({
         inline: "
@@ -456,6 +887,104 @@ mod with_and_resources {
     }
 }
 
+mod with_types {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface geometry {
+                record point {
+                    x: f32,
+                    y: f32,
+                }
+                type bytes = list<u8>;
+
+                midpoint: func(a: point, b: point) -> point;
+                checksum: func(data: bytes) -> bytes;
+            }
+
+            world baz {
+                import geometry;
+                export geometry;
+            }
+        ",
+        with: {
+            "my:inline/geometry/point": domain::Vec2,
+            "my:inline/geometry/bytes": domain::Bytes,
+        },
+        ownership: Borrowing {
+            duplicate_if_necessary: true
+        },
+    });
+
+    pub mod domain {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct Vec2(pub f32, pub f32);
+
+        pub struct Bytes(pub Vec<u8>);
+    }
+
+    use domain::{Bytes, Vec2};
+    use exports::my::inline::geometry as exported;
+    use my::inline::geometry::{self as imported, Point};
+
+    impl WitConvert<Point> for Vec2 {
+        fn from_wit(p: Point) -> Self {
+            Vec2(p.x, p.y)
+        }
+
+        fn into_wit(self) -> Point {
+            Point {
+                x: self.0,
+                y: self.1,
+            }
+        }
+    }
+
+    impl WitConvert<exported::Point> for Vec2 {
+        fn from_wit(p: exported::Point) -> Self {
+            Vec2(p.x, p.y)
+        }
+
+        fn into_wit(self) -> exported::Point {
+            exported::Point {
+                x: self.0,
+                y: self.1,
+            }
+        }
+    }
+
+    impl WitConvert<Vec<u8>> for Bytes {
+        fn from_wit(bytes: Vec<u8>) -> Self {
+            Bytes(bytes)
+        }
+
+        fn into_wit(self) -> Vec<u8> {
+            self.0
+        }
+    }
+
+    struct Component;
+
+    export!(Component);
+
+    impl exported::Guest for Component {
+        fn midpoint(a: Vec2, b: Vec2) -> Vec2 {
+            Vec2((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+        }
+
+        fn checksum(data: Bytes) -> Bytes {
+            Bytes(vec![data.0.iter().fold(0, |a, b| a ^ b)])
+        }
+    }
+
+    #[allow(dead_code)]
+    fn test() {
+        let _: fn(Vec2, Vec2) -> Vec2 = imported::midpoint;
+        let _: fn(Bytes) -> Bytes = imported::checksum;
+    }
+}
+
 #[allow(unused)]
 mod generate_unused_types {
     use exports::foo::bar::component::UnusedEnum;
@@ -486,3 +1015,917 @@ mod generate_unused_types {
         generate_unused_types: true,
     });
 }
+
+// The type guard generated for exported resources checks the `threads`
+// target feature, which rustc doesn't know about.
+#[allow(unexpected_cfgs)]
+mod async_ {
+    wit_bindgen::generate!({
+        inline: "
+            package foo:bar;
+
+            world bindings {
+                import i;
+                export i;
+
+                import f: func(x: string) -> list<u8>;
+                export g: func(x: list<string>);
+            }
+
+            interface i {
+                record r {
+                    a: string,
+                    b: list<list<u8>>,
+                }
+
+                resource y {
+                    constructor();
+                    m: func(x: borrow<y>) -> r;
+                }
+
+                a: func(x: list<r>, y: u32) -> list<r>;
+                b: func() -> tuple<string, u32>;
+                c: func(a: u64, b: u64, c: u64, d: u64, e: u64, f: u64, g: u64, h: u64, i: u64, j: u64, k: u64, l: u64, m: u64, n: u64, o: u64, p: u64, q: u64) -> tuple<u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64>;
+                d: func() -> result<_, string>;
+            }
+        ",
+        async: true,
+        stubs,
+    });
+}
+
+mod async_some {
+    wit_bindgen::generate!({
+        inline: "
+            package foo:bar;
+
+            world bindings {
+                import i;
+                export i;
+            }
+
+            interface i {
+                a: func(x: string) -> string;
+                b: func(x: string) -> string;
+            }
+        ",
+        async: {
+            imports: ["foo:bar/i#a"],
+            exports: ["foo:bar/i#b"],
+        },
+        stubs,
+        export_prefix: "[async-some]",
+    });
+}
+
+mod wasm64 {
+    wit_bindgen::generate!({
+        inline: "
+            package foo:bar;
+
+            world bindings {
+                record r {
+                    a: u8,
+                    b: string,
+                    c: list<u32>,
+                }
+
+                import f: func(x: string) -> r;
+                export g: func(x: r) -> list<r>;
+            }
+        ",
+        wasm64: true,
+        export_prefix: "[wasm64]",
+    });
+
+    struct Component;
+
+    impl Guest for Component {
+        fn g(x: R) -> Vec<R> {
+            vec![x.clone(), x]
+        }
+    }
+
+    export!(Component);
+
+    // Pointers on a 64-bit host are the same size as on wasm64, so the
+    // generated code can be run here to check the layouts it uses.
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn layout() {
+        unsafe fn leak<T>(v: Vec<T>) -> (*mut u8, usize) {
+            let v = v.into_boxed_slice();
+            let len = v.len();
+            (Box::into_raw(v).cast(), len)
+        }
+
+        unsafe {
+            let (b, b_len) = leak(b"hello".to_vec());
+            let (c, c_len) = leak(vec![1u32, 2, 3]);
+            let ret = _export_g_cabi::<Component>(7, b, b_len, c, c_len);
+
+            // The list's pointer and length are each 8 bytes.
+            let list = *ret.cast::<*const u8>();
+            assert_eq!(*ret.add(8).cast::<usize>(), 2);
+
+            // Each record is 40 bytes, with its string at offset 8 and its
+            // list at offset 24.
+            for i in 0..2 {
+                let base = list.add(i * 40);
+                assert_eq!(*base, 7);
+                let s = *base.add(8).cast::<*const u8>();
+                let s_len = *base.add(16).cast::<usize>();
+                assert_eq!(std::slice::from_raw_parts(s, s_len), b"hello");
+                let l = *base.add(24).cast::<*const u32>();
+                let l_len = *base.add(32).cast::<usize>();
+                assert_eq!(std::slice::from_raw_parts(l, l_len), [1, 2, 3]);
+            }
+
+            __post_return_g::<Component>(ret);
+        }
+    }
+}
+
+#[test]
+fn unsupported_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let generate = |wit: &str| {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("input.wit".as_ref(), wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        wit_bindgen_rust::Opts::default()
+            .build()
+            .generate(&resolve, world, &mut Default::default())
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        generate(
+            "package foo:bar;
+            interface events {
+                subscribe: func() -> stream<u32>;
+            }
+            world guest {
+                export events;
+            }"
+        ),
+        "streams are not supported by this generator: \
+         function `subscribe` in interface `foo:bar/events` uses them"
+    );
+}
+
+#[test]
+fn nested_remapped_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let generate = |defs: &str| {
+        let wit = format!(
+            "package foo:bar;
+            interface geometry {{
+                record point {{
+                    x: f32,
+                    y: f32,
+                }}
+                {defs}
+            }}
+            world guest {{
+                import geometry;
+            }}"
+        );
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("input.wit".as_ref(), &wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut opts = wit_bindgen_rust::Opts::default();
+        opts.with
+            .push(("foo:bar/geometry/point".to_string(), "Vec2".to_string()));
+        opts.build()
+            .generate(&resolve, world, &mut Default::default())
+            .map_err(|e| e.to_string())
+    };
+
+    assert!(generate("midpoint: func(a: point, b: point) -> point;").is_ok());
+    assert_eq!(
+        generate("record path { points: list<point> }").unwrap_err(),
+        "types nested in other types and remapped with `with` are not supported by \
+         this generator: type `path` in interface `foo:bar/geometry` uses them"
+    );
+    assert_eq!(
+        generate("first: func(points: list<point>) -> option<point>;").unwrap_err(),
+        "types nested in other types and remapped with `with` are not supported by \
+         this generator: function `first` in interface `foo:bar/geometry` uses them"
+    );
+}
+
+#[test]
+fn source_map() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(
+            UnresolvedPackage::parse(
+                "input.wit".as_ref(),
+                "package foo:bar;
+                interface i {
+                    record r { x: u32 }
+                    f: func(r: r);
+                }
+                world guest {
+                    import i;
+                    export g: func();
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let world = resolve.select_world(pkg, None).unwrap();
+    let generate = |source_map: bool| {
+        let mut files = Default::default();
+        wit_bindgen_rust::Opts {
+            source_map,
+            ..Default::default()
+        }
+        .build()
+        .generate(&resolve, world, &mut files)
+        .unwrap();
+        files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), String::from_utf8_lossy(contents).into()))
+            .collect::<Vec<(String, String)>>()
+    };
+
+    let plain = generate(false);
+    let mapped = generate(true);
+    assert_eq!(plain.len(), 1);
+    assert_eq!(mapped.len(), 2);
+    assert_eq!(mapped[0], plain[0]);
+
+    let (name, map) = &mapped[1];
+    assert_eq!(name, "guest.rs.wit-map.json");
+    let bindings = &plain[0].1;
+    let lines = |needle: &str| {
+        let line = map.lines().find(|l| l.contains(needle)).unwrap();
+        let range = line.split(['[', ']']).nth(1).unwrap();
+        let (start, end) = range.split_once(", ").unwrap();
+        let (start, end) = (
+            start.parse::<usize>().unwrap(),
+            end.parse::<usize>().unwrap(),
+        );
+        bindings.lines().collect::<Vec<_>>()[start - 1..end].join("\n")
+    };
+    assert!(lines(r#""interface": "foo:bar/i"}"#).contains("pub mod i {"));
+    assert!(lines(r#""type": "r""#).contains("pub struct R {"));
+    assert!(lines(r#""interface": "foo:bar/i", "function": "f""#).contains("pub fn f(r: R,)"));
+    assert!(lines(r#""world": "guest", "function": "g""#).contains("fn _export_g_cabi"));
+}
+
+#[test]
+fn multiple_worlds() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(
+            UnresolvedPackage::parse(
+                "input.wit".as_ref(),
+                "package foo:bar;
+                interface shared {
+                    f: func();
+                }
+                interface other {
+                    g: func();
+                }
+                world first {
+                    import shared;
+                }
+                world second {
+                    import shared;
+                    import other;
+                }
+                world third {
+                    import other;
+                    export shared;
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let worlds =
+        ["first", "second", "third"].map(|name| resolve.select_world(pkg, Some(name)).unwrap());
+    let mut files = Default::default();
+    wit_bindgen_rust::Opts::default()
+        .build()
+        .generate_worlds(&resolve, &worlds, &mut files)
+        .unwrap();
+    let files = files
+        .iter()
+        .map(|(name, contents)| (name.to_string(), String::from_utf8_lossy(contents).into()))
+        .collect::<Vec<(String, String)>>();
+    let names = files
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["first.rs", "second.rs", "third.rs"]);
+
+    // `shared` is generated once, by `first`, and referred to by `second`.
+    assert!(files[0].1.contains("pub mod shared {"));
+    assert!(!files[1].1.contains("pub mod shared {"));
+    assert!(files[1]
+        .1
+        .contains("use super::first::foo::bar::shared as "));
+    assert!(files[1].1.contains("pub mod other {"));
+
+    // `third` exports `shared`, so it generates its own bindings for it.
+    assert!(files[2]
+        .1
+        .contains("use super::second::foo::bar::other as "));
+    assert!(!files[2].1.contains("super::first"));
+}
+
+#[test]
+fn multiple_worlds_duplicate_exports_and_world_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(
+            UnresolvedPackage::parse(
+                "input.wit".as_ref(),
+                "package foo:bar;
+                interface i {
+                    record r { x: u32 }
+                    f: func() -> r;
+                }
+                world first {
+                    type t = u32;
+                    import g: func() -> t;
+                    export i;
+                }
+                world second {
+                    type t = u32;
+                    import g: func() -> t;
+                    import i;
+                    export i;
+                }
+                world third {
+                    import i;
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let worlds =
+        ["first", "second", "third"].map(|name| resolve.select_world(pkg, Some(name)).unwrap());
+    let mut files = Default::default();
+    wit_bindgen_rust::Opts::default()
+        .build()
+        .generate_worlds(&resolve, &worlds, &mut files)
+        .unwrap();
+    let files = files
+        .iter()
+        .map(|(_, contents)| String::from_utf8_lossy(contents).into())
+        .collect::<Vec<String>>();
+
+    // Each world defines its own world-level types...
+    assert!(files[0].contains("pub type T = u32;"));
+    assert!(files[1].contains("pub type T = u32;"));
+
+    // ... and exported interfaces, whether or not they're also imported.
+    assert_eq!(files[0].matches("pub struct R {").count(), 1);
+    assert_eq!(files[1].matches("pub struct R {").count(), 2);
+
+    // Only the import of `i` by `third` is generated by `third` itself, as
+    // neither of the earlier worlds only imports it.
+    assert_eq!(files[2].matches("pub struct R {").count(), 1);
+    assert!(!files[2].contains("super::first"));
+    assert!(!files[2].contains("super::second"));
+}
+
+mod arena {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            world foo {
+                export greet: func(names: list<string>) -> u32;
+                export checksum: func(text: string, data: list<u32>) -> u32;
+            }
+        ",
+        arena: true,
+        // Lay lists out with the host's pointer size to call the export.
+        wasm64: true,
+    });
+
+    use std::sync::Mutex;
+    use wit_bindgen::rt::arena;
+
+    static GREETED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static BORROWED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    struct Component;
+
+    impl Guest for Component {
+        fn greet(names: Vec<String>) -> u32 {
+            let len = names.len() as u32;
+            *GREETED.lock().unwrap() = names;
+            len
+        }
+
+        fn checksum(text: &str, data: &[u32]) -> u32 {
+            *BORROWED.lock().unwrap() = vec![text.as_ptr() as usize, data.as_ptr() as usize];
+            text.bytes()
+                .map(u32::from)
+                .chain(data.iter().copied())
+                .sum()
+        }
+    }
+
+    unsafe fn alloc_bytes(bytes: &[u8]) -> *mut u8 {
+        let ptr = arena::alloc(bytes.len(), 1);
+        ptr.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        ptr
+    }
+
+    #[test]
+    fn arguments_outlive_reset() {
+        let _rt = super::RT_LOCK.lock().unwrap();
+        unsafe {
+            // Arguments are allocated from the arena as the host would with
+            // `cabi_realloc`.
+            let names = arena::alloc(4 * 8, 8).cast::<usize>();
+            for (i, name) in ["ada", "grace"].iter().enumerate() {
+                names
+                    .add(2 * i)
+                    .write(alloc_bytes(name.as_bytes()) as usize);
+                names.add(2 * i + 1).write(name.len());
+            }
+            assert!(arena::contains(names.cast()));
+
+            let result = _export_greet_cabi::<Component>(names.cast(), 2);
+            assert_eq!(result, 2);
+            assert!(!arena::contains(names.cast()));
+
+            // The arena is reused by the next call, which must not clobber
+            // the strings moved into the component.
+            let reused = alloc_bytes(b"xxxxxxxxxx");
+            assert_eq!(reused.cast::<usize>(), names);
+            arena::reset();
+        }
+        assert_eq!(*GREETED.lock().unwrap(), ["ada", "grace"]);
+    }
+
+    #[test]
+    fn borrows_arguments() {
+        let _rt = super::RT_LOCK.lock().unwrap();
+        unsafe {
+            // Top-level strings and lists are borrowed from the arena...
+            let text = alloc_bytes(b"ab");
+            let data = arena::alloc(8, 4).cast::<u32>();
+            data.write(1);
+            data.add(1).write(2);
+            let result = _export_checksum_cabi::<Component>(text, 2, data.cast(), 2);
+            assert_eq!(result, 97 + 98 + 1 + 2);
+            assert_eq!(*BORROWED.lock().unwrap(), [text as usize, data as usize]);
+
+            // ... and from allocations outside it, which are freed after the
+            // call.
+            let text = Box::into_raw(Box::<[u8]>::from(&b"c"[..])).cast::<u8>();
+            let data = Box::into_raw(Box::new(3u32));
+            let result = _export_checksum_cabi::<Component>(text, 1, data.cast(), 1);
+            assert_eq!(result, 99 + 3);
+            assert_eq!(*BORROWED.lock().unwrap(), [text as usize, data as usize]);
+        }
+    }
+
+    #[test]
+    fn rejects_async_exports() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+        let mut resolve = Resolve::default();
+        let wit = "package foo:bar; world w { export run: func(); }";
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("input.wit".as_ref(), wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let opts = wit_bindgen_rust::Opts {
+            arena: true,
+            async_: wit_bindgen_rust::AsyncConfig::All,
+            ..Default::default()
+        };
+        let err = opts
+            .build()
+            .generate(&resolve, world, &mut Default::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "async exports with the `arena` option are not supported by this generator: \
+             function `run` in world `w` uses them"
+        );
+    }
+
+    #[test]
+    fn lift_outside_arena() {
+        let _rt = super::RT_LOCK.lock().unwrap();
+        unsafe {
+            let mut vec = vec![1u16, 2, 3];
+            let ptr = vec.as_mut_ptr();
+            std::mem::forget(vec);
+            let lifted = arena::lift_vec(ptr, 3);
+            assert_eq!(lifted, [1, 2, 3]);
+            assert_eq!(lifted.as_ptr(), ptr);
+        }
+    }
+}
+
+mod instrument {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface kv {
+                get: func(key: string) -> option<u32>;
+            }
+
+            world foo {
+                import kv;
+                export sum: func(values: list<u32>) -> u32;
+            }
+        ",
+        instrument_debug: true,
+        mock: true,
+        // Lay lists out with the host's pointer size to call the export.
+        wasm64: true,
+    });
+
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use my::inline::kv;
+
+    /// Records the names of spans and the values recorded in them.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Spans {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let mut spans = self.0.lock().unwrap();
+            spans
+                .last_mut()
+                .unwrap()
+                .push_str(&format!(" {field}={value:?}"));
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            spans.push(span.metadata().name().to_string());
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    struct Kv;
+
+    impl kv::Mock for Kv {
+        fn get(&self, key: &str) -> Option<u32> {
+            key.parse().ok()
+        }
+    }
+
+    struct Component;
+
+    impl Guest for Component {
+        fn sum(values: Vec<u32>) -> u32 {
+            values.iter().sum()
+        }
+    }
+
+    #[test]
+    fn spans() {
+        let spans = Spans::default();
+        tracing::subscriber::with_default(spans.clone(), || {
+            kv::set_mock(Kv);
+            assert_eq!(kv::get("7"), Some(7));
+            let mut values = vec![1u32, 2, 3];
+            let result = unsafe { _export_sum_cabi::<Component>(values.as_mut_ptr().cast(), 3) };
+            assert_eq!(result, 6);
+            std::mem::forget(values);
+        });
+        assert_eq!(
+            *spans.0.lock().unwrap(),
+            [
+                r#"my:inline/kv#get key="7" wit_bindgen.result=Some(7)"#,
+                "sum values=[1, 2, 3] wit_bindgen.result=6",
+            ]
+        );
+    }
+}
+
+// Async imports and exports aren't traced.
+mod instrument_async {
+    wit_bindgen::generate!({
+        inline: "
+            package foo:bar;
+
+            world bindings {
+                import i;
+                export i;
+            }
+
+            interface i {
+                a: func(x: string) -> string;
+            }
+        ",
+        async: true,
+        instrument_debug: true,
+        stubs,
+        export_prefix: "[instrument-async]",
+    });
+}
+
+mod fuzz {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface shapes {
+                flags perms { read, write }
+                enum color { red, green }
+                record point { x: s32, y: s32 }
+                variant shape { dot(point), line(tuple<point, point>), empty }
+                resource canvas {
+                    draw: func(shape: shape);
+                }
+
+                paint: func(shape: shape, color: color, perms: perms) -> option<point>;
+                open: func() -> canvas;
+            }
+
+            world foo {
+                export shapes;
+                export count: func(names: list<string>) -> u32;
+            }
+        ",
+        fuzz_exports: true,
+    });
+
+    use arbitrary::{Arbitrary, Unstructured};
+    use exports::my::inline::shapes::{self, Canvas, Color, Perms, Point, Shape};
+    use std::cell::RefCell;
+
+    thread_local! {
+        static CALLS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    }
+
+    struct Component;
+
+    impl shapes::Guest for Component {
+        type Canvas = Component;
+
+        fn paint(shape: Shape, color: Color, perms: Perms) -> Option<Point> {
+            CALLS.with(|c| {
+                c.borrow_mut()
+                    .push(format!("paint {shape:?} {color:?} {perms:?}"))
+            });
+            None
+        }
+
+        fn open() -> Canvas {
+            CALLS.with(|c| c.borrow_mut().push("open".to_string()));
+            Canvas::new(Component)
+        }
+    }
+
+    impl shapes::GuestCanvas for Component {
+        fn draw(&self, _: Shape) {
+            unreachable!()
+        }
+    }
+
+    impl Guest for Component {
+        fn count(names: Vec<String>) -> u32 {
+            CALLS.with(|c| c.borrow_mut().push(format!("count {names:?}")));
+            names.len() as u32
+        }
+    }
+
+    #[test]
+    fn calls_exports() {
+        let data = (0..=255).cycle().take(4096).collect::<Vec<u8>>();
+        fuzz_exports::<Component>(&data).unwrap();
+        let calls = CALLS.with(|c| c.take());
+        assert!(calls.iter().any(|c| c.starts_with("paint ")));
+        assert!(calls.iter().any(|c| c.starts_with("count ")));
+        assert!(calls.iter().any(|c| c == "open"));
+        assert!(fuzz_exports::<Component>(&[]).is_ok());
+    }
+
+    #[test]
+    fn arbitrary_flags() {
+        let mut u = Unstructured::new(&[1, 0, 0, 1]);
+        assert_eq!(Perms::arbitrary(&mut u).unwrap(), Perms::READ);
+        assert_eq!(Perms::arbitrary(&mut u).unwrap(), Perms::WRITE);
+    }
+}
+
+mod fuzz_without_arguments {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            world foo {
+                export ping: func();
+            }
+        ",
+        export_prefix: "[fuzz-without-arguments]",
+        fuzz_exports: true,
+    });
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static PINGS: AtomicU32 = AtomicU32::new(0);
+
+    struct Component;
+
+    impl Guest for Component {
+        fn ping() {
+            PINGS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn terminates() {
+        fuzz_exports::<Component>(&[1, 2, 3]).unwrap();
+        assert_eq!(PINGS.load(Ordering::Relaxed), 1);
+    }
+}
+
+mod fuzz_resources {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface files {
+                resource file {
+                    size: func() -> u64;
+                }
+                open: func(size: u64) -> file;
+                close: func(f: file);
+            }
+
+            world foo {
+                export files;
+            }
+        ",
+        export_prefix: "[fuzz-resources]",
+        fuzz_exports: true,
+    });
+
+    use exports::my::inline::files::{File, Guest, GuestFile};
+    use std::cell::Cell;
+
+    thread_local! {
+        static OPEN: Cell<u32> = const { Cell::new(0) };
+    }
+
+    struct Component;
+
+    struct MyFile(u64);
+
+    impl Drop for MyFile {
+        fn drop(&mut self) {
+            OPEN.with(|open| open.set(open.get() - 1));
+        }
+    }
+
+    impl GuestFile for MyFile {
+        fn size(&self) -> u64 {
+            self.0
+        }
+    }
+
+    impl Guest for Component {
+        type File = MyFile;
+
+        fn open(size: u64) -> File {
+            OPEN.with(|open| open.set(open.get() + 1));
+            let file = File::new(MyFile(size));
+            assert_eq!(file.get::<MyFile>().size(), size);
+            file
+        }
+
+        fn close(_: File) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn drops_returned_resources() {
+        fuzz_exports::<Component>(&[1; 32]).unwrap();
+        assert_eq!(OPEN.with(Cell::get), 0);
+    }
+}
+
+mod fuzz_nothing {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            world foo {
+                import ping: func();
+            }
+        ",
+        fuzz_exports: true,
+    });
+
+    #[test]
+    fn does_nothing() {
+        fuzz_exports::<()>(&[1, 2, 3]).unwrap();
+    }
+}
+
+mod stateful_exports {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface counter {
+                bump: func(by: u32) -> u32;
+            }
+
+            world foo {
+                export counter;
+                export total: func() -> u32;
+            }
+        ",
+        export_prefix: "[stateful-exports]",
+        stateful_exports: true,
+        fuzz_exports: true,
+    });
+
+    use exports::my::inline::counter;
+
+    struct Counter {
+        count: u32,
+    }
+
+    impl Counter {
+        fn new() -> Counter {
+            Counter { count: 10 }
+        }
+    }
+
+    impl counter::Guest for Counter {
+        fn bump(&mut self, by: u32) -> u32 {
+            self.count = self.count.wrapping_add(by);
+            self.count
+        }
+    }
+
+    impl Guest for Counter {
+        fn total(&mut self) -> u32 {
+            self.count
+        }
+    }
+
+    export!(Counter with Counter::new);
+
+    #[test]
+    fn exports_share_instance() {
+        unsafe {
+            assert_eq!(counter::_export_bump_cabi::<Counter>(1) as u32, 11);
+            assert_eq!(counter::_export_bump_cabi::<Counter>(2) as u32, 13);
+            assert_eq!(_export_total_cabi::<Counter>() as u32, 13);
+        }
+    }
+
+    #[test]
+    fn fuzz_instance() {
+        let mut counter = Counter { count: 0 };
+        let data = (0..=255).cycle().take(4096).collect::<Vec<u8>>();
+        fuzz_exports(&mut counter, &data).unwrap();
+        assert_ne!(counter.count, 0);
+    }
+}
@@ -8,7 +8,7 @@ use std::{
 };
 use wit_bindgen_core::{
     abi::{self, AbiVariant, Bindgen, Bitcast, Instruction, LiftLower, WasmType},
-    uwrite, uwriteln,
+    unsupported, uwrite, uwriteln,
     wit_parser::{
         Docs, Enum, Flags, FlagsRepr, Function, FunctionKind, Int, InterfaceId, Record, Resolve,
         Result_, SizeAlign, Tuple, Type, TypeDef, TypeDefKind, TypeId, TypeOwner, Variant, WorldId,
@@ -81,9 +81,10 @@ impl TeaVmJava {
 }
 
 impl WorldGenerator for TeaVmJava {
-    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
         self.name = world_name(resolve, world);
         self.sizes.fill(resolve);
+        Ok(())
     }
 
     fn import_interface(
@@ -92,17 +93,28 @@ impl WorldGenerator for TeaVmJava {
         key: &WorldKey,
         id: InterfaceId,
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_interface(resolve, key, id, &unsupported_types)?;
         let name = interface_name(resolve, key, Direction::Import);
         self.interface_names.insert(id, name.clone());
         let mut gen = self.interface(resolve, &name);
         gen.types(id);
 
         for (_, func) in resolve.interfaces[id].functions.iter() {
-            gen.import(&resolve.name_world_key(key), func);
+            gen.import(&resolve.name_world_key(key), func)
+                .map_err(|feature| {
+                    unsupported::interface_item(
+                        resolve,
+                        key,
+                        id,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
         }
 
         gen.add_interface_fragment();
+        Ok(())
     }
 
     fn import_funcs(
@@ -111,15 +123,24 @@ impl WorldGenerator for TeaVmJava {
         world: WorldId,
         funcs: &[(&str, &Function)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         let name = world_name(resolve, world);
         let mut gen = self.interface(resolve, &name);
 
         for (_, func) in funcs {
-            gen.import("$root", func);
+            gen.import("$root", func).map_err(|feature| {
+                unsupported::world_item(
+                    resolve,
+                    world,
+                    unsupported::Item::Function(func.name.clone()),
+                    feature,
+                )
+            })?;
         }
 
         gen.add_world_fragment();
+        Ok(())
     }
 
     fn export_interface(
@@ -129,13 +150,23 @@ impl WorldGenerator for TeaVmJava {
         id: InterfaceId,
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_interface(resolve, key, id, &unsupported_types)?;
         let name = interface_name(resolve, key, Direction::Export);
         self.interface_names.insert(id, name.clone());
         let mut gen = self.interface(resolve, &name);
         gen.types(id);
 
         for (_, func) in resolve.interfaces[id].functions.iter() {
-            gen.export(Some(&resolve.name_world_key(key)), func);
+            gen.export(Some(&resolve.name_world_key(key)), func)
+                .map_err(|feature| {
+                    unsupported::interface_item(
+                        resolve,
+                        key,
+                        id,
+                        unsupported::Item::Function(func.name.clone()),
+                        feature,
+                    )
+                })?;
         }
 
         gen.add_interface_fragment();
@@ -149,11 +180,19 @@ impl WorldGenerator for TeaVmJava {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, &[], funcs, &unsupported_types)?;
         let name = world_name(resolve, world);
         let mut gen = self.interface(resolve, &name);
 
         for (_, func) in funcs {
-            gen.export(None, func);
+            gen.export(None, func).map_err(|feature| {
+                unsupported::world_item(
+                    resolve,
+                    world,
+                    unsupported::Item::Function(func.name.clone()),
+                    feature,
+                )
+            })?;
         }
 
         gen.add_world_fragment();
@@ -166,7 +205,8 @@ impl WorldGenerator for TeaVmJava {
         world: WorldId,
         types: &[(&str, TypeId)],
         _files: &mut Files,
-    ) {
+    ) -> Result<()> {
+        unsupported::check_world_items(resolve, world, types, &[], &unsupported_types)?;
         let name = world_name(resolve, world);
         let mut gen = self.interface(resolve, &name);
 
@@ -175,6 +215,7 @@ impl WorldGenerator for TeaVmJava {
         }
 
         gen.add_world_fragment();
+        Ok(())
     }
 
     fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
@@ -479,7 +520,9 @@ impl InterfaceGenerator<'_> {
         });
     }
 
-    fn import(&mut self, module: &str, func: &Function) {
+    /// Generates the bindings of the imported function `func`, returning the
+    /// feature it uses which isn't supported, if any.
+    fn import(&mut self, module: &str, func: &Function) -> Result<(), &'static str> {
         if func.kind != FunctionKind::Freestanding {
             todo!("resources");
         }
@@ -500,6 +543,9 @@ impl InterfaceGenerator<'_> {
             func,
             &mut bindgen,
         );
+        if let Some(feature) = bindgen.unsupported {
+            return Err(feature);
+        }
 
         let src = bindgen.src;
 
@@ -549,9 +595,15 @@ impl InterfaceGenerator<'_> {
                }}
             "#
         );
+        Ok(())
     }
 
-    fn export(&mut self, interface_name: Option<&str>, func: &Function) {
+    /// Same as `import`, but for the exported function `func`.
+    fn export(
+        &mut self,
+        interface_name: Option<&str>,
+        func: &Function,
+    ) -> Result<(), &'static str> {
         let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
 
         let export_name = func.core_export_name(interface_name);
@@ -569,6 +621,9 @@ impl InterfaceGenerator<'_> {
             func,
             &mut bindgen,
         );
+        if let Some(feature) = bindgen.unsupported {
+            return Err(feature);
+        }
 
         assert!(!bindgen.needs_cleanup_list);
 
@@ -648,6 +703,7 @@ impl InterfaceGenerator<'_> {
                 "#
             );
         }
+        Ok(())
     }
 
     fn type_name(&mut self, ty: &Type) -> String {
@@ -1066,8 +1122,8 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         self.type_name(&Type::Id(id));
     }
 
-    fn type_builtin(&mut self, _id: TypeId, _name: &str, _ty: &Type, _docs: &Docs) {
-        unimplemented!();
+    fn type_builtin(&mut self, id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        self.type_alias(id, name, ty, docs)
     }
 }
 
@@ -1102,6 +1158,8 @@ struct FunctionBindgen<'a, 'b> {
     payloads: Vec<String>,
     cleanup: Vec<Cleanup>,
     needs_cleanup_list: bool,
+    /// The first feature used by the function which isn't supported.
+    unsupported: Option<&'static str>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
@@ -1121,6 +1179,7 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             payloads: Vec::new(),
             cleanup: Vec::new(),
             needs_cleanup_list: false,
+            unsupported: None,
         }
     }
 
@@ -1372,7 +1431,22 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 }
             },
 
-            Instruction::HandleLower { .. } | Instruction::HandleLift { .. } => todo!(),
+            // Resources, futures and streams are rejected up front by
+            // `unsupported_types`.
+            Instruction::HandleLower { .. } | Instruction::HandleLift { .. } => {
+                unreachable!("resource handles")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => unreachable!("future and stream handles"),
+
+            Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. } => {
+                self.unsupported.get_or_insert("async functions");
+            }
 
             Instruction::RecordLower { record, .. } => {
                 let op = &operands[0];
@@ -2212,6 +2286,15 @@ fn is_primitive(ty: &Type) -> bool {
     )
 }
 
+/// Describes the types which bindings can't yet be generated for.
+fn unsupported_types(resolve: &Resolve, kind: &TypeDefKind) -> Option<&'static str> {
+    match kind {
+        TypeDefKind::Resource => Some("resources"),
+        TypeDefKind::Flags(f) if f.flags.len() > 64 => Some("flags with more than 64 members"),
+        _ => unsupported::futures_and_streams(resolve, kind),
+    }
+}
+
 fn world_name(resolve: &Resolve, world: WorldId) -> String {
     format!(
         "wit.worlds.{}",
@@ -98,3 +98,35 @@ fn move_java_files(src: &Path, dst: &Path, files: &mut Vec<PathBuf>) {
         files.push(dst.to_owned());
     }
 }
+
+#[test]
+fn unsupported_types() {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    let mut resolve = Resolve::default();
+    let pkg = resolve
+        .push(
+            UnresolvedPackage::parse(
+                "input.wit".as_ref(),
+                "package foo:bar;
+                interface files {
+                    resource file;
+                }
+                world guest {
+                    import files;
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    let world = resolve.select_world(pkg, None).unwrap();
+    let err = wit_bindgen_teavm_java::Opts::default()
+        .build()
+        .generate(&resolve, world, &mut Default::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "resources are not supported by this generator: \
+         type `file` in interface `foo:bar/files` uses them"
+    );
+}
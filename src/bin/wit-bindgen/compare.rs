@@ -0,0 +1,388 @@
+//! Finding the changes between two versions of a world, and whether they break
+//! code using the old version, for `wit-bindgen compare`.
+//!
+//! Worlds are compared through their descriptions from `inspect`. Items are
+//! matched by name, ignoring the versions of interfaces. Additions only break
+//! existing code when they're exports which it now has to implement.
+
+use serde_json::Value;
+use std::fmt;
+
+/// A change between two versions of a world.
+pub struct Change {
+    /// The import, export, type or function which changed.
+    pub path: String,
+    pub message: String,
+    /// Whether source code written against the old version needs to change.
+    pub source: bool,
+    /// Whether code compiled against the old version is incompatible.
+    pub abi: bool,
+}
+
+impl Change {
+    /// Returns whether code using the old version of the world is broken.
+    pub fn breaks(&self) -> bool {
+        self.source || self.abi
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let breaks = match (self.source, self.abi) {
+            (true, true) => "breaks source and ABI",
+            (true, false) => "breaks source",
+            (false, true) => "breaks ABI",
+            (false, false) => "compatible",
+        };
+        write!(f, "{}: {} ({breaks})", self.path, self.message)
+    }
+}
+
+/// Returns the changes from the world described by `old` to the one described
+/// by `new`.
+pub fn compare(old: &Value, new: &Value) -> Vec<Change> {
+    let mut cx = Compare::default();
+    for (dir, prefix) in [("imports", "import"), ("exports", "export")] {
+        cx.export = dir == "exports";
+        let same =
+            |a: &Value, b: &Value| a["item"] == b["item"] && same_name(&a["name"], &b["name"]);
+        for old in list(&old[dir]) {
+            let path = format!("{prefix} `{}`", str(&old["name"]));
+            match list(&new[dir]).iter().find(|new| same(new, old)) {
+                Some(new) => cx.item(&path, old, new),
+                None => cx.report(
+                    &path,
+                    format!("{} was removed", str(&old["item"])),
+                    true,
+                    true,
+                ),
+            }
+        }
+        for new in list(&new[dir]) {
+            if !list(&old[dir]).iter().any(|old| same(new, old)) {
+                let path = format!("{prefix} `{}`", str(&new["name"]));
+                cx.added(&path, str(&new["item"]), new);
+            }
+        }
+    }
+    cx.changes
+}
+
+#[derive(Default)]
+struct Compare {
+    changes: Vec<Change>,
+    /// Whether exports, rather than imports, are being compared.
+    export: bool,
+}
+
+impl Compare {
+    fn report(&mut self, path: &str, message: String, source: bool, abi: bool) {
+        self.changes.push(Change {
+            path: path.to_string(),
+            message,
+            source,
+            abi,
+        });
+    }
+
+    fn item(&mut self, path: &str, old: &Value, new: &Value) {
+        match str(&old["item"]) {
+            "interface" => {
+                for (key, kind) in [("types", "type"), ("functions", "function")] {
+                    for old in list(&old[key]) {
+                        let path = format!("{path}, {kind} `{}`", str(&old["name"]));
+                        match list(&new[key])
+                            .iter()
+                            .find(|new| new["name"] == old["name"])
+                        {
+                            Some(new) if kind == "type" => self.type_(&path, old, new),
+                            Some(new) => self.function(&path, old, new),
+                            None => self.report(&path, format!("{kind} was removed"), true, true),
+                        }
+                    }
+                    for new in list(&new[key]) {
+                        if !list(&old[key]).iter().any(|old| old["name"] == new["name"]) {
+                            let path = format!("{path}, {kind} `{}`", str(&new["name"]));
+                            self.added(&path, kind, new);
+                        }
+                    }
+                }
+            }
+            "function" => self.function(path, old, new),
+            _ => self.type_(path, old, new),
+        }
+    }
+
+    /// Reports the addition of `new`, an interface, function or type, which
+    /// code using the old version has to implement if it's exported.
+    fn added(&mut self, path: &str, what: &str, new: &Value) {
+        let implemented = self.export && (what != "type" || new["kind"] == "resource");
+        self.report(path, format!("{what} was added"), implemented, implemented);
+    }
+
+    fn type_(&mut self, path: &str, old: &Value, new: &Value) {
+        let changes = self.changes.len();
+        let kind = str(&old["kind"]);
+        if old["kind"] != new["kind"] {
+            let message = format!("changed from a {kind} to a {}", str(&new["kind"]));
+            self.report(path, message, true, true);
+            return;
+        }
+        match kind {
+            "record" => self.members(path, "field", &old["fields"], &new["fields"], true),
+            "variant" | "enum" => self.members(path, "case", &old["cases"], &new["cases"], true),
+            // Flags which are added are unset in values from old code.
+            "flags" => self.members(path, "flag", &old["flags"], &new["flags"], false),
+            "resource" => {}
+            _ => {
+                if old["type"] != new["type"] {
+                    let message = format!(
+                        "changed from `{}` to `{}`",
+                        str(&old["type"]),
+                        str(&new["type"])
+                    );
+                    self.report(path, message, true, true);
+                }
+            }
+        }
+
+        // Changes to the types used by this one change its layout without
+        // changing its definition, so those are caught here.
+        let abi_break = self.changes[changes..].iter().any(|c| c.abi);
+        if !abi_break && (old["size"] != new["size"] || old["align"] != new["align"]) {
+            let message = format!(
+                "layout changed from size {} and alignment {} to size {} and alignment {}",
+                old["size"], old["align"], new["size"], new["align"]
+            );
+            self.report(path, message, false, true);
+        }
+    }
+
+    /// Compares the fields, cases or flags of a type, which are identified
+    /// by their position in the canonical ABI.
+    fn members(&mut self, path: &str, what: &str, old: &Value, new: &Value, additions_break: bool) {
+        let (old, new) = (list(old), list(new));
+        let in_old = |member: &Value| old.iter().any(|old| old["name"] == member["name"]);
+        // The positions in `new` of members which were renamed.
+        let mut renamed = Vec::new();
+        for (i, old_member) in old.iter().enumerate() {
+            let name = str(&old_member["name"]);
+            let Some(j) = new.iter().position(|new| new["name"] == old_member["name"]) else {
+                match new.get(i) {
+                    Some(new) if !in_old(new) && new["type"] == old_member["type"] => {
+                        renamed.push(i);
+                        let message =
+                            format!("{what} `{name}` was renamed to `{}`", str(&new["name"]));
+                        self.report(path, message, true, false);
+                    }
+                    _ => self.report(path, format!("{what} `{name}` was removed"), true, true),
+                }
+                continue;
+            };
+            if i != j {
+                let message = format!("{what} `{name}` moved from position {i} to {j}");
+                self.report(path, message, false, true);
+            }
+            if old_member["type"] != new[j]["type"] {
+                let message = format!(
+                    "{what} `{name}` changed from `{}` to `{}`",
+                    type_or_none(&old_member["type"]),
+                    type_or_none(&new[j]["type"])
+                );
+                // Even types of the same size are represented differently,
+                // such as `u32` and `f32`, or interpreted differently, such
+                // as `s32` and `u32`.
+                self.report(path, message, true, true);
+            }
+        }
+        if !additions_break {
+            return;
+        }
+        for (i, new_member) in new.iter().enumerate() {
+            if !in_old(new_member) && !renamed.contains(&i) {
+                let message = format!("{what} `{}` was added", str(&new_member["name"]));
+                self.report(path, message, true, true);
+            }
+        }
+    }
+
+    fn function(&mut self, path: &str, old: &Value, new: &Value) {
+        if old["kind"] != new["kind"] || old["resource"] != new["resource"] {
+            let message = format!(
+                "changed from a {} function to a {} function",
+                str(&old["kind"]),
+                str(&new["kind"])
+            );
+            self.report(path, message, true, true);
+            return;
+        }
+        let types = |values: &Value| {
+            list(values)
+                .iter()
+                .map(|v| str(&v["type"]).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut abi_break = false;
+        for key in ["params", "results"] {
+            let (old_types, new_types) = (types(&old[key]), types(&new[key]));
+            if old_types != new_types {
+                let what = if key == "params" {
+                    "parameters"
+                } else {
+                    "results"
+                };
+                let message = format!("{what} changed from `({old_types})` to `({new_types})`");
+                self.report(path, message, true, true);
+                abi_break = true;
+            }
+        }
+        // Otherwise the layout of a type used by the function may have
+        // changed.
+        if !abi_break && old["abi"] != new["abi"] {
+            let message = format!(
+                "core wasm signature changed from `{}` to `{}`",
+                signature(&old["abi"]),
+                signature(&new["abi"])
+            );
+            self.report(path, message, false, true);
+        }
+    }
+}
+
+fn signature(abi: &Value) -> String {
+    let types = |values: &Value| list(values).iter().map(str).collect::<Vec<_>>().join(", ");
+    format!(
+        "({}) -> ({})",
+        types(&abi["params"]),
+        types(&abi["results"])
+    )
+}
+
+/// Returns whether two interface names are the same, ignoring versions.
+fn same_name(a: &Value, b: &Value) -> bool {
+    let unversioned = |name: &Value| str(name).split('@').next().unwrap_or("").to_string();
+    unversioned(a) == unversioned(b)
+}
+
+fn type_or_none(ty: &Value) -> &str {
+    ty.as_str().unwrap_or("none")
+}
+
+fn list(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn str(value: &Value) -> &str {
+    value.as_str().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        let describe = |wit: &str| {
+            let mut resolve = Resolve::default();
+            let pkg = resolve
+                .push(UnresolvedPackage::parse("test.wit".as_ref(), wit).unwrap())
+                .unwrap();
+            let world = resolve.select_world(pkg, None).unwrap();
+            crate::inspect::world(&resolve, world)
+        };
+        compare(&describe(old), &describe(new))
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn compatible() {
+        let old = "package a:b; interface i { f: func(); } world w { import i; export f: func(); }";
+        let new = "package a:b;
+            interface i { type t = u32; f: func(); g: func(); }
+            world w { import i; import h: func(); export f: func(); }";
+        assert_eq!(
+            changes(old, new),
+            [
+                "import `a:b/i`, type `t`: type was added (compatible)",
+                "import `a:b/i`, function `g`: function was added (compatible)",
+                "import `h`: function was added (compatible)",
+            ]
+        );
+    }
+
+    #[test]
+    fn added_exports() {
+        let old = "package a:b; interface i { f: func(); } world w { export i; }";
+        let new = "package a:b;
+            interface i { type t = u32; resource r; f: func(); g: func(); }
+            world w { export i; export h: func(); }";
+        assert_eq!(
+            changes(old, new),
+            [
+                "export `a:b/i`, type `t`: type was added (compatible)",
+                "export `a:b/i`, type `r`: type was added (breaks source and ABI)",
+                "export `a:b/i`, function `g`: function was added (breaks source and ABI)",
+                "export `h`: function was added (breaks source and ABI)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reordered_cases() {
+        let old = "package a:b; world w { variant v { a(u8), b } export f: func(v: v); }";
+        let new = "package a:b; world w { variant v { b, a(u8) } export f: func(v: v); }";
+        assert_eq!(
+            changes(old, new),
+            [
+                "import `v`: case `a` moved from position 0 to 1 (breaks ABI)",
+                "import `v`: case `b` moved from position 1 to 0 (breaks ABI)",
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_fields() {
+        let old = "package a:b; interface i { record r { x: u8, y: u8 } } world w { import i; }";
+        let new = "package a:b; interface i { record r { x: u32, z: u16 } } world w { import i; }";
+        assert_eq!(
+            changes(old, new),
+            [
+                "import `a:b/i`, type `r`: field `x` changed from `u8` to `u32` (breaks source and ABI)",
+                "import `a:b/i`, type `r`: field `y` was removed (breaks source and ABI)",
+                "import `a:b/i`, type `r`: field `z` was added (breaks source and ABI)",
+            ]
+        );
+    }
+
+    #[test]
+    fn same_size_types() {
+        let old = "package a:b;
+            interface i {
+                record r { x: u32 }
+                variant v { a(s32) }
+                type t = u32;
+                f: func(x: s32) -> u32;
+            }
+            world w { import i; }";
+        let new = "package a:b;
+            interface i {
+                record r { x: f32 }
+                variant v { a(u32) }
+                type t = s32;
+                f: func(x: u32) -> u32;
+            }
+            world w { import i; }";
+        assert_eq!(
+            changes(old, new),
+            [
+                "import `a:b/i`, type `r`: field `x` changed from `u32` to `f32` (breaks source and ABI)",
+                "import `a:b/i`, type `v`: case `a` changed from `s32` to `u32` (breaks source and ABI)",
+                "import `a:b/i`, type `t`: changed from `u32` to `s32` (breaks source and ABI)",
+                "import `a:b/i`, function `f`: parameters changed from `(s32)` to `(u32)` (breaks source and ABI)",
+            ]
+        );
+    }
+}
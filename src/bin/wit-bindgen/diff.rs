@@ -0,0 +1,233 @@
+//! Unified diffs between the files on disk and freshly generated bindings.
+
+use std::fmt::Write;
+
+/// The number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// The largest number of edits searched for before giving up and showing the
+/// whole of the differing lines as replaced, which bounds the memory used.
+const MAX_EDITS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Returns a unified diff turning `old`, named `old_name`, into `new`, named
+/// `new_name`.
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let a = old.split_inclusive('\n').collect::<Vec<_>>();
+    let b = new.split_inclusive('\n').collect::<Vec<_>>();
+    let edits = edits(&a, &b);
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    // The lines of `a` and `b` preceding each edit.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut x, mut y) = (0, 0);
+    for edit in edits.iter() {
+        positions.push((x, y));
+        match edit {
+            Edit::Keep => (x, y) = (x + 1, y + 1),
+            Edit::Delete => x += 1,
+            Edit::Insert => y += 1,
+        }
+    }
+    positions.push((x, y));
+
+    let mut next = 0;
+    while let Some(first) = (next..edits.len()).find(|i| edits[*i] != Edit::Keep) {
+        // Changes separated by little enough context share a hunk.
+        let mut last = first;
+        while let Some(i) = (last + 1..edits.len()).find(|i| edits[*i] != Edit::Keep) {
+            if i - last > 2 * CONTEXT + 1 {
+                break;
+            }
+            last = i;
+        }
+        let start = first.saturating_sub(CONTEXT).max(next);
+        let end = (last + 1 + CONTEXT).min(edits.len());
+        next = end;
+
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let range = |start: usize, len: usize| match len {
+            // Empty ranges are named by the line preceding them.
+            0 => format!("{start},0"),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{len}", start + 1),
+        };
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start),
+        )
+        .unwrap();
+        for (edit, (x, y)) in edits[start..end].iter().zip(&positions[start..end]) {
+            let (prefix, line) = match edit {
+                Edit::Keep => (' ', a[*x]),
+                Edit::Delete => ('-', a[*x]),
+                Edit::Insert => ('+', b[*y]),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// Returns the edits turning `a` into `b`, which are the fewest possible
+/// unless there are more than `MAX_EDITS` of them.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let middle =
+        myers(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]).unwrap_or_else(|| {
+            let deletes = std::iter::repeat_n(Edit::Delete, a.len() - prefix - suffix);
+            let inserts = std::iter::repeat_n(Edit::Insert, b.len() - prefix - suffix);
+            deletes.chain(inserts).collect()
+        });
+
+    let mut edits = vec![Edit::Keep; prefix];
+    edits.extend(middle);
+    edits.extend(std::iter::repeat_n(Edit::Keep, suffix));
+    edits
+}
+
+/// Finds the shortest edit script with Myers' algorithm, or returns `None` if
+/// it has more than `MAX_EDITS` edits.
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (a.len() + b.len()).min(MAX_EDITS) as isize;
+    // The furthest `x` reached on each diagonal `k = x - y`, offset so that
+    // `k` may be negative.
+    let offset = max + 1;
+    let mut v = vec![0; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    let furthest = |v: &[isize], k: isize, d: isize| {
+        if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        }
+    };
+
+    'search: {
+        for d in 0..=max {
+            trace.push(v.clone());
+            for k in (-d..=d).step_by(2) {
+                let prev = furthest(&v, k, d);
+                let mut x = v[(prev + offset) as usize] + if prev < k { 1 } else { 0 };
+                let mut y = x - k;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[(k + offset) as usize] = x;
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev = furthest(v, k, d);
+        let prev_x = v[(prev + offset) as usize];
+        let prev_y = prev_x - prev;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert);
+                y -= 1;
+            } else {
+                edits.push(Edit::Delete);
+                x -= 1;
+            }
+        }
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical() {
+        assert_eq!(unified("a", "b", "x\ny\n", "x\ny\n"), "--- a\n+++ b\n");
+    }
+
+    #[test]
+    fn changed_line() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            unified("a", "b", old, new),
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let old = (1..=20).map(|i| format!("{i}\n")).collect::<String>();
+        let new = (1..=20)
+            .filter(|i| *i != 19)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                _ => format!("{i}\n"),
+            })
+            .collect::<String>();
+        assert_eq!(
+            unified("a", "b", &old, &new),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn insert_into_empty() {
+        assert_eq!(
+            unified("a", "b", "", "x\ny"),
+            "--- a\n+++ b\n@@ -0,0 +1,2 @@\n+x\n+y\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn shortest_edits() {
+        let a = ["a", "b", "c", "a", "b", "b", "a"];
+        let b = ["c", "b", "a", "b", "a", "c"];
+        let edits = edits(&a, &b);
+        assert_eq!(edits.iter().filter(|e| **e != Edit::Keep).count(), 5);
+        assert_eq!(
+            edits.iter().filter(|e| **e != Edit::Insert).count(),
+            a.len()
+        );
+        assert_eq!(
+            edits.iter().filter(|e| **e != Edit::Delete).count(),
+            b.len()
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! A JSON description of a world, for `wit-bindgen inspect`.
+//!
+//! Named types are referred to by their name, qualified by the interface or
+//! world defining them when that's not where they're used, as in
+//! `foo:bar/baz.name`. Other types are written in WIT syntax, as in
+//! `list<u8>`. The canonical ABI is described for 32-bit memories.
+
+use serde_json::{json, Value};
+use wit_bindgen_core::wit_parser::abi::{AbiVariant, WasmType};
+use wit_bindgen_core::wit_parser::*;
+
+/// Returns the description of `world`.
+pub fn world(resolve: &Resolve, world: WorldId) -> Value {
+    let mut sizes = SizeAlign::default();
+    sizes.fill(resolve);
+    let cx = Context { resolve, sizes };
+    let owner = TypeOwner::World(world);
+    let world = &resolve.worlds[world];
+    let imports = world
+        .imports
+        .iter()
+        .map(|(key, item)| cx.item(owner, key, item, AbiVariant::GuestImport))
+        .collect::<Vec<_>>();
+    let exports = world
+        .exports
+        .iter()
+        .map(|(key, item)| cx.item(owner, key, item, AbiVariant::GuestExport))
+        .collect::<Vec<_>>();
+    json!({
+        "world": cx.owner_name(owner),
+        "docs": world.docs.contents,
+        "imports": imports,
+        "exports": exports,
+    })
+}
+
+struct Context<'a> {
+    resolve: &'a Resolve,
+    sizes: SizeAlign,
+}
+
+impl Context<'_> {
+    /// Describes a world's import or export, with an `item` key saying
+    /// whether it's an interface, function or type.
+    fn item(
+        &self,
+        owner: TypeOwner,
+        key: &WorldKey,
+        item: &WorldItem,
+        variant: AbiVariant,
+    ) -> Value {
+        let (kind, mut value) = match item {
+            WorldItem::Interface(id) => {
+                let iface = &self.resolve.interfaces[*id];
+                let owner = TypeOwner::Interface(*id);
+                let value = json!({
+                    "name": self.resolve.name_world_key(key),
+                    "docs": iface.docs.contents,
+                    "types": iface
+                        .types
+                        .values()
+                        .map(|id| self.type_def(owner, *id))
+                        .collect::<Vec<_>>(),
+                    "functions": iface
+                        .functions
+                        .values()
+                        .map(|func| self.function(owner, func, variant))
+                        .collect::<Vec<_>>(),
+                });
+                ("interface", value)
+            }
+            WorldItem::Function(func) => ("function", self.function(owner, func, variant)),
+            WorldItem::Type(id) => ("type", self.type_def(owner, *id)),
+        };
+        value["item"] = json!(kind);
+        value
+    }
+
+    /// Describes the named type `id`, used within `owner`.
+    fn type_def(&self, owner: TypeOwner, id: TypeId) -> Value {
+        let ty = &self.resolve.types[id];
+        // Resources are only passed around by handle, and have no size of
+        // their own.
+        let layout = |f: fn(&SizeAlign, &Type) -> usize| match ty.kind {
+            TypeDefKind::Resource => None,
+            _ => Some(f(&self.sizes, &Type::Id(id))),
+        };
+        let mut value = json!({
+            "name": ty.name,
+            "docs": ty.docs.contents,
+            "size": layout(SizeAlign::size),
+            "align": layout(SizeAlign::align),
+        });
+        let (kind, definition) = match &ty.kind {
+            TypeDefKind::Record(record) => (
+                "record",
+                json!({
+                    "fields": record
+                        .fields
+                        .iter()
+                        .map(|field| json!({
+                            "name": field.name,
+                            "type": self.type_name(owner, &field.ty),
+                            "docs": field.docs.contents,
+                        }))
+                        .collect::<Vec<_>>(),
+                }),
+            ),
+            TypeDefKind::Variant(variant) => (
+                "variant",
+                json!({
+                    "cases": variant
+                        .cases
+                        .iter()
+                        .map(|case| json!({
+                            "name": case.name,
+                            "type": case.ty.map(|ty| self.type_name(owner, &ty)),
+                            "docs": case.docs.contents,
+                        }))
+                        .collect::<Vec<_>>(),
+                }),
+            ),
+            TypeDefKind::Enum(enum_) => (
+                "enum",
+                json!({
+                    "cases": enum_
+                        .cases
+                        .iter()
+                        .map(|case| json!({ "name": case.name, "docs": case.docs.contents }))
+                        .collect::<Vec<_>>(),
+                }),
+            ),
+            TypeDefKind::Flags(flags) => (
+                "flags",
+                json!({
+                    "flags": flags
+                        .flags
+                        .iter()
+                        .map(|flag| json!({ "name": flag.name, "docs": flag.docs.contents }))
+                        .collect::<Vec<_>>(),
+                }),
+            ),
+            TypeDefKind::Resource => ("resource", json!({})),
+            TypeDefKind::Type(target) => {
+                ("alias", json!({ "type": self.type_name(owner, target) }))
+            }
+            // Other named types are named anonymous types, described by their
+            // definition.
+            _ => ("type", json!({ "type": self.definition(owner, &ty.kind) })),
+        };
+        value["kind"] = json!(kind);
+        if let (Value::Object(value), Value::Object(definition)) = (&mut value, definition) {
+            value.extend(definition);
+        }
+        value
+    }
+
+    fn function(&self, owner: TypeOwner, func: &Function, variant: AbiVariant) -> Value {
+        let (kind, resource) = match func.kind {
+            FunctionKind::Freestanding => ("freestanding", None),
+            FunctionKind::Method(id) => ("method", Some(id)),
+            FunctionKind::Static(id) => ("static", Some(id)),
+            FunctionKind::Constructor(id) => ("constructor", Some(id)),
+        };
+        let params = |params: &Params| {
+            params
+                .iter()
+                .map(|(name, ty)| json!({ "name": name, "type": self.type_name(owner, ty) }))
+                .collect::<Vec<_>>()
+        };
+        let results = match &func.results {
+            Results::Named(results) => params(results),
+            Results::Anon(ty) => vec![json!({ "name": null, "type": self.type_name(owner, ty) })],
+        };
+        let sig = self.resolve.wasm_signature(variant, func);
+        let wasm_types =
+            |types: &[WasmType]| types.iter().map(|ty| wasm_type(*ty)).collect::<Vec<_>>();
+        json!({
+            "kind": kind,
+            "name": func.name,
+            "item-name": func.item_name(),
+            "resource": resource.map(|id| self.resolve.types[id].name.clone()),
+            "docs": func.docs.contents,
+            "params": params(&func.params),
+            "results": results,
+            "abi": {
+                "params": wasm_types(&sig.params),
+                "results": wasm_types(&sig.results),
+                "indirect-params": sig.indirect_params,
+                "retptr": sig.retptr,
+            },
+        })
+    }
+
+    /// Returns the WIT syntax for `ty`, used within `owner`.
+    fn type_name(&self, owner: TypeOwner, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::U8 => "u8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::U32 => "u32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::S8 => "s8".to_string(),
+            Type::S16 => "s16".to_string(),
+            Type::S32 => "s32".to_string(),
+            Type::S64 => "s64".to_string(),
+            Type::F32 => "f32".to_string(),
+            Type::F64 => "f64".to_string(),
+            Type::Char => "char".to_string(),
+            Type::String => "string".to_string(),
+            Type::Id(id) => {
+                let ty = &self.resolve.types[*id];
+                match &ty.name {
+                    Some(name) if ty.owner == owner || ty.owner == TypeOwner::None => name.clone(),
+                    Some(name) => format!("{}.{name}", self.owner_name(ty.owner)),
+                    None => self.definition(owner, &ty.kind),
+                }
+            }
+        }
+    }
+
+    /// Returns the WIT syntax for the definition of a type.
+    fn definition(&self, owner: TypeOwner, kind: &TypeDefKind) -> String {
+        let name = |ty: &Type| self.type_name(owner, ty);
+        let optional = |ty: &Option<Type>| ty.as_ref().map(name).unwrap_or_else(|| "_".to_string());
+        match kind {
+            TypeDefKind::Handle(Handle::Own(id)) => format!("own<{}>", name(&Type::Id(*id))),
+            TypeDefKind::Handle(Handle::Borrow(id)) => format!("borrow<{}>", name(&Type::Id(*id))),
+            TypeDefKind::Tuple(tuple) => format!(
+                "tuple<{}>",
+                tuple.types.iter().map(name).collect::<Vec<_>>().join(", ")
+            ),
+            TypeDefKind::Option(ty) => format!("option<{}>", name(ty)),
+            TypeDefKind::Result(result) => match (&result.ok, &result.err) {
+                (None, None) => "result".to_string(),
+                (Some(ok), None) => format!("result<{}>", name(ok)),
+                (ok, Some(err)) => format!("result<{}, {}>", optional(ok), name(err)),
+            },
+            TypeDefKind::List(ty) => format!("list<{}>", name(ty)),
+            TypeDefKind::Future(None) => "future".to_string(),
+            TypeDefKind::Future(Some(ty)) => format!("future<{}>", name(ty)),
+            TypeDefKind::Stream(stream) => match (&stream.element, &stream.end) {
+                (None, None) => "stream".to_string(),
+                (Some(element), None) => format!("stream<{}>", name(element)),
+                (element, Some(end)) => format!("stream<{}, {}>", optional(element), name(end)),
+            },
+            TypeDefKind::Type(ty) => name(ty),
+            TypeDefKind::Record(_)
+            | TypeDefKind::Variant(_)
+            | TypeDefKind::Enum(_)
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Resource
+            | TypeDefKind::Unknown => unreachable!("types of this kind are always named"),
+        }
+    }
+
+    fn owner_name(&self, owner: TypeOwner) -> String {
+        match owner {
+            TypeOwner::Interface(id) => {
+                let iface = &self.resolve.interfaces[id];
+                match (iface.package, &iface.name) {
+                    (Some(pkg), Some(name)) => self.resolve.id_of_name(pkg, name),
+                    (_, name) => name.clone().unwrap_or_default(),
+                }
+            }
+            TypeOwner::World(id) => {
+                let world = &self.resolve.worlds[id];
+                match world.package {
+                    Some(pkg) => self.resolve.id_of_name(pkg, &world.name),
+                    None => world.name.clone(),
+                }
+            }
+            TypeOwner::None => String::new(),
+        }
+    }
+}
+
+fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "i32",
+        WasmType::I64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+        WasmType::Pointer => "pointer",
+        WasmType::PointerOrI64 => "pointer-or-i64",
+        WasmType::Length => "length",
+    }
+}
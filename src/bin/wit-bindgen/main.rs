@@ -0,0 +1,732 @@
+mod compare;
+mod diff;
+mod inspect;
+mod watch;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use std::path::{Path, PathBuf};
+use std::str;
+use wit_bindgen_core::{wit_parser, Files, Unsupported, WorldGenerator};
+use wit_component::DecodedWasm;
+use wit_parser::{Resolve, WorldId};
+
+/// Helper for passing VERSION to opt.
+/// If CARGO_VERSION_INFO is set, use it, otherwise use CARGO_PKG_VERSION.
+fn version() -> &'static str {
+    option_env!("CARGO_VERSION_INFO").unwrap_or(env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(version = version())]
+enum Opt {
+    /// This generator outputs a Markdown file describing an interface.
+    #[cfg(feature = "markdown")]
+    Markdown {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_markdown::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+    /// Generates bindings for Rust guest modules.
+    #[cfg(feature = "rust")]
+    Rust {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_rust::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+    /// Generates bindings for C/CPP guest modules.
+    #[cfg(feature = "c")]
+    C {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_c::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for TeaVM-based Java guest modules.
+    #[cfg(feature = "teavm-java")]
+    TeavmJava {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_teavm_java::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+    /// Generates bindings for TinyGo-based Go guest modules.
+    #[cfg(feature = "go")]
+    TinyGo {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_go::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for C# guest modules.
+    #[cfg(feature = "csharp")]
+    CSharp {
+        #[clap(flatten)]
+        opts: Box<wit_bindgen_csharp::Opts>,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Prints a JSON description of a world's imports and exports, their
+    /// types and functions, and the canonical ABI of those.
+    Inspect {
+        /// WIT document to describe a world of.
+        ///
+        /// This may also be a WIT package encoded in the wasm binary format, or
+        /// a component whose world is decoded from its type information.
+        #[clap(value_name = "DOCUMENT", index = 1)]
+        wit: PathBuf,
+
+        /// World within the WIT document to describe.
+        #[clap(short, long)]
+        world: Option<String>,
+    },
+
+    /// Compares two versions of a world, and reports the changes between them,
+    /// failing if any break source or ABI compatibility.
+    Compare {
+        /// WIT document of the old version.
+        #[clap(value_name = "OLD", index = 1)]
+        old: PathBuf,
+
+        /// WIT document of the new version.
+        #[clap(value_name = "NEW", index = 2)]
+        new: PathBuf,
+
+        /// World within both WIT documents to compare.
+        #[clap(short, long)]
+        world: Option<String>,
+    },
+
+    /// Generates bindings for each of the targets listed in a config file.
+    ///
+    /// The config file names the WIT document to generate bindings for with
+    /// `wit`, and optionally the worlds within it with `world`. Every other
+    /// top-level table is a target named after one of the other subcommands,
+    /// whose keys are the options of that subcommand, and which may name its
+    /// own `world`. Paths are relative to the config file.
+    ///
+    /// ```toml
+    /// wit = "wit"
+    /// world = "my-world"
+    ///
+    /// [rust]
+    /// out-dir = "src/bindings"
+    /// with = { "wasi:io/streams" = "wasi::io::streams" }
+    ///
+    /// [c]
+    /// out-dir = "c"
+    /// no-helpers = true
+    /// ```
+    ///
+    /// A target may be an array of tables to generate several sets of
+    /// bindings with the same subcommand.
+    ///
+    /// Targets are generated in the order they're listed, stopping at the
+    /// first which fails.
+    Generate {
+        /// The config file listing the bindings to generate.
+        #[clap(long, default_value = "wit-bindgen.toml")]
+        config: PathBuf,
+
+        /// Indicates that no files are written and instead files are checked if
+        /// they're up-to-date with the source files.
+        ///
+        /// Differences are printed as unified diffs. The exit status is 2 if
+        /// files are out of date, or 3 if any files are missing.
+        #[clap(long)]
+        check: bool,
+    },
+}
+
+#[derive(Debug, Clone, Parser)]
+struct Common {
+    /// Where to place output files
+    #[clap(long = "out-dir")]
+    out_dir: Option<PathBuf>,
+
+    /// WIT document to generate bindings for.
+    ///
+    /// This may also be a WIT package encoded in the wasm binary format, or a
+    /// component whose world is decoded from its type information.
+    #[clap(value_name = "DOCUMENT", index = 1)]
+    wit: PathBuf,
+
+    /// World within the WIT document specified to generate bindings for.
+    ///
+    /// This can either be `foo` which is the default world in document `foo` or
+    /// it's `foo.bar` which is the world named `bar` within document `foo`.
+    ///
+    /// This may be passed multiple times to generate bindings for several
+    /// worlds at once, if the generator supports it.
+    #[clap(short, long)]
+    world: Vec<String>,
+
+    /// Indicates that no files are written and instead files are checked if
+    /// they're up-to-date with the source files.
+    ///
+    /// Differences are printed as unified diffs. The exit status is 2 if
+    /// files are out of date, or 3 if any files are missing.
+    #[clap(long)]
+    check: bool,
+
+    /// Keeps running and regenerates bindings whenever the WIT files they're
+    /// generated from change.
+    ///
+    /// Errors are printed rather than exiting, and files whose contents are
+    /// unchanged aren't rewritten.
+    #[clap(long, conflicts_with = "check")]
+    watch: bool,
+}
+
+/// `--check` exit status when generated files differ from those on disk.
+const EXIT_DIFFERS: i32 = 2;
+/// `--check` exit status when generated files don't exist on disk.
+const EXIT_MISSING: i32 = 3;
+
+fn main() -> Result<()> {
+    let mut stale = Stale::default();
+    match Opt::parse() {
+        Opt::Generate { config, check } => generate_from_config(&config, check, &mut stale)?,
+        Opt::Inspect { wit, world } => {
+            let (resolve, worlds, _) = parse_input(&wit, Vec::from_iter(world).as_slice())?;
+            let description = inspect::world(&resolve, worlds[0]);
+            println!("{}", serde_json::to_string_pretty(&description)?);
+        }
+        Opt::Compare { old, new, world } => {
+            let world = Vec::from_iter(world);
+            let describe = |wit: &Path| -> Result<_> {
+                let (resolve, worlds, _) = parse_input(wit, &world)?;
+                Ok(inspect::world(&resolve, worlds[0]))
+            };
+            let changes = compare::compare(&describe(&old)?, &describe(&new)?);
+            for change in changes.iter() {
+                println!("{change}");
+            }
+            let breaks = changes.iter().filter(|c| c.breaks()).count();
+            if breaks > 0 {
+                bail!("found {breaks} breaking changes");
+            }
+        }
+        opt => {
+            let (generator, args) = build(opt.clone())?;
+            if args.watch {
+                return watch(generator, args, opt);
+            }
+            run(generator, &args, &mut stale)?;
+        }
+    }
+    stale.exit();
+    Ok(())
+}
+
+/// Generates bindings with `generator`, and then again with a generator built
+/// from `opt` each time the WIT files which were read change.
+fn watch(mut generator: Box<dyn WorldGenerator>, args: Common, opt: Opt) -> Result<()> {
+    let mut sources = Vec::new();
+    loop {
+        match run(generator, &args, &mut Stale::default()) {
+            Ok(read) => sources = read,
+            // The files read by the last successful run are still watched.
+            Err(err) => eprintln!("Error: {:?}", err),
+        }
+        println!("Watching for changes to {:?}...", args.wit);
+        watch::Watched::new(&args.wit, &sources).wait();
+        generator = build(opt.clone())?.0;
+    }
+}
+
+/// Files found to be out of date by `--check`.
+#[derive(Default)]
+struct Stale {
+    differ: Vec<PathBuf>,
+    missing: Vec<PathBuf>,
+}
+
+impl Stale {
+    /// Lists the stale files and exits with a status saying why, if there are
+    /// any.
+    fn exit(&self) {
+        for path in self.differ.iter() {
+            eprintln!("not up to date: {}", path.display());
+        }
+        for path in self.missing.iter() {
+            eprintln!("missing: {}", path.display());
+        }
+        if !self.missing.is_empty() {
+            std::process::exit(EXIT_MISSING);
+        }
+        if !self.differ.is_empty() {
+            std::process::exit(EXIT_DIFFERS);
+        }
+    }
+}
+
+fn build(opt: Opt) -> Result<(Box<dyn WorldGenerator>, Common)> {
+    Ok(match opt {
+        #[cfg(feature = "markdown")]
+        Opt::Markdown { opts, args } => (opts.build(), args),
+        #[cfg(feature = "c")]
+        Opt::C { opts, args } => (opts.build(), args),
+        #[cfg(feature = "rust")]
+        Opt::Rust { opts, args } => (opts.build(), args),
+        #[cfg(feature = "teavm-java")]
+        Opt::TeavmJava { opts, args } => (opts.build(), args),
+        #[cfg(feature = "go")]
+        Opt::TinyGo { opts, args } => (opts.build(), args),
+        #[cfg(feature = "csharp")]
+        Opt::CSharp { opts, args } => (opts.build(), args),
+        Opt::Generate { .. } | Opt::Inspect { .. } | Opt::Compare { .. } => {
+            bail!("only subcommands generating bindings can be targets of a config file")
+        }
+    })
+}
+
+/// Runs each of the targets in the config file at `path`, in the order they're
+/// listed.
+///
+/// The whole config file is parsed before anything is generated, but this
+/// stops at the first target which fails to generate, leaving the bindings of
+/// any later targets untouched.
+fn generate_from_config(path: &Path, check: bool, stale: &mut Stale) -> Result<()> {
+    for target in parse_config(path, check)? {
+        run(target.generator, &target.args, stale)
+            .with_context(|| format!("failed to generate bindings for `{}`", target.name))?;
+    }
+    Ok(())
+}
+
+/// A target of a config file.
+struct Target {
+    /// The subcommand which the target is named after.
+    name: String,
+    generator: Box<dyn WorldGenerator>,
+    /// The target's options, with paths made relative to the current
+    /// directory.
+    args: Common,
+}
+
+/// Parses the targets of the config file at `path`.
+fn parse_config(path: &Path, check: bool) -> Result<Vec<Target>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+    let mut config: toml::Table = contents
+        .parse()
+        .with_context(|| format!("failed to parse {:?}", path))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let wit = match config.remove("wit") {
+        Some(toml::Value::String(wit)) => wit,
+        Some(_) => bail!("`wit` in {:?} must be a string", path),
+        None => bail!("{:?} doesn't name a WIT document with `wit`", path),
+    };
+    let world = config.remove("world");
+
+    let mut targets = Vec::new();
+    for (name, value) in config {
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let toml::Value::Table(options) = value else {
+                bail!("`{}` in {:?} must be a table of options", name, path);
+            };
+            let mut args = vec!["wit-bindgen".to_string(), name.clone(), wit.clone()];
+            if check {
+                args.push("--check".to_string());
+            }
+            // Targets may override the worlds named at the top level.
+            if let (Some(world), false) = (&world, options.contains_key("world")) {
+                push_option(&mut args, "world", world.clone())?;
+            }
+            for (key, value) in options {
+                push_option(&mut args, &key, value)
+                    .with_context(|| format!("invalid option for `{}` in {:?}", name, path))?;
+            }
+            let opt = Opt::try_parse_from(args)
+                .with_context(|| format!("invalid options for `{}` in {:?}", name, path))?;
+            let (generator, mut args) = build(opt)?;
+            if args.watch {
+                bail!("`watch` can't be used in {:?}", path);
+            }
+            args.wit = dir.join(&args.wit);
+            args.out_dir = args.out_dir.map(|out_dir| dir.join(out_dir));
+            targets.push(Target {
+                name: name.clone(),
+                generator,
+                args,
+            });
+        }
+    }
+    Ok(targets)
+}
+
+/// Appends the command line arguments which pass `value` to the option `key`.
+fn push_option(args: &mut Vec<String>, key: &str, value: toml::Value) -> Result<()> {
+    match value {
+        toml::Value::Boolean(true) => args.push(format!("--{key}")),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(value) => args.push(format!("--{key}={value}")),
+        toml::Value::Integer(value) => args.push(format!("--{key}={value}")),
+        toml::Value::Float(value) => args.push(format!("--{key}={value}")),
+        toml::Value::Array(values) => {
+            for value in values {
+                push_option(args, key, value)?;
+            }
+        }
+        // Tables are the `name=value` pairs of options such as `with`.
+        toml::Value::Table(pairs) => {
+            for (name, value) in pairs {
+                match value {
+                    toml::Value::String(value) => args.push(format!("--{key}={name}={value}")),
+                    _ => bail!("the values of `{key}` must be strings"),
+                }
+            }
+        }
+        toml::Value::Datetime(_) => bail!("`{key}` can't be a date or time"),
+    }
+    Ok(())
+}
+
+/// Generates bindings and writes or checks them, returning the WIT source
+/// files which were read.
+fn run(
+    generator: Box<dyn WorldGenerator>,
+    opt: &Common,
+    stale: &mut Stale,
+) -> Result<Vec<PathBuf>> {
+    let mut files = Files::default();
+    let sources = gen_world(generator, opt, &mut files)?;
+
+    for (name, contents) in files.iter() {
+        let dst = match &opt.out_dir {
+            Some(path) => path.join(name),
+            None => name.into(),
+        };
+        if opt.watch && std::fs::read(&dst).is_ok_and(|prev| prev == contents) {
+            continue;
+        }
+        println!("Generating {:?}", dst);
+
+        if opt.check {
+            match std::fs::read(&dst) {
+                Ok(prev) if prev != contents => {
+                    print_difference(&dst, &prev, contents);
+                    stale.differ.push(dst);
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => stale.missing.push(dst),
+                Err(err) => return Err(err).with_context(|| format!("failed to read {:?}", dst)),
+            }
+            continue;
+        }
+
+        if let Some(parent) = dst.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {:?}", parent))?;
+        }
+        std::fs::write(&dst, contents).with_context(|| format!("failed to write {:?}", dst))?;
+    }
+
+    Ok(sources)
+}
+
+/// Prints how the file at `dst`, containing `prev`, differs from the generated
+/// `contents`.
+fn print_difference(dst: &Path, prev: &[u8], contents: &[u8]) {
+    // If it looks like textual contents, do a line-by-line comparison so that
+    // we can tell users what the problem is directly.
+    if let (Ok(utf8_prev), Ok(utf8_contents)) = (str::from_utf8(prev), str::from_utf8(contents)) {
+        if !utf8_prev
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            if utf8_prev.lines().eq(utf8_contents.lines()) {
+                eprintln!("{} differs only in line endings (CRLF vs. LF). If this is a text file, configure git to mark the file as `text eol=lf`.", dst.display());
+            } else {
+                let name = dst.display();
+                let old = format!("a/{name}");
+                let new = format!("b/{name}");
+                print!("{}", diff::unified(&old, &new, utf8_prev, utf8_contents));
+            }
+            return;
+        }
+    }
+    // The contents are binary; just issue a generic message.
+    eprintln!("binary contents differ: {}", dst.display());
+}
+
+fn gen_world(
+    mut generator: Box<dyn WorldGenerator>,
+    opts: &Common,
+    files: &mut Files,
+) -> Result<Vec<PathBuf>> {
+    let (resolve, worlds, sources) = parse_input(&opts.wit, &opts.world)?;
+    generator
+        .generate_worlds(&resolve, &worlds, files)
+        .map_err(|err| {
+            match err.downcast::<Unsupported>() {
+                // Point at the declaration in the WIT which needs to change.
+                Ok(mut unsupported) => {
+                    unsupported.locate(&sources);
+                    unsupported.into()
+                }
+                Err(err) => err,
+            }
+        })?;
+    // Point source maps, if any were generated, at the WIT declarations.
+    files.locate(&sources);
+    Ok(sources)
+}
+
+/// Resolves the input `wit` and the worlds named by `names` within it, along
+/// with the WIT source files which were read.
+fn parse_input(wit: &Path, names: &[String]) -> Result<(Resolve, Vec<WorldId>, Vec<PathBuf>)> {
+    // Wasm inputs are decoded here rather than by `push_path`, which rejects
+    // components.
+    let decoded = match std::fs::read(wit) {
+        Ok(bytes) if bytes.starts_with(b"\0asm") => {
+            // `decode` takes a core module to be a component with an empty
+            // world, which is never what was meant.
+            if !wasmparser::Parser::is_component(&bytes) {
+                bail!(
+                    "{:?} is a core wasm module, not a component or a WIT package",
+                    wit
+                );
+            }
+            Some(
+                wit_component::decode(&bytes)
+                    .with_context(|| format!("failed to decode {:?}", wit))?,
+            )
+        }
+        _ => None,
+    };
+    let mut resolve = Resolve::default();
+    let (pkg, sources) = match decoded {
+        // A component's type information describes a single world.
+        Some(DecodedWasm::Component(resolve, world)) => {
+            if !names.is_empty() {
+                bail!(
+                    "{:?} is a component, which has a single world, so `--world` can't be used with it",
+                    wit
+                );
+            }
+            return Ok((resolve, vec![world], Vec::new()));
+        }
+        Some(DecodedWasm::WitPackage(decoded, pkg)) => {
+            resolve = decoded;
+            (pkg, Vec::new())
+        }
+        None => resolve.push_path(wit)?,
+    };
+    let worlds = if names.is_empty() {
+        vec![resolve.select_world(pkg, None)?]
+    } else {
+        names
+            .iter()
+            .map(|world| resolve.select_world(pkg, Some(world)))
+            .collect::<Result<Vec<_>>>()?
+    };
+    Ok((resolve, worlds, sources))
+}
+
+#[test]
+fn verify_cli() {
+    use clap::CommandFactory;
+    Opt::command().debug_assert()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wit_bindgen_core::wit_parser::UnresolvedPackage;
+    use wit_component::StringEncoding;
+
+    const WIT: &str = "package a:b; world w { export f: func(x: u32) -> string; }";
+
+    /// Returns an empty directory for the test `name` to write files to.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wit-bindgen-cli-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn resolve() -> (Resolve, wit_parser::PackageId) {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("test.wit".as_ref(), WIT).unwrap())
+            .unwrap();
+        (resolve, pkg)
+    }
+
+    /// Asserts that `world` of `resolve` is `w` from `WIT`, named `name`.
+    fn assert_world(resolve: &Resolve, world: WorldId, name: &str) {
+        let world = &resolve.worlds[world];
+        assert_eq!(world.name, name);
+        assert_eq!(world.exports.len(), 1);
+        assert!(world
+            .exports
+            .contains_key(&wit_parser::WorldKey::Name("f".into())));
+    }
+
+    #[test]
+    fn parse_component() {
+        let (resolve, pkg) = resolve();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut module = wit_component::dummy_module(&resolve, world);
+        wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)
+            .unwrap();
+        let component = wit_component::ComponentEncoder::default()
+            .module(&module)
+            .unwrap()
+            .validate(true)
+            .encode()
+            .unwrap();
+        let path = test_dir("parse-component").join("component.wasm");
+        std::fs::write(&path, component).unwrap();
+
+        let (resolve, worlds, sources) = parse_input(&path, &[]).unwrap();
+        assert_eq!(worlds.len(), 1);
+        // Components don't record the name of their world.
+        assert_world(&resolve, worlds[0], "root");
+        assert!(sources.is_empty());
+
+        // A component only has the one world to pick from.
+        let err = parse_input(&path, &["w".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("is a component"), "{err}");
+    }
+
+    #[test]
+    fn parse_wit_package() {
+        let (resolve, pkg) = resolve();
+        let encoded = wit_component::encode(Some(true), &resolve, pkg).unwrap();
+        let path = test_dir("parse-wit-package").join("package.wasm");
+        std::fs::write(&path, encoded).unwrap();
+
+        let (resolve, worlds, sources) = parse_input(&path, &[]).unwrap();
+        assert_world(&resolve, worlds[0], "w");
+        assert!(sources.is_empty());
+        let (resolve, worlds, _) = parse_input(&path, &["w".to_string()]).unwrap();
+        assert_world(&resolve, worlds[0], "w");
+    }
+
+    /// Parses the config file `contents`, returning the name and options of
+    /// each target.
+    fn config(name: &str, contents: &str) -> Result<Vec<(String, Common)>> {
+        let path = test_dir(name).join("wit-bindgen.toml");
+        std::fs::write(&path, contents).unwrap();
+        let targets = parse_config(&path, false)?;
+        Ok(targets.into_iter().map(|t| (t.name, t.args)).collect())
+    }
+
+    #[test]
+    fn config_targets() {
+        let targets = config(
+            "config-targets",
+            r#"
+                wit = "wit"
+                world = "w"
+
+                [c]
+                out-dir = "c"
+                no-helpers = true
+
+                [[rust]]
+                out-dir = "a"
+                with = { "a:b/i" = "crate::i" }
+
+                [[rust]]
+                out-dir = "b"
+                world = ["x", "y"]
+            "#,
+        )
+        .unwrap();
+        let dir = test_dir("config-targets");
+        let targets = targets
+            .iter()
+            .map(|(name, args)| {
+                assert_eq!(args.wit, dir.join("wit"));
+                assert!(!args.check);
+                (
+                    name.as_str(),
+                    args.out_dir.clone().unwrap(),
+                    &args.world[..],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                ("c", dir.join("c"), &["w".to_string()][..]),
+                ("rust", dir.join("a"), &["w".to_string()][..]),
+                (
+                    "rust",
+                    dir.join("b"),
+                    &["x".to_string(), "y".to_string()][..]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_errors() {
+        let error =
+            |contents: &str| format!("{:#}", config("config-errors", contents).err().unwrap());
+
+        let err = error("[rust]\nout-dir = \"src\"");
+        assert!(
+            err.contains("doesn't name a WIT document with `wit`"),
+            "{err}"
+        );
+        let err = error("wit = 1");
+        assert!(err.contains("`wit` in"), "{err}");
+        let err = error("wit = \"wit\"\nrust = true");
+        assert!(
+            err.contains("`rust` in") && err.contains("must be a table"),
+            "{err}"
+        );
+
+        // Options which the subcommand doesn't have.
+        let err = error("wit = \"wit\"\n[rust]\nno-such-option = true");
+        assert!(err.contains("invalid options for `rust`"), "{err}");
+        assert!(err.contains("--no-such-option"), "{err}");
+        let err = error("wit = \"wit\"\n[no-such-target]");
+        assert!(
+            err.contains("invalid options for `no-such-target`"),
+            "{err}"
+        );
+        let err = error("wit = \"wit\"\n[inspect]");
+        assert!(
+            err.contains("only subcommands generating bindings"),
+            "{err}"
+        );
+        let err = error("wit = \"wit\"\n[rust]\nwatch = true");
+        assert!(err.contains("`watch` can't be used"), "{err}");
+
+        // Values which `push_option` can't turn into arguments.
+        let err = error("wit = \"wit\"\n[rust]\nwith = { \"a:b/i\" = 1 }");
+        assert!(err.contains("invalid option for `rust`"), "{err}");
+        assert!(
+            err.contains("the values of `with` must be strings"),
+            "{err}"
+        );
+        let err = error("wit = \"wit\"\n[rust]\nout-dir = 1979-05-27");
+        assert!(err.contains("`out-dir` can't be a date or time"), "{err}");
+    }
+
+    #[test]
+    fn parse_core_module() {
+        // A core wasm module is neither a component nor a WIT package.
+        let path = test_dir("parse-core-module").join("module.wasm");
+        std::fs::write(&path, wasm_encoder::Module::new().finish()).unwrap();
+        let err = parse_input(&path, &[]).unwrap_err();
+        assert!(err.to_string().contains("is a core wasm module"), "{err}");
+    }
+}
@@ -0,0 +1,87 @@
+//! Polling the WIT sources of generated bindings for changes, for `--watch`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the watched files are checked for changes.
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// The files which bindings were generated from, along with the times they
+/// were last modified.
+pub struct Watched {
+    input: PathBuf,
+    sources: Vec<PathBuf>,
+    snapshot: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watched {
+    /// Watches the `input` to the CLI and the `sources` read from it.
+    ///
+    /// If `input` is a directory then the files directly within it and
+    /// anywhere within its `deps` directory are watched as well, so that files
+    /// which are added are noticed too.
+    pub fn new(input: &Path, sources: &[PathBuf]) -> Watched {
+        let mut watched = Watched {
+            input: input.to_path_buf(),
+            sources: sources.to_vec(),
+            snapshot: BTreeMap::new(),
+        };
+        watched.snapshot = watched.snapshot();
+        watched
+    }
+
+    /// Blocks until any of the watched files are changed, added or removed,
+    /// and then stop changing.
+    pub fn wait(&self) {
+        let mut snapshot = self.snapshot.clone();
+        let mut changed = false;
+        loop {
+            std::thread::sleep(INTERVAL);
+            let next = self.snapshot();
+            if next != snapshot {
+                changed = true;
+                snapshot = next;
+            } else if changed {
+                // Editors may save files in several steps, so only stop once
+                // they've finished.
+                return;
+            }
+        }
+    }
+
+    fn snapshot(&self) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        let mut snapshot = BTreeMap::new();
+        let mut add = |path: &Path| {
+            let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            snapshot.insert(path.to_path_buf(), modified);
+        };
+        add(&self.input);
+        for source in self.sources.iter() {
+            add(source);
+        }
+        if self.input.is_dir() {
+            for path in entries(&self.input) {
+                add(&path);
+            }
+            let mut dirs = vec![self.input.join("deps")];
+            while let Some(dir) = dirs.pop() {
+                for path in entries(&dir) {
+                    if path.is_dir() {
+                        dirs.push(path.clone());
+                    }
+                    add(&path);
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+/// Returns the paths of the entries of the directory `dir`, if it exists.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| Some(e.ok()?.path())).collect(),
+        Err(_) => Vec::new(),
+    }
+}
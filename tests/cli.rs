@@ -0,0 +1,69 @@
+//! Tests of the `wit-bindgen` command line tool itself.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Returns an empty directory for the test `name` to write files to.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("cli")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn wit_bindgen(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wit-bindgen"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn check_exit_codes() {
+    let dir = test_dir("check-exit-codes");
+    std::fs::write(
+        dir.join("test.wit"),
+        "package a:b; world w { export f: func(); }",
+    )
+    .unwrap();
+    let generate = ["markdown", "test.wit", "--out-dir", "out"];
+    let check = ["markdown", "test.wit", "--out-dir", "out", "--check"];
+    let md = dir.join("out/w.md");
+
+    let output = wit_bindgen(&dir, &generate);
+    assert!(output.status.success(), "{output:?}");
+    let contents = std::fs::read_to_string(&md).unwrap();
+
+    // Up to date.
+    let output = wit_bindgen(&dir, &check);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+    // Stale files are printed as a diff against what would be generated.
+    std::fs::write(&md, contents.replace("World w</a>", "World v</a>")).unwrap();
+    let output = wit_bindgen(&dir, &check);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("-# <a name=\"w\">World v</a>\n+# <a name=\"w\">World w</a>\n"),
+        "{stdout}"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("not up to date: out/w.md"), "{stderr}");
+
+    // Missing files take precedence over stale ones, but both are reported.
+    std::fs::remove_file(dir.join("out/w.html")).unwrap();
+    let output = wit_bindgen(&dir, &check);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("not up to date: out/w.md"), "{stderr}");
+    assert!(stderr.contains("missing: out/w.html"), "{stderr}");
+
+    // Nothing was written while checking.
+    assert!(!dir.join("out/w.html").exists());
+    assert!(std::fs::read_to_string(&md)
+        .unwrap()
+        .contains("World v</a>"));
+}